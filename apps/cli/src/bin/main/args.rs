@@ -0,0 +1,781 @@
+//! Command line arguments, parsed by hand: global options go anywhere, the rest belongs to the command.
+
+use std::{
+  net::SocketAddr,
+  path::{ Path, PathBuf },
+};
+
+use tracing::Level;
+use xrconnect::{
+  bhaptics_studio::{ listener::Listener, proxy::ProxyRules },
+  haptics::{ calibration::ResponseCurve, model::PlaybackOptions },
+  tls::{ TlsCertificate, TlsConfig },
+};
+
+pub const USAGE: &str = "\
+Usage: main [--log-level LEVEL] [--config-dir DIR] [COMMAND] [OPTIONS]
+
+Commands:
+  serve                 Runs the server, the default when no command is given
+  status                Shows what a running instance is doing
+  stop-all              Stops every effect of a running instance
+  reload                Makes a running instance apply its configuration file right away
+  play FILE.tact        Plays a .tact file on a running instance, or on the outputs given
+                        with --audio-wav, --audio-live or --frames
+  validate FILE         Checks a .tact file, session or configuration file (.toml)
+  convert IN OUT        Converts a .tact file or pattern into a pattern (.json) or audio (.wav)
+  devices               Lists the devices of a running instance, through its HTTP API with --address
+  monitor               Shows the motors, clients, devices and events of a running instance live
+  replay SESSION        Feeds a recorded session into a player
+  calibration           Lists, shows, sets or deletes calibration profiles
+  panic                 Engages or clears the kill switch of a running instance
+  apps                  Lists, approves or denies applications waiting on a running instance
+  client                Connects to a running instance like a game, see the client options
+
+Options:
+  --log-level LEVEL     Most verbose messages logged: error, warn, info, debug or trace
+  --config-dir DIR      Directory the configuration is kept in, instead of the platform's
+  --socket PATH         Control socket of the running instance, control.sock of the config directory
+                        by default, for status, stop-all, reload, play and devices
+  -V, --version         Prints the version
+  -h, --help            Prints this help
+
+Serve options:
+  --config FILE         Configuration, xrconnect.toml of the config directory by default,
+                        reloaded on change. The options below take precedence over it
+  --bind HOST:PORT      Listens on this address, repeatable, 127.0.0.1:15881 when none
+  --unix PATH           Listens on this Unix domain socket, repeatable
+  --tls, --tls-bind HOST:PORT, --tls-cert FILE, --tls-key FILE, --tls-name NAME
+                        Also serves wss://, with a self-signed certificate unless given one
+  --access FILE         Access policy, access.json of the config directory by default
+  --apps FILE           Per application settings, apps.json of the config directory by default
+  --calibration NAME    Calibration profile applied from startup
+  --share-app-namespace Clients with the same app_id share their keys
+  --audio-wav FILE      Renders the output into a WAV file
+  --audio-live          Plays the output on the default sound card
+  --record FILE         Records the client traffic into a session file
+  --upstream ws://HOST:PORT
+                        Relays the clients to a bHaptics Player, see --proxy-drop,
+                        --proxy-intensity and --proxy-mirror
+  --shutdown-timeout S  Seconds given to the clients to disconnect when shutting down
+  --control-socket PATH Answers the commands acting on a running instance on this socket,
+                        control.sock of the config directory by default
+  --tui                 Shows the live view of monitor instead of the log, quitting it stops the server
+
+Client options:
+  --address HOST:PORT   Instance to connect to, 127.0.0.1:15881 by default
+  --app-id ID, --app-name NAME
+                        Application connecting, suffixed with its index when there are several
+  --register FILE.tact  Registers the file under its name, repeatable
+  --script FILE.json    Submits at the times the script gives
+  --random RATE         Submits registered keys, frames and turn offs at random, RATE per second
+  --seed N              Same random submissions for the same seed
+  --duration S          Disconnects after S seconds, 10 by default with --random
+  --apps N              Simulates N applications at once, reporting latency and throughput
+
+Exit codes:
+  0  success
+  1  failure, e.g. a file could not be written or the server could not start
+  2  invalid arguments
+  3  invalid input file
+  4  no running instance answered";
+
+pub struct XRConnectCLIArgs {
+  pub version: bool,
+  pub help: bool,
+
+  /// Most verbose level logged, info when absent
+  pub log_level: Option<Level>,
+
+  /// Directory the configuration is kept in, instead of the platform's
+  pub config_dir: Option<String>,
+
+  pub command: Command,
+}
+
+#[derive(Default)]
+pub struct ServeArgs {
+  /// Shows the live view of `monitor` in the terminal, the log going into its event log
+  pub tui: bool,
+
+  /// Configuration file, xrconnect.toml of the config directory when absent
+  pub config: Option<String>,
+
+  /// Renders the audio output backend into this WAV file
+  pub audio_wav: Option<String>,
+
+  /// Plays the audio output backend on the default sound card
+  pub audio_live: bool,
+
+  /// Calibration profile applied from startup
+  pub calibration: Option<String>,
+
+  /// Seconds given to the clients to disconnect when shutting down
+  pub shutdown_timeout: Option<f32>,
+
+  /// Clients with the same app_id share their keys, across reconnects
+  pub share_app_namespace: bool,
+
+  /// Per application settings, read from the config directory when absent
+  pub apps: Option<String>,
+
+  /// Addresses and Unix domain sockets the server listens on, 127.0.0.1:15881 when none
+  pub listeners: Vec<Listener>,
+
+  /// Access policy, read from the config directory when absent
+  pub access: Option<String>,
+
+  /// Additional wss:// listener
+  pub tls: Option<TlsConfig>,
+
+  /// Certificate and key of the TLS listener, self-signed when absent
+  pub tls_cert: Option<String>,
+  pub tls_key: Option<String>,
+
+  /// Session file the client traffic is recorded to
+  pub record: Option<String>,
+
+  /// bHaptics Player the clients are relayed to, ws://host:port
+  pub upstream: Option<String>,
+
+  /// What is changed in the messages relayed upstream
+  pub proxy_rules: ProxyRules,
+
+  /// Plays the relayed messages on the local devices as well
+  pub proxy_mirror: bool,
+
+  /// Where the commands acting on a running instance reach it, read from the config directory when absent
+  pub control_socket: Option<String>,
+}
+
+pub enum Command {
+  Serve(Box<ServeArgs>),
+  Play(PlayArgs),
+  Validate(ValidateArgs),
+  Convert(ConvertArgs),
+  Status(Option<PathBuf>),
+  StopAll(Option<PathBuf>),
+  Reload(Option<PathBuf>),
+  Devices(Instance),
+  Monitor(SocketAddr),
+  CalibrationList,
+  CalibrationShow(String),
+  CalibrationSet(CalibrationSetArgs),
+  CalibrationDelete(String),
+  Panic(PanicArgs),
+  Apps(AppsArgs),
+  Replay(ReplayArgs),
+  Client(ClientArgs),
+}
+
+/// Where a command reaches the running instance
+pub enum Instance {
+  /// Control socket, the one of the config directory when absent
+  Socket(Option<PathBuf>),
+
+  /// HTTP control API
+  Address(SocketAddr),
+}
+
+/// Address the commands acting on a running instance reach it at
+const CONTROL_ADDRESS: ([u8; 4], u16) = ([127, 0, 0, 1], 15881);
+
+/// Engages or clears the kill switch of a running instance, through its HTTP API
+pub struct PanicArgs {
+  pub address: SocketAddr,
+  pub clear: bool,
+}
+
+impl Default for PanicArgs {
+  fn default() -> Self {
+    Self {
+      address: CONTROL_ADDRESS.into(),
+      clear: false,
+    }
+  }
+}
+
+/// Lists, approves or denies the applications waiting for approval on a running instance
+pub struct AppsArgs {
+  pub action: AppsAction,
+  pub address: SocketAddr,
+}
+
+pub enum AppsAction {
+  Pending,
+  Approve(String),
+  Deny(String),
+}
+
+/// Plays a `.tact` file on a running instance, or on the local outputs given
+pub struct PlayArgs {
+  pub file: String,
+  pub options: PlaybackOptions,
+
+  /// Control socket of the running instance, the one of the config directory when absent
+  pub socket: Option<PathBuf>,
+
+  /// Plays frame by frame, writing every frame to this JSON file
+  pub frames: Option<String>,
+
+  pub audio_wav: Option<String>,
+  pub audio_live: bool,
+}
+
+impl PlayArgs {
+  /// Whether the file is played by this process, on the outputs given, rather than by a running instance.
+  pub fn is_local(&self) -> bool {
+    self.frames.is_some() || self.audio_wav.is_some() || self.audio_live
+  }
+}
+
+/// What a file given to `validate` holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+  Tact,
+  Session,
+  Pattern,
+  Mapping,
+  Calibration,
+  Apps,
+  Access,
+  Config,
+}
+
+impl FileKind {
+  const NAMES: &'static str = "tact, session, pattern, mapping, calibration, apps, access or config";
+
+  fn parse(name: &str) -> Result<Self, String> {
+    match name {
+      "tact" => Ok(FileKind::Tact),
+      "session" => Ok(FileKind::Session),
+      "pattern" => Ok(FileKind::Pattern),
+      "mapping" => Ok(FileKind::Mapping),
+      "calibration" => Ok(FileKind::Calibration),
+      "apps" => Ok(FileKind::Apps),
+      "access" => Ok(FileKind::Access),
+      "config" => Ok(FileKind::Config),
+      _ => Err(format!("unknown file kind {}, expected {}", name, Self::NAMES)),
+    }
+  }
+
+  /// Kind of `file` told by its extension, `.tact` files, `.jsonl` sessions and `.toml` configurations.
+  pub fn of(file: &str) -> Option<Self> {
+    match Path::new(file).extension()?.to_str()? {
+      "tact" => Some(FileKind::Tact),
+      "jsonl" => Some(FileKind::Session),
+      "toml" => Some(FileKind::Config),
+      _ => None,
+    }
+  }
+}
+
+/// Checks that a file can be used as the given kind
+pub struct ValidateArgs {
+  pub file: String,
+  pub kind: FileKind,
+}
+
+/// Converts a `.tact` file or a pattern into a pattern, or renders it into a WAV file
+pub struct ConvertArgs {
+  pub input: String,
+  pub output: String,
+}
+
+/// Connects to a running instance like games do, as many of them as asked
+pub struct ClientArgs {
+  pub address: SocketAddr,
+  pub app_id: Option<String>,
+  pub app_name: Option<String>,
+
+  /// `.tact` files registered by every application
+  pub register: Vec<String>,
+
+  pub script: Option<String>,
+
+  /// Submissions per second and application
+  pub random: Option<f64>,
+  pub seed: Option<u64>,
+
+  pub duration: Option<f32>,
+  pub apps: usize,
+}
+
+/// Feeds a recorded session into a player
+pub struct ReplayArgs {
+  pub session: String,
+  pub speed: f32,
+
+  /// Replays frame by frame, writing every frame to this JSON file
+  pub frames: Option<String>,
+
+  /// Renders the replay into this WAV file
+  pub audio_wav: Option<String>,
+
+  pub share_app_namespace: bool,
+}
+
+#[derive(Default)]
+pub struct CalibrationSetArgs {
+  pub profile: String,
+  pub part: String,
+  pub motor: Option<usize>,
+  pub gain: Option<f32>,
+  pub threshold: Option<f32>,
+  pub cap: Option<f32>,
+  pub curve: Option<ResponseCurve>,
+}
+
+impl XRConnectCLIArgs {
+  pub fn parse() -> Result<Self, String> {
+    Self::parse_from(std::env::args().skip(1))
+  }
+
+  /// Global options go anywhere, the rest belongs to the command, `serve` when none is given.
+  pub fn parse_from(arguments: impl Iterator<Item = String>) -> Result<Self, String> {
+    let mut args = Self {
+      version: false,
+      help: false,
+      log_level: None,
+      config_dir: None,
+      command: Command::Serve(Box::default()),
+    };
+
+    let mut rest = vec![];
+    let mut iter = arguments;
+    while let Some(arg) = iter.next() {
+      match arg.as_str() {
+        "--version" | "-V" => args.version = true,
+        "--help" | "-h" => args.help = true,
+        "--log-level" => {
+          let level = value(&mut iter, &arg)?;
+          args.log_level = Some(level.parse().map_err(|_| format!("--log-level expects error, warn, info, debug or trace, got {}", level))?);
+        },
+        "--config-dir" => args.config_dir = Some(value(&mut iter, &arg)?),
+        _ => rest.push(arg),
+      }
+    }
+
+    if args.version || args.help {
+      return Ok(args);
+    }
+
+    let mut iter = rest.into_iter().peekable();
+    let command = match iter.peek() {
+      Some(arg) if !arg.starts_with('-') => iter.next(),
+      _ => None,
+    };
+
+    args.command = match command.as_deref() {
+      None | Some("serve") => Command::Serve(Box::new(parse_serve_command(&mut iter)?)),
+      Some("play") => parse_play_command(&mut iter)?,
+      Some("validate") => parse_validate_command(&mut iter)?,
+      Some("convert") => parse_convert_command(&mut iter)?,
+      Some("status") => Command::Status(parse_socket_only(&mut iter)?),
+      Some("stop-all") => Command::StopAll(parse_socket_only(&mut iter)?),
+      Some("reload") => Command::Reload(parse_socket_only(&mut iter)?),
+      Some("devices") => Command::Devices(parse_instance(&mut iter)?),
+      Some("monitor") => Command::Monitor(parse_address_only(&mut iter)?),
+      Some("calibration") => parse_calibration_command(&mut iter)?,
+      Some("panic") => parse_panic_command(&mut iter)?,
+      Some("apps") => parse_apps_command(&mut iter)?,
+      Some("replay") => parse_replay_command(&mut iter)?,
+      Some("client") => parse_client_command(&mut iter)?,
+      Some(command) => return Err(format!("unknown command {}", command)),
+    };
+
+    Ok(args)
+  }
+}
+
+fn parse_serve_command(iter: &mut impl Iterator<Item = String>) -> Result<ServeArgs, String> {
+  let mut args = ServeArgs::default();
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--config" => args.config = Some(value(iter, &arg)?),
+      "--tui" => args.tui = true,
+      "--audio-wav" => args.audio_wav = Some(value(iter, &arg)?),
+      "--audio-live" => args.audio_live = true,
+      "--calibration" => args.calibration = Some(value(iter, &arg)?),
+      "--shutdown-timeout" => args.shutdown_timeout = Some(number(iter, &arg)?),
+      "--share-app-namespace" => args.share_app_namespace = true,
+      "--apps" => args.apps = Some(value(iter, &arg)?),
+      "--bind" => args.listeners.push(Listener::Tcp(address(iter, &arg)?)),
+      "--unix" => args.listeners.push(Listener::Unix(value(iter, &arg)?.into())),
+      "--access" => args.access = Some(value(iter, &arg)?),
+      "--tls" => { args.tls.get_or_insert_with(TlsConfig::default); },
+      "--tls-bind" => args.tls.get_or_insert_with(TlsConfig::default).address = address(iter, &arg)?,
+      "--tls-cert" => args.tls_cert = Some(value(iter, &arg)?),
+      "--tls-key" => args.tls_key = Some(value(iter, &arg)?),
+      "--record" => args.record = Some(value(iter, &arg)?),
+      "--upstream" => args.upstream = Some(value(iter, &arg)?),
+      "--proxy-drop" => args.proxy_rules.drop.push(value(iter, &arg)?),
+      "--proxy-intensity" => args.proxy_rules.intensity = number(iter, &arg)?,
+      "--proxy-mirror" => args.proxy_mirror = true,
+      "--control-socket" => args.control_socket = Some(value(iter, &arg)?),
+      "--tls-name" => {
+        let name = value(iter, &arg)?;
+        if let TlsCertificate::SelfSigned { names } = &mut args.tls.get_or_insert_with(TlsConfig::default).certificate {
+          names.push(name);
+        }
+      },
+      _ => return Err(format!("unknown argument {}", arg)),
+    }
+  }
+
+  match (args.tls_cert.take(), args.tls_key.take()) {
+    (Some(cert), Some(key)) => {
+      args.tls.get_or_insert_with(TlsConfig::default).certificate = TlsCertificate::Files {
+        cert: cert.into(),
+        key: key.into(),
+      };
+    },
+    (None, None) => {},
+    _ => return Err(String::from("--tls-cert and --tls-key go together")),
+  }
+
+  if args.upstream.is_none() && (args.proxy_mirror || args.proxy_rules != ProxyRules::default()) {
+    return Err(String::from("--proxy-drop, --proxy-intensity and --proxy-mirror need --upstream"));
+  }
+
+  Ok(args)
+}
+
+fn value(iter: &mut impl Iterator<Item = String>, name: &str) -> Result<String, String> {
+  iter.next().ok_or_else(|| format!("{} expects a value", name))
+}
+
+fn number<T: std::str::FromStr>(iter: &mut impl Iterator<Item = String>, name: &str) -> Result<T, String> {
+  let value = value(iter, name)?;
+  value.parse().map_err(|_| format!("{} expects a number, got {}", name, value))
+}
+
+fn address(iter: &mut impl Iterator<Item = String>, name: &str) -> Result<SocketAddr, String> {
+  let value = value(iter, name)?;
+  value.parse().map_err(|_| format!("{} expects host:port, got {}", name, value))
+}
+
+fn parse_calibration_command(iter: &mut impl Iterator<Item = String>) -> Result<Command, String> {
+  match value(iter, "calibration")?.as_str() {
+    "list" => Ok(Command::CalibrationList),
+    "show" => Ok(Command::CalibrationShow(value(iter, "calibration show")?)),
+    "delete" => Ok(Command::CalibrationDelete(value(iter, "calibration delete")?)),
+    "set" => {
+      let mut args = CalibrationSetArgs {
+        profile: value(iter, "calibration set")?,
+        part: value(iter, "calibration set")?,
+        ..Default::default()
+      };
+
+      while let Some(arg) = iter.next() {
+        match arg.as_str() {
+          "--motor" => args.motor = Some(number(iter, &arg)?),
+          "--gain" => args.gain = Some(number(iter, &arg)?),
+          "--threshold" => args.threshold = Some(number(iter, &arg)?),
+          "--cap" => args.cap = Some(number(iter, &arg)?),
+          "--gamma" => args.curve = Some(ResponseCurve::Gamma { gamma: number(iter, &arg)? }),
+          "--linear" => args.curve = Some(ResponseCurve::Linear),
+          _ => return Err(format!("unknown argument {}", arg)),
+        }
+      }
+
+      Ok(Command::CalibrationSet(args))
+    },
+    command => Err(format!("unknown calibration command {}, expected list, show, set or delete", command)),
+  }
+}
+
+fn parse_panic_command(iter: &mut impl Iterator<Item = String>) -> Result<Command, String> {
+  let mut args = PanicArgs::default();
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--clear" => args.clear = true,
+      "--address" => args.address = address(iter, &arg)?,
+      _ => return Err(format!("unknown argument {}", arg)),
+    }
+  }
+
+  Ok(Command::Panic(args))
+}
+
+fn parse_apps_command(iter: &mut impl Iterator<Item = String>) -> Result<Command, String> {
+  let action = match value(iter, "apps")?.as_str() {
+    "pending" => AppsAction::Pending,
+    "approve" => AppsAction::Approve(value(iter, "apps approve")?),
+    "deny" => AppsAction::Deny(value(iter, "apps deny")?),
+    command => return Err(format!("unknown apps command {}, expected pending, approve or deny", command)),
+  };
+
+  let mut args = AppsArgs {
+    action,
+    address: CONTROL_ADDRESS.into(),
+  };
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--address" => args.address = address(iter, &arg)?,
+      _ => return Err(format!("unknown argument {}", arg)),
+    }
+  }
+
+  Ok(Command::Apps(args))
+}
+
+fn parse_replay_command(iter: &mut impl Iterator<Item = String>) -> Result<Command, String> {
+  let mut args = ReplayArgs {
+    session: value(iter, "replay")?,
+    speed: 1.0,
+    frames: None,
+    audio_wav: None,
+    share_app_namespace: false,
+  };
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--speed" => args.speed = number(iter, &arg)?,
+      "--frames" => args.frames = Some(value(iter, &arg)?),
+      "--audio-wav" => args.audio_wav = Some(value(iter, &arg)?),
+      "--share-app-namespace" => args.share_app_namespace = true,
+      _ => return Err(format!("unknown argument {}", arg)),
+    }
+  }
+
+  if args.speed <= 0.0 {
+    return Err(format!("--speed expects a positive number, got {}", args.speed));
+  }
+
+  Ok(Command::Replay(args))
+}
+
+fn parse_client_command(iter: &mut impl Iterator<Item = String>) -> Result<Command, String> {
+  let mut args = ClientArgs {
+    address: CONTROL_ADDRESS.into(),
+    app_id: None,
+    app_name: None,
+    register: vec![],
+    script: None,
+    random: None,
+    seed: None,
+    duration: None,
+    apps: 1,
+  };
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--address" => args.address = address(iter, &arg)?,
+      "--app-id" => args.app_id = Some(value(iter, &arg)?),
+      "--app-name" => args.app_name = Some(value(iter, &arg)?),
+      "--register" => args.register.push(value(iter, &arg)?),
+      "--script" => args.script = Some(value(iter, &arg)?),
+      "--random" => args.random = Some(number(iter, &arg)?),
+      "--seed" => args.seed = Some(number(iter, &arg)?),
+      "--duration" => args.duration = Some(number(iter, &arg)?),
+      "--apps" => args.apps = number(iter, &arg)?,
+      _ => return Err(format!("unknown argument {}", arg)),
+    }
+  }
+
+  if args.script.is_some() && args.random.is_some() {
+    return Err(String::from("--script and --random do not go together"));
+  }
+  if let Some(rate) = args.random.filter(|rate| !(*rate > 0.0 && rate.is_finite())) {
+    return Err(format!("--random expects a positive number, got {}", rate));
+  }
+  if let Some(duration) = args.duration.filter(|duration| !(*duration > 0.0 && duration.is_finite())) {
+    return Err(format!("--duration expects a positive number, got {}", duration));
+  }
+  if args.apps == 0 {
+    return Err(String::from("--apps expects at least 1"));
+  }
+
+  Ok(Command::Client(args))
+}
+
+fn parse_play_command(iter: &mut impl Iterator<Item = String>) -> Result<Command, String> {
+  let mut args = PlayArgs {
+    file: value(iter, "play")?,
+    options: PlaybackOptions::default(),
+    socket: None,
+    frames: None,
+    audio_wav: None,
+    audio_live: false,
+  };
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--intensity" => args.options.intensity = number(iter, &arg)?,
+      "--duration" => args.options.duration = number(iter, &arg)?,
+      "--socket" => args.socket = Some(value(iter, &arg)?.into()),
+      "--frames" => args.frames = Some(value(iter, &arg)?),
+      "--audio-wav" => args.audio_wav = Some(value(iter, &arg)?),
+      "--audio-live" => args.audio_live = true,
+      _ => return Err(format!("unknown argument {}", arg)),
+    }
+  }
+
+  if args.options.duration <= 0.0 {
+    return Err(format!("--duration expects a positive number, got {}", args.options.duration));
+  }
+
+  if args.socket.is_some() && args.is_local() {
+    return Err(String::from("--socket plays on a running instance, --audio-wav, --audio-live and --frames on the local outputs"));
+  }
+
+  Ok(Command::Play(args))
+}
+
+fn parse_validate_command(iter: &mut impl Iterator<Item = String>) -> Result<Command, String> {
+  let file = value(iter, "validate")?;
+  let mut kind = FileKind::of(&file);
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--as" => kind = Some(FileKind::parse(&value(iter, &arg)?)?),
+      _ => return Err(format!("unknown argument {}", arg)),
+    }
+  }
+
+  let kind = kind.ok_or_else(|| format!("cannot tell what {} holds, pass --as {}", file, FileKind::NAMES))?;
+  Ok(Command::Validate(ValidateArgs { file, kind }))
+}
+
+fn parse_convert_command(iter: &mut impl Iterator<Item = String>) -> Result<Command, String> {
+  let args = ConvertArgs {
+    input: value(iter, "convert")?,
+    output: value(iter, "convert")?,
+  };
+
+  if let Some(arg) = iter.next() {
+    return Err(format!("unknown argument {}", arg));
+  }
+
+  Ok(Command::Convert(args))
+}
+
+/// Options of the commands only taking the address of the running instance.
+fn parse_address_only(iter: &mut impl Iterator<Item = String>) -> Result<SocketAddr, String> {
+  let mut address = CONTROL_ADDRESS.into();
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--address" => address = self::address(iter, &arg)?,
+      _ => return Err(format!("unknown argument {}", arg)),
+    }
+  }
+
+  Ok(address)
+}
+
+/// Options of the commands only taking the control socket of the running instance.
+fn parse_socket_only(iter: &mut impl Iterator<Item = String>) -> Result<Option<PathBuf>, String> {
+  let mut socket = None;
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--socket" => socket = Some(value(iter, &arg)?.into()),
+      _ => return Err(format!("unknown argument {}", arg)),
+    }
+  }
+
+  Ok(socket)
+}
+
+/// Options of the commands reaching the running instance through its control socket, or its HTTP API.
+fn parse_instance(iter: &mut impl Iterator<Item = String>) -> Result<Instance, String> {
+  let mut instance = Instance::Socket(None);
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--socket" => instance = Instance::Socket(Some(value(iter, &arg)?.into())),
+      "--address" => instance = Instance::Address(address(iter, &arg)?),
+      _ => return Err(format!("unknown argument {}", arg)),
+    }
+  }
+
+  Ok(instance)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parsed(line: &str) -> XRConnectCLIArgs {
+    XRConnectCLIArgs::parse_from(line.split_whitespace().map(String::from)).unwrap_or_else(|why| panic!("{}: {}", line, why))
+  }
+
+  fn error(line: &str) -> String {
+    XRConnectCLIArgs::parse_from(line.split_whitespace().map(String::from)).err().unwrap_or_else(|| panic!("{} parsed", line))
+  }
+
+  #[test]
+  fn serve_is_the_default_command() {
+    let args = parsed("");
+    assert!(!args.help && !args.version);
+    assert!(matches!(args.command, Command::Serve(serve) if serve.listeners.is_empty() && !serve.tui));
+
+    let Command::Serve(serve) = parsed("--tui --bind 0.0.0.0:15881 --unix /tmp/xr.sock --calibration alice").command else {
+      panic!("not serve");
+    };
+    assert!(serve.tui);
+    assert_eq!(serve.listeners, [Listener::Tcp("0.0.0.0:15881".parse().unwrap()), Listener::Unix("/tmp/xr.sock".into())]);
+    assert_eq!(serve.calibration.as_deref(), Some("alice"));
+  }
+
+  #[test]
+  fn global_options_go_anywhere() {
+    let args = parsed("status --log-level debug --socket /tmp/control.sock --config-dir /tmp/xr");
+    assert_eq!(args.log_level, Some(Level::DEBUG));
+    assert_eq!(args.config_dir.as_deref(), Some("/tmp/xr"));
+    assert!(matches!(args.command, Command::Status(Some(socket)) if socket == Path::new("/tmp/control.sock")));
+
+    assert!(parsed("play --help").help);
+    assert!(parsed("-V").version);
+  }
+
+  #[test]
+  fn commands_take_their_options() {
+    let Command::Play(play) = parsed("play hit.tact --intensity 0.5 --frames frames.json").command else {
+      panic!("not play");
+    };
+    assert_eq!(play.file, "hit.tact");
+    assert_eq!(play.options.intensity, 0.5);
+    assert!(play.is_local());
+
+    assert!(matches!(parsed("validate session.jsonl").command, Command::Validate(args) if args.kind == FileKind::Session));
+    assert!(matches!(parsed("validate pattern.json --as pattern").command, Command::Validate(args) if args.kind == FileKind::Pattern));
+    assert!(matches!(parsed("devices --address 127.0.0.1:9000").command, Command::Devices(Instance::Address(address)) if address.port() == 9000));
+    assert!(matches!(parsed("devices").command, Command::Devices(Instance::Socket(None))));
+    assert!(matches!(parsed("panic --clear").command, Command::Panic(args) if args.clear && args.address == CONTROL_ADDRESS.into()));
+    assert!(matches!(parsed("apps approve com.example.game").command, Command::Apps(AppsArgs { action: AppsAction::Approve(id), .. }) if id == "com.example.game"));
+    assert!(matches!(parsed("replay session.jsonl --speed 2").command, Command::Replay(args) if args.speed == 2.0));
+    assert!(matches!(parsed("client --random 5 --seed 7 --apps 3").command, Command::Client(args) if args.random == Some(5.0) && args.seed == Some(7) && args.apps == 3));
+
+    let Command::CalibrationSet(set) = parsed("calibration set alice ChestFront --motor 3 --gain 1.5 --gamma 2").command else {
+      panic!("not calibration set");
+    };
+    assert_eq!((set.profile.as_str(), set.part.as_str(), set.motor, set.gain), ("alice", "ChestFront", Some(3), Some(1.5)));
+    assert_eq!(set.curve, Some(ResponseCurve::Gamma { gamma: 2.0 }));
+  }
+
+  #[test]
+  fn invalid_arguments_are_explained() {
+    assert_eq!(error("fly"), "unknown command fly");
+    assert_eq!(error("status --verbose"), "unknown argument --verbose");
+    assert_eq!(error("--log-level loud"), "--log-level expects error, warn, info, debug or trace, got loud");
+    assert_eq!(error("serve --bind"), "--bind expects a value");
+    assert_eq!(error("serve --bind localhost"), "--bind expects host:port, got localhost");
+    assert_eq!(error("serve --tls-cert cert.pem"), "--tls-cert and --tls-key go together");
+    assert_eq!(error("serve --proxy-mirror"), "--proxy-drop, --proxy-intensity and --proxy-mirror need --upstream");
+    assert_eq!(error("play"), "play expects a value");
+    assert_eq!(error("play hit.tact --socket control.sock --audio-live"), "--socket plays on a running instance, --audio-wav, --audio-live and --frames on the local outputs");
+    assert_eq!(error("replay session.jsonl --speed 0"), "--speed expects a positive number, got 0");
+    assert_eq!(error("client --script script.json --random 5"), "--script and --random do not go together");
+    assert_eq!(error("client --apps 0"), "--apps expects at least 1");
+    assert_eq!(error("client --random many"), "--random expects a number, got many");
+    assert_eq!(error("convert in.tact out.json extra"), "unknown argument extra");
+    assert_eq!(error("calibration rename"), "unknown calibration command rename, expected list, show, set or delete");
+    assert!(error("validate notes.txt").starts_with("cannot tell what notes.txt holds"));
+  }
+}
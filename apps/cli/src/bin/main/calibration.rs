@@ -0,0 +1,58 @@
+//! `calibration`, managing the profiles kept in the config directory.
+
+use xrconnect::haptics::calibration::{ CalibrationError, CalibrationProfile, CalibrationStore };
+
+use crate::{ args::CalibrationSetArgs, Failure };
+
+pub fn list(store: &CalibrationStore) -> Result<(), Failure> {
+  for name in store.list().map_err(|why| why.to_string())? {
+    println!("{}", name);
+  }
+
+  Ok(())
+}
+
+pub fn show(store: &CalibrationStore, name: &str) -> Result<(), Failure> {
+  let profile = store.load(name).map_err(|why| why.to_string())?;
+  println!("{}", serde_json::to_string_pretty(&profile).map_err(|why| why.to_string())?);
+  Ok(())
+}
+
+pub fn delete(store: &CalibrationStore, name: &str) -> Result<(), Failure> {
+  store.delete(name).map_err(|why| why.to_string())?;
+  Ok(())
+}
+
+/// Changes a body part, or one of its motors, creating the profile when needed.
+pub fn set(store: &CalibrationStore, args: CalibrationSetArgs) -> Result<(), Failure> {
+  let mut profile = match store.load(&args.profile) {
+    Ok(profile) => profile,
+    Err(CalibrationError::NotFound(_)) => CalibrationProfile::new(&args.profile),
+    Err(why) => return Err(why.to_string().into()),
+  };
+
+  let part = serde_json::from_value(serde_json::Value::String(args.part.clone()))
+    .map_err(|_| format!("unknown body part {}", args.part))?;
+  let calibration = profile.part_mut(part);
+
+  match args.motor {
+    Some(index) => {
+      let motor = calibration.motors.entry(index).or_default();
+      motor.gain = args.gain.or(motor.gain);
+      motor.threshold = args.threshold.or(motor.threshold);
+      motor.cap = args.cap.or(motor.cap);
+      motor.curve = args.curve.or(motor.curve.take());
+    },
+    None => {
+      calibration.gain = args.gain.unwrap_or(calibration.gain);
+      calibration.threshold = args.threshold.unwrap_or(calibration.threshold);
+      calibration.cap = args.cap.unwrap_or(calibration.cap);
+      if let Some(curve) = args.curve {
+        calibration.curve = curve;
+      }
+    },
+  }
+
+  store.save(&profile).map_err(|why| why.to_string())?;
+  Ok(())
+}
@@ -0,0 +1,73 @@
+//! `client`, connecting to a running instance like games do.
+
+use std::{ path::Path, time::Duration };
+
+use tokio_util::sync::CancellationToken;
+use xrconnect::bhaptics_studio::client::{
+  load::Simulation,
+  schedule::{ RandomSchedule, Schedule, Script },
+  TactFile,
+};
+
+use crate::{ args::ClientArgs, Failure };
+
+/// How long random submissions go on when no duration is given
+const RANDOM_DURATION: Duration = Duration::from_secs(10);
+
+pub async fn client(args: ClientArgs) -> Result<(), Failure> {
+  let mut tact_files = vec![];
+  for path in &args.register {
+    if !Path::new(path).exists() {
+      return Err(Failure::Failed(format!("{}: no such file", path)));
+    }
+    tact_files.push(TactFile::load(path).map_err(|why| Failure::Invalid(format!("{}: {}", path, why)))?);
+  }
+
+  let schedule = match (&args.script, args.random) {
+    (Some(path), _) => Schedule::Script(Script::load(path).map_err(|why| Failure::Invalid(format!("{}: {}", path, why)))?),
+    (None, Some(rate)) => {
+      let keys = tact_files.iter().map(|tact| tact.key.clone()).collect();
+      let random = RandomSchedule::new(rate, keys);
+      Schedule::Random(match args.seed {
+        Some(seed) => random.with_seed(seed),
+        None => random,
+      })
+    },
+    (None, None) => Schedule::Script(Script { steps: vec![] }),
+  };
+  let duration = args.duration.map(Duration::from_secs_f32)
+    .or(matches!(schedule, Schedule::Random(_)).then_some(RANDOM_DURATION));
+
+  let mut simulation = Simulation::new(&format!("ws://{}", args.address), schedule)
+    .with_apps(args.apps)
+    .with_tact_files(tact_files);
+  if let Some(id) = &args.app_id {
+    simulation = simulation.with_app_id(id);
+  }
+  if let Some(name) = &args.app_name {
+    simulation = simulation.with_app_name(name);
+  }
+  if let Some(duration) = duration {
+    simulation = simulation.with_duration(duration);
+  }
+
+  let stop = CancellationToken::new();
+  tokio::spawn({
+    let stop = stop.clone();
+    async move {
+      if tokio::signal::ctrl_c().await.is_ok() {
+        stop.cancel();
+      }
+    }
+  });
+
+  println!("Running {} application(s) against {}", args.apps, args.address);
+  let report = simulation.run(stop).await;
+  println!("{}", report);
+
+  match report {
+    report if report.connected == 0 => Err(Failure::Unreachable(format!("failed to reach {}", args.address))),
+    report if !report.is_success() => Err(Failure::Failed(format!("{} of the requests failed", report.failures))),
+    _ => Ok(()),
+  }
+}
@@ -0,0 +1,34 @@
+//! `convert`, turning `.tact` files and patterns into patterns or audio.
+
+use std::{ path::Path, time::Instant };
+
+use xrconnect::haptics::{ model::PlaybackOptions, player::HapticPlayer };
+
+use crate::{
+  args::ConvertArgs,
+  play::{ add_audio_outputs, load_pattern, play_stepped },
+  Failure,
+};
+
+pub fn convert(args: ConvertArgs) -> Result<(), Failure> {
+  let pattern = load_pattern(&args.input)?;
+  let extension = Path::new(&args.output).extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+
+  match extension {
+    "json" => {
+      let json = serde_json::to_string_pretty(&pattern).map_err(|why| why.to_string())?;
+      std::fs::write(&args.output, json).map_err(|why| format!("failed to write {}: {}", args.output, why))?;
+    },
+    "wav" => {
+      let player = HapticPlayer::with_manual_clock(Instant::now());
+      add_audio_outputs(&player, Some(&args.output), false)?;
+      player.play_pattern("convert", pattern, PlaybackOptions::default()).map_err(|why| why.to_string())?;
+      play_stepped(&player, "convert");
+      player.shutdown();
+    },
+    _ => return Err(Failure::Usage(format!("cannot convert to {}, expected a .json or .wav file", args.output))),
+  }
+
+  println!("{} converted to {}", args.input, args.output);
+  Ok(())
+}
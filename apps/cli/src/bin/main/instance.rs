@@ -0,0 +1,127 @@
+//! Commands acting on a running instance, through its control socket or its HTTP API.
+
+use std::{ net::SocketAddr, path::PathBuf };
+
+use tokio::{
+  io::{ AsyncReadExt, AsyncWriteExt },
+  net::TcpStream,
+};
+use xrconnect::{
+  access::PendingApp,
+  control::{ self, ControlError, ControlRequest, ControlResponse },
+  paths,
+};
+
+use crate::{
+  args::{ AppsAction, AppsArgs, Instance, PanicArgs },
+  Failure,
+};
+
+/// Sends a request to the control socket of a running instance, returning its response unless it is an error.
+pub async fn socket_request(socket: Option<PathBuf>, request: ControlRequest) -> Result<ControlResponse, Failure> {
+  let path = socket.unwrap_or_else(paths::control_socket);
+
+  match control::request(&path, &request).await {
+    Ok(ControlResponse::Error(why)) => Err(Failure::Failed(why)),
+    Ok(response) => Ok(response),
+    Err(why @ ControlError::Unreachable { .. }) => Err(Failure::Unreachable(why.to_string())),
+    Err(why) => Err(Failure::Failed(why.to_string())),
+  }
+}
+
+/// Sends a bodiless HTTP request to a running instance, returning the response body.
+pub async fn control_request(address: SocketAddr, method: &str, path: &str) -> Result<String, Failure> {
+  let unreachable = |why: std::io::Error| Failure::Unreachable(format!("failed to reach {}: {}", address, why));
+
+  let mut stream = TcpStream::connect(address).await.map_err(unreachable)?;
+  let request = format!("{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", method, path, address);
+  stream.write_all(request.as_bytes()).await.map_err(unreachable)?;
+
+  let mut response = String::new();
+  stream.read_to_string(&mut response).await.map_err(unreachable)?;
+
+  let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+  let status = head.split_whitespace().nth(1).unwrap_or_default();
+  if !status.starts_with('2') {
+    return Err(Failure::Failed(format!("{} {} failed: {}", method, path, head.lines().next().unwrap_or_default())));
+  }
+
+  Ok(body.to_string())
+}
+
+/// What a running instance did, as it describes it.
+pub fn done(response: ControlResponse) -> Result<String, Failure> {
+  match response {
+    ControlResponse::Done(done) => Ok(done),
+    response => Err(unexpected(response)),
+  }
+}
+
+pub fn unexpected(response: ControlResponse) -> Failure {
+  Failure::Failed(format!("unexpected response from the running instance: {:?}", response))
+}
+
+pub async fn status(socket: Option<PathBuf>) -> Result<(), Failure> {
+  let status = match socket_request(socket, ControlRequest::Status).await? {
+    ControlResponse::Status(status) => status,
+    response => return Err(unexpected(response)),
+  };
+
+  let names = |names: Vec<String>| if names.is_empty() { String::from("none") } else { names.join(", ") };
+  println!("xrconnect {}, up {} s", status.version, status.uptime_secs);
+  println!("listening    {}", names(status.listeners));
+  println!("clients      {}", status.clients);
+  println!("devices      {}", status.devices);
+  println!("keys         {} registered, active: {}", status.registered_keys, names(status.active_keys));
+  println!("muted        {}", names(status.muted.iter().map(|part| format!("{:?}", part)).collect()));
+  println!("kill switch  {}", if status.panicked { "engaged" } else { "off" });
+  Ok(())
+}
+
+pub async fn devices(instance: Instance) -> Result<(), Failure> {
+  match instance {
+    Instance::Socket(socket) => match socket_request(socket, ControlRequest::Devices).await? {
+      ControlResponse::Devices(devices) => {
+        for device in devices {
+          let positions = device.positions.iter().map(|part| format!("{:?}", part)).collect::<Vec<_>>();
+          let status = if device.stopped { "stopped" } else { "running" };
+          println!("{}\t{}\t{}", device.name, positions.join(","), status);
+        }
+      },
+      response => return Err(unexpected(response)),
+    },
+    Instance::Address(address) => {
+      let body = control_request(address, "GET", "/api/devices").await?;
+      let devices: Vec<serde_json::Value> = serde_json::from_str(&body).map_err(|why| why.to_string())?;
+      for device in devices {
+        let positions = device["positions"].as_array().into_iter().flatten().filter_map(|part| part.as_str()).collect::<Vec<_>>();
+        let status = if device["stopped"].as_bool().unwrap_or(false) { "stopped" } else { "running" };
+        println!("{}\t{}\t{}", device["name"].as_str().unwrap_or_default(), positions.join(","), status);
+      }
+    },
+  }
+
+  Ok(())
+}
+
+pub async fn panic(args: PanicArgs) -> Result<(), Failure> {
+  let method = if args.clear { "DELETE" } else { "POST" };
+  println!("{}", control_request(args.address, method, "/api/panic").await?);
+  Ok(())
+}
+
+pub async fn apps(args: AppsArgs) -> Result<(), Failure> {
+  match args.action {
+    AppsAction::Pending => {
+      let body = control_request(args.address, "GET", "/api/apps/pending").await?;
+      let pending: Vec<PendingApp> = serde_json::from_str(&body).map_err(|why| why.to_string())?;
+      for app in pending {
+        println!("{}\t{}", app.id, app.name);
+      }
+    },
+    AppsAction::Approve(id) => { control_request(args.address, "POST", &format!("/api/apps/{}/approve", id)).await?; },
+    AppsAction::Deny(id) => { control_request(args.address, "POST", &format!("/api/apps/{}/deny", id)).await?; },
+  }
+
+  Ok(())
+}
@@ -0,0 +1,194 @@
+use std::{ fmt, process::ExitCode };
+
+use tokio_util::sync::CancellationToken;
+use tracing::Level;
+use tracing_subscriber::{
+  filter::LevelFilter,
+  layer::SubscriberExt,
+  reload,
+  util::SubscriberInitExt,
+  Registry,
+};
+use xrconnect::{ control::ControlRequest, haptics::calibration::CalibrationStore };
+
+mod args;
+mod calibration;
+mod client;
+mod convert;
+mod instance;
+mod monitor;
+mod play;
+mod replay;
+mod serve;
+mod validate;
+
+use args::{ Command, XRConnectCLIArgs, USAGE };
+use instance::{ control_request, done, socket_request };
+use monitor::LogLines;
+
+/// Why the program failed, every kind has its own exit code, see [`args::USAGE`].
+#[derive(Debug)]
+pub enum Failure {
+  /// Anything not covered by the other kinds
+  Failed(String),
+
+  /// Arguments do not make sense
+  Usage(String),
+
+  /// Input file is not valid
+  Invalid(String),
+
+  /// No running instance answered
+  Unreachable(String),
+}
+
+impl Failure {
+  fn exit_code(&self) -> ExitCode {
+    match self {
+      Failure::Failed(_) => ExitCode::from(1),
+      Failure::Usage(_) => ExitCode::from(2),
+      Failure::Invalid(_) => ExitCode::from(3),
+      Failure::Unreachable(_) => ExitCode::from(4),
+    }
+  }
+}
+
+impl fmt::Display for Failure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Failure::Failed(why) | Failure::Invalid(why) | Failure::Unreachable(why) => write!(f, "{}", why),
+      Failure::Usage(why) => write!(f, "{}\n\nSee --help for usage", why),
+    }
+  }
+}
+
+impl From<String> for Failure {
+  fn from(why: String) -> Self {
+    Failure::Failed(why)
+  }
+}
+
+/// Changes the level of the messages logged while running
+type LogLevelHandle = reload::Handle<LevelFilter, Registry>;
+
+/// Where messages are logged, set up before the command runs
+struct Logging {
+  /// Unless the level was given on the command line
+  level: Option<LogLevelHandle>,
+
+  /// Messages kept for the event log of `serve --tui`, instead of being written to the terminal
+  lines: Option<LogLines>,
+}
+
+async fn run_command(command: Command, logging: Logging) -> Result<(), Failure> {
+  let store = CalibrationStore::default();
+
+  match command {
+    Command::Serve(args) => serve::serve(*args, logging).await,
+    Command::Play(args) => play::play(args).await,
+    Command::Validate(args) => validate::validate(args),
+    Command::Convert(args) => convert::convert(args),
+    Command::Status(socket) => instance::status(socket).await,
+    Command::StopAll(socket) => {
+      println!("{}", done(socket_request(socket, ControlRequest::StopAll).await?)?);
+      Ok(())
+    },
+    Command::Reload(socket) => {
+      println!("{}", done(socket_request(socket, ControlRequest::Reload).await?)?);
+      Ok(())
+    },
+    Command::Devices(instance) => instance::devices(instance).await,
+    Command::Monitor(address) => {
+      control_request(address, "GET", "/api/clients").await?;
+      monitor::run(address, None, CancellationToken::new()).await
+    },
+    Command::CalibrationList => calibration::list(&store),
+    Command::CalibrationShow(name) => calibration::show(&store, &name),
+    Command::CalibrationDelete(name) => calibration::delete(&store, &name),
+    Command::CalibrationSet(args) => calibration::set(&store, args),
+    Command::Panic(args) => instance::panic(args).await,
+    Command::Apps(args) => instance::apps(args).await,
+    Command::Replay(args) => replay::replay(args).await,
+    Command::Client(args) => client::client(args).await,
+  }
+}
+
+fn main() -> ExitCode {
+  let args = match XRConnectCLIArgs::parse() {
+    Ok(args) => args,
+    Err(why) => {
+      let failure = Failure::Usage(why);
+      eprintln!("{}", failure);
+      return failure.exit_code();
+    },
+  };
+
+  // Changing the environment is only sound while no other thread may read it, i.e. before the runtime starts
+  if let Some(dir) = &args.config_dir {
+    std::env::set_var("XRCONNECT_CONFIG_DIR", dir);
+  }
+
+  match tokio::runtime::Builder::new_multi_thread().enable_all().build() {
+    Ok(runtime) => runtime.block_on(run(args)),
+    Err(why) => {
+      let failure = Failure::Failed(format!("failed to start the runtime: {}", why));
+      eprintln!("{}", failure);
+      failure.exit_code()
+    },
+  }
+}
+
+async fn run(args: XRConnectCLIArgs) -> ExitCode {
+  // Level given on the command line takes precedence over the configuration file
+  let (filter, handle) = reload::Layer::new(LevelFilter::from_level(args.log_level.unwrap_or(Level::INFO)));
+  let lines = matches!(&args.command, Command::Serve(serve) if serve.tui).then(LogLines::default);
+  let captured = lines.clone().map(|lines| tracing_subscriber::fmt::layer().with_ansi(false).with_writer(move || lines.clone()));
+  tracing_subscriber::registry()
+    .with(filter)
+    .with(lines.is_none().then(tracing_subscriber::fmt::layer))
+    .with(captured)
+    .init();
+  let logging = Logging {
+    level: args.log_level.is_none().then_some(handle),
+    lines,
+  };
+
+  if args.help {
+    println!("{}", USAGE);
+    return ExitCode::SUCCESS;
+  }
+
+  if args.version {
+    println!("xrconnect {}", env!("CARGO_PKG_VERSION"));
+    return ExitCode::SUCCESS;
+  }
+
+  match run_command(args.command, logging).await {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(failure) => {
+      eprintln!("{}", failure);
+      failure.exit_code()
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn failures_have_their_own_exit_codes() {
+    let why = String::from("why");
+    assert_eq!(Failure::Failed(why.clone()).exit_code(), ExitCode::from(1));
+    assert_eq!(Failure::Usage(why.clone()).exit_code(), ExitCode::from(2));
+    assert_eq!(Failure::Invalid(why.clone()).exit_code(), ExitCode::from(3));
+    assert_eq!(Failure::Unreachable(why.clone()).exit_code(), ExitCode::from(4));
+    assert_eq!(Failure::from(why).exit_code(), ExitCode::from(1));
+  }
+
+  #[test]
+  fn usage_failures_point_to_the_help() {
+    assert_eq!(Failure::Usage(String::from("unknown command nope")).to_string(), "unknown command nope\n\nSee --help for usage");
+    assert_eq!(Failure::Invalid(String::from("bad.tact: not a project")).to_string(), "bad.tact: not a project");
+  }
+}
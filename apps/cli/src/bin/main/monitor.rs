@@ -0,0 +1,606 @@
+//! Live terminal view of a running instance, built on its control API: the motors of every
+//! body part, the connected clients, the active keys per application, the devices and the
+//! events as they happen. Used by `monitor`, and by `serve --tui` on the server itself.
+
+use std::{
+  collections::{ BTreeMap, BTreeSet, VecDeque },
+  io,
+  net::SocketAddr,
+  sync::{ Arc, Mutex },
+  time::{ Duration, SystemTime, UNIX_EPOCH },
+};
+
+use futures_util::StreamExt;
+use haptic_lib::BodyPart;
+use ratatui::{
+  crossterm::event::{ self, Event, KeyCode, KeyEventKind, KeyModifiers },
+  layout::{ Constraint, Layout, Rect },
+  style::{ Color, Modifier, Style, Stylize },
+  text::{ Line, Span },
+  widgets::{ Block, Paragraph },
+  DefaultTerminal, Frame,
+};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::sync::CancellationToken;
+use xrconnect::{
+  bhaptics_studio::clients::ConnectedClient,
+  haptics::{
+    model::{ HapticFrame, MotorLayout },
+    player::DeviceStatus,
+  },
+};
+
+use crate::{ instance::control_request, Failure };
+
+/// Lines kept in the event log
+const EVENT_LOG_LENGTH: usize = 500;
+
+/// How often the clients, devices, active keys and muted body parts are fetched
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Motor states per second asked of the live stream
+const STREAM_RATE: u32 = 20;
+
+/// Longest wait for a key press between two redraws
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Body parts drawn on the first row, the others going on the second one
+const FIRST_ROW: usize = 5;
+
+const HOTKEYS: &str = "s stop all  ←/→ select  m mute  u unmute all  q quit";
+
+/// Messages logged while the monitor owns the terminal, shown in its event log instead.
+#[derive(Clone, Default)]
+pub struct LogLines(Arc<Mutex<VecDeque<String>>>);
+
+impl LogLines {
+  fn drain(&self) -> Vec<String> {
+    self.0.lock().unwrap().drain(..).collect()
+  }
+}
+
+impl io::Write for LogLines {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let mut lines = self.0.lock().unwrap();
+    for line in String::from_utf8_lossy(buf).lines().filter(|line| !line.trim().is_empty()) {
+      lines.push_back(line.trim_end().to_string());
+    }
+    while lines.len() > EVENT_LOG_LENGTH {
+      lines.pop_front();
+    }
+
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// What the monitor knows of the instance.
+#[derive(Default)]
+struct State {
+  connected: bool,
+  panicked: bool,
+  frame: HapticFrame,
+  clients: Vec<ConnectedClient>,
+  devices: Vec<DeviceStatus>,
+  active: Vec<String>,
+  muted: BTreeSet<BodyPart>,
+  events: VecDeque<String>,
+}
+
+impl State {
+  fn log(&mut self, line: String) {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() % 86_400;
+    self.push(format!("{:02}:{:02}:{:02} {}", seconds / 3600, seconds / 60 % 60, seconds % 60, line));
+  }
+
+  fn push(&mut self, line: String) {
+    self.events.push_back(line);
+    if self.events.len() > EVENT_LOG_LENGTH {
+      self.events.pop_front();
+    }
+  }
+
+  fn set_connected(&mut self, connected: bool, address: SocketAddr) {
+    if self.connected != connected {
+      self.connected = connected;
+      self.log(match connected {
+        true => format!("connected to {}", address),
+        false => format!("lost {}", address),
+      });
+    }
+  }
+
+  /// Takes in a message of the live stream, see [`xrconnect::api::StreamMessage`].
+  fn receive(&mut self, message: &str) {
+    let Ok(message) = serde_json::from_str::<Value>(message) else {
+      return;
+    };
+
+    match message["type"].as_str() {
+      Some("state") => {
+        self.panicked = message["panicked"].as_bool().unwrap_or(false);
+        if let Ok(frame) = serde_json::from_value(message["frame"].clone()) {
+          self.frame = frame;
+        }
+      },
+      Some("event") => self.log(describe(&message)),
+      Some("lagged") => self.log(format!("missed {} events", message["missed"])),
+      _ => {},
+    }
+  }
+
+  /// Active keys grouped by the application owning them, keys played through the API under `local`.
+  fn active_per_app(&self) -> BTreeMap<String, Vec<&str>> {
+    let mut apps: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for key in &self.active {
+      let (app, key) = match key.split_once('/') {
+        Some((namespace, key)) => {
+          let app = self.clients
+            .iter()
+            .find(|client| client.namespace == namespace)
+            .map_or_else(|| namespace.to_string(), |client| client.app_name.clone());
+          (app, key)
+        },
+        None => (String::from("local"), key.as_str()),
+      };
+      apps.entry(app).or_default().push(key);
+    }
+    apps
+  }
+}
+
+/// One line summary of an event of the live stream.
+fn describe(message: &Value) -> String {
+  let text = |field: &str| message[field].as_str().unwrap_or_default().to_string();
+
+  match message["event"].as_str().unwrap_or_default() {
+    "played" => match message["app"].as_str() {
+      Some(app) => format!("played {} ({})", text("active_key"), app),
+      None => format!("played {}", text("active_key")),
+    },
+    event @ ("stopped" | "finished") => format!("{} {}", event, text("active_key")),
+    event @ ("registered" | "unregistered") => format!("{} {}", event, text("key")),
+    "device" => {
+      let device = &message["device"];
+      let status = if device["stopped"].as_bool().unwrap_or(false) { "stopped" } else { "running" };
+      format!("device {} {}", device["name"].as_str().unwrap_or_default(), status)
+    },
+    "device_disconnected" => format!("device {} disconnected", text("name")),
+    "panic" => match message["panicked"].as_bool().unwrap_or(false) {
+      true => String::from("kill switch engaged"),
+      false => String::from("kill switch cleared"),
+    },
+    "muted" => match message["muted"].as_bool().unwrap_or(false) {
+      true => format!("{} muted", text("part")),
+      false => format!("{} unmuted", text("part")),
+    },
+    event @ ("client_connected" | "client_disconnected") => {
+      let client = &message["client"];
+      format!(
+        "{} ({}) {}",
+        client["app_name"].as_str().unwrap_or_default(),
+        client["app_id"].as_str().unwrap_or_default(),
+        event.trim_start_matches("client_"),
+      )
+    },
+    _ => message.to_string(),
+  }
+}
+
+/// Requests of the hotkeys, sent by the terminal thread.
+enum Action {
+  StopAll,
+  Mute(BodyPart, bool),
+}
+
+/// Shows the instance at `address` until `q` is pressed or `shutdown` is cancelled.
+pub async fn run(address: SocketAddr, logs: Option<LogLines>, shutdown: CancellationToken) -> Result<(), Failure> {
+  let state = Arc::new(Mutex::new(State::default()));
+  let (actions, requests) = mpsc::unbounded_channel();
+  let tasks = [
+    tokio::spawn(stream(address, state.clone())),
+    tokio::spawn(poll(address, state.clone())),
+    tokio::spawn(act(address, requests, state.clone())),
+  ];
+
+  let terminal = ratatui::try_init().map_err(|why| format!("failed to set up the terminal: {}", why))?;
+  let result = tokio::task::spawn_blocking(move || ui(terminal, address, &state, &actions, logs, &shutdown)).await;
+  ratatui::restore();
+
+  for task in tasks {
+    task.abort();
+  }
+
+  result
+    .map_err(|why| why.to_string())?
+    .map_err(|why| Failure::Failed(format!("terminal error: {}", why)))
+}
+
+/// Follows the live stream, reconnecting until the monitor stops.
+async fn stream(address: SocketAddr, state: Arc<Mutex<State>>) {
+  let url = format!("ws://{}/api/stream?rate={}", address, STREAM_RATE);
+
+  loop {
+    if let Ok((mut socket, _)) = tokio_tungstenite::connect_async(&url).await {
+      state.lock().unwrap().set_connected(true, address);
+      while let Some(Ok(message)) = socket.next().await {
+        if let Message::Text(message) = message {
+          state.lock().unwrap().receive(&message);
+        }
+      }
+    }
+
+    state.lock().unwrap().set_connected(false, address);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+  }
+}
+
+/// Fetches what the live stream does not carry, every [`POLL_INTERVAL`].
+async fn poll(address: SocketAddr, state: Arc<Mutex<State>>) {
+  let mut ticks = tokio::time::interval(POLL_INTERVAL);
+
+  loop {
+    ticks.tick().await;
+
+    let invalid = |why: serde_json::Error| Failure::Failed(why.to_string());
+    let snapshot = async {
+      let clients: Vec<ConnectedClient> = serde_json::from_str(&control_request(address, "GET", "/api/clients").await?).map_err(invalid)?;
+      let devices: Vec<DeviceStatus> = serde_json::from_str(&control_request(address, "GET", "/api/devices").await?).map_err(invalid)?;
+      let active: Vec<String> = serde_json::from_str(&control_request(address, "GET", "/api/active").await?).map_err(invalid)?;
+      let muted: BTreeSet<BodyPart> = serde_json::from_str(&control_request(address, "GET", "/api/muted").await?).map_err(invalid)?;
+      Ok::<_, Failure>((clients, devices, active, muted))
+    };
+
+    // Unreachable instances are reported by the live stream
+    if let Ok((clients, devices, active, muted)) = snapshot.await {
+      let mut state = state.lock().unwrap();
+      state.clients = clients;
+      state.devices = devices;
+      state.active = active;
+      state.muted = muted;
+    }
+  }
+}
+
+async fn act(address: SocketAddr, mut requests: mpsc::UnboundedReceiver<Action>, state: Arc<Mutex<State>>) {
+  while let Some(action) = requests.recv().await {
+    let result = match action {
+      Action::StopAll => {
+        state.lock().unwrap().log(String::from("stopping everything"));
+        control_request(address, "DELETE", "/api/active").await
+      },
+      Action::Mute(part, muted) => {
+        let method = if muted { "PUT" } else { "DELETE" };
+        control_request(address, method, &format!("/api/muted/{:?}", part)).await
+      },
+    };
+
+    if let Err(why) = result {
+      state.lock().unwrap().log(why.to_string());
+    }
+  }
+}
+
+/// Draws and handles the hotkeys, on a thread of its own as reading the terminal blocks.
+fn ui(
+  mut terminal: DefaultTerminal,
+  address: SocketAddr,
+  state: &Mutex<State>,
+  actions: &mpsc::UnboundedSender<Action>,
+  logs: Option<LogLines>,
+  shutdown: &CancellationToken,
+) -> io::Result<()> {
+  let mut selected = 0;
+
+  while !shutdown.is_cancelled() {
+    {
+      let mut state = state.lock().unwrap();
+      for line in logs.iter().flat_map(LogLines::drain) {
+        state.push(line);
+      }
+      terminal.draw(|frame| draw(frame, address, &state, selected))?;
+    }
+
+    if !event::poll(REDRAW_INTERVAL)? {
+      continue;
+    }
+    let Event::Key(key) = event::read()? else {
+      continue;
+    };
+    if key.kind != KeyEventKind::Press {
+      continue;
+    }
+
+    let parts = BodyPart::ALL.len();
+    match key.code {
+      KeyCode::Char('q') | KeyCode::Esc => break,
+      KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+      KeyCode::Char('s') => { let _ = actions.send(Action::StopAll); },
+      KeyCode::Left | KeyCode::BackTab => selected = (selected + parts - 1) % parts,
+      KeyCode::Right | KeyCode::Tab => selected = (selected + 1) % parts,
+      KeyCode::Char('m') => {
+        let part = BodyPart::ALL[selected];
+        let muted = state.lock().unwrap().muted.contains(&part);
+        let _ = actions.send(Action::Mute(part, !muted));
+      },
+      KeyCode::Char('u') => {
+        for part in state.lock().unwrap().muted.iter() {
+          let _ = actions.send(Action::Mute(*part, false));
+        }
+      },
+      _ => {},
+    }
+  }
+
+  Ok(())
+}
+
+fn draw(frame: &mut Frame, address: SocketAddr, state: &State, selected: usize) {
+  let [header, first_row, second_row, lists, log] = Layout::vertical([
+    Constraint::Length(1),
+    Constraint::Length(7),
+    Constraint::Length(4),
+    Constraint::Length(10),
+    Constraint::Min(3),
+  ]).areas(frame.area());
+
+  let mut status = vec![Span::from(format!("xrconnect {} ", address)).bold()];
+  status.push(match state.connected {
+    true => Span::from("connected").green(),
+    false => Span::from("unreachable").red(),
+  });
+  if state.panicked {
+    status.push(Span::from("  KILL SWITCH ENGAGED").red().bold());
+  }
+  status.push(Span::from(format!("  {}", HOTKEYS)).dark_gray());
+  frame.render_widget(Line::from(status), header);
+
+  for (area, first) in [(first_row, 0), (second_row, FIRST_ROW)] {
+    let parts = match first {
+      0 => &BodyPart::ALL[..FIRST_ROW],
+      _ => &BodyPart::ALL[FIRST_ROW..],
+    };
+    let widths = parts.iter().map(|part| Constraint::Length(part_width(*part)));
+    let areas = Layout::horizontal(widths).spacing(1).split(area);
+    for (index, (part, area)) in parts.iter().zip(areas.iter()).enumerate() {
+      draw_part(frame, *part, *area, state, first + index == selected);
+    }
+  }
+
+  let [clients, active, devices] = Layout::horizontal([
+    Constraint::Percentage(30),
+    Constraint::Percentage(40),
+    Constraint::Percentage(30),
+  ]).areas(lists);
+
+  let lines: Vec<Line> = state.clients
+    .iter()
+    .map(|client| Line::from(vec![Span::from(client.app_name.clone()), Span::from(format!(" {}", client.app_id)).dark_gray()]))
+    .collect();
+  frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(format!("Clients ({})", state.clients.len()))), clients);
+
+  let lines: Vec<Line> = state.active_per_app()
+    .into_iter()
+    .flat_map(|(app, keys)| {
+      let keys = keys.into_iter().map(|key| Line::from(format!("  {}", key)));
+      std::iter::once(Line::from(app).bold()).chain(keys)
+    })
+    .collect();
+  frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(format!("Active keys ({})", state.active.len()))), active);
+
+  let lines: Vec<Line> = state.devices
+    .iter()
+    .map(|device| Line::from(vec![
+      match device.stopped {
+        true => Span::from("● ").red(),
+        false => Span::from("● ").green(),
+      },
+      Span::from(device.name.clone()),
+      Span::from(format!(" {} positions", device.positions.len())).dark_gray(),
+    ]))
+    .collect();
+  frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(format!("Devices ({})", state.devices.len()))), devices);
+
+  let height = log.height.saturating_sub(2) as usize;
+  let lines: Vec<Line> = state.events
+    .iter()
+    .skip(state.events.len().saturating_sub(height))
+    .map(|line| Line::from(line.as_str()))
+    .collect();
+  frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Events")), log);
+}
+
+/// Draws the motors of `part` where they are on the body part, shaded by intensity.
+fn draw_part(frame: &mut Frame, part: BodyPart, area: Rect, state: &State, selected: bool) {
+  let (cells, rows, columns) = grid(part);
+  let motors = state.frame.get(part).unwrap_or_default();
+
+  let mut lines = vec![vec![Span::from("  "); columns]; rows];
+  for (index, (row, column)) in cells.into_iter().enumerate() {
+    lines[row][column] = shade(motors.get(index).copied().unwrap_or(0.0));
+  }
+
+  let muted = state.muted.contains(&part);
+  let mut block = Block::bordered().title(format!("{:?}", part));
+  if muted {
+    block = block.title_bottom(Line::from("muted").red()).border_style(Style::new().red());
+  }
+  if selected {
+    block = block.border_style(Style::new().yellow().add_modifier(Modifier::BOLD));
+  }
+
+  frame.render_widget(Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>()).block(block), area);
+}
+
+fn part_width(part: BodyPart) -> u16 {
+  let (_, _, columns) = grid(part);
+  (columns * 2).max(format!("{:?}", part).len()) as u16 + 2
+}
+
+/// Row and column of every motor of `part` in its default layout, along with the number
+/// of rows and columns.
+fn grid(part: BodyPart) -> (Vec<(usize, usize)>, usize, usize) {
+  let layout = MotorLayout::default_for(part);
+  let key = |coordinate: f32| (coordinate * 1000.0).round() as i32;
+  let distinct = |coordinates: Vec<i32>| {
+    let mut coordinates = coordinates;
+    coordinates.sort();
+    coordinates.dedup();
+    coordinates
+  };
+
+  let xs = distinct(layout.motors().iter().map(|(x, _)| key(*x)).collect());
+  let ys = distinct(layout.motors().iter().map(|(_, y)| key(*y)).collect());
+  let cells = layout.motors()
+    .iter()
+    .map(|(x, y)| (
+      ys.binary_search(&key(*y)).unwrap_or_default(),
+      xs.binary_search(&key(*x)).unwrap_or_default(),
+    ))
+    .collect();
+
+  (cells, ys.len(), xs.len())
+}
+
+fn shade(intensity: f32) -> Span<'static> {
+  const SHADES: [(&str, Color); 4] = [
+    ("░░", Color::Blue),
+    ("▒▒", Color::Cyan),
+    ("▓▓", Color::Yellow),
+    ("██", Color::Red),
+  ];
+
+  if intensity <= 0.0 {
+    return Span::styled("··", Style::new().fg(Color::DarkGray));
+  }
+
+  let (text, color) = SHADES[((intensity * 4.0).ceil() as usize).clamp(1, 4) - 1];
+  Span::styled(text, Style::new().fg(color))
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  fn client(app_name: &str, namespace: &str) -> ConnectedClient {
+    ConnectedClient {
+      id: 1,
+      app_id: format!("com.example.{}", app_name.to_lowercase()),
+      app_name: app_name.to_string(),
+      namespace: namespace.to_string(),
+      connected_at: 0,
+    }
+  }
+
+  #[test]
+  fn states_update_the_motors_and_the_kill_switch() {
+    let mut state = State::default();
+    state.receive(&json!({ "type": "state", "panicked": true, "frame": { "parts": { "ChestFront": [0.5, 1.0] } } }).to_string());
+    assert!(state.panicked);
+    assert_eq!(state.frame.get(BodyPart::ChestFront), Some(&[0.5, 1.0][..]));
+
+    // Frames that cannot be read keep the last one
+    state.receive(&json!({ "type": "state", "panicked": false, "frame": "garbled" }).to_string());
+    assert!(!state.panicked);
+    assert_eq!(state.frame.get(BodyPart::ChestFront), Some(&[0.5, 1.0][..]));
+    assert!(state.events.is_empty());
+  }
+
+  #[test]
+  fn events_go_into_the_event_log() {
+    let mut state = State::default();
+    state.receive(&json!({ "type": "event", "event": "stopped", "active_key": "hit" }).to_string());
+    state.receive(&json!({ "type": "lagged", "missed": 3 }).to_string());
+    state.receive("not json");
+    state.receive(&json!({ "type": "unknown" }).to_string());
+
+    assert_eq!(state.events.len(), 2);
+    assert!(state.events[0].ends_with(" stopped hit"), "{}", state.events[0]);
+    assert!(state.events[1].ends_with(" missed 3 events"), "{}", state.events[1]);
+  }
+
+  #[test]
+  fn event_log_keeps_the_latest_lines() {
+    let mut state = State::default();
+    for index in 0..EVENT_LOG_LENGTH + 10 {
+      state.push(index.to_string());
+    }
+
+    assert_eq!(state.events.len(), EVENT_LOG_LENGTH);
+    assert_eq!(state.events.front().map(String::as_str), Some("10"));
+  }
+
+  #[test]
+  fn active_keys_are_grouped_per_application() {
+    let state = State {
+      clients: vec![client("Game", "1"), client("Other", "2")],
+      active: ["1/hit", "1/shot", "2/rumble", "7/orphan", "test"].map(String::from).to_vec(),
+      ..Default::default()
+    };
+
+    assert_eq!(state.active_per_app(), BTreeMap::from([
+      (String::from("7"), vec!["orphan"]),
+      (String::from("Game"), vec!["hit", "shot"]),
+      (String::from("Other"), vec!["rumble"]),
+      (String::from("local"), vec!["test"]),
+    ]));
+  }
+
+  #[test]
+  fn events_are_described_in_one_line() {
+    let cases = [
+      (json!({ "event": "played", "active_key": "hit", "app": "Game" }), "played hit (Game)"),
+      (json!({ "event": "played", "active_key": "hit" }), "played hit"),
+      (json!({ "event": "finished", "active_key": "hit" }), "finished hit"),
+      (json!({ "event": "registered", "key": "hit" }), "registered hit"),
+      (json!({ "event": "device", "device": { "name": "vest", "stopped": true } }), "device vest stopped"),
+      (json!({ "event": "device_disconnected", "name": "vest" }), "device vest disconnected"),
+      (json!({ "event": "panic", "panicked": true }), "kill switch engaged"),
+      (json!({ "event": "panic", "panicked": false }), "kill switch cleared"),
+      (json!({ "event": "muted", "part": "Head", "muted": true }), "Head muted"),
+      (json!({ "event": "muted", "part": "Head", "muted": false }), "Head unmuted"),
+      (json!({ "event": "client_connected", "client": { "app_name": "Game", "app_id": "com.example.game" } }), "Game (com.example.game) connected"),
+      (json!({ "event": "client_disconnected", "client": { "app_name": "Game", "app_id": "com.example.game" } }), "Game (com.example.game) disconnected"),
+      (json!({ "event": "new" }), r#"{"event":"new"}"#),
+    ];
+
+    for (message, described) in cases {
+      assert_eq!(describe(&message), described);
+    }
+  }
+
+  #[test]
+  fn motors_are_placed_on_the_grid_of_their_layout() {
+    let (cells, rows, columns) = grid(BodyPart::ChestFront);
+    assert_eq!((cells.len(), rows, columns), (20, 5, 4));
+    assert_eq!(cells[0], (0, 0));
+    assert_eq!(cells[5], (1, 1));
+    assert_eq!(cells[19], (4, 3));
+
+    // The thumb of the glove sits alone on its row
+    let (cells, rows, columns) = grid(BodyPart::GloveL);
+    assert_eq!((rows, columns), (2, 5));
+    assert_eq!(cells.last(), Some(&(1, 2)));
+  }
+
+  #[test]
+  fn intensities_are_shaded_by_quarter() {
+    let text = |intensity: f32| shade(intensity).content.into_owned();
+    assert_eq!(text(0.0), "··");
+    assert_eq!(text(-1.0), "··");
+    assert_eq!(text(0.1), "░░");
+    assert_eq!(text(0.25), "░░");
+    assert_eq!(text(0.3), "▒▒");
+    assert_eq!(text(0.75), "▓▓");
+    assert_eq!(text(1.0), "██");
+    assert_eq!(text(2.0), "██");
+    assert_eq!(shade(1.0).style.fg, Some(Color::Red));
+  }
+}
@@ -0,0 +1,133 @@
+//! `play`, on a running instance or on local outputs, and the helpers of the commands playing locally.
+
+use std::{ path::Path, time::Instant };
+
+use haptic_lib::BodyPart;
+use xrconnect::{
+  bhaptics_studio::tact::project::Project,
+  control::ControlRequest,
+  devices::audio::{ AudioOutputConfig, WavAudioDevice },
+  haptics::{
+    device::RecordingDevice,
+    model::HapticPattern,
+    player::{ HapticPlayer, FRAME_INTERVAL },
+  },
+};
+
+use crate::{
+  args::{ FileKind, PlayArgs },
+  instance::{ done, socket_request },
+  Failure,
+};
+
+/// Adds the audio outputs asked for on the command line to `player`.
+pub fn add_audio_outputs(player: &HapticPlayer, audio_wav: Option<&str>, audio_live: bool) -> Result<(), Failure> {
+  if let Some(path) = audio_wav {
+    let device = WavAudioDevice::create(path, &AudioOutputConfig::default())
+      .map_err(|why| format!("failed to create {}: {}", path, why))?;
+    player.add_device(Box::new(device));
+  }
+
+  if audio_live {
+    add_live_audio_device(player)?;
+  }
+
+  Ok(())
+}
+
+/// Writes frames to the devices of `player`, which needs a manual clock, until `active_key` ends.
+pub fn play_stepped(player: &HapticPlayer, active_key: &str) {
+  loop {
+    player.tick(player.now());
+    if !player.is_playing(active_key) {
+      break;
+    }
+
+    player.advance(FRAME_INTERVAL);
+  }
+}
+
+pub fn write_frames(path: &str, recording: &RecordingDevice) -> Result<(), Failure> {
+  let frames = recording.frames();
+  let json = serde_json::to_string(&frames).map_err(|why| why.to_string())?;
+  std::fs::write(path, json).map_err(|why| format!("failed to write {}: {}", path, why))?;
+  println!("{} frames written to {}", frames.len(), path);
+  Ok(())
+}
+
+/// Reads a `.tact` file, or a pattern as written by `convert`.
+pub fn load_pattern(path: &str) -> Result<HapticPattern, Failure> {
+  if !Path::new(path).exists() {
+    return Err(Failure::Failed(format!("{}: no such file", path)));
+  }
+
+  match FileKind::of(path) {
+    Some(FileKind::Tact) => Project::load(path)
+      .map(|project| project.to_pattern())
+      .map_err(|why| Failure::Invalid(format!("{}: {}", path, why))),
+    _ => {
+      let content = std::fs::read_to_string(path).map_err(|why| format!("failed to read {}: {}", path, why))?;
+      serde_json::from_str(&content).map_err(|why| Failure::Invalid(format!("{}: not a pattern: {}", path, why)))
+    },
+  }
+}
+
+pub async fn play(args: PlayArgs) -> Result<(), Failure> {
+  let pattern = load_pattern(&args.file)?;
+  let key = Path::new(&args.file).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+  let duration = pattern.duration_millis();
+
+  if !args.is_local() {
+    let request = ControlRequest::Play { key, pattern, options: args.options };
+    println!("{}", done(socket_request(args.socket, request).await?)?);
+    return Ok(());
+  }
+
+  let player = match args.frames {
+    Some(_) => HapticPlayer::with_manual_clock(Instant::now()),
+    None => HapticPlayer::new(),
+  };
+  add_audio_outputs(&player, args.audio_wav.as_deref(), args.audio_live)?;
+
+  let recording = RecordingDevice::new("play", BodyPart::ALL.to_vec());
+  if args.frames.is_some() {
+    player.add_device(Box::new(recording.clone()));
+  }
+
+  player.play_pattern(key.as_str(), pattern, args.options).map_err(|why| why.to_string())?;
+  println!("Playing {} ({} ms)", key, duration);
+
+  match &args.frames {
+    Some(path) => {
+      play_stepped(&player, &key);
+      player.shutdown();
+      write_frames(path, &recording)?;
+    },
+    None => {
+      tokio::select! {
+        _ = player.run() => {},
+        _ = async {
+          while player.is_playing(&key) {
+            tokio::time::sleep(FRAME_INTERVAL).await;
+          }
+        } => {},
+      }
+      player.shutdown();
+    },
+  }
+
+  Ok(())
+}
+
+#[cfg(feature = "audio-live")]
+fn add_live_audio_device(player: &HapticPlayer) -> Result<(), Failure> {
+  let device = xrconnect::devices::audio::LiveAudioDevice::open(None, &AudioOutputConfig::default())
+    .map_err(|why| format!("failed to open the audio output: {}", why))?;
+  player.add_device(Box::new(device));
+  Ok(())
+}
+
+#[cfg(not(feature = "audio-live"))]
+fn add_live_audio_device(_player: &HapticPlayer) -> Result<(), Failure> {
+  Err(Failure::Failed(String::from("live audio output is not available, rebuild with the `audio-live` feature")))
+}
@@ -0,0 +1,54 @@
+//! `replay`, feeding a recorded session into a player.
+
+use std::time::Instant;
+
+use haptic_lib::BodyPart;
+use xrconnect::{
+  bhaptics_studio::{
+    namespace::NamespaceMode,
+    session::{ Session, SessionError, SessionReplayer },
+  },
+  haptics::{ device::RecordingDevice, player::HapticPlayer },
+};
+
+use crate::{
+  args::ReplayArgs,
+  play::{ add_audio_outputs, write_frames },
+  Failure,
+};
+
+pub async fn replay(args: ReplayArgs) -> Result<(), Failure> {
+  let session = Session::load(&args.session).map_err(|why| match why {
+    SessionError::Io(_) => Failure::Failed(why.to_string()),
+    SessionError::Parse { .. } => Failure::Invalid(format!("{}: {}", args.session, why)),
+  })?;
+  let namespaces = if args.share_app_namespace { NamespaceMode::PerApp } else { NamespaceMode::PerConnection };
+  let replayer = SessionReplayer::new(session)
+    .with_speed(args.speed)
+    .with_namespaces(namespaces);
+
+  let player = match args.frames {
+    Some(_) => HapticPlayer::with_manual_clock(Instant::now()),
+    None => HapticPlayer::new(),
+  };
+  add_audio_outputs(&player, args.audio_wav.as_deref(), false)?;
+
+  match &args.frames {
+    Some(path) => {
+      let recording = RecordingDevice::new("replay", BodyPart::ALL.to_vec());
+      player.add_device(Box::new(recording.clone()));
+      replayer.replay_stepped(&player);
+      player.shutdown();
+      write_frames(path, &recording)?;
+    },
+    None => {
+      tokio::select! {
+        _ = player.run() => {},
+        _ = replayer.replay(&player) => {},
+      }
+      player.shutdown();
+    },
+  }
+
+  Ok(())
+}
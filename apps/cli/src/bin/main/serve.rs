@@ -0,0 +1,281 @@
+//! `serve`, running the server with the configuration file and the command line options.
+
+use std::{
+  net::SocketAddr,
+  path::PathBuf,
+  sync::{ Arc, Mutex },
+  time::Duration,
+};
+
+use tokio_util::sync::CancellationToken;
+use tracing::{ error, info, warn };
+use tracing_subscriber::filter::LevelFilter;
+use xrconnect::{
+  access::{ AccessControl, AccessPolicy },
+  bhaptics_studio::{
+    listener::Listener,
+    namespace::NamespaceMode,
+    proxy::BHapticsProxy,
+    server::BHapticsStudioServer,
+    session::SessionRecorder,
+  },
+  config::{ self, Config, ConfigError },
+  control::ControlSocket,
+  haptics::{ apps::AppProfiles, calibration::CalibrationStore, player::HapticPlayer },
+  paths,
+  state::SavedState,
+};
+
+use crate::{
+  args::ServeArgs,
+  monitor,
+  play::add_audio_outputs,
+  Failure, LogLevelHandle, Logging,
+};
+
+/// How often the configuration file is checked for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub async fn serve(args: ServeArgs, logging: Logging) -> Result<(), Failure> {
+  let config_file = args.config.clone().map(PathBuf::from).unwrap_or_else(paths::config_file);
+  let config = Config::load(&config_file).map_err(|why| Failure::Invalid(why.to_string()))?;
+  if let (Some(handle), Ok(Some(level))) = (&logging.level, config.log_level()) {
+    let _ = handle.reload(LevelFilter::from_level(level));
+  }
+
+  let player = HapticPlayer::new();
+
+  match SavedState::load(paths::state_file()) {
+    Ok(state) => state.restore(&player, &CalibrationStore::default()),
+    Err(why) => error!("Failed to read the saved state: {}", why),
+  }
+
+  let profiles = match (&args.apps, &config.apps) {
+    (None, Some(profiles)) => profiles.clone(),
+    _ => {
+      let apps = args.apps.clone().map(PathBuf::from).unwrap_or_else(paths::app_profiles_file);
+      AppProfiles::load(&apps).map_err(|why| Failure::Invalid(format!("failed to load {}: {}", apps.display(), why)))?
+    },
+  };
+  player.set_app_profiles(profiles);
+
+  let policy = match (&args.access, &config.access) {
+    (None, Some(policy)) => policy.clone(),
+    _ => {
+      let path = args.access.clone().map(PathBuf::from).unwrap_or_else(paths::access_policy_file);
+      AccessPolicy::load(&path).map_err(|why| Failure::Invalid(format!("failed to load {}: {}", path.display(), why)))?
+    },
+  };
+  let access = AccessControl::new(policy)
+    .with_approvals_file(paths::approvals_file())
+    .map_err(|why| format!("failed to load {}: {}", paths::approvals_file().display(), why))?;
+
+  config.apply_live(&player, &access).map_err(|why| format!("failed to apply {}: {}", config_file.display(), why))?;
+
+  if let Some(name) = &args.calibration {
+    let profile = CalibrationStore::default()
+      .load(name)
+      .map_err(|why| format!("failed to load calibration: {}", why))?;
+    player.set_calibration(profile);
+  }
+
+  add_audio_outputs(&player, args.audio_wav.as_deref(), args.audio_live)?;
+  for (index, output) in config.outputs.iter().enumerate() {
+    let device = output.open().map_err(|why| format!("{}: outputs[{}]: {}", config_file.display(), index, why))?;
+    player.add_device(device);
+  }
+
+  let listeners = match args.listeners.is_empty() {
+    true => config.listeners(),
+    false => args.listeners.clone(),
+  };
+  let mut server = BHapticsStudioServer::with_listeners(listeners)
+    .with_frontends(config.frontends.clone())
+    .with_player(player.clone())
+    .with_access(access.clone());
+  if let Some(tls) = args.tls.clone() {
+    server = server.with_tls(tls);
+  }
+
+  if args.share_app_namespace || config.server.share_app_namespace {
+    server = server.with_namespaces(NamespaceMode::PerApp);
+  }
+
+  if let Some(path) = args.record.clone().map(PathBuf::from).or_else(|| config.server.record.clone()) {
+    let recorder = SessionRecorder::create(&path).map_err(|why| format!("failed to create {}: {}", path.display(), why))?;
+    server = server.with_recorder(recorder);
+  }
+
+  let proxy = match (&args.upstream, &config.proxy) {
+    (Some(upstream), _) => Some((upstream.as_str(), args.proxy_rules.clone(), args.proxy_mirror)),
+    (None, Some(proxy)) => Some((proxy.upstream.as_str(), proxy.rules.clone(), proxy.mirror)),
+    (None, None) => None,
+  };
+  if let Some((upstream, rules, mirror)) = proxy {
+    let proxy = BHapticsProxy::new(upstream)
+      .map_err(|why| Failure::Usage(why.to_string()))?
+      .with_rules(rules)
+      .with_mirror(mirror);
+    server = server.with_proxy(proxy);
+  }
+
+  let control_socket = args.control_socket.clone().map(PathBuf::from)
+    .or_else(|| config.server.control_socket.clone())
+    .unwrap_or_else(paths::control_socket);
+
+  if let Some(seconds) = args.shutdown_timeout.or(config.server.shutdown_timeout_secs) {
+    server = server.with_shutdown_timeout(Duration::from_secs_f32(seconds.max(0.0)));
+  }
+
+  tokio::spawn(panic_on_signal(player.clone()));
+  tokio::spawn(shutdown_on_signal(server.shutdown_token()));
+  let monitor = match args.tui {
+    true => {
+      let address = monitor_address(server.listeners()).filter(|_| config.frontends.api)
+        .ok_or_else(|| Failure::Usage(String::from("--tui needs a TCP listener serving the control API")))?;
+      let shutdown = server.shutdown_token();
+      let lines = logging.lines.clone();
+      Some(tokio::spawn(async move {
+        let result = monitor::run(address, lines, shutdown.clone()).await;
+        shutdown.cancel();
+        result
+      }))
+    },
+    false => None,
+  };
+
+  let reloader = ConfigReloader {
+    path: config_file,
+    current: Arc::new(Mutex::new(config)),
+    player,
+    access,
+    log_level: logging.level,
+  };
+  server = server.with_control_socket(ControlSocket::new(control_socket).with_reload({
+    let reloader = reloader.clone();
+    move || reloader.reload()
+  }));
+  tokio::spawn(reloader.watch());
+
+  let result = server.run().await;
+  if let Some(monitor) = monitor {
+    server.shutdown_token().cancel();
+    monitor.await.map_err(|why| why.to_string())??;
+  }
+  result.map_err(|why| why.to_string())?;
+  println!("Server shut down");
+
+  Ok(())
+}
+
+/// Where `serve --tui` reaches its own control API, the first TCP listener.
+fn monitor_address(listeners: &[Listener]) -> Option<SocketAddr> {
+  listeners.iter().find_map(|listener| match listener {
+    Listener::Tcp(address) if address.ip().is_unspecified() => match address {
+      SocketAddr::V4(_) => Some(SocketAddr::from((std::net::Ipv4Addr::LOCALHOST, address.port()))),
+      SocketAddr::V6(_) => Some(SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, address.port()))),
+    },
+    Listener::Tcp(address) => Some(*address),
+    _ => None,
+  })
+}
+
+/// Applies the configuration file to the running server, whenever it changes and on `reload`
+#[derive(Clone)]
+struct ConfigReloader {
+  path: PathBuf,
+
+  /// Configuration applied last, telling which changes need a restart
+  current: Arc<Mutex<Config>>,
+
+  player: HapticPlayer,
+  access: AccessControl,
+  log_level: Option<LogLevelHandle>,
+}
+
+impl ConfigReloader {
+  /// Applies `config`, describing what happened. Changes needing a restart are only reported, as
+  /// are invalid files, which leave the current settings in place.
+  fn apply(&self, config: Result<Config, ConfigError>) -> Result<String, String> {
+    let applied = config
+      .map_err(|why| why.to_string())
+      .and_then(|config| match config.apply_live(&self.player, &self.access) {
+        Ok(()) => Ok(config),
+        Err(why) => Err(format!("{}: {}", self.path.display(), why)),
+      });
+    let config = match applied {
+      Ok(config) => config,
+      Err(why) => {
+        error!("Configuration not reloaded: {}", why);
+        return Err(why);
+      },
+    };
+
+    if let (Some(handle), Ok(Some(level))) = (&self.log_level, config.log_level()) {
+      let _ = handle.reload(LevelFilter::from_level(level));
+    }
+
+    let mut current = self.current.lock().unwrap();
+    let restart = config.restart_required(&current);
+    *current = config;
+
+    if restart.is_empty() {
+      let done = format!("Configuration reloaded from {}", self.path.display());
+      info!("{}", done);
+      return Ok(done);
+    }
+
+    let done = format!("Configuration reloaded from {}, changes to {} take effect after a restart", self.path.display(), restart.join(", "));
+    warn!("{}", done);
+    Ok(done)
+  }
+
+  fn reload(&self) -> Result<String, String> {
+    self.apply(Config::load(&self.path))
+  }
+
+  async fn watch(self) {
+    config::watch(self.path.clone(), CONFIG_POLL_INTERVAL, move |config| {
+      let _ = self.apply(config);
+    }).await
+  }
+}
+
+/// Shuts the server down on Ctrl-C, or SIGTERM on Unix.
+async fn shutdown_on_signal(shutdown: CancellationToken) {
+  #[cfg(unix)]
+  let terminate = async {
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+      Ok(mut signals) => { signals.recv().await; },
+      Err(_) => std::future::pending().await,
+    }
+  };
+
+  #[cfg(not(unix))]
+  let terminate = std::future::pending::<()>();
+
+  tokio::select! {
+    _ = tokio::signal::ctrl_c() => println!("Ctrl-C received, shutting down"),
+    _ = terminate => println!("SIGTERM received, shutting down"),
+  }
+
+  shutdown.cancel();
+}
+
+/// Engages the kill switch whenever the process receives SIGUSR1.
+#[cfg(unix)]
+async fn panic_on_signal(player: HapticPlayer) {
+  use tokio::signal::unix::{ signal, SignalKind };
+
+  let mut signals = match signal(SignalKind::user_defined1()) {
+    Ok(signals) => signals,
+    Err(why) => return tracing::warn!("Failed to listen for SIGUSR1, the kill switch will not react to it: {}", why),
+  };
+
+  while signals.recv().await.is_some() {
+    player.panic();
+  }
+}
+
+#[cfg(not(unix))]
+async fn panic_on_signal(_player: HapticPlayer) {}
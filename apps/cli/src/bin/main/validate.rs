@@ -0,0 +1,66 @@
+//! `validate`, checking the files xrconnect reads.
+
+use std::path::Path;
+
+use xrconnect::{
+  access::AccessPolicy,
+  bhaptics_studio::{ session::Session, tact::project::Project },
+  config::Config,
+  haptics::{ apps::AppProfiles, calibration::CalibrationProfile, mapping::BodyMapping },
+};
+
+use crate::{
+  args::{ FileKind, ValidateArgs },
+  play::load_pattern,
+  Failure,
+};
+
+pub fn validate(args: ValidateArgs) -> Result<(), Failure> {
+  let path = args.file.as_str();
+  if !Path::new(path).exists() {
+    return Err(Failure::Failed(format!("{}: no such file", path)));
+  }
+
+  let invalid = |why: String| Failure::Invalid(format!("{}: {}", path, why));
+  let read = || std::fs::read_to_string(path).map_err(|why| Failure::Failed(format!("failed to read {}: {}", path, why)));
+
+  let summary = match args.kind {
+    FileKind::Tact => {
+      let project = Project::load(path).map_err(|why| invalid(why.to_string()))?;
+      let pattern = project.to_pattern();
+      format!("project {:?}, {} clips, {} ms", project.name(), pattern.clips.len(), pattern.duration_millis())
+    },
+    FileKind::Pattern => {
+      let pattern = load_pattern(path)?;
+      format!("pattern, {} clips, {} ms", pattern.clips.len(), pattern.duration_millis())
+    },
+    FileKind::Session => {
+      let session = Session::load(path).map_err(|why| invalid(why.to_string()))?;
+      format!("session, {} entries over {:?}", session.entries.len(), session.duration())
+    },
+    FileKind::Mapping => {
+      let mapping = BodyMapping::load(path).map_err(|why| invalid(why.to_string()))?;
+      format!("mapping {:?}, {} routes", mapping.name, mapping.routes.len())
+    },
+    FileKind::Calibration => {
+      let profile: CalibrationProfile = serde_json::from_str(&read()?).map_err(|why| invalid(why.to_string()))?;
+      profile.validate().map_err(|why| invalid(why.to_string()))?;
+      format!("calibration {:?}, {} body parts", profile.name, profile.parts.len())
+    },
+    FileKind::Apps => {
+      let profiles = AppProfiles::load(path).map_err(|why| invalid(why.to_string()))?;
+      format!("application settings, {} applications", profiles.apps.len())
+    },
+    FileKind::Access => {
+      AccessPolicy::load(path).map_err(|why| invalid(why.to_string()))?;
+      String::from("access policy")
+    },
+    FileKind::Config => {
+      let config = Config::load(path).map_err(|why| Failure::Invalid(why.to_string()))?;
+      format!("configuration, {} listeners, {} outputs", config.listeners().len(), config.outputs.len())
+    },
+  };
+
+  println!("{}: valid {}", path, summary);
+  Ok(())
+}
@@ -1,15 +1,153 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use xrconnect::{
+    access::{ AccessControl, AccessPolicy, PendingApp, UnknownApps },
+    bhaptics_studio::server::BHapticsStudioServer,
+    haptics::{
+        apps::AppProfiles,
+        calibration::{ CalibrationProfile, CalibrationStore },
+        player::HapticPlayer,
+    },
+    paths,
+    state::SavedState,
+};
+
+struct AppState {
+    player: HapticPlayer,
+    calibrations: CalibrationStore,
+    access: AccessControl,
+}
+
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 #[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
+fn list_calibrations(state: tauri::State<AppState>) -> Result<Vec<String>, String> {
+    state.calibrations.list().map_err(|why| why.to_string())
+}
+
+#[tauri::command]
+fn get_calibration(name: String, state: tauri::State<AppState>) -> Result<CalibrationProfile, String> {
+    state.calibrations.load(&name).map_err(|why| why.to_string())
+}
+
+#[tauri::command]
+fn save_calibration(profile: CalibrationProfile, state: tauri::State<AppState>) -> Result<(), String> {
+    state.calibrations.save(&profile).map_err(|why| why.to_string())?;
+
+    // Edits to the profile in use take effect immediately
+    if state.player.calibration().name == profile.name {
+        state.player.set_calibration(profile);
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+fn activate_calibration(name: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let profile = state.calibrations.load(&name).map_err(|why| why.to_string())?;
+    state.player.set_calibration(profile);
+    Ok(())
+}
+
+#[tauri::command]
+fn active_calibration(state: tauri::State<AppState>) -> CalibrationProfile {
+    state.player.calibration()
+}
+
+#[tauri::command]
+fn engage_panic(state: tauri::State<AppState>) -> bool {
+    state.player.panic();
+    state.player.is_panicked()
+}
+
+#[tauri::command]
+fn clear_panic(state: tauri::State<AppState>) -> bool {
+    state.player.clear_panic();
+    state.player.is_panicked()
+}
+
+#[tauri::command]
+fn is_panicked(state: tauri::State<AppState>) -> bool {
+    state.player.is_panicked()
+}
+
+#[tauri::command]
+fn pending_apps(state: tauri::State<AppState>) -> Vec<PendingApp> {
+    state.access.pending()
+}
+
+#[tauri::command]
+fn approve_app(id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.access.approve(&id).map_err(|why| why.to_string())
+}
+
+#[tauri::command]
+fn deny_app(id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.access.deny(&id).map_err(|why| why.to_string())
 }
 
 fn main() {
-    tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![greet])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+    let player = HapticPlayer::new();
+    let calibrations = CalibrationStore::default();
+
+    match SavedState::load(paths::state_file()) {
+        Ok(state) => state.restore(&player, &calibrations),
+        Err(why) => eprintln!("Failed to read the saved state: {}", why),
+    }
+
+    match AppProfiles::load(paths::app_profiles_file()) {
+        Ok(profiles) => player.set_app_profiles(profiles),
+        Err(why) => eprintln!("Failed to read the application settings: {}", why),
+    }
+
+    let access = AccessPolicy::load(paths::access_policy_file())
+        .map(AccessControl::new)
+        .and_then(|access| access.with_approvals_file(paths::approvals_file()))
+        .unwrap_or_else(|why| {
+            // Refusing every application is safer than allowing all of them
+            eprintln!("Failed to read the access policy, denying unknown applications: {}", why);
+            AccessControl::new(AccessPolicy {
+                unknown_apps: UnknownApps::Deny,
+                ..Default::default()
+            })
+        });
+
+    let server = BHapticsStudioServer::default()
+        .with_player(player.clone())
+        .with_access(access.clone());
+    let shutdown = server.shutdown_token();
+    let server = tauri::async_runtime::spawn(async move { server.run().await });
+
+    let app = tauri::Builder::default()
+        .manage(AppState {
+            player,
+            calibrations,
+            access,
+        })
+        .invoke_handler(tauri::generate_handler![
+            list_calibrations,
+            get_calibration,
+            save_calibration,
+            activate_calibration,
+            active_calibration,
+            engage_panic,
+            clear_panic,
+            is_panicked,
+            pending_apps,
+            approve_app,
+            deny_app,
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application");
+
+    // Devices are turned off and the state saved before the process exits
+    let mut server = Some(server);
+    app.run(move |_, event| {
+        if let tauri::RunEvent::Exit = event {
+            shutdown.cancel();
+            if let Some(server) = server.take() {
+                let _ = tauri::async_runtime::block_on(server);
+            }
+        }
+    });
 }
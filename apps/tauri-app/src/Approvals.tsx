@@ -0,0 +1,61 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/tauri";
+import { PendingApp } from "./types";
+
+// Interval between two checks for applications waiting for approval, in milliseconds
+const POLL_INTERVAL = 2000;
+
+function Approvals() {
+  const [pending, setPending] = useState<PendingApp[]>([]);
+  const [error, setError] = useState("");
+
+  async function refresh() {
+    try {
+      setPending(await invoke<PendingApp[]>("pending_apps"));
+    } catch (why) {
+      setError(String(why));
+    }
+  }
+
+  useEffect(() => {
+    refresh();
+    const interval = setInterval(refresh, POLL_INTERVAL);
+    return () => clearInterval(interval);
+  }, []);
+
+  async function decide(command: "approve_app" | "deny_app", id: string) {
+    try {
+      await invoke(command, { id });
+      setError("");
+      refresh();
+    } catch (why) {
+      setError(String(why));
+    }
+  }
+
+  if (pending.length === 0 && !error) {
+    return null;
+  }
+
+  return (
+    <div className="approvals">
+      <h2>Waiting for approval</h2>
+      <ul>
+        {pending.map((app) => (
+          <li key={app.id}>
+            <strong>{app.name || app.id}</strong> ({app.id}){" "}
+            <button type="button" onClick={() => decide("approve_app", app.id)}>
+              Allow
+            </button>{" "}
+            <button type="button" onClick={() => decide("deny_app", app.id)}>
+              Deny
+            </button>
+          </li>
+        ))}
+      </ul>
+      {error && <p className="error">{error}</p>}
+    </div>
+  );
+}
+
+export default Approvals;
@@ -0,0 +1,217 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/tauri";
+import {
+  BODY_PARTS,
+  BodyPart,
+  CalibrationProfile,
+  PartCalibration,
+  ResponseCurve,
+} from "./types";
+
+const DEFAULT_PART: PartCalibration = {
+  gain: 1,
+  threshold: 0,
+  cap: 1,
+  curve: "Linear",
+};
+
+function gammaOf(curve: ResponseCurve): number | null {
+  return typeof curve === "object" && "Gamma" in curve ? curve.Gamma.gamma : null;
+}
+
+function Calibration() {
+  const [names, setNames] = useState<string[]>([]);
+  const [active, setActive] = useState<string>("");
+  const [profile, setProfile] = useState<CalibrationProfile | null>(null);
+  const [newName, setNewName] = useState("");
+  const [error, setError] = useState("");
+
+  async function refresh() {
+    try {
+      setNames(await invoke("list_calibrations"));
+      setActive((await invoke<CalibrationProfile>("active_calibration")).name);
+    } catch (why) {
+      setError(String(why));
+    }
+  }
+
+  useEffect(() => {
+    refresh();
+  }, []);
+
+  async function open(name: string) {
+    try {
+      setProfile(await invoke("get_calibration", { name }));
+      setError("");
+    } catch (why) {
+      setError(String(why));
+    }
+  }
+
+  async function save() {
+    if (!profile) return;
+    try {
+      await invoke("save_calibration", { profile });
+      setError("");
+      refresh();
+    } catch (why) {
+      setError(String(why));
+    }
+  }
+
+  async function activate(name: string) {
+    try {
+      await invoke("activate_calibration", { name });
+      setError("");
+      refresh();
+    } catch (why) {
+      setError(String(why));
+    }
+  }
+
+  function updatePart(part: BodyPart, changes: Partial<PartCalibration>) {
+    if (!profile) return;
+    const current = profile.parts[part] ?? DEFAULT_PART;
+    setProfile({
+      ...profile,
+      parts: { ...profile.parts, [part]: { ...current, ...changes } },
+    });
+  }
+
+  function removePart(part: BodyPart) {
+    if (!profile) return;
+    const parts = { ...profile.parts };
+    delete parts[part];
+    setProfile({ ...profile, parts });
+  }
+
+  const missingParts = BODY_PARTS.filter((part) => !profile?.parts[part]);
+
+  return (
+    <div className="calibration">
+      <h2>Calibration</h2>
+
+      <div className="row">
+        <select value="" onChange={(e) => open(e.currentTarget.value)}>
+          <option value="" disabled>
+            Open profile...
+          </option>
+          {names.map((name) => (
+            <option key={name} value={name}>
+              {name}
+              {name === active ? " (active)" : ""}
+            </option>
+          ))}
+        </select>
+        <form
+          onSubmit={(e) => {
+            e.preventDefault();
+            if (newName) setProfile({ name: newName, parts: {} });
+          }}
+        >
+          <input
+            onChange={(e) => setNewName(e.currentTarget.value)}
+            placeholder="New profile name..."
+          />
+          <button type="submit">New</button>
+        </form>
+      </div>
+
+      {error && <p className="error">{error}</p>}
+
+      {profile && (
+        <div>
+          <h3>{profile.name}</h3>
+          <table>
+            <thead>
+              <tr>
+                <th>Position</th>
+                <th>Gain</th>
+                <th>Threshold</th>
+                <th>Cap</th>
+                <th>Gamma</th>
+                <th />
+              </tr>
+            </thead>
+            <tbody>
+              {BODY_PARTS.filter((part) => profile.parts[part]).map((part) => {
+                const calibration = profile.parts[part]!;
+                const gamma = gammaOf(calibration.curve);
+                return (
+                  <tr key={part}>
+                    <td>{part}</td>
+                    <td>
+                      <input
+                        type="number"
+                        step="0.05"
+                        min="0"
+                        value={calibration.gain}
+                        onChange={(e) => updatePart(part, { gain: e.currentTarget.valueAsNumber })}
+                      />
+                    </td>
+                    <td>
+                      <input
+                        type="number"
+                        step="0.05"
+                        min="0"
+                        max="1"
+                        value={calibration.threshold}
+                        onChange={(e) => updatePart(part, { threshold: e.currentTarget.valueAsNumber })}
+                      />
+                    </td>
+                    <td>
+                      <input
+                        type="number"
+                        step="0.05"
+                        min="0"
+                        max="1"
+                        value={calibration.cap}
+                        onChange={(e) => updatePart(part, { cap: e.currentTarget.valueAsNumber })}
+                      />
+                    </td>
+                    <td>
+                      <input
+                        type="number"
+                        step="0.1"
+                        min="0.1"
+                        placeholder="linear"
+                        value={gamma ?? ""}
+                        onChange={(e) =>
+                          updatePart(part, {
+                            curve: e.currentTarget.value
+                              ? { Gamma: { gamma: e.currentTarget.valueAsNumber } }
+                              : "Linear",
+                          })
+                        }
+                      />
+                    </td>
+                    <td>
+                      <button onClick={() => removePart(part)}>Remove</button>
+                    </td>
+                  </tr>
+                );
+              })}
+            </tbody>
+          </table>
+
+          <div className="row">
+            <select value="" onChange={(e) => updatePart(e.currentTarget.value as BodyPart, {})}>
+              <option value="" disabled>
+                Add position...
+              </option>
+              {missingParts.map((part) => (
+                <option key={part} value={part}>
+                  {part}
+                </option>
+              ))}
+            </select>
+            <button onClick={save}>Save</button>
+            <button onClick={() => save().then(() => activate(profile.name))}>Save and activate</button>
+          </div>
+        </div>
+      )}
+    </div>
+  );
+}
+
+export default Calibration;
@@ -0,0 +1,32 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/tauri";
+
+function KillSwitch() {
+  const [panicked, setPanicked] = useState(false);
+  const [error, setError] = useState("");
+
+  useEffect(() => {
+    invoke<boolean>("is_panicked").then(setPanicked, (why) => setError(String(why)));
+  }, []);
+
+  async function toggle() {
+    try {
+      setPanicked(await invoke<boolean>(panicked ? "clear_panic" : "engage_panic"));
+      setError("");
+    } catch (why) {
+      setError(String(why));
+    }
+  }
+
+  return (
+    <div className={panicked ? "kill-switch engaged" : "kill-switch"}>
+      <button type="button" onClick={toggle}>
+        {panicked ? "Resume haptics" : "Emergency stop"}
+      </button>
+      {panicked && <p>Every output is off, new effects are rejected.</p>}
+      {error && <p className="error">{error}</p>}
+    </div>
+  );
+}
+
+export default KillSwitch;
@@ -0,0 +1,45 @@
+export const BODY_PARTS = [
+  "ChestFront",
+  "ChestBack",
+  "Head",
+  "ForearmL",
+  "ForearmR",
+  "HandL",
+  "HandR",
+  "FootL",
+  "FootR",
+  "GloveL",
+  "GloveR",
+] as const;
+
+export type BodyPart = (typeof BODY_PARTS)[number];
+
+export type ResponseCurve =
+  | "Linear"
+  | { Gamma: { gamma: number } }
+  | { Lookup: { points: number[] } };
+
+export interface MotorCalibration {
+  gain?: number;
+  threshold?: number;
+  cap?: number;
+  curve?: ResponseCurve;
+}
+
+export interface PartCalibration {
+  gain: number;
+  threshold: number;
+  cap: number;
+  curve: ResponseCurve;
+  motors?: Record<string, MotorCalibration>;
+}
+
+export interface CalibrationProfile {
+  name: string;
+  parts: Partial<Record<BodyPart, PartCalibration>>;
+}
+
+export interface PendingApp {
+  id: string;
+  name: string;
+}
@@ -1,9 +1,34 @@
 use serde::{ self, Serialize, Deserialize };
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum BodyPart {
   ChestFront = 0x00,
   ChestBack = 0x01,
+  Head = 0x02,
+  ForearmL = 0x03,
+  ForearmR = 0x04,
+  HandL = 0x05,
+  HandR = 0x06,
+  FootL = 0x07,
+  FootR = 0x08,
+  GloveL = 0x09,
+  GloveR = 0x0A,
+}
+
+impl BodyPart {
+  pub const ALL: [BodyPart; 11] = [
+    BodyPart::ChestFront,
+    BodyPart::ChestBack,
+    BodyPart::Head,
+    BodyPart::ForearmL,
+    BodyPart::ForearmR,
+    BodyPart::HandL,
+    BodyPart::HandR,
+    BodyPart::FootL,
+    BodyPart::FootR,
+    BodyPart::GloveL,
+    BodyPart::GloveR,
+  ];
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -19,7 +44,7 @@ pub struct EffectFrame {
   points: Vec<EffectPoint>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum EffectInterpolation {
   None,
@@ -28,6 +53,20 @@ pub enum EffectInterpolation {
   FadeInOut,
 }
 
+impl EffectInterpolation {
+  /// Intensity multiplier at `progress` (`0.0..=1.0`) through an effect.
+  pub fn factor(&self, progress: f32) -> f32 {
+    let progress = progress.clamp(0.0, 1.0);
+
+    match self {
+      EffectInterpolation::None => 1.0,
+      EffectInterpolation::FadeIn => progress,
+      EffectInterpolation::FadeOut => 1.0 - progress,
+      EffectInterpolation::FadeInOut => 1.0 - (2.0 * progress - 1.0).abs(),
+    }
+  }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct EffectPoint {
   pub x: u8,
@@ -0,0 +1,213 @@
+"use strict";
+
+// Columns of the default motor layout of every body part, motors are laid out row by row
+const COLUMNS = {
+  ChestFront: 4,
+  ChestBack: 4,
+  Head: 6,
+  ForearmL: 3,
+  ForearmR: 3,
+  HandL: 3,
+  HandR: 3,
+  FootL: 3,
+  FootR: 3,
+  GloveL: 5,
+  GloveR: 5,
+};
+
+// Motor states per second asked to the server
+const STREAM_RATE = 30;
+
+// Interval between two refreshes of the lists, in milliseconds, events refresh them as well
+const REFRESH_INTERVAL = 5000;
+
+// Delay between an event and the refresh of the lists, events often come in bursts
+const REFRESH_DELAY = 200;
+
+// Delay before reconnecting to the state stream, in milliseconds
+const RECONNECT_DELAY = 2000;
+
+// Events kept in the log
+const MAX_EVENTS = 100;
+
+const motors = {};
+let refreshScheduled = false;
+
+function element(tag, text) {
+  const created = document.createElement(tag);
+  if (text !== undefined) {
+    created.textContent = text;
+  }
+  return created;
+}
+
+function row(cells) {
+  const tr = element("tr");
+  for (const cell of cells) {
+    const td = element("td");
+    if (cell instanceof Node) {
+      td.appendChild(cell);
+    } else {
+      td.textContent = cell;
+    }
+    tr.appendChild(td);
+  }
+  return tr;
+}
+
+function showError(why) {
+  document.getElementById("error").textContent = why ? String(why) : "";
+}
+
+async function api(method, path, body) {
+  const response = await fetch(`/api/${path}`, {
+    method,
+    headers: body === undefined ? {} : { "Content-Type": "application/json" },
+    body: body === undefined ? undefined : JSON.stringify(body),
+  });
+
+  if (!response.ok) {
+    const reason = await response.json().then((error) => error.error, () => response.statusText);
+    throw new Error(reason);
+  }
+
+  return response.status === 204 ? null : response.json();
+}
+
+function motorsOf(part, count) {
+  if (motors[part] && motors[part].length === count) {
+    return motors[part];
+  }
+
+  const container = element("div");
+  container.className = "part";
+  container.style.gridArea = part;
+  container.appendChild(element("h3", part));
+
+  const grid = element("div");
+  grid.className = "motors";
+  grid.style.gridTemplateColumns = `repeat(${COLUMNS[part] || count}, max-content)`;
+  container.appendChild(grid);
+
+  motors[part] = [];
+  for (let index = 0; index < count; index++) {
+    const motor = element("div");
+    motor.className = "motor";
+    motor.title = `${part} ${index}`;
+    grid.appendChild(motor);
+    motors[part].push(motor);
+  }
+
+  const existing = document.querySelector(`.part[data-part="${part}"]`);
+  container.dataset.part = part;
+  if (existing) {
+    existing.replaceWith(container);
+  } else {
+    document.getElementById("parts").appendChild(container);
+  }
+
+  return motors[part];
+}
+
+function showState(state) {
+  document.getElementById("panicked").hidden = !state.panicked;
+
+  for (const [part, intensities] of Object.entries(state.frame.parts)) {
+    motorsOf(part, intensities.length).forEach((motor, index) => {
+      motor.style.backgroundColor = `rgba(229, 72, 77, ${intensities[index]})`;
+    });
+  }
+}
+
+function showEvent(event) {
+  const { type, event: name, ...details } = event;
+  const log = document.getElementById("events");
+  const time = new Date().toLocaleTimeString();
+
+  log.prepend(element("li", `${time} ${name || type} ${JSON.stringify(details)}`));
+  while (log.children.length > MAX_EVENTS) {
+    log.lastChild.remove();
+  }
+}
+
+async function refreshClients() {
+  const clients = await api("GET", "clients");
+  document.getElementById("clients").replaceChildren(...clients.map((client) => row([
+    client.app_name || "?",
+    client.app_id,
+    client.namespace,
+    new Date(client.connected_at * 1000).toLocaleTimeString(),
+  ])));
+}
+
+async function refreshDevices() {
+  const devices = await api("GET", "devices");
+  document.getElementById("devices").replaceChildren(...devices.map((device) => {
+    const status = element("span", device.stopped ? "stopped" : "running");
+    status.className = device.stopped ? "stopped" : "";
+    return row([device.name, device.positions.join(", "), status]);
+  }));
+}
+
+async function refreshPatterns() {
+  const patterns = await api("GET", "patterns");
+  document.getElementById("patterns").replaceChildren(...patterns.map((pattern) => {
+    const play = element("button", "Play");
+    play.type = "button";
+    play.onclick = () => api("POST", "play", { key: pattern.key, active_key: `dashboard/${pattern.key}` })
+      .then(() => showError(), showError);
+    return row([pattern.key, `${pattern.duration_millis} ms`, play]);
+  }));
+}
+
+function refresh() {
+  Promise.all([refreshClients(), refreshDevices(), refreshPatterns()]).then(() => showError(), showError);
+}
+
+function scheduleRefresh() {
+  if (!refreshScheduled) {
+    refreshScheduled = true;
+    setTimeout(() => {
+      refreshScheduled = false;
+      refresh();
+    }, REFRESH_DELAY);
+  }
+}
+
+function connect() {
+  const scheme = location.protocol === "https:" ? "wss" : "ws";
+  const socket = new WebSocket(`${scheme}://${location.host}/api/stream?rate=${STREAM_RATE}`);
+  const connection = document.getElementById("connection");
+
+  socket.onopen = () => {
+    connection.textContent = "Connected";
+    connection.className = "connection connected";
+    refresh();
+  };
+
+  socket.onmessage = (message) => {
+    const data = JSON.parse(message.data);
+    if (data.type === "state") {
+      showState(data);
+      return;
+    }
+
+    showEvent(data);
+    if (data.type === "event" && data.event !== "played" && data.event !== "stopped" && data.event !== "finished") {
+      scheduleRefresh();
+    }
+  };
+
+  socket.onclose = () => {
+    connection.textContent = "Disconnected, reconnecting…";
+    connection.className = "connection";
+    setTimeout(connect, RECONNECT_DELAY);
+  };
+}
+
+document.getElementById("stop-all").onclick = () => {
+  api("DELETE", "active").then(() => showError(), showError);
+};
+
+connect();
+setInterval(refresh, REFRESH_INTERVAL);
@@ -0,0 +1,446 @@
+//! Who may drive the wearer's devices.
+//!
+//! Every HTTP and WebSocket request first goes through the remote address and `Origin`
+//! allowlists. bHaptics clients are then checked against the `app_id` deny and allow
+//! lists, unknown applications being allowed, denied or held until approved, depending
+//! on [`AccessPolicy::unknown_apps`]. Approval decisions are kept across restarts.
+//!
+//! # Example Policy
+//! ```json
+//! {
+//!     "allowed_addresses": ["127.0.0.1", "192.168.1.0/24"],
+//!     "allowed_origins": ["http://localhost:5173"],
+//!     "allowed_apps": ["com.example.game"],
+//!     "denied_apps": ["com.example.spam"],
+//!     "unknown_apps": "Ask"
+//! }
+//! ```
+
+use std::{
+    collections::{ BTreeMap, BTreeSet },
+    fmt,
+    fs,
+    io,
+    net::{ IpAddr, SocketAddr },
+    path::{ Path, PathBuf },
+    str::FromStr,
+    sync::{ Arc, Mutex },
+};
+
+use serde::{ Serialize, Deserialize };
+use tokio::sync::watch;
+use tracing::{ info, warn };
+use warp::{ self, Filter, Rejection };
+
+#[derive(Debug)]
+pub enum AccessError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessError::Io(why) => write!(f, "access policy I/O error: {}", why),
+            AccessError::Parse(why) => write!(f, "failed to parse access policy: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+/// Single address, or network in CIDR notation such as `192.168.1.0/24`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AddressRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl AddressRange {
+    pub fn contains(&self, address: IpAddr) -> bool {
+        // IPv4 clients of a dual stack listener show up as IPv4-mapped IPv6 addresses
+        let address = match address {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(address),
+            address => address,
+        };
+
+        match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for AddressRange {
+    type Err = String;
+
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match range.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (range, None),
+        };
+
+        let network: IpAddr = address.parse().map_err(|_| format!("{:?} is not an IP address", address))?;
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().ok().filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| format!("{:?} is not a prefix length between 0 and {}", prefix, bits))?,
+            None => bits,
+        };
+
+        Ok(Self {
+            network,
+            prefix,
+        })
+    }
+}
+
+impl fmt::Display for AddressRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl Serialize for AddressRange {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for AddressRange {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// What happens to applications in neither the allow nor the deny list.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownApps {
+    #[default]
+    Allow,
+    Deny,
+
+    /// Held until approved or denied, from the CLI or the app
+    Ask,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AccessPolicy {
+    /// Remote addresses allowed to connect, any when empty
+    #[serde(default)]
+    pub allowed_addresses: Vec<AddressRange>,
+
+    /// `Origin` headers allowed, `*` allowing any. Requests without one, i.e. not
+    /// coming from a web page, are always allowed, as are pages served by the server
+    /// itself when it is reached through `localhost` or an IP address
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+
+    #[serde(default)]
+    pub allowed_apps: BTreeSet<String>,
+
+    /// Takes precedence over every other list
+    #[serde(default)]
+    pub denied_apps: BTreeSet<String>,
+
+    #[serde(default)]
+    pub unknown_apps: UnknownApps,
+}
+
+impl AccessPolicy {
+    /// Reads the policy from `path`, the default policy when there is no such file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AccessError> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(AccessError::Parse),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(why) => Err(AccessError::Io(why)),
+        }
+    }
+
+    /// Whether a request from `address`, with the `Origin` header `origin`, may go through.
+    pub fn check_peer(&self, address: Option<IpAddr>, origin: Option<&str>) -> Result<(), String> {
+        // Requests without address come from a local socket
+        if let Some(address) = address {
+            if !self.allowed_addresses.is_empty() && !self.allowed_addresses.iter().any(|range| range.contains(address)) {
+                return Err(format!("address {} is not allowed", address));
+            }
+        }
+
+        if let Some(origin) = origin {
+            if !self.allowed_origins.iter().any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin)) {
+                return Err(format!("origin {} is not allowed", origin));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `origin` is the server itself, e.g. the dashboard.
+///
+/// Only `localhost` and IP addresses count: a page of any domain name could have it
+/// resolve to the server, and would then look like it is served by it.
+fn is_same_origin(origin: &str, host: Option<&str>) -> bool {
+    let Some(host) = host else {
+        return false;
+    };
+
+    let Some((_, authority)) = origin.split_once("://") else {
+        return false;
+    };
+
+    let name = match authority.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => authority,
+    };
+    let local = name.eq_ignore_ascii_case("localhost")
+        || name.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().is_ok();
+
+    local && authority.eq_ignore_ascii_case(host)
+}
+
+/// Decision about an application.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Approved,
+    Denied,
+}
+
+/// Approval decisions taken at runtime, kept across restarts.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Approvals {
+    #[serde(default)]
+    pub apps: BTreeMap<String, Decision>,
+}
+
+impl Approvals {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AccessError> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(AccessError::Parse),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(why) => Err(AccessError::Io(why)),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), AccessError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(AccessError::Io)?;
+        }
+
+        let content = serde_json::to_string_pretty(self).map_err(AccessError::Parse)?;
+        fs::write(path, content).map_err(AccessError::Io)
+    }
+}
+
+/// Application waiting for approval.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PendingApp {
+    pub id: String,
+    pub name: String,
+}
+
+struct Pending {
+    name: String,
+    decision: watch::Sender<Option<Decision>>,
+}
+
+#[derive(Default)]
+struct AccessState {
+    policy: AccessPolicy,
+    approvals: Approvals,
+    pending: BTreeMap<String, Pending>,
+}
+
+impl AccessState {
+    fn decision(&self, id: &str) -> Option<Decision> {
+        if self.policy.denied_apps.contains(id) {
+            return Some(Decision::Denied);
+        }
+
+        if self.policy.allowed_apps.contains(id) {
+            return Some(Decision::Approved);
+        }
+
+        if let Some(decision) = self.approvals.apps.get(id) {
+            return Some(*decision);
+        }
+
+        match self.policy.unknown_apps {
+            UnknownApps::Allow => Some(Decision::Approved),
+            UnknownApps::Deny => Some(Decision::Denied),
+            UnknownApps::Ask => None,
+        }
+    }
+}
+
+/// Runtime side of the [`AccessPolicy`], shared by every route.
+///
+/// Cloning is cheap, clones share the same state.
+#[derive(Clone, Default)]
+pub struct AccessControl {
+    state: Arc<Mutex<AccessState>>,
+
+    /// Where approval decisions are saved, not saved when absent
+    approvals_file: Option<PathBuf>,
+}
+
+/// Rejection of a request refused by the policy.
+#[derive(Debug)]
+pub struct Forbidden(pub String);
+
+impl warp::reject::Reject for Forbidden {}
+
+impl AccessControl {
+    pub fn new(policy: AccessPolicy) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(AccessState {
+                policy,
+                ..Default::default()
+            })),
+            approvals_file: None,
+        }
+    }
+
+    /// Keeps the approval decisions in `path`, reading the ones taken so far.
+    pub fn with_approvals_file(self, path: impl Into<PathBuf>) -> Result<Self, AccessError> {
+        let path = path.into();
+        self.state.lock().unwrap().approvals = Approvals::load(&path)?;
+
+        Ok(Self {
+            approvals_file: Some(path),
+            ..self
+        })
+    }
+
+    pub fn policy(&self) -> AccessPolicy {
+        self.state.lock().unwrap().policy.clone()
+    }
+
+    pub fn set_policy(&self, policy: AccessPolicy) {
+        self.state.lock().unwrap().policy = policy;
+    }
+
+    /// Filter rejecting requests whose remote address or origin is not allowed.
+    pub fn peer_filter(&self) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        let access = self.clone();
+
+        warp::addr::remote()
+            .and(warp::header::optional::<String>("origin"))
+            .and(warp::header::optional::<String>("host"))
+            .and_then(move |address: Option<SocketAddr>, origin: Option<String>, host: Option<String>| {
+                let origin = origin.filter(|origin| !is_same_origin(origin, host.as_deref()));
+                let checked = access.state.lock().unwrap().policy
+                    .check_peer(address.map(|address| address.ip()), origin.as_deref());
+
+                async move {
+                    checked.map_err(|why| {
+                        warn!("Refused request: {}", why);
+                        warp::reject::custom(Forbidden(why))
+                    })
+                }
+            })
+            .untuple_one()
+    }
+
+    /// Decision about the application `id`, without waiting for approval.
+    pub fn decision(&self, id: &str) -> Option<Decision> {
+        self.state.lock().unwrap().decision(id)
+    }
+
+    /// Decision about the application `id`, waiting for it to be approved or denied when unknown.
+    pub async fn authorize(&self, id: &str, name: &str) -> Decision {
+        // Decided and held under the same lock, a decision taken in between would never reach the connection
+        let decision = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(decision) = state.decision(id) {
+                return decision;
+            }
+
+            let pending = state.pending.entry(id.to_string()).or_insert_with(|| {
+                info!("Application {:?} ({}) is waiting for approval", name, id);
+                Pending {
+                    name: name.to_string(),
+                    decision: watch::channel(None).0,
+                }
+            });
+            pending.decision.subscribe()
+        };
+
+        let mut waiter = Waiter { access: self, id, decision: Some(decision) };
+        let decision = waiter.decision.as_mut().expect("waiting for a decision");
+        let decided = decision.wait_for(Option::is_some).await.map(|decision| *decision);
+        decided.ok().flatten().unwrap_or(Decision::Denied)
+    }
+
+    pub fn pending(&self) -> Vec<PendingApp> {
+        self.state.lock().unwrap().pending
+            .iter()
+            .map(|(id, pending)| PendingApp { id: id.clone(), name: pending.name.clone() })
+            .collect()
+    }
+
+    pub fn approvals(&self) -> Approvals {
+        self.state.lock().unwrap().approvals.clone()
+    }
+
+    pub fn approve(&self, id: &str) -> Result<(), AccessError> {
+        self.decide(id, Decision::Approved)
+    }
+
+    pub fn deny(&self, id: &str) -> Result<(), AccessError> {
+        self.decide(id, Decision::Denied)
+    }
+
+    /// Records `decision` about the application `id`, releasing its held connections.
+    pub fn decide(&self, id: &str, decision: Decision) -> Result<(), AccessError> {
+        let approvals = {
+            let mut state = self.state.lock().unwrap();
+            state.approvals.apps.insert(id.to_string(), decision);
+            if let Some(pending) = state.pending.remove(id) {
+                pending.decision.send_replace(Some(decision));
+            }
+            state.approvals.clone()
+        };
+
+        info!("Application {} {}", id, match decision {
+            Decision::Approved => "approved",
+            Decision::Denied => "denied",
+        });
+
+        match &self.approvals_file {
+            Some(path) => approvals.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Connection waiting for the decision about an application.
+///
+/// The application is no longer pending once every connection waiting for it went away
+/// without a decision, e.g. when the clients disconnected.
+struct Waiter<'a> {
+    access: &'a AccessControl,
+    id: &'a str,
+    decision: Option<watch::Receiver<Option<Decision>>>,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let mut state = self.access.state.lock().unwrap();
+        self.decision = None;
+
+        let abandoned = state.pending.get(self.id).is_some_and(|pending| pending.decision.receiver_count() == 0);
+        if abandoned {
+            state.pending.remove(self.id);
+            info!("Application {} is no longer waiting for approval", self.id);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use warp::{ self, http::{ header, Uri }, Filter, Reply, Rejection };
+
+use super::ControlApi;
+
+const INDEX: &str = include_str!("../../dashboard/index.html");
+const SCRIPT: &str = include_str!("../../dashboard/dashboard.js");
+const STYLE: &str = include_str!("../../dashboard/dashboard.css");
+
+impl ControlApi {
+    pub(super) fn dashboard_routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        let root = warp::path::end()
+            .and(warp::get())
+            .map(|| warp::redirect::see_other(Uri::from_static("/dashboard/")));
+
+        let index = warp::path!("dashboard")
+            .and(warp::get())
+            .map(|| asset(INDEX, "text/html; charset=utf-8"));
+
+        let script = warp::path!("dashboard" / "dashboard.js")
+            .and(warp::get())
+            .map(|| asset(SCRIPT, "text/javascript; charset=utf-8"));
+
+        let style = warp::path!("dashboard" / "dashboard.css")
+            .and(warp::get())
+            .map(|| asset(STYLE, "text/css; charset=utf-8"));
+
+        root.or(index).or(script).or(style)
+    }
+}
+
+fn asset(content: &'static str, content_type: &'static str) -> impl Reply {
+    warp::reply::with_header(content, header::CONTENT_TYPE, content_type)
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+
+use warp::{ self, http::header, Filter, Reply, Rejection };
+
+use crate::{
+    bhaptics_studio::clients::Clients,
+    haptics::player::HapticPlayer,
+    metrics::Exposition,
+};
+
+use super::ControlApi;
+
+impl ControlApi {
+    pub(super) fn metrics_routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        let clients = self.clients.clone();
+
+        warp::path!("metrics")
+            .and(warp::get())
+            .and(self.with_player())
+            .map(move |player: HapticPlayer| {
+                warp::reply::with_header(render(&player, &clients), header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            })
+    }
+}
+
+fn render(player: &HapticPlayer, clients: &Clients) -> String {
+    let metrics = player.metrics();
+    let mut exposition = Exposition::new();
+
+    let mut per_app: BTreeMap<String, u64> = BTreeMap::new();
+    for client in clients.list() {
+        *per_app.entry(client.app_id).or_default() += 1;
+    }
+
+    exposition.labelled("xrconnect_connected_clients", "gauge", "bHaptics clients connected, per application", "app", &per_app);
+    exposition.labelled_counter("xrconnect_messages_received_total", "bHaptics messages received, per type", "type", metrics.messages());
+    exposition.counter("xrconnect_parse_errors_total", "bHaptics messages which could not be parsed", metrics.parse_errors().get());
+    exposition.gauge("xrconnect_registered_keys", "Patterns registered with the player", player.registered_keys().len() as u64);
+    exposition.gauge("xrconnect_active_keys", "Effects playing", player.active_keys().len() as u64);
+    exposition.histogram(
+        "xrconnect_playback_latency_seconds",
+        "Time from an effect being started to its first frame being written to the devices",
+        metrics.playback_latency(),
+    );
+    exposition.labelled_counter("xrconnect_device_write_failures_total", "Frames which could not be written, per device", "device", metrics.device_write_failures());
+    exposition.gauge("xrconnect_devices", "Devices attached to the player", player.device_count() as u64);
+    exposition.counter("xrconnect_safety_interventions_total", "Times the safety limiter stepped in", player.safety_interventions());
+    exposition.gauge("xrconnect_panicked", "Whether the kill switch is engaged", player.is_panicked() as u64);
+
+    exposition.finish()
+}
@@ -0,0 +1,323 @@
+//! HTTP control API, served next to the bHaptics WebSocket routes.
+//!
+//! A dashboard built on the API is served at `/dashboard/`, `/` redirecting to it.
+//!
+//! Requests and responses are JSON, errors are answered with a non-2xx status and
+//! `{"error":"<reason>"}`. Keys are the player's keys: keys of bHaptics clients are
+//! prefixed with their namespace, e.g. `connection:3/Hit`, and have to be percent-encoded
+//! in paths, e.g. `connection:3%2FHit`. Request bodies need a `Content-Length` of 1 MiB at most.
+//!
+//! # Access
+//!
+//! The API drives the devices and decides which applications may connect, it only answers
+//! local peers: connections over loopback or a Unix domain socket listener. Other peers,
+//! e.g. games on the network sharing a listener with the API, are refused with `403 Forbidden`
+//! unless a token is configured and they send it as `Authorization: Bearer <token>`.
+//! A reverse proxy on the same host makes every peer local, it has to authenticate them itself.
+//!
+//! # Kill switch
+//!
+//! - `GET /api/panic` tells whether the kill switch is engaged
+//! - `POST /api/panic` engages it
+//! - `DELETE /api/panic` clears it
+//!
+//! Each of them answers with the resulting state, e.g. `{"panicked":true}`.
+//!
+//! # Application approval
+//!
+//! - `GET /api/apps/pending` lists the applications waiting for approval
+//! - `GET /api/apps/approvals` lists the decisions taken so far
+//! - `POST /api/apps/<app_id>/approve` approves an application
+//! - `POST /api/apps/<app_id>/deny` denies an application
+//!
+//! # Player
+//!
+//! - `GET /api/clients` lists the connected bHaptics clients
+//! - `GET /api/patterns` lists the registered keys, `GET /api/patterns/<key>` gives a pattern
+//! - `GET /api/projects` lists the registered keys per namespace
+//! - `GET /api/active` lists the active keys
+//! - `POST /api/play` plays a registered key, e.g. `{"key":"Hit","active_key":"qa","intensity":0.5}`
+//! - `POST /api/play/frame` plays an ad-hoc bHaptics frame, e.g.
+//!   `{"key":"qa","frame":{"Position":"VestFront","DotPoints":[{"index":0,"intensity":100}],"DurationMillis":500}}`
+//! - `DELETE /api/active/<key>` stops a key, `DELETE /api/active` stops everything
+//! - `GET /api/devices` lists the devices
+//! - `GET /api/muted` lists the muted body parts, `PUT /api/muted/<part>` mutes one, e.g.
+//!   `PUT /api/muted/Head`, and `DELETE /api/muted/<part>` unmutes it
+//!
+//! # Live state
+//!
+//! - `GET /api/stream` is a WebSocket streaming JSON messages
+//! - `GET /api/stream/events` streams the same messages as server-sent events, named after their `type`
+//!
+//! Both send the motor state of every body part, as the wearer feels it, `rate` times per
+//! second (`?rate=<1..50>`, 30 by default), e.g. `{"type":"state","panicked":false,"frame":{"parts":{"Head":[0.0,0.5,...]}}}`,
+//! and the events of the player and of the bHaptics clients as they happen, e.g.
+//! `{"type":"event","event":"played","active_key":"connection:3/Hit","key":"connection:3/Hit","app":"com.example.game"}`.
+//! Events are `registered`, `unregistered`, `played`, `stopped`, `finished`, `device`,
+//! `device_disconnected`, `panic`, `muted`, `client_connected` and `client_disconnected`.
+//!
+//! # Metrics
+//!
+//! - `GET /metrics` gives the metrics in the Prometheus text format, see [`crate::metrics`]
+//!
+//! # Settings
+//!
+//! - `GET`/`PUT /api/settings/calibration` reads or replaces the calibration in use
+//! - `GET /api/calibrations` lists the stored calibration profiles
+//! - `POST /api/calibrations/<name>/activate` uses a stored calibration profile
+//! - `GET`/`PUT /api/settings/safety` reads or replaces the safety limits, out of range ones answered with `400 Bad Request`
+//! - `GET`/`PUT /api/settings/apps` reads or replaces the per application settings
+
+use std::{
+    fmt,
+    net::SocketAddr,
+};
+
+use serde::{ de::DeserializeOwned, Serialize, Deserialize };
+use tokio_util::sync::CancellationToken;
+use warp::{ self, http::StatusCode, hyper::body::Bytes, reply::Response, Filter, Reply, Rejection };
+
+use crate::{
+    access::{ AccessControl, Decision, Forbidden },
+    bhaptics_studio::clients::Clients,
+    haptics::{
+        calibration::CalibrationStore,
+        player::HapticPlayer,
+    },
+};
+
+mod dashboard;
+mod metrics;
+mod player;
+mod settings;
+mod stream;
+
+pub use stream::{ StreamEvent, StreamMessage, DEFAULT_STREAM_RATE };
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PanicState {
+    pub panicked: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApiError {
+    pub error: String,
+}
+
+pub struct ControlApi {
+    player: HapticPlayer,
+    access: AccessControl,
+    clients: Clients,
+    calibrations: CalibrationStore,
+
+    /// Token peers other than local ones authenticate with, only local peers are answered when absent
+    token: Option<String>,
+
+    /// Cancelled when the server shuts down, ending the live streams
+    shutdown: CancellationToken,
+}
+
+impl ControlApi {
+    pub fn new(player: HapticPlayer, access: AccessControl) -> Self {
+        Self {
+            player,
+            access,
+            clients: Clients::new(),
+            calibrations: CalibrationStore::default(),
+            token: None,
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Lists the clients of `clients`, those of the WebSocket behavior.
+    pub fn with_clients(mut self, clients: Clients) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    pub fn with_calibrations(mut self, calibrations: CalibrationStore) -> Self {
+        self.calibrations = calibrations;
+        self
+    }
+
+    /// Answers peers other than local ones sending `Authorization: Bearer <token>` as well.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token.filter(|token| !token.is_empty());
+        self
+    }
+
+    pub fn with_shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn routes(&self) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        self.local_or_token().and(
+            self.panic_routes()
+                .or(self.app_routes())
+                .or(self.client_routes())
+                .or(self.player_routes())
+                .or(self.settings_routes())
+                .or(self.stream_routes())
+                .or(self.dashboard_routes())
+                .or(self.metrics_routes())
+        )
+    }
+
+    /// Filter rejecting requests from peers which are neither local nor send the token.
+    fn local_or_token(&self) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        let token = self.token.clone();
+
+        warp::addr::remote()
+            .and(warp::header::optional::<String>("authorization"))
+            .and_then(move |address: Option<SocketAddr>, authorization: Option<String>| {
+                // Unix domain sockets have no remote address
+                let local = address.is_none_or(|address| address.ip().to_canonical().is_loopback());
+                let authenticated = match (&token, authorization.as_deref().and_then(|value| value.strip_prefix("Bearer "))) {
+                    (Some(token), Some(given)) => same_token(token, given.trim()),
+                    _ => false,
+                };
+
+                async move {
+                    match local || authenticated {
+                        true => Ok(()),
+                        false => Err(warp::reject::custom(Forbidden(String::from("the control API only answers local peers, or with its token")))),
+                    }
+                }
+            })
+            .untuple_one()
+    }
+
+    fn with_player(&self) -> impl Filter<Extract = (HapticPlayer,), Error = std::convert::Infallible> + Clone {
+        let player = self.player.clone();
+        warp::any().map(move || player.clone())
+    }
+
+    fn panic_routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        let panic = warp::path!("api" / "panic").and(self.with_player());
+
+        let get = panic.clone()
+            .and(warp::get())
+            .map(|player: HapticPlayer| panic_state(&player));
+
+        let engage = panic.clone()
+            .and(warp::post())
+            .map(|player: HapticPlayer| {
+                player.panic();
+                panic_state(&player)
+            });
+
+        let clear = panic
+            .and(warp::delete())
+            .map(|player: HapticPlayer| {
+                player.clear_panic();
+                panic_state(&player)
+            });
+
+        get.or(engage).or(clear)
+    }
+
+    fn app_routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        let access = self.access.clone();
+        let with_access = warp::any().map(move || access.clone());
+
+        let pending = warp::path!("api" / "apps" / "pending")
+            .and(warp::get())
+            .and(with_access.clone())
+            .map(|access: AccessControl| warp::reply::json(&access.pending()));
+
+        let approvals = warp::path!("api" / "apps" / "approvals")
+            .and(warp::get())
+            .and(with_access.clone())
+            .map(|access: AccessControl| warp::reply::json(&access.approvals()));
+
+        let decide = warp::path!("api" / "apps" / String / String)
+            .and(warp::post())
+            .and(with_access)
+            .map(|id: String, action: String, access: AccessControl| {
+                let decision = match action.as_str() {
+                    "approve" => Decision::Approved,
+                    "deny" => Decision::Denied,
+                    _ => return error(StatusCode::NOT_FOUND, format!("unknown action {}", action)),
+                };
+
+                match access.decide(&id, decision) {
+                    Ok(()) => StatusCode::NO_CONTENT.into_response(),
+                    Err(why) => error(StatusCode::INTERNAL_SERVER_ERROR, why),
+                }
+            });
+
+        pending.or(approvals).or(decide)
+    }
+
+    fn client_routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        let clients = self.clients.clone();
+
+        warp::path!("api" / "clients")
+            .and(warp::get())
+            .map(move || warp::reply::json(&clients.list()))
+    }
+}
+
+/// Compares tokens in a time independent of where they differ.
+fn same_token(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected.bytes().zip(given.bytes()).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+fn panic_state(player: &HapticPlayer) -> warp::reply::Json {
+    warp::reply::json(&PanicState { panicked: player.is_panicked() })
+}
+
+fn error(status: StatusCode, why: impl fmt::Display) -> Response {
+    Failure { status, why: why.to_string() }.into_response()
+}
+
+/// Error answered with `status` and `{"error":"<why>"}`.
+struct Failure {
+    status: StatusCode,
+    why: String,
+}
+
+impl Reply for Failure {
+    fn into_response(self) -> Response {
+        warp::reply::with_status(warp::reply::json(&ApiError { error: self.why }), self.status).into_response()
+    }
+}
+
+/// Largest request body accepted.
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Request body, refused with `413 Payload Too Large` beyond [`MAX_BODY_BYTES`].
+fn body() -> impl Filter<Extract = (Bytes,), Error = Rejection> + Clone {
+    warp::body::content_length_limit(MAX_BODY_BYTES).and(warp::body::bytes())
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, Failure> {
+    serde_json::from_slice(body).map_err(|why| Failure { status: StatusCode::BAD_REQUEST, why: why.to_string() })
+}
+
+/// Percent-decodes a path segment, keys may contain `/`.
+fn decode(segment: &str) -> Result<String, Failure> {
+    percent_decode(segment).ok_or_else(|| Failure {
+        status: StatusCode::BAD_REQUEST,
+        why: format!("invalid percent-encoding in {}", segment),
+    })
+}
+
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut iter = segment.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+
+        let hex = [iter.next()?, iter.next()?];
+        bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+    }
+
+    String::from_utf8(bytes).ok()
+}
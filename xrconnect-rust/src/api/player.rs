@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+
+use haptic_lib::BodyPart;
+use serde::{ Serialize, Deserialize };
+use warp::{ self, http::StatusCode, hyper::body::Bytes, reply::Response, Filter, Reply, Rejection };
+
+use crate::{
+    bhaptics_studio::tact::SubmitFrame,
+    haptics::{
+        model::PlaybackOptions,
+        player::{ HapticPlayer, PlayerError },
+    },
+};
+
+use super::{ body, decode, error, parse, ControlApi };
+
+/// Registered key, as listed by `GET /api/patterns`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PatternInfo {
+    pub key: String,
+    pub duration_millis: u32,
+}
+
+/// Keys registered within a namespace, as listed by `GET /api/projects`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ProjectInfo {
+    /// Namespace of the keys, `null` for keys registered outside of any namespace
+    pub namespace: Option<String>,
+
+    /// Keys without the namespace
+    pub keys: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PlayRequest {
+    pub key: String,
+
+    /// Key the effect plays under, `key` when absent
+    #[serde(default)]
+    pub active_key: Option<String>,
+
+    #[serde(default, flatten)]
+    pub options: PlaybackOptions,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PlayFrameRequest {
+    /// Key the frame plays under
+    pub key: String,
+    pub frame: SubmitFrame,
+}
+
+impl ControlApi {
+    pub(super) fn player_routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        let patterns = warp::path!("api" / "patterns")
+            .and(warp::get())
+            .and(self.with_player())
+            .map(|player: HapticPlayer| warp::reply::json(&patterns(&player)));
+
+        let pattern = warp::path!("api" / "patterns" / String)
+            .and(warp::get())
+            .and(self.with_player())
+            .map(|key: String, player: HapticPlayer| {
+                let key = match decode(&key) {
+                    Ok(key) => key,
+                    Err(failure) => return failure.into_response(),
+                };
+
+                match player.pattern(&key) {
+                    Some(pattern) => warp::reply::json(&*pattern).into_response(),
+                    None => error(StatusCode::NOT_FOUND, PlayerError::UnknownKey(key)),
+                }
+            });
+
+        let projects = warp::path!("api" / "projects")
+            .and(warp::get())
+            .and(self.with_player())
+            .map(|player: HapticPlayer| warp::reply::json(&projects(&player)));
+
+        let active = warp::path!("api" / "active")
+            .and(warp::get())
+            .and(self.with_player())
+            .map(|player: HapticPlayer| {
+                let mut keys = player.active_keys();
+                keys.sort();
+                warp::reply::json(&keys)
+            });
+
+        let stop_all = warp::path!("api" / "active")
+            .and(warp::delete())
+            .and(self.with_player())
+            .map(|player: HapticPlayer| {
+                player.stop_all();
+                StatusCode::NO_CONTENT
+            });
+
+        let stop = warp::path!("api" / "active" / String)
+            .and(warp::delete())
+            .and(self.with_player())
+            .map(|key: String, player: HapticPlayer| match decode(&key) {
+                Ok(key) => {
+                    player.stop(&key);
+                    StatusCode::NO_CONTENT.into_response()
+                },
+                Err(failure) => failure.into_response(),
+            });
+
+        let play = warp::path!("api" / "play")
+            .and(warp::post())
+            .and(body())
+            .and(self.with_player())
+            .map(|body: Bytes, player: HapticPlayer| {
+                let request: PlayRequest = match parse(&body) {
+                    Ok(request) => request,
+                    Err(failure) => return failure.into_response(),
+                };
+
+                let active_key = request.active_key.unwrap_or_else(|| request.key.clone());
+                played(player.play(&request.key, active_key, request.options))
+            });
+
+        let play_frame = warp::path!("api" / "play" / "frame")
+            .and(warp::post())
+            .and(body())
+            .and(self.with_player())
+            .map(|body: Bytes, player: HapticPlayer| {
+                let request: PlayFrameRequest = match parse(&body) {
+                    Ok(request) => request,
+                    Err(failure) => return failure.into_response(),
+                };
+
+                match request.frame.to_pattern() {
+                    Ok(pattern) => played(player.play_pattern(request.key, pattern, PlaybackOptions::default())),
+                    Err(why) => error(StatusCode::BAD_REQUEST, why),
+                }
+            });
+
+        let devices = warp::path!("api" / "devices")
+            .and(warp::get())
+            .and(self.with_player())
+            .map(|player: HapticPlayer| warp::reply::json(&player.devices()));
+
+        let muted = warp::path!("api" / "muted")
+            .and(warp::get())
+            .and(self.with_player())
+            .map(|player: HapticPlayer| warp::reply::json(&player.muted()));
+
+        let mute = warp::path!("api" / "muted" / String)
+            .and(warp::put().map(|| true).or(warp::delete().map(|| false)).unify())
+            .and(self.with_player())
+            .map(|part: String, muted: bool, player: HapticPlayer| {
+                match serde_json::from_value::<BodyPart>(serde_json::Value::String(part.clone())) {
+                    Ok(part) => {
+                        player.set_muted(part, muted);
+                        warp::reply::json(&player.muted()).into_response()
+                    },
+                    Err(_) => error(StatusCode::NOT_FOUND, format!("no body part named {:?}", part)),
+                }
+            });
+
+        patterns
+            .or(pattern)
+            .or(projects)
+            .or(active)
+            .or(stop_all)
+            .or(stop)
+            .or(play)
+            .or(play_frame)
+            .or(devices)
+            .or(muted)
+            .or(mute)
+    }
+}
+
+fn patterns(player: &HapticPlayer) -> Vec<PatternInfo> {
+    let mut keys = player.registered_keys();
+    keys.sort();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let duration_millis = player.pattern(&key)?.duration_millis();
+            Some(PatternInfo { key, duration_millis })
+        })
+        .collect()
+}
+
+/// Registered keys grouped by namespace, keys of bHaptics clients are `<namespace>/<key>`.
+fn projects(player: &HapticPlayer) -> Vec<ProjectInfo> {
+    let mut projects: BTreeMap<Option<String>, Vec<String>> = BTreeMap::new();
+    for key in player.registered_keys() {
+        let (namespace, key) = match key.split_once('/') {
+            Some((namespace, key)) => (Some(namespace.to_string()), key.to_string()),
+            None => (None, key),
+        };
+        projects.entry(namespace).or_default().push(key);
+    }
+
+    projects.into_iter()
+        .map(|(namespace, mut keys)| {
+            keys.sort();
+            ProjectInfo { namespace, keys }
+        })
+        .collect()
+}
+
+fn played(result: Result<(), PlayerError>) -> Response {
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(why @ PlayerError::UnknownKey(_)) => error(StatusCode::NOT_FOUND, why),
+        Err(why @ PlayerError::Panicked) => error(StatusCode::CONFLICT, why),
+    }
+}
@@ -0,0 +1,137 @@
+use warp::{ self, http::StatusCode, hyper::body::Bytes, Filter, Reply, Rejection };
+
+use crate::haptics::{
+    apps::AppProfiles,
+    calibration::{ CalibrationError, CalibrationProfile, CalibrationStore },
+    player::HapticPlayer,
+    safety::SafetyLimits,
+};
+
+use super::{ body, error, parse, ControlApi };
+
+impl ControlApi {
+    pub(super) fn settings_routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        let calibrations = self.calibrations.clone();
+        let with_calibrations = warp::any().map(move || calibrations.clone());
+
+        let get_calibration = warp::path!("api" / "settings" / "calibration")
+            .and(warp::get())
+            .and(self.with_player())
+            .map(|player: HapticPlayer| warp::reply::json(&player.calibration()));
+
+        // Named profiles are stored as well, so that they are restored after a restart
+        let put_calibration = warp::path!("api" / "settings" / "calibration")
+            .and(warp::put())
+            .and(body())
+            .and(self.with_player())
+            .and(with_calibrations.clone())
+            .map(|body: Bytes, player: HapticPlayer, calibrations: CalibrationStore| {
+                let profile: CalibrationProfile = match parse(&body) {
+                    Ok(profile) => profile,
+                    Err(failure) => return failure.into_response(),
+                };
+
+                let saved = if profile.name.is_empty() {
+                    profile.validate()
+                } else {
+                    calibrations.save(&profile)
+                };
+
+                match saved {
+                    Ok(()) => {
+                        player.set_calibration(profile);
+                        warp::reply::json(&player.calibration()).into_response()
+                    },
+                    Err(why) => calibration_error(why),
+                }
+            });
+
+        let list_calibrations = warp::path!("api" / "calibrations")
+            .and(warp::get())
+            .and(with_calibrations.clone())
+            .map(|calibrations: CalibrationStore| match calibrations.list() {
+                Ok(names) => warp::reply::json(&names).into_response(),
+                Err(why) => calibration_error(why),
+            });
+
+        let activate_calibration = warp::path!("api" / "calibrations" / String / "activate")
+            .and(warp::post())
+            .and(self.with_player())
+            .and(with_calibrations)
+            .map(|name: String, player: HapticPlayer, calibrations: CalibrationStore| match calibrations.load(&name) {
+                Ok(profile) => {
+                    player.set_calibration(profile);
+                    warp::reply::json(&player.calibration()).into_response()
+                },
+                Err(why) => calibration_error(why),
+            });
+
+        let get_safety = warp::path!("api" / "settings" / "safety")
+            .and(warp::get())
+            .and(self.with_player())
+            .map(|player: HapticPlayer| warp::reply::json(&player.safety_limits()));
+
+        let put_safety = warp::path!("api" / "settings" / "safety")
+            .and(warp::put())
+            .and(body())
+            .and(self.with_player())
+            .map(|body: Bytes, player: HapticPlayer| {
+                let limits: SafetyLimits = match parse(&body) {
+                    Ok(limits) => limits,
+                    Err(failure) => return failure.into_response(),
+                };
+
+                match limits.validate() {
+                    Ok(()) => {
+                        player.set_safety_limits(limits);
+                        warp::reply::json(&player.safety_limits()).into_response()
+                    },
+                    Err(why) => error(StatusCode::BAD_REQUEST, why),
+                }
+            });
+
+        let get_apps = warp::path!("api" / "settings" / "apps")
+            .and(warp::get())
+            .and(self.with_player())
+            .map(|player: HapticPlayer| warp::reply::json(&player.app_profiles()));
+
+        // Applies until the next restart, the settings file is left untouched
+        let put_apps = warp::path!("api" / "settings" / "apps")
+            .and(warp::put())
+            .and(body())
+            .and(self.with_player())
+            .map(|body: Bytes, player: HapticPlayer| {
+                let profiles: AppProfiles = match parse(&body) {
+                    Ok(profiles) => profiles,
+                    Err(failure) => return failure.into_response(),
+                };
+
+                match profiles.validate() {
+                    Ok(()) => {
+                        player.set_app_profiles(profiles);
+                        warp::reply::json(&player.app_profiles()).into_response()
+                    },
+                    Err(why) => error(StatusCode::UNPROCESSABLE_ENTITY, why),
+                }
+            });
+
+        get_calibration
+            .or(put_calibration)
+            .or(list_calibrations)
+            .or(activate_calibration)
+            .or(get_safety)
+            .or(put_safety)
+            .or(get_apps)
+            .or(put_apps)
+    }
+}
+
+fn calibration_error(why: CalibrationError) -> warp::reply::Response {
+    let status = match why {
+        CalibrationError::NotFound(_) => StatusCode::NOT_FOUND,
+        CalibrationError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    error(status, why)
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use futures_util::{ stream::{ self, BoxStream }, SinkExt, StreamExt };
+use haptic_lib::BodyPart;
+use serde::{ Serialize, Deserialize };
+use tokio_stream::wrappers::{ errors::BroadcastStreamRecvError, BroadcastStream, IntervalStream };
+use tokio_util::sync::CancellationToken;
+use warp::{
+    self,
+    sse,
+    ws::{ Message, WebSocket, Ws },
+    Filter, Reply, Rejection,
+};
+
+use crate::{
+    bhaptics_studio::clients::{ ClientEvent, Clients },
+    haptics::{
+        events::PlayerEvent,
+        model::HapticFrame,
+        player::HapticPlayer,
+    },
+};
+
+use super::ControlApi;
+
+/// States sent per second when the subscriber does not ask for a rate.
+pub const DEFAULT_STREAM_RATE: u32 = 30;
+
+/// The player writes a frame every 20 ms, more states would repeat themselves.
+const MAX_STREAM_RATE: u32 = 50;
+
+/// Close code sent to the subscribers when the server shuts down, "going away".
+const SHUTDOWN_CLOSE_CODE: u16 = 1001;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+struct StreamQuery {
+    /// States per second
+    rate: Option<u32>,
+}
+
+/// Message of the live stream.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamMessage {
+    /// What the wearer feels: the frame last written to the devices, with every body part
+    State { panicked: bool, frame: HapticFrame },
+
+    Event(StreamEvent),
+
+    /// The subscriber fell behind and missed `missed` events
+    Lagged { missed: u64 },
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum StreamEvent {
+    Player(PlayerEvent),
+    Client(ClientEvent),
+}
+
+impl StreamMessage {
+    /// Name of the server-sent event carrying the message.
+    fn kind(&self) -> &'static str {
+        match self {
+            StreamMessage::State { .. } => "state",
+            StreamMessage::Event(_) => "event",
+            StreamMessage::Lagged { .. } => "lagged",
+        }
+    }
+}
+
+impl ControlApi {
+    pub(super) fn stream_routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        let player = self.player.clone();
+        let clients = self.clients.clone();
+        let shutdown = self.shutdown.clone();
+        let with_messages = warp::query::<StreamQuery>().map(move |query: StreamQuery| {
+            let rate = query.rate.unwrap_or(DEFAULT_STREAM_RATE).clamp(1, MAX_STREAM_RATE);
+            (messages(&player, &clients, rate), shutdown.clone())
+        });
+
+        let websocket = warp::path!("api" / "stream")
+            .and(warp::ws())
+            .and(with_messages.clone())
+            .map(|ws: Ws, (messages, shutdown): (BoxStream<'static, StreamMessage>, CancellationToken)| {
+                ws.on_upgrade(move |socket| stream_websocket(socket, messages, shutdown))
+            });
+
+        let events = warp::path!("api" / "stream" / "events")
+            .and(warp::get())
+            .and(with_messages)
+            .map(|(messages, shutdown): (BoxStream<'static, StreamMessage>, CancellationToken)| {
+                let events = messages
+                    .take_until(shutdown.cancelled_owned())
+                    .map(|message| sse::Event::default().event(message.kind()).json_data(&message));
+
+                sse::reply(sse::keep_alive().stream(events))
+            });
+
+        websocket.or(events)
+    }
+}
+
+/// States at `rate` per second, along with the events of the player and of the clients.
+fn messages(player: &HapticPlayer, clients: &Clients, rate: u32) -> BoxStream<'static, StreamMessage> {
+    let events = stream::select(
+        BroadcastStream::new(player.subscribe()).map(|event| event.map(StreamEvent::Player)),
+        BroadcastStream::new(clients.subscribe()).map(|event| event.map(StreamEvent::Client)),
+    )
+    .map(|event| match event {
+        Ok(event) => StreamMessage::Event(event),
+        Err(BroadcastStreamRecvError::Lagged(missed)) => StreamMessage::Lagged { missed },
+    });
+
+    let mut interval = tokio::time::interval(Duration::from_secs(1) / rate);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    let player = player.clone();
+    let states = IntervalStream::new(interval).map(move |_| {
+        let mut frame = HapticFrame::silent(&BodyPart::ALL);
+        frame.mix(&player.output(), 1.0);
+
+        StreamMessage::State { panicked: player.is_panicked(), frame }
+    });
+
+    stream::select(states, events).boxed()
+}
+
+async fn stream_websocket(socket: WebSocket, mut messages: BoxStream<'static, StreamMessage>, shutdown: CancellationToken) {
+    let (mut tx, mut rx) = socket.split();
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                let _ = tx.send(Message::close_with(SHUTDOWN_CLOSE_CODE, "server shutting down")).await;
+                break;
+            },
+            message = messages.next() => {
+                let Some(message) = message else { break };
+                let text = serde_json::to_string(&message).expect("stream messages serialize to JSON");
+                if tx.send(Message::text(text)).await.is_err() {
+                    break;
+                }
+            },
+            // Subscribers have nothing to say, anything but a close frame is ignored
+            incoming = rx.next() => match incoming {
+                Some(Ok(message)) if !message.is_close() => {},
+                _ => break,
+            },
+        }
+    }
+}
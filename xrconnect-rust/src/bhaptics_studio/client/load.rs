@@ -0,0 +1,269 @@
+//! Many simulated applications connected at once, to measure how the player keeps up.
+//!
+//! Every application gets its own connection, with `app_id` `<app_id>.<index>` when there are
+//! several, registers the same `.tact` files then follows its own run of the [`Schedule`]. The latency of a request
+//! is the time from sending it to receiving a [`PlayerResponse`](super::PlayerResponse) reflecting it.
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{ Duration, Instant },
+};
+
+use tokio_util::sync::CancellationToken;
+
+use super::{ schedule::Schedule, BHapticsClient, ClientError, TactFile };
+
+/// Errors kept in a report, the first ones being telling enough.
+const REPORTED_ERRORS: usize = 10;
+
+/// Applications following a schedule, see the [module](self) documentation.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    /// `ws://host:port` of the player
+    address: String,
+
+    app_id: String,
+    app_name: String,
+    apps: usize,
+    tact_files: Vec<TactFile>,
+    schedule: Schedule,
+
+    /// Applications disconnect after this long, or once their schedule ends
+    duration: Option<Duration>,
+}
+
+impl Simulation {
+    pub fn new(address: &str, schedule: Schedule) -> Self {
+        Self {
+            address: address.to_string(),
+            app_id: String::from("xrconnect.client"),
+            app_name: String::from("XRConnect Client"),
+            apps: 1,
+            tact_files: vec![],
+            schedule,
+            duration: None,
+        }
+    }
+
+    pub fn with_app_id(mut self, id: &str) -> Self {
+        self.app_id = id.to_string();
+        self
+    }
+
+    pub fn with_app_name(mut self, name: &str) -> Self {
+        self.app_name = name.to_string();
+        self
+    }
+
+    /// Number of applications connected at once, one by default.
+    pub fn with_apps(mut self, apps: usize) -> Self {
+        self.apps = apps;
+        self
+    }
+
+    pub fn with_tact_files(mut self, tact_files: Vec<TactFile>) -> Self {
+        self.tact_files = tact_files;
+        self
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// `app_id` of the application `index`, the base one when there is a single application.
+    fn app_id(&self, index: usize) -> String {
+        match self.apps {
+            1 => self.app_id.clone(),
+            _ => format!("{}.{}", self.app_id, index),
+        }
+    }
+
+    /// Runs every application until its schedule ends, the duration elapses or `stop` is cancelled.
+    pub async fn run(self, stop: CancellationToken) -> SimulationReport {
+        let started = Instant::now();
+        let simulation = Arc::new(self);
+
+        let tasks = (0..simulation.apps)
+            .map(|index| {
+                let simulation = simulation.clone();
+                let stop = stop.clone();
+                tokio::spawn(async move { simulation.run_app(index, stop).await })
+            })
+            .collect::<Vec<_>>();
+
+        let mut report = SimulationReport {
+            apps: simulation.apps,
+            ..Default::default()
+        };
+        for task in tasks {
+            match task.await {
+                Ok(outcome) => report.add(outcome),
+                Err(why) => report.add(AppOutcome {
+                    errors: vec![format!("task failed: {}", why)],
+                    ..Default::default()
+                }),
+            }
+        }
+
+        report.latencies.sort();
+        report.elapsed = started.elapsed();
+        report
+    }
+
+    async fn run_app(&self, index: usize, stop: CancellationToken) -> AppOutcome {
+        let mut outcome = AppOutcome::default();
+        let app_id = self.app_id(index);
+        let label = |why: ClientError| format!("{}: {}", app_id, why);
+
+        let mut client = match BHapticsClient::connect(&self.address, &app_id, &self.app_name).await {
+            Ok(client) => client,
+            Err(why) => {
+                outcome.errors.push(label(why));
+                return outcome;
+            },
+        };
+        outcome.connected = true;
+        let connected = Instant::now();
+
+        for tact in &self.tact_files {
+            match client.register(tact).await {
+                Ok(latency) => {
+                    outcome.registrations += 1;
+                    outcome.latencies.push(latency);
+                },
+                Err(why) => outcome.errors.push(label(why)),
+            }
+        }
+
+        for (at, submission) in self.schedule.submissions(index) {
+            // Applications falling behind their schedule stop on time all the same
+            if self.duration.is_some_and(|duration| at.max(connected.elapsed()) >= duration) {
+                break;
+            }
+
+            let idle = tokio::select! {
+                idle = client.idle_until(connected + at) => idle,
+                _ = stop.cancelled() => break,
+            };
+
+            let result = match idle {
+                Ok(()) => client.submit(&submission).await,
+                Err(why) => Err(why),
+            };
+
+            outcome.submissions += 1;
+            match result {
+                Ok(latency) => outcome.latencies.push(latency),
+                Err(ClientError::Closed) => {
+                    outcome.errors.push(label(ClientError::Closed));
+                    break;
+                },
+                Err(why) => outcome.errors.push(format!("{}: {}: {}", app_id, submission.kind(), why)),
+            }
+        }
+
+        outcome.responses = client.responses();
+        client.close().await;
+        outcome
+    }
+}
+
+/// What a single application went through.
+#[derive(Default)]
+struct AppOutcome {
+    connected: bool,
+    registrations: usize,
+    submissions: usize,
+    responses: u64,
+    latencies: Vec<Duration>,
+    errors: Vec<String>,
+}
+
+/// Results of a [`Simulation`], printed as a summary.
+#[derive(Clone, Debug, Default)]
+pub struct SimulationReport {
+    pub apps: usize,
+
+    /// Applications which could connect
+    pub connected: usize,
+
+    pub registrations: usize,
+    pub submissions: usize,
+
+    /// Responses received, every one checked
+    pub responses: u64,
+
+    /// Latency of every request which succeeded, registrations included, from the fastest
+    pub latencies: Vec<Duration>,
+
+    /// Number of errors, connections and requests failing
+    pub failures: usize,
+
+    /// First errors, naming the application
+    pub errors: Vec<String>,
+
+    pub elapsed: Duration,
+}
+
+impl SimulationReport {
+    fn add(&mut self, outcome: AppOutcome) {
+        self.connected += outcome.connected as usize;
+        self.registrations += outcome.registrations;
+        self.submissions += outcome.submissions;
+        self.responses += outcome.responses;
+        self.latencies.extend(outcome.latencies);
+        self.failures += outcome.errors.len();
+
+        let room = REPORTED_ERRORS.saturating_sub(self.errors.len());
+        self.errors.extend(outcome.errors.into_iter().take(room));
+    }
+
+    /// Whether every application connected and every request was reflected in time.
+    pub fn is_success(&self) -> bool {
+        self.failures == 0
+    }
+
+    /// Latency under which `percentile`, from 0 to 100, of the requests were.
+    pub fn latency(&self, percentile: f64) -> Option<Duration> {
+        let last = self.latencies.len().checked_sub(1)?;
+        let index = ((percentile / 100.0) * last as f64).round() as usize;
+        self.latencies.get(index.min(last)).copied()
+    }
+
+    /// Requests which succeeded, per second.
+    pub fn throughput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            seconds if seconds > 0.0 => self.latencies.len() as f64 / seconds,
+            _ => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = |latency: Option<Duration>| latency.map(|latency| latency.as_secs_f64() * 1000.0).unwrap_or_default();
+
+        writeln!(f, "applications   {} connected of {}", self.connected, self.apps)?;
+        writeln!(f, "registrations  {}", self.registrations)?;
+        writeln!(f, "submissions    {}", self.submissions)?;
+        writeln!(f, "responses      {} checked", self.responses)?;
+        writeln!(f, "failures       {}", self.failures)?;
+        writeln!(f, "throughput     {:.1} requests/s over {:.1} s", self.throughput(), self.elapsed.as_secs_f64())?;
+        write!(
+            f,
+            "latency        p50 {:.2} ms, p90 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+            millis(self.latency(50.0)), millis(self.latency(90.0)), millis(self.latency(99.0)), millis(self.latency(100.0)),
+        )?;
+
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+        if self.failures > self.errors.len() {
+            write!(f, "\n  and {} more", self.failures - self.errors.len())?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+//! Client of `/v2/feedbacks`, connecting to a player the way a game using the bHaptics SDK does.
+//!
+//! [`BHapticsClient`] registers `.tact` files and submits effects, then waits for a
+//! [`PlayerResponse`] reflecting what was sent, every response being checked on the way.
+//! What is submitted, and when, comes from a [`schedule::Schedule`], and a
+//! [`load::Simulation`] runs many simulated applications at once.
+
+use std::{
+    fmt,
+    path::Path,
+    time::{ Duration, Instant },
+};
+
+use futures_util::{ SinkExt, StreamExt };
+use serde::{ Serialize, Deserialize };
+use serde_json::{ json, Value };
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    tungstenite::Message,
+    MaybeTlsStream,
+    WebSocketStream,
+};
+
+use super::tact::project::{ Project, ProjectError };
+
+pub use super::ws::v2::model::PlayerResponse;
+
+pub mod load;
+pub mod schedule;
+
+/// Time given to the player to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time given to the player to reflect a registration or a submission in its responses.
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientError {
+    /// The player could not be reached at the given address
+    Connect(String),
+
+    /// The player closed the connection
+    Closed,
+
+    /// No response reflected what was sent in time
+    Timeout(String),
+
+    /// A response is not a valid `PlayerResponse`
+    InvalidResponse(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Connect(why) => write!(f, "failed to connect: {}", why),
+            ClientError::Closed => write!(f, "connection closed by the player"),
+            ClientError::Timeout(expected) => write!(f, "no response with {} within {:?}", expected, RESPONSE_TIMEOUT),
+            ClientError::InvalidResponse(why) => write!(f, "invalid response: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+/// Dot of a submitted frame.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FrameDot {
+    pub index: u32,
+
+    /// From 0 to 100
+    pub intensity: u32,
+}
+
+/// Request a game submits, as written in scripts.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Submission {
+    /// Plays a registered key
+    Key {
+        key: String,
+
+        #[serde(default = "unit_scale")]
+        intensity: f32,
+
+        #[serde(default = "unit_scale")]
+        duration: f32,
+
+        /// Key the effect is active under, instead of `key`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        alt_key: Option<String>,
+    },
+
+    /// Plays dots on a position, such as `VestFront`
+    Frame {
+        key: String,
+        position: String,
+        dots: Vec<FrameDot>,
+        duration_millis: u32,
+    },
+
+    TurnOff {
+        key: String,
+    },
+
+    TurnOffAll,
+}
+
+fn unit_scale() -> f32 {
+    1.0
+}
+
+impl Submission {
+    /// `Type` of the request, as sent by the games.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Submission::Key { .. } => "key",
+            Submission::Frame { .. } => "frame",
+            Submission::TurnOff { .. } => "turnOff",
+            Submission::TurnOffAll => "turnOffAll",
+        }
+    }
+
+    /// Message sent to the player.
+    pub fn to_message(&self) -> Value {
+        let submit = match self {
+            Submission::Key { key, intensity, duration, alt_key } => json!({
+                "Type": "key",
+                "Key": key,
+                "Parameters": {
+                    "altKey": alt_key,
+                    "scaleOption": { "intensity": intensity, "duration": duration },
+                },
+            }),
+            Submission::Frame { key, position, dots, duration_millis } => json!({
+                "Type": "frame",
+                "Key": key,
+                "Frame": {
+                    "Position": position,
+                    "DotPoints": dots,
+                    "PathPoints": [],
+                    "DurationMillis": duration_millis,
+                },
+            }),
+            Submission::TurnOff { key } => json!({ "Type": "turnOff", "Key": key }),
+            Submission::TurnOffAll => json!({ "Type": "turnOffAll" }),
+        };
+
+        json!({ "Submit": [submit] })
+    }
+
+    /// What a response reflecting the submission shows, described for errors.
+    fn expectation(&self) -> String {
+        match self {
+            Submission::Key { key, alt_key, .. } => format!("{} active", alt_key.as_deref().unwrap_or(key)),
+            Submission::Frame { key, .. } => format!("{} active", key),
+            Submission::TurnOff { key } => format!("{} inactive", key),
+            Submission::TurnOffAll => String::from("no active key"),
+        }
+    }
+
+    fn is_reflected(&self, response: &PlayerResponse) -> bool {
+        let active = |key: &str| response.active_keys.iter().any(|active| active == key);
+
+        match self {
+            Submission::Key { key, alt_key, .. } => active(alt_key.as_deref().unwrap_or(key)),
+            Submission::Frame { key, .. } => active(key),
+            Submission::TurnOff { key } => !active(key),
+            Submission::TurnOffAll => response.active_keys.is_empty(),
+        }
+    }
+}
+
+/// `.tact` file registered by a client.
+#[derive(Clone, Debug)]
+pub struct TactFile {
+    /// Name of the file, without its extension
+    pub key: String,
+
+    /// Project as found in the file, sent as it is
+    pub project: Value,
+}
+
+impl TactFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProjectError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(ProjectError::Io)?;
+        Project::from_json(&content)?;
+
+        let mut file: Value = serde_json::from_str(&content).map_err(ProjectError::Parse)?;
+        let project = match file.get_mut("project") {
+            Some(project) => project.take(),
+            None => file,
+        };
+
+        Ok(Self {
+            key: path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+            project,
+        })
+    }
+}
+
+/// Connection to `/v2/feedbacks`, see the [module](self) documentation.
+pub struct BHapticsClient {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+
+    /// Responses received and checked so far
+    responses: u64,
+}
+
+impl BHapticsClient {
+    /// Connects to the player at `address`, `ws://host:port`, as the given application.
+    pub async fn connect(address: &str, app_id: &str, app_name: &str) -> Result<Self, ClientError> {
+        let base = address.trim_end_matches('/').trim_end_matches("/v2/feedbacks");
+        let query = serde_urlencoded::to_string([("app_id", app_id), ("app_name", app_name)]).unwrap_or_default();
+        let url = format!("{}/v2/feedbacks?{}", base, query);
+
+        let (socket, _) = tokio::time::timeout(CONNECT_TIMEOUT, tokio_tungstenite::connect_async(url.as_str()))
+            .await
+            .map_err(|_| ClientError::Connect(format!("{} timed out", base)))?
+            .map_err(|why| ClientError::Connect(format!("{}: {}", base, why)))?;
+
+        Ok(Self {
+            socket,
+            responses: 0,
+        })
+    }
+
+    /// Number of responses received so far, every one valid.
+    pub fn responses(&self) -> u64 {
+        self.responses
+    }
+
+    /// Registers a `.tact` file, returning the time it took to show in the registered keys.
+    pub async fn register(&mut self, tact: &TactFile) -> Result<Duration, ClientError> {
+        let message = json!({ "Register": [{ "Key": tact.key, "Project": tact.project }] });
+        let key = tact.key.as_str();
+
+        self.exchange(message, |response| response.registered_keys.iter().any(|registered| registered == key), || format!("{} registered", key)).await
+    }
+
+    /// Submits, returning the time it took to show in the active keys.
+    pub async fn submit(&mut self, submission: &Submission) -> Result<Duration, ClientError> {
+        self.exchange(submission.to_message(), |response| submission.is_reflected(response), || submission.expectation()).await
+    }
+
+    async fn exchange<F, D>(&mut self, message: Value, reflected: F, expectation: D) -> Result<Duration, ClientError>
+    where
+        F: Fn(&PlayerResponse) -> bool,
+        D: FnOnce() -> String,
+    {
+        let sent = Instant::now();
+        self.socket.send(Message::Text(message.to_string())).await.map_err(|_| ClientError::Closed)?;
+
+        let deadline = tokio::time::Instant::from_std(sent + RESPONSE_TIMEOUT);
+        loop {
+            let Ok(response) = tokio::time::timeout_at(deadline, self.next_response()).await else {
+                return Err(ClientError::Timeout(expectation()));
+            };
+
+            if reflected(&response?) {
+                return Ok(sent.elapsed());
+            }
+        }
+    }
+
+    /// Next response of the player, checked.
+    pub async fn next_response(&mut self) -> Result<PlayerResponse, ClientError> {
+        loop {
+            match self.socket.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    let response: PlayerResponse = serde_json::from_str(&text)
+                        .map_err(|why| ClientError::InvalidResponse(why.to_string()))?;
+                    response.validate().map_err(ClientError::InvalidResponse)?;
+                    self.responses += 1;
+                    return Ok(response);
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Err(ClientError::Closed),
+                Some(Ok(_)) => {},
+            }
+        }
+    }
+
+    /// Checks the responses the player sends on its own until `deadline`.
+    pub async fn idle_until(&mut self, deadline: Instant) -> Result<(), ClientError> {
+        let deadline = tokio::time::Instant::from_std(deadline);
+        loop {
+            match tokio::time::timeout_at(deadline, self.next_response()).await {
+                Ok(response) => { response?; },
+                Err(_) => return Ok(()),
+            }
+        }
+    }
+
+    pub async fn close(mut self) {
+        let _ = self.socket.close(None).await;
+    }
+}
@@ -0,0 +1,171 @@
+//! What a simulated application submits, and when: steps of a script, or random submissions.
+//!
+//! # Example Script
+//! ```json
+//! {
+//!    "steps": [
+//!       { "at_millis": 0, "type": "key", "key": "hit", "intensity": 0.8 },
+//!       { "at_millis": 250, "type": "frame", "key": "pulse", "position": "VestFront",
+//!         "dots": [{ "index": 0, "intensity": 100 }], "duration_millis": 200 },
+//!       { "at_millis": 500, "type": "turnOff", "key": "hit" },
+//!       { "at_millis": 1000, "type": "turnOffAll" }
+//!    ]
+//! }
+//! ```
+
+use std::{
+    fmt,
+    fs,
+    io,
+    path::Path,
+    time::Duration,
+};
+
+use rand::{ rngs::StdRng, seq::SliceRandom, Rng, SeedableRng };
+use serde::{ Serialize, Deserialize };
+
+use super::{ FrameDot, Submission };
+use crate::{
+    bhaptics_studio::ws::v2::model::PositionType,
+    haptics::model::MotorLayout,
+};
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Io(why) => write!(f, "failed to read script: {}", why),
+            ScriptError::Parse(why) => write!(f, "failed to parse script: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScriptStep {
+    /// Milliseconds since the application connected
+    pub at_millis: u64,
+
+    #[serde(flatten)]
+    pub submission: Submission,
+}
+
+/// Submissions at fixed times, see the [module](self) documentation.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Script {
+    pub steps: Vec<ScriptStep>,
+}
+
+impl Script {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScriptError> {
+        let content = fs::read_to_string(path).map_err(ScriptError::Io)?;
+        Self::from_json(&content)
+    }
+
+    /// Parses a script, its steps being sorted by time.
+    pub fn from_json(content: &str) -> Result<Self, ScriptError> {
+        let mut script: Self = serde_json::from_str(content).map_err(ScriptError::Parse)?;
+        script.steps.sort_by_key(|step| step.at_millis);
+        Ok(script)
+    }
+}
+
+/// Submissions at random times, as a Poisson process, mostly of registered keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RandomSchedule {
+    /// Submissions per second, on average
+    pub rate: f64,
+
+    /// Registered keys played and turned off
+    pub keys: Vec<String>,
+
+    /// Same submissions for the same seed, different ones on every run when absent
+    pub seed: Option<u64>,
+}
+
+impl RandomSchedule {
+    pub fn new(rate: f64, keys: Vec<String>) -> Self {
+        Self {
+            rate,
+            keys,
+            seed: None,
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    fn submission(&self, rng: &mut StdRng) -> Submission {
+        let roll: f64 = rng.gen();
+
+        match self.keys.choose(rng) {
+            Some(key) if roll < 0.7 => Submission::Key {
+                key: key.clone(),
+                intensity: rng.gen_range(0.5..=1.0),
+                duration: 1.0,
+                alt_key: None,
+            },
+            Some(key) if roll < 0.8 => Submission::TurnOff { key: key.clone() },
+            _ if roll < 0.95 => {
+                let position = PositionType::REPORTED.choose(rng).copied().unwrap_or(PositionType::VestFront);
+                // Dots on motors the device has, like games do
+                let motor_count = position.body_parts().first().map_or(1, |part| MotorLayout::default_for(*part).len()) as u32;
+                let dots = (0..rng.gen_range(1..=3))
+                    .map(|_| FrameDot {
+                        index: rng.gen_range(0..motor_count),
+                        intensity: rng.gen_range(20..=100),
+                    })
+                    .collect();
+
+                Submission::Frame {
+                    key: format!("frame-{}", position.name()),
+                    position: position.name().to_string(),
+                    dots,
+                    duration_millis: rng.gen_range(100..=500),
+                }
+            },
+            _ => Submission::TurnOffAll,
+        }
+    }
+}
+
+/// What a simulated application submits, and when.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schedule {
+    Script(Script),
+    Random(RandomSchedule),
+}
+
+impl Schedule {
+    /// Submissions of the application `index`, each with its time since the application
+    /// connected, ending with the script or, for random schedules, never.
+    pub fn submissions(&self, index: usize) -> Box<dyn Iterator<Item = (Duration, Submission)> + Send> {
+        match self {
+            Schedule::Script(script) => Box::new(script.steps.clone().into_iter()
+                .map(|step| (Duration::from_millis(step.at_millis), step.submission))),
+            Schedule::Random(random) => {
+                let random = random.clone();
+                let mut rng = match random.seed {
+                    Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(index as u64)),
+                    None => StdRng::from_entropy(),
+                };
+                let mut at = Duration::ZERO;
+
+                Box::new(std::iter::from_fn(move || {
+                    // Exponential intervals between the submissions
+                    let interval = -(1.0 - rng.gen::<f64>()).ln() / random.rate;
+                    at += Duration::from_secs_f64(interval);
+                    Some((at, random.submission(&mut rng)))
+                }))
+            },
+        }
+    }
+}
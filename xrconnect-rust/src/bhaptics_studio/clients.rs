@@ -0,0 +1,114 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{ AtomicU64, Ordering },
+        Arc, Mutex,
+    },
+    time::{ SystemTime, UNIX_EPOCH },
+};
+
+use serde::{ Serialize, Deserialize };
+use tokio::sync::broadcast;
+
+use super::server::BHapticsAppInfo;
+use crate::haptics::events::EventSender;
+
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(1);
+
+/// Client connected to the bHaptics WebSocket.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConnectedClient {
+    /// Unique for the lifetime of the process
+    pub id: u64,
+
+    pub app_id: String,
+    pub app_name: String,
+
+    /// Namespace the keys of the client live in
+    pub namespace: String,
+
+    /// Seconds since the Unix epoch
+    pub connected_at: u64,
+}
+
+/// Client connecting to or disconnecting from the bHaptics WebSocket.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ClientEvent {
+    ClientConnected { client: ConnectedClient },
+    ClientDisconnected { client: ConnectedClient },
+}
+
+/// Clients currently connected, shared by the WebSocket behavior and the control API.
+///
+/// Cloning is cheap, clones share the same clients.
+#[derive(Clone, Default)]
+pub struct Clients {
+    clients: Arc<Mutex<BTreeMap<u64, ConnectedClient>>>,
+    events: EventSender<ClientEvent>,
+}
+
+impl Clients {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Identifier for a new connection.
+    pub fn next_id() -> u64 {
+        NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Registers a client until the returned guard is dropped.
+    pub fn connect(&self, id: u64, app_info: &BHapticsAppInfo, namespace: &str) -> ClientGuard {
+        let connected_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+
+        let client = ConnectedClient {
+            id,
+            app_id: app_info.id().to_string(),
+            app_name: app_info.name().to_string(),
+            namespace: namespace.to_string(),
+            connected_at,
+        };
+
+        self.clients.lock().unwrap().insert(id, client.clone());
+        self.events.send(ClientEvent::ClientConnected { client });
+
+        ClientGuard {
+            clients: self.clone(),
+            id,
+        }
+    }
+
+    /// Connected clients, oldest first.
+    pub fn list(&self) -> Vec<ConnectedClient> {
+        self.clients.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Receives the clients connecting and disconnecting from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.events.subscribe()
+    }
+}
+
+/// Keeps a client listed in [`Clients`] while alive.
+pub struct ClientGuard {
+    clients: Clients,
+    id: u64,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        let client = self.clients.clients.lock().unwrap().remove(&self.id);
+        if let Some(client) = client {
+            self.clients.events.send(ClientEvent::ClientDisconnected { client });
+        }
+    }
+}
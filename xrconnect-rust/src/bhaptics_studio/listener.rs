@@ -0,0 +1,122 @@
+use std::{
+    fmt,
+    net::SocketAddr,
+    path::PathBuf,
+};
+
+use futures_util::future::BoxFuture;
+use tracing::{ info, warn };
+use warp::{ Filter, Rejection, Reply };
+
+use crate::tls::TlsConfig;
+
+/// Where the server accepts connections, every listener serves the same routes and player.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Listener {
+    /// Plain TCP, IPv4 or IPv6. On most systems `[::]` also accepts IPv4 connections,
+    /// and cannot be listened on along with `0.0.0.0` on the same port
+    Tcp(SocketAddr),
+
+    /// TLS (`wss://`) over TCP
+    Tls(TlsConfig),
+
+    /// Unix domain socket, for local tools. Only the current user may connect to it,
+    /// and the access policy does not apply to it
+    Unix(PathBuf),
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(address) => write!(f, "http://{}", address),
+            Listener::Tls(tls) => write!(f, "https://{}", tls.address),
+            Listener::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl Listener {
+    /// Starts listening, the returned future serves `routes` until dropped.
+    pub(crate) fn serve<F>(&self, routes: F) -> Result<BoxFuture<'static, ()>, String>
+    where
+        F: Filter<Error = Rejection> + Clone + Send + Sync + 'static,
+        F::Extract: Reply,
+    {
+        match self {
+            Listener::Tcp(address) => {
+                let (address, server) = warp::serve(routes)
+                    .try_bind_ephemeral(*address)
+                    .map_err(|why| why.to_string())?;
+                info!("Listening on http://{}", address);
+                Ok(Box::pin(server))
+            },
+            Listener::Tls(tls) => {
+                let (cert, key) = tls.load().map_err(|why| why.to_string())?;
+                // Stops when dropped, like the other listeners, rather than on a signal
+                let (address, server) = warp::serve(routes)
+                    .tls()
+                    .cert(cert)
+                    .key(key)
+                    .try_bind_with_graceful_shutdown(tls.address, std::future::pending())
+                    .map_err(|why| why.to_string())?;
+                info!("Listening on https://{}", address);
+                Ok(Box::pin(server))
+            },
+            #[cfg(unix)]
+            Listener::Unix(path) => {
+                let listener = bind_unix(path)?;
+                info!("Listening on unix:{}", path.display());
+
+                let incoming = tokio_stream::wrappers::UnixListenerStream::new(listener);
+                Ok(Box::pin(warp::serve(routes).run_incoming(incoming)))
+            },
+            #[cfg(not(unix))]
+            Listener::Unix(_) => Err(String::from("Unix domain sockets are not supported on this platform")),
+        }
+    }
+
+    /// Removes what listening left behind, i.e. the socket file of Unix domain sockets.
+    pub(crate) fn cleanup(&self) {
+        if let Listener::Unix(path) = self {
+            if let Err(why) = std::fs::remove_file(path) {
+                warn!("Failed to remove {}: {}", path.display(), why);
+            }
+        }
+    }
+}
+
+/// Binds a Unix domain socket only the current user may connect to.
+#[cfg(unix)]
+pub(crate) fn bind_unix(path: &std::path::Path) -> Result<tokio::net::UnixListener, String> {
+    use std::os::unix::fs::{ DirBuilderExt, FileTypeExt, PermissionsExt };
+
+    // Left over by a previous run which did not shut down cleanly, unless something still listens on it
+    if std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(String::from("another process is listening on it"));
+        }
+        std::fs::remove_file(path).map_err(|why| why.to_string())?;
+    }
+
+    if std::fs::symlink_metadata(path).is_ok() {
+        return Err(String::from("a file is in the way"));
+    }
+
+    // Bound in a directory only the current user may enter, then moved into place once private,
+    // so that nobody can connect in between
+    let file_name = path.file_name().ok_or_else(|| String::from("not a file path"))?;
+    let staging = path.with_file_name(format!(".{}.{}", file_name.to_string_lossy(), std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::DirBuilder::new().mode(0o700).create(&staging).map_err(|why| why.to_string())?;
+
+    let staged = staging.join("socket");
+    let bound = tokio::net::UnixListener::bind(&staged)
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+            std::fs::rename(&staged, path)?;
+            Ok(listener)
+        })
+        .map_err(|why| why.to_string());
+    let _ = std::fs::remove_dir_all(&staging);
+    bound
+}
@@ -1,32 +1,42 @@
 use crate::{
     haptics::{
-        player::{ HapticPlayer },
+        player::{ HapticPlayer, PlayerError },
     },
-    bhaptics_studio::tact::{
-        PlayerRequest, PlayerRegisterRequest, PlayerSubmitRequest,
+    bhaptics_studio::{
+        namespace::KeyNamespace,
+        tact::{ PlayerRequest, PlayerRegisterRequest, PlayerSubmitRequest },
     },
 };
 
+use tracing::warn;
+
+pub mod client;
+pub mod clients;
+pub mod listener;
+pub mod namespace;
+pub mod proxy;
 pub mod tact;
 pub mod server;
+pub mod session;
 
 mod ws;
 
-trait BHapticsStudioPlayer {
-    fn handle_request(&self, request: PlayerRequest) -> Result<(), ()>;
+pub trait BHapticsStudioPlayer {
+    /// Handles a request of a client whose keys live in `namespace`.
+    fn handle_request(&self, request: PlayerRequest, namespace: &KeyNamespace) -> Result<(), PlayerError>;
 }
 
 impl BHapticsStudioPlayer for HapticPlayer {
-    fn handle_request(&self, request: PlayerRequest) -> Result<(), ()> {
+    fn handle_request(&self, request: PlayerRequest, namespace: &KeyNamespace) -> Result<(), PlayerError> {
         match request {
             PlayerRequest::Register(registers) => {
                 for register in registers {
-                    self.handle_register_request(register)?;
+                    self.handle_register_request(register, namespace)?;
                 }
             }
             PlayerRequest::Submit(submits) => {
                 for submit in submits {
-                    self.handle_submit_request(submit)?;
+                    self.handle_submit_request(submit, namespace)?;
                 }
             }
         }
@@ -36,10 +46,39 @@ impl BHapticsStudioPlayer for HapticPlayer {
 }
 
 impl HapticPlayer {
-    fn handle_register_request(&self, request: PlayerRegisterRequest) -> Result<(), ()> {
+    fn handle_register_request(&self, request: PlayerRegisterRequest, namespace: &KeyNamespace) -> Result<(), PlayerError> {
+        self.register(namespace.scope(&request.key), request.project.to_pattern());
         Ok(())
     }
-    fn handle_submit_request(&self, request: PlayerSubmitRequest) -> Result<(), ()> {
+
+    fn handle_submit_request(&self, request: PlayerSubmitRequest, namespace: &KeyNamespace) -> Result<(), PlayerError> {
+        match request {
+            // Only the effects of the client itself are turned off
+            PlayerSubmitRequest::TurnOffAll => self.stop_where(|key| namespace.contains(key)),
+            PlayerSubmitRequest::TurnOff { key } => self.stop(&namespace.scope(&key)),
+            PlayerSubmitRequest::SubmitFrame { key, frame } => match frame.to_pattern() {
+                Ok(pattern) => self.play_pattern_for(namespace.app(), namespace.scope(&key), pattern, Default::default())?,
+                // Unknown positions are ignored, like the official player does
+                Err(why) => warn!("Ignoring frame {:?}: {}", key, why),
+            },
+            PlayerSubmitRequest::SubmitRegistered { key, parameters } => {
+                let active_key = namespace.scope(parameters.alt_key.as_deref().unwrap_or(&key));
+                let pattern_key = namespace.scope(&key);
+
+                match &parameters.rotation_option {
+                    Some(rotation) if !rotation.is_identity() => {
+                        let pattern = self.pattern(&pattern_key).ok_or_else(|| PlayerError::UnknownKey(key.clone()))?;
+                        self.play_pattern_for(namespace.app(), active_key, rotation.apply(&pattern), parameters.playback_options())?;
+                    },
+                    // Errors report the key as the client knows it
+                    _ => self.play_for(namespace.app(), &pattern_key, active_key, parameters.playback_options()).map_err(|why| match why {
+                        PlayerError::UnknownKey(_) => PlayerError::UnknownKey(key.clone()),
+                        why => why,
+                    })?,
+                }
+            },
+        }
+
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,76 @@
+use serde::{ Serialize, Deserialize };
+
+use super::server::BHapticsAppInfo;
+
+/// How the keys of bHaptics clients are kept apart.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NamespaceMode {
+    /// Every connection has its own keys, forgotten once it disconnects
+    #[default]
+    PerConnection,
+
+    /// Connections with the same `app_id` share their keys, kept across reconnects
+    PerApp,
+}
+
+/// Scope of the keys of a client.
+///
+/// Projects registered and effects played by a client live under `<name>/` in the
+/// player, so that two applications registering the same key do not clash. Names are
+/// escaped to never contain `/`, e.g. `app:a%2Fb/` for the application `a/b`, which would
+/// otherwise live within the namespace of the application `a`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyNamespace {
+    prefix: String,
+
+    /// Application the keys belong to
+    app: Option<String>,
+}
+
+impl KeyNamespace {
+    pub fn new(name: impl AsRef<str>) -> Self {
+        let name = name.as_ref().replace('%', "%25").replace('/', "%2F");
+
+        Self {
+            prefix: format!("{}/", name),
+            app: None,
+        }
+    }
+
+    /// Namespace of the client connection `connection` of `app_info`.
+    pub fn for_client(mode: NamespaceMode, connection: u64, app_info: &BHapticsAppInfo) -> Self {
+        let namespace = match mode {
+            NamespaceMode::PerConnection => Self::new(format!("connection:{}", connection)),
+            NamespaceMode::PerApp => Self::new(format!("app:{}", app_info.id())),
+        };
+
+        namespace.with_app(app_info.id())
+    }
+
+    pub fn with_app(mut self, app: impl Into<String>) -> Self {
+        self.app = Some(app.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.prefix[..self.prefix.len() - 1]
+    }
+
+    pub fn app(&self) -> Option<&str> {
+        self.app.as_deref()
+    }
+
+    /// Player key of the client key `key`.
+    pub fn scope(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// Client key of the player key `key`, if it belongs to this namespace.
+    pub fn unscope<'a>(&self, key: &'a str) -> Option<&'a str> {
+        key.strip_prefix(&self.prefix)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        key.starts_with(&self.prefix)
+    }
+}
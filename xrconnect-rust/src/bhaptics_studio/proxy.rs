@@ -0,0 +1,409 @@
+//! Bridge to an upstream bHaptics Player, such as the official one driving genuine hardware.
+//!
+//! Every client gets its own connection to the upstream `/v2/feedbacks`, with the same
+//! `app_id` and `app_name`. Messages of the client are forwarded upstream, after the
+//! [`ProxyRules`] are applied, and the `PlayerResponse` of the upstream are relayed back
+//! as they are. Forwarded messages can be played on the local devices as well.
+//!
+//! # Example Rules
+//! ```json
+//! {
+//!    "drop": ["frame"],
+//!    "intensity": 0.5
+//! }
+//! ```
+
+use std::{
+    fmt,
+    time::Duration,
+};
+
+use futures_util::{ stream::SplitSink, SinkExt, Stream, StreamExt };
+use serde::{ Serialize, Deserialize };
+use serde_json::{ Map, Value };
+use tokio_tungstenite::tungstenite::{
+    self,
+    protocol::{ frame::coding::CloseCode, CloseFrame },
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{ debug, error, info };
+use warp::ws::{ Message, WebSocket };
+
+use super::{
+    namespace::KeyNamespace,
+    server::BHapticsAppInfo,
+    session::SessionEvent,
+    tact::{ PlayerRequest, PlayerSubmitRequest },
+    ws::v2::behavior::{ count_messages, ClientEffects },
+    BHapticsStudioPlayer,
+};
+use crate::haptics::player::HapticPlayer;
+
+/// Time given to the upstream to accept a connection.
+const UPSTREAM_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Close code sent to the clients when the upstream cannot be reached, or goes away, "internal error".
+const UPSTREAM_CLOSE_CODE: u16 = 1011;
+
+/// Close code sent to the clients when the server shuts down, "going away".
+const SHUTDOWN_CLOSE_CODE: u16 = 1001;
+
+/// Intensity of submitted frames goes from 0 to 100.
+const FRAME_INTENSITY_MAX: f64 = 100.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProxyError {
+    /// The upstream is not a `ws://host:port` address
+    InvalidUpstream(String),
+
+    /// Intensity factor of the rules is negative or not a number
+    InvalidIntensity(f32),
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::InvalidUpstream(upstream) => write!(f, "invalid upstream {}, expected ws://host:port", upstream),
+            ProxyError::InvalidIntensity(intensity) => write!(f, "intensity {} is not a non-negative number", intensity),
+        }
+    }
+}
+
+impl std::error::Error for ProxyError {}
+
+/// What is changed in the messages forwarded upstream.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ProxyRules {
+    /// Messages dropped instead of being forwarded, by type: `Register`, `key`, `frame`, `turnOff` or `turnOffAll`
+    pub drop: Vec<String>,
+
+    /// Factor applied to the intensity of the effects started
+    pub intensity: f32,
+}
+
+impl Default for ProxyRules {
+    fn default() -> Self {
+        Self {
+            drop: vec![],
+            intensity: 1.0,
+        }
+    }
+}
+
+impl ProxyRules {
+    /// Whether messages are forwarded as they are.
+    pub fn is_identity(&self) -> bool {
+        self.drop.is_empty() && self.intensity == 1.0
+    }
+
+    pub fn validate(&self) -> Result<(), ProxyError> {
+        if !(self.intensity.is_finite() && self.intensity >= 0.0) {
+            return Err(ProxyError::InvalidIntensity(self.intensity));
+        }
+
+        Ok(())
+    }
+
+    fn drops(&self, kind: &str) -> bool {
+        self.drop.iter().any(|dropped| dropped == kind)
+    }
+
+    /// Message to forward in place of `message`, `None` when nothing is left of it.
+    ///
+    /// Fields the rules do not touch are kept, as are messages which are not valid
+    /// requests, the upstream decides what to do with them.
+    pub fn apply(&self, message: &str) -> Option<String> {
+        if self.is_identity() {
+            return Some(message.to_string());
+        }
+
+        let Ok(Value::Object(mut request)) = serde_json::from_str::<Value>(message) else {
+            return Some(message.to_string());
+        };
+
+        if self.drops("Register") {
+            request.remove("Register");
+        }
+
+        if let Some(Value::Array(submits)) = request.get_mut("Submit") {
+            submits.retain_mut(|submit| self.apply_submit(submit));
+            if submits.is_empty() {
+                request.remove("Submit");
+            }
+        }
+
+        if request.is_empty() {
+            return None;
+        }
+
+        Some(Value::Object(request).to_string())
+    }
+
+    /// Changes a submission as the rules say, false when it is dropped.
+    fn apply_submit(&self, submit: &mut Value) -> bool {
+        let Ok(parsed) = serde_json::from_value::<PlayerSubmitRequest>(submit.clone()) else {
+            return true;
+        };
+
+        if self.drops(parsed.kind()) {
+            return false;
+        }
+
+        if self.intensity == 1.0 {
+            return true;
+        }
+
+        let Value::Object(submit) = submit else {
+            return true;
+        };
+
+        match parsed {
+            PlayerSubmitRequest::SubmitRegistered { parameters, .. } => {
+                let scale = parameters.scale_option.unwrap_or_default();
+                let parameters = object_field(submit, &["Parameters", "parameters"]);
+                parameters.insert(String::from("scaleOption"), serde_json::json!({
+                    "intensity": scale.intensity * self.intensity,
+                    "duration": scale.duration,
+                }));
+            },
+            PlayerSubmitRequest::SubmitFrame { .. } => {
+                let frame = object_field(submit, &["Frame", "frame"]);
+                for points in ["DotPoints", "dotPoints", "PathPoints", "pathPoints"] {
+                    let Some(Value::Array(points)) = frame.get_mut(points) else {
+                        continue;
+                    };
+
+                    for point in points.iter_mut().filter_map(Value::as_object_mut) {
+                        for intensity in ["Intensity", "intensity"] {
+                            if let Some(value) = point.get(intensity).and_then(Value::as_f64) {
+                                let scaled = (value * self.intensity as f64).clamp(0.0, FRAME_INTENSITY_MAX);
+                                point.insert(intensity.to_string(), serde_json::json!(scaled));
+                            }
+                        }
+                    }
+                }
+            },
+            PlayerSubmitRequest::TurnOff { .. } | PlayerSubmitRequest::TurnOffAll => {},
+        }
+
+        true
+    }
+}
+
+/// Object under the first of `names` present in `object`, created under the first name when absent.
+fn object_field<'a>(object: &'a mut Map<String, Value>, names: &[&str]) -> &'a mut Map<String, Value> {
+    let name = names.iter().find(|name| object.contains_key(**name)).unwrap_or(&names[0]).to_string();
+    let field = object.entry(name).or_insert_with(|| Value::Object(Map::new()));
+    if !field.is_object() {
+        *field = Value::Object(Map::new());
+    }
+
+    field.as_object_mut().unwrap()
+}
+
+/// Forwards the clients to an upstream bHaptics Player, see the [module](self) documentation.
+#[derive(Clone, Debug)]
+pub struct BHapticsProxy {
+    /// `ws://host:port`, without the path
+    upstream: String,
+
+    rules: ProxyRules,
+
+    /// Whether the forwarded messages are played on the local devices as well
+    mirror: bool,
+}
+
+/// Client being relayed, as connected to the server.
+pub(crate) struct RelayedClient<'a> {
+    pub app_info: &'a BHapticsAppInfo,
+    pub player: &'a HapticPlayer,
+    pub namespace: &'a KeyNamespace,
+    pub effects: &'a mut ClientEffects,
+    pub shutdown: &'a CancellationToken,
+}
+
+impl BHapticsProxy {
+    /// Forwards to `upstream`, `ws://host:port`, the `/v2/feedbacks` path being optional.
+    pub fn new(upstream: &str) -> Result<Self, ProxyError> {
+        let base = upstream.trim_end_matches('/').trim_end_matches("/v2/feedbacks");
+        let valid = base
+            .strip_prefix("ws://")
+            .is_some_and(|authority| !authority.is_empty() && !authority.contains(['/', '?', '#']));
+        if !valid {
+            return Err(ProxyError::InvalidUpstream(upstream.to_string()));
+        }
+
+        Ok(Self {
+            upstream: base.to_string(),
+            rules: ProxyRules::default(),
+            mirror: false,
+        })
+    }
+
+    pub fn with_rules(mut self, rules: ProxyRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Also plays the forwarded messages on the local devices, off by default.
+    pub fn with_mirror(mut self, mirror: bool) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    pub fn upstream(&self) -> &str {
+        &self.upstream
+    }
+
+    pub fn rules(&self) -> &ProxyRules {
+        &self.rules
+    }
+
+    fn url(&self, app_info: &BHapticsAppInfo) -> String {
+        let query = serde_urlencoded::to_string(app_info).unwrap_or_default();
+        format!("{}/v2/feedbacks?{}", self.upstream, query)
+    }
+
+    /// Relays the client to the upstream until either side disconnects, or the server shuts down.
+    pub(crate) async fn relay<R>(
+        &self,
+        mut client_tx: SplitSink<WebSocket, Message>,
+        mut client_rx: R,
+        client: RelayedClient<'_>,
+        record: &(dyn Fn(SessionEvent) + Sync),
+    )
+    where
+        R: Stream<Item = Result<Message, warp::Error>> + Unpin,
+    {
+        let RelayedClient { app_info, player, namespace, effects, shutdown } = client;
+
+        let url = self.url(app_info);
+        let connected = tokio::select! {
+            connected = tokio::time::timeout(UPSTREAM_CONNECT_TIMEOUT, tokio_tungstenite::connect_async(url.as_str())) => connected,
+            _ = shutdown.cancelled() => {
+                let _ = client_tx.send(Message::close_with(SHUTDOWN_CLOSE_CODE, "server shutting down")).await;
+                return;
+            },
+        };
+
+        let upstream = match connected {
+            Ok(Ok((upstream, _))) => upstream,
+            Ok(Err(why)) => return close_unreachable(client_tx, &url, why.to_string()).await,
+            Err(_) => return close_unreachable(client_tx, &url, String::from("timed out")).await,
+        };
+
+        info!("Relaying the client to {}", self.upstream);
+        let (mut upstream_tx, mut upstream_rx) = upstream.split();
+
+        loop {
+            tokio::select! {
+                message = client_rx.next() => match message {
+                    Some(Ok(message)) if message.is_close() => {
+                        let _ = upstream_tx.send(tungstenite::Message::Close(None)).await;
+                        break;
+                    },
+                    Some(Ok(message)) if message.is_text() || message.is_binary() => {
+                        let message = String::from_utf8_lossy(message.as_bytes()).into_owned();
+                        record(SessionEvent::Message { message: message.clone() });
+                        self.observe(&message, player);
+
+                        let Some(forwarded) = self.rules.apply(&message) else {
+                            debug!("Nothing left to forward of the message");
+                            continue;
+                        };
+
+                        if self.mirror {
+                            mirror(&forwarded, player, namespace, effects);
+                        }
+
+                        if let Err(why) = upstream_tx.send(tungstenite::Message::Text(forwarded)).await {
+                            error!("Failed to forward the message upstream: {}", why);
+                            let _ = client_tx.send(Message::close_with(UPSTREAM_CLOSE_CODE, "upstream connection lost")).await;
+                            break;
+                        }
+                    },
+                    Some(Ok(_)) => {},
+                    Some(Err(why)) => {
+                        error!("Error receiving message: {:?}", why);
+                        let _ = upstream_tx.send(tungstenite::Message::Close(None)).await;
+                        break;
+                    },
+                    None => {
+                        let _ = upstream_tx.send(tungstenite::Message::Close(None)).await;
+                        break;
+                    },
+                },
+                message = upstream_rx.next() => match message {
+                    Some(Ok(tungstenite::Message::Text(response))) => {
+                        if client_tx.send(Message::text(response)).await.is_err() {
+                            break;
+                        }
+                    },
+                    Some(Ok(tungstenite::Message::Binary(response))) => {
+                        if client_tx.send(Message::binary(response)).await.is_err() {
+                            break;
+                        }
+                    },
+                    Some(Ok(tungstenite::Message::Close(frame))) => {
+                        let (code, reason) = frame
+                            .map(|frame| (u16::from(frame.code), frame.reason.into_owned()))
+                            .unwrap_or((u16::from(CloseCode::Normal), String::new()));
+                        let _ = client_tx.send(Message::close_with(code, reason)).await;
+                        break;
+                    },
+                    // Pings are answered by the upstream connection itself
+                    Some(Ok(_)) => {},
+                    Some(Err(why)) => {
+                        error!("Upstream connection failed: {}", why);
+                        let _ = client_tx.send(Message::close_with(UPSTREAM_CLOSE_CODE, "upstream connection lost")).await;
+                        break;
+                    },
+                    None => {
+                        let _ = client_tx.send(Message::close_with(UPSTREAM_CLOSE_CODE, "upstream connection lost")).await;
+                        break;
+                    },
+                },
+                _ = shutdown.cancelled() => {
+                    let _ = upstream_tx.send(tungstenite::Message::Close(Some(CloseFrame {
+                        code: CloseCode::Away,
+                        reason: "server shutting down".into(),
+                    }))).await;
+                    let _ = client_tx.send(Message::close_with(SHUTDOWN_CLOSE_CODE, "server shutting down")).await;
+                    break;
+                },
+            }
+        }
+    }
+
+    /// Counts a message received from the client, as it was sent.
+    fn observe(&self, message: &str, player: &HapticPlayer) {
+        match serde_json::from_str::<PlayerRequest>(message) {
+            Ok(request) => count_messages(player, &request),
+            Err(why) => {
+                debug!("Forwarding a message which is not a valid request: {}", why);
+                player.metrics().parse_errors().inc();
+            },
+        }
+    }
+}
+
+/// Plays a forwarded message on the local devices.
+fn mirror(message: &str, player: &HapticPlayer, namespace: &KeyNamespace, effects: &mut ClientEffects) {
+    let Ok(request) = serde_json::from_str::<PlayerRequest>(message) else {
+        return;
+    };
+
+    effects.track(&request);
+    if let Err(why) = player.handle_request(request, namespace) {
+        debug!("Failed to mirror the request: {}", why);
+    }
+}
+
+async fn close_unreachable<S>(mut client_tx: S, url: &str, why: String)
+where
+    S: futures_util::Sink<Message> + Unpin,
+{
+    error!("Failed to connect to the upstream {}: {}", url, why);
+    let _ = client_tx.send(Message::close_with(UPSTREAM_CLOSE_CODE, "upstream unreachable")).await;
+}
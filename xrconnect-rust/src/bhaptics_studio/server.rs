@@ -1,12 +1,50 @@
 use super::{
+    clients::Clients,
+    listener::Listener,
+    proxy::BHapticsProxy,
+    session::SessionRecorder,
+    namespace::NamespaceMode,
     ws::v2::behavior::BHapticsWebsocketV2Behavior,
 };
+use crate::access::{ AccessControl, Forbidden };
+use crate::api::ControlApi;
+use crate::control::{ ControlSocket, ControlTarget };
+use crate::haptics::{
+    player::HapticPlayer,
+    watchdog,
+};
+use crate::{ paths, state::SavedState, tls::TlsConfig };
 
 use std::{
+    fmt,
     net::SocketAddr,
+    path::PathBuf,
+    time::{ Duration, Instant },
 };
 use std::convert::Into;
 use serde::{self, Serialize, Deserialize};
+use tokio_util::sync::CancellationToken;
+use tracing::{ error, info, warn };
+use warp::{ http::StatusCode, Filter, Rejection, Reply };
+
+/// Time given to the clients to disconnect when shutting down.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerError {
+    /// None of the listeners could be started, e.g. their address is in use
+    NoListener,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::NoListener => write!(f, "no listener could be started"),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct BHapticsAppInfo {
@@ -17,6 +55,16 @@ pub struct BHapticsAppInfo {
     name: String,
 }
 
+impl BHapticsAppInfo {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
 impl Default for BHapticsAppInfo {
     fn default() -> Self {
         Self {
@@ -26,31 +74,279 @@ impl Default for BHapticsAppInfo {
     }
 }
 
+/// Protocols the server speaks, every one by default.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Frontends {
+    /// bHaptics Player WebSocket, `/v2/feedbacks`
+    pub bhaptics: bool,
+
+    /// Control API, dashboard and metrics, see [`ControlApi`]
+    pub api: bool,
+
+    /// Lets peers other than local ones use the control API with `Authorization: Bearer <token>`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_token: Option<String>,
+}
+
+impl Default for Frontends {
+    fn default() -> Self {
+        Self {
+            bhaptics: true,
+            api: true,
+            api_token: None,
+        }
+    }
+}
+
 pub struct BHapticsStudioServer {
-    /// Socket Address for WebSocket Server
-    address: SocketAddr,
+    /// Where the WebSocket server accepts connections
+    listeners: Vec<Listener>,
+
+    frontends: Frontends,
+
+    player: HapticPlayer,
+
+    /// Cancelled to shut the server down
+    shutdown: CancellationToken,
+
+    shutdown_timeout: Duration,
+
+    /// Where the player state is saved on shutdown, not saved when absent
+    state_file: Option<PathBuf>,
+
+    namespaces: NamespaceMode,
+
+    access: AccessControl,
+
+    clients: Clients,
+
+    /// Where the client traffic is recorded, not recorded when absent
+    recorder: Option<SessionRecorder>,
+
+    /// Upstream bHaptics Player the clients are relayed to, played locally when absent
+    proxy: Option<BHapticsProxy>,
+
+    /// Where the CLI commands reach the server, unreachable when absent
+    control: Option<ControlSocket>,
 }
 
 impl Default for BHapticsStudioServer {
     fn default() -> Self {
         Self {
-            address: ([0, 0, 0, 0], 15881).into(),
+            // Only local clients by default, anything on the network could drive the devices otherwise
+            listeners: vec![Listener::Tcp(([127, 0, 0, 1], 15881).into())],
+            frontends: Frontends::default(),
+            player: HapticPlayer::new(),
+            shutdown: CancellationToken::new(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            state_file: Some(paths::state_file()),
+            namespaces: NamespaceMode::default(),
+            access: AccessControl::default(),
+            clients: Clients::new(),
+            recorder: None,
+            proxy: None,
+            control: None,
         }
     }
 }
 
 impl BHapticsStudioServer {
     pub fn new(address: SocketAddr) -> Self {
+        Self::with_listeners(vec![Listener::Tcp(address)])
+    }
+
+    pub fn with_listeners(listeners: Vec<Listener>) -> Self {
         Self {
-            address,
+            listeners,
+            ..Default::default()
         }
     }
 
-    pub async fn run(&self) {
-        let routes = BHapticsWebsocketV2Behavior::new().routes();
+    /// Also accepts connections on `listener`.
+    pub fn with_listener(mut self, listener: Listener) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    pub fn with_frontends(mut self, frontends: Frontends) -> Self {
+        self.frontends = frontends;
+        self
+    }
 
-        warp::serve(routes)
-            .run(self.address)
-            .await;
+    /// Uses `player` instead of a fresh one, so that devices can be attached to it beforehand.
+    pub fn with_player(mut self, player: HapticPlayer) -> Self {
+        self.player = player;
+        self
     }
-}
\ No newline at end of file
+
+    /// Time given to the clients to disconnect when shutting down, devices are turned off either way.
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn with_state_file(mut self, state_file: Option<PathBuf>) -> Self {
+        self.state_file = state_file;
+        self
+    }
+
+    /// How the keys of the clients are kept apart, each connection has its own by default.
+    pub fn with_namespaces(mut self, mode: NamespaceMode) -> Self {
+        self.namespaces = mode;
+        self
+    }
+
+    /// Who may use the server, everyone able to reach the address by default.
+    pub fn with_access(mut self, access: AccessControl) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Also serves the same routes over TLS, on the address of `tls`.
+    pub fn with_tls(self, tls: TlsConfig) -> Self {
+        self.with_listener(Listener::Tls(tls))
+    }
+
+    pub fn listeners(&self) -> &[Listener] {
+        &self.listeners
+    }
+
+    /// Records every message of the clients, see [`super::session`].
+    pub fn with_recorder(mut self, recorder: SessionRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Relays the clients to an upstream bHaptics Player, see [`super::proxy`].
+    pub fn with_proxy(mut self, proxy: BHapticsProxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Answers the CLI commands on a Unix domain socket, see [`crate::control`].
+    pub fn with_control_socket(mut self, control: ControlSocket) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// Clients connected to the WebSocket.
+    pub fn clients(&self) -> &Clients {
+        &self.clients
+    }
+
+    pub fn access(&self) -> &AccessControl {
+        &self.access
+    }
+
+    pub fn player(&self) -> &HapticPlayer {
+        &self.player
+    }
+
+    /// Token shutting the server down once cancelled, see [`BHapticsStudioServer::run`].
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Serves the clients until the shutdown token is cancelled.
+    ///
+    /// Shutting down stops accepting connections, closes every client with a close
+    /// frame, turns off every effect, stops and flushes the devices and saves the
+    /// player state, before returning. Fails when none of the listeners could be started.
+    pub async fn run(&self) -> Result<(), ServerError> {
+        let started = Instant::now();
+        let websocket = BHapticsWebsocketV2Behavior::new(self.player.clone())
+            .with_namespaces(self.namespaces)
+            .with_access(self.access.clone())
+            .with_clients(self.clients.clone())
+            .with_recorder(self.recorder.clone())
+            .with_proxy(self.proxy.clone());
+        let api = ControlApi::new(self.player.clone(), self.access.clone())
+            .with_clients(self.clients.clone())
+            .with_token(self.frontends.api_token.clone())
+            .with_shutdown(self.shutdown.clone());
+        let routes = self.access.peer_filter()
+            .and(enabled(self.frontends.bhaptics).and(websocket.routes()).or(enabled(self.frontends.api).and(api.routes())))
+            .recover(forbidden);
+        let _watchdog = self.player.spawn_watchdog(watchdog::DEFAULT_DEADLINE);
+
+        let mut listening = vec![];
+        let mut servers = vec![];
+        for listener in &self.listeners {
+            match listener.serve(routes.clone()) {
+                Ok(server) => {
+                    listening.push(listener);
+                    servers.push(server);
+                },
+                Err(why) => error!("Not listening on {}: {}", listener, why),
+            }
+        }
+
+        let mut controlled = None;
+        let result = if servers.is_empty() {
+            error!("No listener could be started");
+            Err(ServerError::NoListener)
+        } else {
+            if let Some(control) = &self.control {
+                let target = ControlTarget {
+                    player: self.player.clone(),
+                    clients: self.clients.clone(),
+                    listeners: listening.iter().map(|listener| listener.to_string()).collect(),
+                    started,
+                };
+
+                match control.serve(target, self.shutdown.clone()) {
+                    Ok(server) => {
+                        controlled = Some(control);
+                        servers.push(server);
+                    },
+                    Err(why) => error!("No control socket at {}: {}", control.path().display(), why),
+                }
+            }
+
+            tokio::select! {
+                _ = self.player.run() => {},
+                _ = futures_util::future::join_all(servers) => {},
+                _ = self.shutdown.cancelled() => {},
+            }
+            Ok(())
+        };
+
+        info!("Shutting down");
+        if tokio::time::timeout(self.shutdown_timeout, websocket.close_clients()).await.is_err() {
+            warn!("Clients did not disconnect within {:?}", self.shutdown_timeout);
+        }
+
+        self.player.shutdown();
+
+        if let Some(path) = &self.state_file {
+            if let Err(why) = SavedState::capture(&self.player).save(path) {
+                error!("Failed to save the state to {}: {}", path.display(), why);
+            }
+        }
+
+        listening.into_iter().for_each(Listener::cleanup);
+        controlled.into_iter().for_each(ControlSocket::cleanup);
+        result
+    }
+}
+
+/// Lets requests through to a frontend only when it is enabled.
+fn enabled(enabled: bool) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::any()
+        .and_then(move || async move {
+            match enabled {
+                true => Ok(()),
+                false => Err(warp::reject::not_found()),
+            }
+        })
+        .untuple_one()
+}
+
+/// Answers requests refused by the access policy with `403 Forbidden`.
+async fn forbidden(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    match rejection.find::<Forbidden>() {
+        Some(Forbidden(why)) => Ok(warp::reply::with_status(why.clone(), StatusCode::FORBIDDEN)),
+        None => Err(rejection),
+    }
+}
@@ -0,0 +1,262 @@
+//! Recording of the bHaptics WebSocket traffic, and its replay into a player.
+//!
+//! A session file holds one JSON entry per line: clients connecting, with their
+//! `app_id` and `app_name`, every message they send, as received, and clients
+//! disconnecting. Times are milliseconds since the recording started.
+//!
+//! # Example Session
+//! ```json
+//! {"at_millis":0,"connection":1,"event":"Connected","app":{"app_id":"com.example.game","app_name":"Game"}}
+//! {"at_millis":15,"connection":1,"event":"Message","message":"{\"Submit\":[{\"Type\":\"turnOffAll\"}]}"}
+//! {"at_millis":2040,"connection":1,"event":"Disconnected"}
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{ self, File },
+    io::{ self, LineWriter, Write },
+    path::Path,
+    sync::{ Arc, Mutex },
+    time::{ Duration, Instant },
+};
+
+use serde::{ Serialize, Deserialize };
+use tracing::{ debug, warn };
+
+use super::{
+    namespace::{ KeyNamespace, NamespaceMode },
+    server::BHapticsAppInfo,
+    tact::PlayerRequest,
+    ws::v2::behavior::{ ClientEffects, NamespaceEffects },
+    BHapticsStudioPlayer,
+};
+use crate::haptics::player::{ HapticPlayer, FRAME_INTERVAL };
+
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+
+    /// Line `line` of the session is not a valid entry
+    Parse { line: usize, error: serde_json::Error },
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Io(why) => write!(f, "session I/O error: {}", why),
+            SessionError::Parse { line, error } => write!(f, "invalid session entry on line {}: {}", line, error),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "event")]
+pub enum SessionEvent {
+    Connected { app: BHapticsAppInfo },
+
+    /// Message as received, valid or not
+    Message { message: String },
+
+    Disconnected,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SessionEntry {
+    /// Milliseconds since the recording started
+    pub at_millis: u64,
+
+    /// Client connection the entry is about, unique within the session
+    pub connection: u64,
+
+    #[serde(flatten)]
+    pub event: SessionEvent,
+}
+
+/// Writes the traffic of every client to a session file, as it comes.
+///
+/// Cloning is cheap, clones write to the same file.
+#[derive(Clone)]
+pub struct SessionRecorder {
+    file: Arc<Mutex<LineWriter<File>>>,
+    started_at: Instant,
+}
+
+impl SessionRecorder {
+    /// Records to `path`, replacing any previous session there.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        Ok(Self {
+            file: Arc::new(Mutex::new(LineWriter::new(File::create(path)?))),
+            started_at: Instant::now(),
+        })
+    }
+
+    pub fn record(&self, connection: u64, event: SessionEvent) {
+        let entry = SessionEntry {
+            at_millis: self.started_at.elapsed().as_millis() as u64,
+            connection,
+            event,
+        };
+
+        let written = serde_json::to_string(&entry)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(self.file.lock().unwrap(), "{}", line));
+        if let Err(why) = written {
+            warn!("Failed to record the session: {}", why);
+        }
+    }
+}
+
+/// Recorded session, entries sorted by time.
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    pub entries: Vec<SessionEntry>,
+}
+
+impl Session {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SessionError> {
+        let content = fs::read_to_string(path).map_err(SessionError::Io)?;
+
+        let mut entries = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| serde_json::from_str(line).map_err(|error| SessionError::Parse { line: index + 1, error }))
+            .collect::<Result<Vec<SessionEntry>, _>>()?;
+        entries.sort_by_key(|entry| entry.at_millis);
+
+        Ok(Self { entries })
+    }
+
+    /// Time from the first entry to the last one.
+    pub fn duration(&self) -> Duration {
+        match (self.entries.first(), self.entries.last()) {
+            (Some(first), Some(last)) => Duration::from_millis(last.at_millis - first.at_millis),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Feeds a session back into a player, as the clients would have.
+pub struct SessionReplayer {
+    session: Session,
+
+    /// Speed factor, `2.0` replays twice as fast
+    speed: f32,
+
+    namespaces: NamespaceMode,
+}
+
+impl SessionReplayer {
+    pub fn new(session: Session) -> Self {
+        Self {
+            session,
+            speed: 1.0,
+            namespaces: NamespaceMode::default(),
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed.max(f32::EPSILON);
+        self
+    }
+
+    /// Should match the namespaces of the server the session was recorded with.
+    pub fn with_namespaces(mut self, mode: NamespaceMode) -> Self {
+        self.namespaces = mode;
+        self
+    }
+
+    /// Replays the session in real time, at the replayer's speed.
+    ///
+    /// The player has to be running meanwhile, see [`HapticPlayer::run`].
+    pub async fn replay(&self, player: &HapticPlayer) {
+        let started_at = tokio::time::Instant::now();
+        let mut clients = ReplayedClients::default();
+
+        for entry in &self.session.entries {
+            tokio::time::sleep_until(started_at + self.scaled(entry.at_millis)).await;
+            clients.apply(player, self.namespaces, entry);
+        }
+    }
+
+    /// Replays the session frame by frame, writing every frame to the player's devices.
+    ///
+    /// The player needs a manual clock, see [`HapticPlayer::with_manual_clock`]: the
+    /// same session then always gives the same frames, however long replaying takes.
+    /// Frames are written until the last entry, and until the effects still playing end.
+    pub fn replay_stepped(&self, player: &HapticPlayer) {
+        let started_at = player.now();
+        let mut clients = ReplayedClients::default();
+        let mut entries = self.session.entries.iter().peekable();
+
+        loop {
+            let elapsed = player.now().saturating_duration_since(started_at);
+            while let Some(entry) = entries.next_if(|entry| self.scaled(entry.at_millis) <= elapsed) {
+                clients.apply(player, self.namespaces, entry);
+            }
+
+            player.tick(player.now());
+            if entries.peek().is_none() && player.active_keys().is_empty() {
+                break;
+            }
+
+            player.advance(FRAME_INTERVAL);
+        }
+    }
+
+    /// Time from the first entry to an entry at `at_millis`, at the replayer's speed.
+    ///
+    /// Recordings start with the server, the wait for the first client is skipped. Rounded
+    /// to the nanosecond, entries would otherwise fall just after the frame they were recorded at.
+    fn scaled(&self, at_millis: u64) -> Duration {
+        let first = self.session.entries.first().map(|entry| entry.at_millis).unwrap_or(0);
+        let nanos = at_millis.saturating_sub(first) as f64 * 1_000_000.0 / self.speed as f64;
+        Duration::from_nanos(nanos.round() as u64)
+    }
+}
+
+/// Clients of the session being replayed.
+#[derive(Default)]
+struct ReplayedClients {
+    connected: HashMap<u64, (KeyNamespace, ClientEffects)>,
+    effects: NamespaceEffects,
+}
+
+impl ReplayedClients {
+    fn apply(&mut self, player: &HapticPlayer, namespaces: NamespaceMode, entry: &SessionEntry) {
+        match &entry.event {
+            SessionEvent::Connected { app } => {
+                let namespace = KeyNamespace::for_client(namespaces, entry.connection, app);
+                let effects = self.effects.join(player.clone(), namespace.clone(), namespaces == NamespaceMode::PerConnection);
+                self.connected.insert(entry.connection, (namespace, effects));
+            },
+            SessionEvent::Message { message } => {
+                let Some((namespace, effects)) = self.connected.get_mut(&entry.connection) else {
+                    return warn!("Ignoring a message of connection {}, which is not connected", entry.connection);
+                };
+
+                match serde_json::from_str::<PlayerRequest>(message) {
+                    Ok(request) => {
+                        effects.track(&request);
+                        if let Err(why) = player.handle_request(request, namespace) {
+                            debug!("Failed to handle the request: {}", why);
+                        }
+                    },
+                    Err(why) => debug!("Invalid message from connection {}: {}", entry.connection, why),
+                }
+            },
+            // Effects are turned off when dropped, like when a client disconnects
+            SessionEvent::Disconnected => {
+                self.connected.remove(&entry.connection);
+            },
+        }
+    }
+}
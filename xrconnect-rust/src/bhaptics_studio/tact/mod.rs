@@ -1,10 +1,19 @@
-use std::collections::HashMap;
-
+use haptic_lib::{ BodyPart, EffectInterpolation };
 use serde::{self, Serialize, Deserialize, Deserializer, de};
 use serde_json::Value;
 
+use tracing::warn;
+
+use crate::{
+    devices::bhaptics::DeviceFamily,
+    haptics::model::{
+        ClipOutput, DotIntensity, HapticPattern, MotorLayout, PathIntensity, PatternClip, PatternPoints, PlaybackOptions,
+    },
+};
+
 use super::{
-    tact::project::{ Project }
+    tact::project::{ Project },
+    ws::v2::model::PositionType,
 };
 
 pub mod project;
@@ -268,9 +277,9 @@ pub enum PlayerRequest {
 #[derive(Deserialize, Clone, Debug)]
 pub struct PlayerRegisterRequest {
     #[serde(alias = "Key", deserialize_with = "de_bhaptics_project_id")]
-    key: String,
+    pub key: String,
     #[serde(alias = "Project")]
-    project: Project,
+    pub project: Project,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -298,39 +307,152 @@ pub enum PlayerSubmitRequest {
     SubmitRegistered {
         #[serde(alias = "Key", deserialize_with = "de_bhaptics_project_id")]
         key: String,
-        #[serde(alias = "Parameters")]
+        #[serde(default, alias = "Parameters")]
         parameters: RegisteredParameters
     },
 }
 
-#[derive(Deserialize, Clone, Debug)]
+impl PlayerSubmitRequest {
+    /// `Type` of the request, as sent by the clients.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PlayerSubmitRequest::TurnOffAll => "turnOffAll",
+            PlayerSubmitRequest::TurnOff { .. } => "turnOff",
+            PlayerSubmitRequest::SubmitFrame { .. } => "frame",
+            PlayerSubmitRequest::SubmitRegistered { .. } => "key",
+        }
+    }
+
+    /// Key of the effect this request starts playing, if any.
+    pub fn active_key(&self) -> Option<&str> {
+        match self {
+            PlayerSubmitRequest::SubmitFrame { key, .. } => Some(key),
+            PlayerSubmitRequest::SubmitRegistered { key, parameters } => Some(parameters.alt_key.as_deref().unwrap_or(key)),
+            PlayerSubmitRequest::TurnOffAll | PlayerSubmitRequest::TurnOff { .. } => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
 pub struct RegisteredParameters {
-    #[serde(rename = "altKey", deserialize_with = "de_bhaptics_alt_id")]
-    alt_key: Option<String>,
+    #[serde(default, rename = "altKey", deserialize_with = "de_bhaptics_alt_id")]
+    pub alt_key: Option<String>,
+
+    #[serde(default, rename = "startTimeMillis")]
+    pub start_time_millis: Option<u32>,
 
-    #[serde(rename = "startTimeMillis")]
-    start_time_millis: Option<u32>,
+    #[serde(default, rename = "scaleOption")]
+    pub scale_option: Option<RegisteredParametersScaleOption>,
 
-    #[serde(rename = "scaleOption")]
-    scale_option: Option<RegisteredParametersScaleOption>,
+    #[serde(default, rename = "rotationOption")]
+    pub rotation_option: Option<RegisteredParametersRotationOption>,
+}
+
+impl RegisteredParameters {
+    pub fn playback_options(&self) -> PlaybackOptions {
+        let scale = self.scale_option.clone().unwrap_or_default();
 
-    #[serde(rename = "rotationOption")]
-    rotation_option: Option<RegisteredParametersRotationOption>,
+        PlaybackOptions {
+            intensity: scale.intensity,
+            duration: scale.duration,
+            start_millis: self.start_time_millis.unwrap_or(0),
+        }
+    }
 }
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct RegisteredParametersScaleOption {
-    intensity: f32,
-    duration: f32,
+    pub intensity: f32,
+    pub duration: f32,
+}
+
+impl Default for RegisteredParametersScaleOption {
+    fn default() -> Self {
+        Self {
+            intensity: 1.0,
+            duration: 1.0,
+        }
+    }
 }
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct RegisteredParametersRotationOption {
     #[serde(rename = "offsetAngleX")]
-    offset_angle_x: f32,
+    pub offset_angle_x: f32,
 
     #[serde(alias = "offsetY")]
-    offset_y: f32,
+    pub offset_y: f32,
+}
+
+impl RegisteredParametersRotationOption {
+    pub fn is_identity(&self) -> bool {
+        self.offset_angle_x.rem_euclid(360.0) == 0.0 && self.offset_y == 0.0
+    }
+
+    /// Rotates the vest clips of `pattern` around the torso and shifts them vertically.
+    ///
+    /// Going around the torso, the front spans `0..180` degrees from its `x = 0` edge and
+    /// the back spans `180..360` degrees, so the front's `x = 1` edge meets the back's `x = 0` edge.
+    pub fn apply(&self, pattern: &HapticPattern) -> HapticPattern {
+        if self.is_identity() {
+            return pattern.clone();
+        }
+
+        let mut clips = vec![];
+        for clip in &pattern.clips {
+            if clip.part != BodyPart::ChestFront && clip.part != BodyPart::ChestBack {
+                clips.push(clip.clone());
+                continue;
+            }
+
+            let layout = clip.layout.clone().unwrap_or_else(|| MotorLayout::default_for(clip.part));
+            let points: Vec<PathIntensity> = match &clip.points {
+                PatternPoints::Path(points) => points.clone(),
+                PatternPoints::Dot(points) => points
+                    .iter()
+                    .filter_map(|point| layout.motors().get(point.index).map(|(x, y)| PathIntensity {
+                        x: *x,
+                        y: *y,
+                        intensity: point.intensity,
+                    }))
+                    .collect(),
+            };
+
+            let mut front = vec![];
+            let mut back = vec![];
+            for point in points {
+                let y = point.y - self.offset_y;
+                if !(0.0..=1.0).contains(&y) {
+                    continue;
+                }
+
+                let angle = match clip.part {
+                    BodyPart::ChestFront => point.x * 180.0,
+                    _ => 180.0 + point.x * 180.0,
+                };
+                let angle = (angle + self.offset_angle_x).rem_euclid(360.0);
+
+                if angle < 180.0 {
+                    front.push(PathIntensity { x: angle / 180.0, y, ..point });
+                } else {
+                    back.push(PathIntensity { x: (angle - 180.0) / 180.0, y, ..point });
+                }
+            }
+
+            for (part, points) in [(BodyPart::ChestFront, front), (BodyPart::ChestBack, back)] {
+                if !points.is_empty() {
+                    clips.push(PatternClip {
+                        part,
+                        points: PatternPoints::Path(points),
+                        layout: None,
+                        ..clip.clone()
+                    });
+                }
+            }
+        }
+
+        HapticPattern::new(clips)
+    }
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -338,16 +460,107 @@ pub struct SubmitFrame {
     #[serde(alias = "Position")]
     position: String,
 
-    #[serde(alias = "PathPoints")]
+    #[serde(default, alias = "PathPoints")]
     path_points: Vec<PathPoint>,
 
-    #[serde(alias = "DotPoints")]
+    #[serde(default, alias = "DotPoints")]
     dot_points: Vec<DotPoint>,
 
     #[serde(alias = "DurationMillis")]
     duration_millis: u32,
 }
 
+/// Intensity of submitted frames goes from 0 to 100.
+const FRAME_INTENSITY_SCALE: f32 = 100.0;
+
+impl SubmitFrame {
+    pub fn to_pattern(&self) -> Result<HapticPattern, String> {
+        let position: PositionType = self.position.parse()?;
+        let parts = position.body_parts();
+        let layouts = self.dot_layouts(&parts);
+
+        let mut clips = vec![];
+        let mut first_index = 0;
+        for (part, layout) in parts.iter().zip(layouts) {
+            let motor_count = layout.len();
+            let dots: Vec<DotIntensity> = self.dot_points
+                .iter()
+                .filter(|point| (first_index..first_index + motor_count).contains(&(point.index as usize)))
+                .map(|point| DotIntensity {
+                    index: point.index as usize - first_index,
+                    intensity: point.intensity as f32 / FRAME_INTENSITY_SCALE,
+                })
+                .collect();
+            first_index += motor_count;
+
+            if !dots.is_empty() {
+                clips.push(PatternClip {
+                    part: *part,
+                    start_millis: 0,
+                    end_millis: self.duration_millis,
+                    interpolation: EffectInterpolation::None,
+                    points: PatternPoints::Dot(dots),
+                    output: ClipOutput::Motors,
+                    layout: Some(layout),
+                });
+            }
+        }
+
+        if let Some(point) = self.dot_points.iter().find(|point| point.index as usize >= first_index) {
+            warn!("Dropping dot {} of frame on {}, which has {} motors", point.index, self.position, first_index);
+        }
+
+        if let (Some(part), false) = (parts.first(), self.path_points.is_empty()) {
+            clips.push(PatternClip {
+                part: *part,
+                start_millis: 0,
+                end_millis: self.duration_millis,
+                interpolation: EffectInterpolation::None,
+                points: PatternPoints::Path(
+                    self.path_points
+                        .iter()
+                        .map(|point| point.to_intensity(FRAME_INTENSITY_SCALE))
+                        .collect()
+                ),
+                output: ClipOutput::Motors,
+                layout: None,
+            });
+        }
+
+        Ok(HapticPattern::new(clips))
+    }
+
+    /// Layout of each part the dot indices were written for.
+    ///
+    /// Games assume the motor count of the device they were made for, which is given by
+    /// `MotorCount` when present, and otherwise guessed from the highest index.
+    fn dot_layouts(&self, parts: &[BodyPart]) -> Vec<MotorLayout> {
+        let defaults: Vec<MotorLayout> = parts.iter().map(|part| MotorLayout::default_for(*part)).collect();
+        let Some(part) = parts.first() else {
+            return defaults;
+        };
+
+        let motor_count = self.dot_points.iter().find_map(|point| point.motor_count);
+        let highest_index = self.dot_points.iter().map(|point| point.index as usize).max().unwrap_or(0);
+        let default_count: usize = defaults.iter().map(MotorLayout::len).sum();
+
+        let family = match motor_count {
+            Some(count) => DeviceFamily::for_motor_count(*part, parts, count as usize),
+            None if highest_index >= default_count => DeviceFamily::for_highest_index(*part, parts, highest_index),
+            None => None,
+        };
+
+        match family {
+            Some(family) => parts
+                .iter()
+                .zip(defaults)
+                .map(|(part, default)| family.layout(*part).unwrap_or(default))
+                .collect(),
+            None => defaults,
+        }
+    }
+}
+
 /// Can have fields in both camelCase and PascalCase
 ///
 /// Inspired by [this](https://github.com/bhaptics/haptic-library/blob/master/include/shared/model.h#L53)
@@ -361,6 +574,20 @@ struct PathPoint {
 
     #[serde(alias = "Intensity")]
     intensity: f64,
+
+    /// Only present in `.tact` path mode feedback, relative to the effect start
+    #[serde(default, alias = "Time", skip_serializing_if = "Option::is_none")]
+    time: Option<u32>,
+}
+
+impl PathPoint {
+    fn to_intensity(&self, scale: f32) -> PathIntensity {
+        PathIntensity {
+            x: self.x,
+            y: self.y,
+            intensity: self.intensity as f32 / scale,
+        }
+    }
 }
 
 /// Can have fields in both camelCase and PascalCase
@@ -396,6 +623,7 @@ fn de_bhaptics_alt_id<'de, D>(deserializer: D) -> Result<Option<String>, D::Erro
         D: Deserializer<'de>,
 {
     Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) if s.is_empty() => None,
         Value::String(s) => Some(s),
         Value::Number(n) => Some(n.to_string()),
         Value::Null => None,
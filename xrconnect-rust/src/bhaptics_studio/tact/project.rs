@@ -1,9 +1,40 @@
-use std::collections::HashMap;
+use std::{ collections::HashMap, fmt, fs, path::Path };
 use serde::{self, Serialize, Deserialize};
 
 use haptic_lib::EffectInterpolation;
 
-use super::{ DotPoint, PathPoint };
+use haptic_lib::BodyPart;
+use tracing::warn;
+
+use crate::{
+    devices::bhaptics::DeviceFamily,
+    haptics::{
+        model::{ ClipOutput, DotIntensity, HapticPattern, MotorLayout, PathIntensity, PatternClip, PatternPoints },
+        player::FRAME_INTERVAL,
+    },
+};
+
+use super::{ DotPoint, PathPoint, PositionType };
+
+/// Intensity in `.tact` files goes from 0 to 1.
+const PROJECT_INTENSITY_SCALE: f32 = 1.0;
+
+#[derive(Debug)]
+pub enum ProjectError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectError::Io(why) => write!(f, "failed to read project: {}", why),
+            ProjectError::Parse(why) => write!(f, "failed to parse project: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for ProjectError {}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Project {
@@ -41,6 +72,22 @@ pub struct ProjectLayoutObject {
     y: f32,
 }
 
+impl ProjectLayout {
+    /// Layout the project was authored for on `position`.
+    ///
+    /// Motor coordinates stored in the project win, then the canonical layout of its type.
+    pub fn motor_layout(&self, position: &str, part: BodyPart) -> Option<MotorLayout> {
+        match self.layouts.get(position) {
+            Some(objects) if !objects.is_empty() => {
+                let mut objects = objects.clone();
+                objects.sort_by_key(|object| object.index);
+                Some(MotorLayout::new(objects.iter().map(|object| (object.x, object.y)).collect()))
+            },
+            _ => DeviceFamily::for_layout_type(&self._type, part).and_then(|family| family.layout(part)),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ProjectTrack {
     #[serde(alias = "Enable")]
@@ -56,11 +103,12 @@ pub struct HapticEffect {
 
     modes: HashMap<String, HapticEffectMode>,
 
+    /// Milliseconds, projects with times beyond `u32` are rejected when parsed
     #[serde(alias = "startTime")]
-    start_time: u64,
+    start_time: u32,
 
     #[serde(alias = "offsetTime")]
-    offset_time: u64,
+    offset_time: u32,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -92,13 +140,13 @@ pub struct DotMode {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DotModeFeedbackCollection {
     #[serde(alias = "startTime")]
-    start_time: u64,
+    start_time: u32,
 
     #[serde(alias = "endTime")]
-    end_time: u64,
+    end_time: u32,
 
     #[serde(alias = "playbackType")]
-    playback_type: String,
+    playback_type: EffectInterpolation,
 
     #[serde(alias = "pointList")]
     point_list: Vec<DotPoint>,
@@ -131,3 +179,148 @@ pub enum PathMovingPattern {
     #[serde(alias = "CONST_TDM")]
     ConstTDM,
 }
+
+impl Project {
+    /// Reads a `.tact` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProjectError> {
+        let content = fs::read_to_string(path).map_err(ProjectError::Io)?;
+        Self::from_json(&content)
+    }
+
+    /// Parses a `.tact` file, as exported by bHaptics Designer with the project under
+    /// `project`, or the project alone, as registered by the clients.
+    pub fn from_json(content: &str) -> Result<Self, ProjectError> {
+        let mut file: serde_json::Value = serde_json::from_str(content).map_err(ProjectError::Parse)?;
+        let project = match file.get_mut("project") {
+            Some(project) => project.take(),
+            None => file,
+        };
+
+        serde_json::from_value(project).map_err(ProjectError::Parse)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Flattens every enabled track into a single pattern.
+    ///
+    /// Modes for positions unknown to the player are skipped.
+    pub fn to_pattern(&self) -> HapticPattern {
+        let mut clips = vec![];
+
+        for track in self.tracks.iter().filter(|track| track.enable) {
+            for effect in &track.effects {
+                for (position, mode) in &effect.modes {
+                    let Some(part) = position.parse::<PositionType>().ok()
+                        .and_then(|position| position.body_parts().first().copied()) else {
+                        continue;
+                    };
+
+                    let start = effect.start_time;
+                    match mode.mode {
+                        HapticFeedbackMode::DotMode => {
+                            let layout = self.layout.motor_layout(position, part);
+                            let motor_count = layout.as_ref().map_or_else(|| MotorLayout::default_for(part).len(), MotorLayout::len);
+                            let dropped = mode.dot_mode.feedback
+                                .iter()
+                                .flat_map(|feedback| &feedback.point_list)
+                                .filter(|point| point.index as usize >= motor_count)
+                                .count();
+                            if dropped > 0 {
+                                warn!("Dropping {} dots of {} on {}, which has {} motors", dropped, self.name, position, motor_count);
+                            }
+
+                            clips.extend(mode.dot_mode.feedback.iter().map(|feedback| PatternClip {
+                                part,
+                                start_millis: start.saturating_add(feedback.start_time),
+                                end_millis: start.saturating_add(feedback.end_time),
+                                interpolation: feedback.playback_type,
+                                points: PatternPoints::Dot(
+                                    feedback.point_list
+                                        .iter()
+                                        .map(|point| DotIntensity {
+                                            index: point.index as usize,
+                                            intensity: point.intensity as f32 / PROJECT_INTENSITY_SCALE,
+                                        })
+                                        .collect()
+                                ),
+                                output: ClipOutput::Motors,
+                                layout: layout.clone(),
+                            }));
+                        },
+                        HapticFeedbackMode::PathMode => {
+                            for feedback in &mode.path_mode.feedback {
+                                clips.extend(feedback.to_clips(part, start, effect.offset_time));
+                            }
+                        },
+                    }
+                }
+            }
+        }
+
+        HapticPattern::new(clips)
+    }
+}
+
+impl PathModeFeedbackCollection {
+    /// Moving feedback is sampled every [`FRAME_INTERVAL`], interpolating between the timed points.
+    fn to_clips(&self, part: BodyPart, start: u32, duration: u32) -> Vec<PatternClip> {
+        let mut points: Vec<(u32, PathIntensity)> = self.point_list
+            .iter()
+            .filter_map(|point| point.time.map(|time| (time, point.to_intensity(PROJECT_INTENSITY_SCALE))))
+            .collect();
+        points.sort_by_key(|(time, _)| *time);
+
+        let (first, last) = match (points.first(), points.last()) {
+            (Some((first, _)), Some((last, _))) if points.len() == self.point_list.len() && first < last => (*first, *last),
+            _ => {
+                if self.point_list.is_empty() {
+                    return vec![];
+                }
+
+                return vec![PatternClip {
+                    part,
+                    start_millis: start,
+                    end_millis: start.saturating_add(duration),
+                    interpolation: self.playback_type,
+                    points: PatternPoints::Path(
+                        self.point_list.iter().map(|point| point.to_intensity(PROJECT_INTENSITY_SCALE)).collect()
+                    ),
+                    output: ClipOutput::Motors,
+                    layout: None,
+                }];
+            },
+        };
+
+        let step = FRAME_INTERVAL.as_millis() as u32;
+        (first..last)
+            .step_by(step as usize)
+            .map(|time| {
+                let next = points.iter().position(|(t, _)| *t > time).unwrap_or(points.len() - 1);
+                let (t0, p0) = points[next - 1];
+                let (t1, p1) = points[next];
+                let progress = (time - t0) as f32 / (t1 - t0).max(1) as f32;
+                let factor = self.playback_type.factor((time - first) as f32 / (last - first) as f32);
+
+                PatternClip {
+                    part,
+                    start_millis: start.saturating_add(time),
+                    end_millis: start.saturating_add(time.saturating_add(step).min(last)),
+                    interpolation: EffectInterpolation::None,
+                    points: PatternPoints::Path(vec![PathIntensity {
+                        x: p0.x + (p1.x - p0.x) * progress,
+                        y: p0.y + (p1.y - p0.y) * progress,
+                        intensity: (p0.intensity + (p1.intensity - p0.intensity) * progress) * factor,
+                    }]),
+                    output: ClipOutput::Motors,
+                    layout: None,
+                }
+            })
+            .collect()
+    }
+}
@@ -1,17 +1,31 @@
-use crate::bhaptics_studio::{
-    server::{
-        BHapticsStudioServer,
-        BHapticsAppInfo,
+use crate::{
+    access::{ AccessControl, Decision },
+    bhaptics_studio::{
+        clients::Clients,
+        session::{ SessionEvent, SessionRecorder },
+        namespace::{ KeyNamespace, NamespaceMode },
+        proxy::{ BHapticsProxy, RelayedClient },
+        server::BHapticsAppInfo,
+        tact::{ PlayerRequest, PlayerSubmitRequest },
+        BHapticsStudioPlayer,
     },
-    tact::PlayerRequest,
+    haptics::player::HapticPlayer,
 };
 
-use futures_util::{SinkExt, StreamExt, TryFutureExt};
-use serde_json::Value::Null;
-use tokio::sync::{mpsc, RwLock};
+use super::model::PlayerResponse;
+
+use std::{
+    collections::{ HashMap, HashSet },
+    sync::{ Arc, Mutex, Weak },
+    time::Duration,
+};
+
+use futures_util::{ stream::SplitStream, SinkExt, StreamExt, TryFutureExt };
+use tokio::sync::mpsc;
 use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_util::{ sync::CancellationToken, task::TaskTracker };
 
-use tracing::{ instrument, debug, error, info };
+use tracing::{ instrument, debug, error, info, warn };
 
 use warp::{
     self,
@@ -19,54 +33,435 @@ use warp::{
     Filter, Reply, Rejection,
 };
 
+/// Interval between two unsolicited [`PlayerResponse`] sent to a client.
+const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Close code sent to the clients when the server shuts down, "going away".
+const SHUTDOWN_CLOSE_CODE: u16 = 1001;
+
+/// Close code sent to the clients of denied applications, "policy violation".
+const DENIED_CLOSE_CODE: u16 = 1008;
+
+/// Messages kept from a client held for approval, it is closed beyond.
+const MAX_HELD_MESSAGES: usize = 256;
+
+/// Bytes kept from a client held for approval, it is closed beyond.
+const MAX_HELD_BYTES: usize = 1024 * 1024;
+
 pub struct BHapticsWebsocketV2Behavior {
+    player: HapticPlayer,
+
+    /// Cancelled to disconnect every client
+    shutdown: CancellationToken,
+
+    /// Connection of every client
+    tasks: TaskTracker,
+
+    namespaces: NamespaceMode,
+
+    /// Effects of the connections of every namespace
+    effects: NamespaceEffects,
+
+    access: AccessControl,
+
+    clients: Clients,
 
+    /// Where the traffic is recorded, not recorded when absent
+    recorder: Option<SessionRecorder>,
+
+    /// Upstream the clients are relayed to, played locally when absent
+    proxy: Option<BHapticsProxy>,
 }
 
 impl BHapticsWebsocketV2Behavior {
-    pub fn new() -> Self {
+    pub fn new(player: HapticPlayer) -> Self {
         Self {
-
+            player,
+            shutdown: CancellationToken::new(),
+            tasks: TaskTracker::new(),
+            namespaces: NamespaceMode::default(),
+            effects: NamespaceEffects::default(),
+            access: AccessControl::default(),
+            clients: Clients::new(),
+            recorder: None,
+            proxy: None,
         }
     }
 
-    pub fn routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    /// Applications are allowed, denied or held for approval by `access`, every one is allowed by default.
+    pub fn with_access(mut self, access: AccessControl) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Lists the connected clients in `clients`.
+    pub fn with_clients(mut self, clients: Clients) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    /// Records the traffic of every client with `recorder`.
+    pub fn with_recorder(mut self, recorder: Option<SessionRecorder>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    /// Relays the clients to the upstream of `proxy` instead of playing their messages locally.
+    pub fn with_proxy(mut self, proxy: Option<BHapticsProxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    pub fn with_namespaces(mut self, mode: NamespaceMode) -> Self {
+        self.namespaces = mode;
+        self
+    }
+
+    pub fn routes(&self) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        let client = Client {
+            player: self.player.clone(),
+            shutdown: self.shutdown.clone(),
+            tracker: self.tasks.clone(),
+            namespaces: self.namespaces,
+            effects: self.effects.clone(),
+            access: self.access.clone(),
+            clients: self.clients.clone(),
+            recorder: self.recorder.clone(),
+            proxy: self.proxy.clone(),
+        };
+
         warp::path!("v2" / "feedbacks")
             .and(warp::ws())
             .and(warp::query::<BHapticsAppInfo>())
+            .and(warp::any().map(move || client.clone()))
             .and_then(ws_handler)
     }
+
+    /// Sends a close frame to every connected client and waits for their connection to end.
+    ///
+    /// Clients connecting afterwards are closed right away.
+    pub async fn close_clients(&self) {
+        self.shutdown.cancel();
+        self.tasks.close();
+        self.tasks.wait().await;
+    }
+}
+
+/// What a client connection needs from the behavior.
+#[derive(Clone)]
+struct Client {
+    player: HapticPlayer,
+    shutdown: CancellationToken,
+    tracker: TaskTracker,
+    namespaces: NamespaceMode,
+    effects: NamespaceEffects,
+    access: AccessControl,
+    clients: Clients,
+    recorder: Option<SessionRecorder>,
+    proxy: Option<BHapticsProxy>,
 }
 
-#[instrument]
-async fn ws_handler(ws: warp::ws::Ws, app_info: BHapticsAppInfo) -> Result<impl Reply, Rejection> {
+#[instrument(skip(client))]
+async fn ws_handler(ws: warp::ws::Ws, app_info: BHapticsAppInfo, client: Client) -> Result<impl Reply, Rejection> {
     info!("Client connected to bHaptics Studio /v2/feedbacks");
 
-    Ok(ws.on_upgrade(|socket| async move {
-        tokio::task::spawn(async move {
-            let (mut _tx, mut rx) = socket.split();
-
-            while let Some(result) = rx.next().await {
-                match result {
-                    Ok(msg) => {
-                        if msg.is_close() {
-                            info!("Client disconnected from bHaptics Studio /v2/feedbacks");
-                            break;
-                        }
-
-                        match serde_json::from_slice::<PlayerRequest>(&msg.as_bytes()) {
-                            Err(why) => error!("Invalid message from the client: {:?}", why),
-                            Ok(message) => handle_haptic_request(message.clone(), app_info.clone()).await,
-                        }
+    let tracker = client.tracker.clone();
+    Ok(ws.on_upgrade(move |socket| tracker.track_future(client_connected(socket, app_info, client))))
+}
+
+async fn client_connected(socket: WebSocket, app_info: BHapticsAppInfo, client: Client) {
+    let Client { player, shutdown, namespaces, effects, access, clients, recorder, proxy, .. } = client;
+    let (mut ws_tx, mut ws_rx) = socket.split();
+
+    // Unknown applications may be held here until approved, their requests are handled once they are
+    let (decision, held) = match held_until_decided(&mut ws_rx, &access, &app_info, &shutdown).await {
+        Held::Decided(decision, held) => (decision, held),
+        Held::Abandoned => return info!("Client of {} disconnected while waiting for approval", app_info.id()),
+        Held::ShuttingDown => {
+            let _ = ws_tx.send(Message::close_with(SHUTDOWN_CLOSE_CODE, "server shutting down")).await;
+            return;
+        },
+        Held::Overflowed => {
+            warn!("Client of {} sent too much while waiting for approval, closing the connection", app_info.id());
+            let _ = ws_tx.send(Message::close_with(DENIED_CLOSE_CODE, "too many messages while waiting for approval")).await;
+            return;
+        },
+    };
+
+    if decision == Decision::Denied {
+        info!("Application {} is not allowed, closing the connection", app_info.id());
+        let _ = ws_tx.send(Message::close_with(DENIED_CLOSE_CODE, "application not allowed")).await;
+        return;
+    }
+
+    let mut ws_rx = futures_util::stream::iter(held.into_iter().map(Ok)).chain(ws_rx);
+
+    let connection = Clients::next_id();
+    let namespace = KeyNamespace::for_client(namespaces, connection, &app_info);
+    debug!("Keys of the client live in namespace {}", namespace.name());
+    let _listed = clients.connect(connection, &app_info, namespace.name());
+    let record = |event| if let Some(recorder) = &recorder {
+        recorder.record(connection, event);
+    };
+    record(SessionEvent::Connected { app: app_info.clone() });
+
+    let mut effects = effects.join(player.clone(), namespace.clone(), namespaces == NamespaceMode::PerConnection);
+
+    if let Some(proxy) = &proxy {
+        let relayed = RelayedClient {
+            app_info: &app_info,
+            player: &player,
+            namespace: &namespace,
+            effects: &mut effects,
+            shutdown: &shutdown,
+        };
+        proxy.relay(ws_tx, ws_rx, relayed, &record).await;
+        record(SessionEvent::Disconnected);
+        return info!("Client disconnected from bHaptics Studio /v2/feedbacks");
+    }
+
+    // Every message to the client goes through this channel, so that both the
+    // status ticker and the request handler can send responses
+    let (tx, rx) = mpsc::unbounded_channel::<Message>();
+    let mut rx = UnboundedReceiverStream::new(rx);
+
+    let forwarder = tokio::task::spawn(async move {
+        while let Some(message) = rx.next().await {
+            ws_tx
+                .send(message)
+                .unwrap_or_else(|why| debug!("Failed to send message to the client: {:?}", why))
+                .await;
+        }
+    });
+
+    let status = tokio::task::spawn({
+        let tx = tx.clone();
+        let player = player.clone();
+        let namespace = namespace.clone();
+
+        async move {
+            let mut interval = tokio::time::interval(STATUS_INTERVAL);
+            loop {
+                interval.tick().await;
+                if send_response(&tx, &player, &namespace).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    loop {
+        let result = tokio::select! {
+            result = ws_rx.next() => match result {
+                Some(result) => result,
+                None => break,
+            },
+            _ = shutdown.cancelled() => {
+                let _ = tx.send(Message::close_with(SHUTDOWN_CLOSE_CODE, "server shutting down"));
+                break;
+            },
+        };
+
+        match result {
+            Ok(msg) => {
+                if msg.is_close() {
+                    break;
+                }
+
+                if !msg.is_text() && !msg.is_binary() {
+                    continue;
+                }
+
+                record(SessionEvent::Message { message: String::from_utf8_lossy(msg.as_bytes()).into_owned() });
+
+                match serde_json::from_slice::<PlayerRequest>(msg.as_bytes()) {
+                    Err(why) => {
+                        error!("Invalid message from the client: {:?}", why);
+                        player.metrics().parse_errors().inc();
+                    },
+                    Ok(message) => {
+                        count_messages(&player, &message);
+                        effects.track(&message);
+                        handle_haptic_request(message, app_info.clone(), &player, &namespace).await;
+                        let _ = send_response(&tx, &player, &namespace);
                     },
-                    Err(why) => error!("Error receiving message: {:?}", why),
                 }
+            },
+            Err(why) => {
+                error!("Error receiving message: {:?}", why);
+                break;
+            },
+        }
+    }
+
+    record(SessionEvent::Disconnected);
+
+    // Once every sender is gone, the forwarder sends what is left, such as the close frame, and ends
+    status.abort();
+    drop(tx);
+    let _ = forwarder.await;
+
+    info!("Client disconnected from bHaptics Studio /v2/feedbacks");
+}
+
+/// How waiting for the decision about the application of a client ended.
+enum Held {
+    /// Along with the messages the client sent meanwhile
+    Decided(Decision, Vec<Message>),
+
+    /// Client disconnected before the decision was taken
+    Abandoned,
+
+    ShuttingDown,
+
+    /// Client sent more than [`MAX_HELD_MESSAGES`] or [`MAX_HELD_BYTES`] meanwhile
+    Overflowed,
+}
+
+/// Waits for the decision about the application of the client, keeping the messages it sends meanwhile.
+async fn held_until_decided(
+    ws_rx: &mut SplitStream<WebSocket>,
+    access: &AccessControl,
+    app_info: &BHapticsAppInfo,
+    shutdown: &CancellationToken,
+) -> Held {
+    let authorized = access.authorize(app_info.id(), app_info.name());
+    tokio::pin!(authorized);
+    let mut held = vec![];
+    let mut held_bytes = 0;
+
+    loop {
+        tokio::select! {
+            biased;
+            decision = &mut authorized => return Held::Decided(decision, held),
+            _ = shutdown.cancelled() => return Held::ShuttingDown,
+            message = ws_rx.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {
+                    held_bytes += message.as_bytes().len();
+                    held.push(message);
+                    if held.len() > MAX_HELD_MESSAGES || held_bytes > MAX_HELD_BYTES {
+                        return Held::Overflowed;
+                    }
+                },
+                _ => return Held::Abandoned,
+            },
+        }
+    }
+}
+
+/// Active keys of a namespace, scoped to it.
+type ActiveKeys = Mutex<HashSet<String>>;
+
+/// Effects of the clients of every namespace, shared by the connections of a namespace.
+#[derive(Clone, Default)]
+pub(crate) struct NamespaceEffects {
+    /// Active keys of every namespace with a connection left
+    namespaces: Arc<Mutex<HashMap<KeyNamespace, Weak<ActiveKeys>>>>,
+}
+
+impl NamespaceEffects {
+    /// Effects of a new connection of `namespace`, shared with the other connections of the namespace.
+    pub(crate) fn join(&self, player: HapticPlayer, namespace: KeyNamespace, forget: bool) -> ClientEffects {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        let keys = match namespaces.get(&namespace).and_then(Weak::upgrade) {
+            Some(keys) => keys,
+            None => {
+                let keys = Arc::default();
+                namespaces.insert(namespace.clone(), Arc::downgrade(&keys));
+                keys
+            },
+        };
+
+        ClientEffects {
+            player,
+            namespace,
+            forget,
+            keys,
+            namespaces: self.clone(),
+        }
+    }
+}
+
+/// Effects started by the clients of a namespace, turned off once the last one is gone.
+///
+/// Stopping happens on drop, so that effects do not keep playing whether the
+/// connection was closed, errored or its task panicked.
+pub(crate) struct ClientEffects {
+    player: HapticPlayer,
+    namespace: KeyNamespace,
+
+    /// Whether the projects registered in the namespace are forgotten as well
+    forget: bool,
+
+    /// Active keys, shared by the connections of the namespace
+    keys: Arc<ActiveKeys>,
+
+    namespaces: NamespaceEffects,
+}
+
+impl ClientEffects {
+    pub(crate) fn track(&mut self, request: &PlayerRequest) {
+        let PlayerRequest::Submit(submits) = request else {
+            return;
+        };
+
+        let mut keys = self.keys.lock().unwrap();
+        for submit in submits {
+            match submit {
+                PlayerSubmitRequest::TurnOffAll => keys.clear(),
+                PlayerSubmitRequest::TurnOff { key } => {
+                    keys.remove(&self.namespace.scope(key));
+                },
+                _ => {
+                    if let Some(key) = submit.active_key() {
+                        keys.insert(self.namespace.scope(key));
+                    }
+                },
             }
-        });
-    }))
+        }
+    }
+}
+
+impl Drop for ClientEffects {
+    fn drop(&mut self) {
+        // Held until the effects are off, so that a connection joining meanwhile does not see them stopped
+        let mut namespaces = self.namespaces.namespaces.lock().unwrap();
+        if Arc::strong_count(&self.keys) > 1 {
+            return debug!("Keeping the effects of {} for its other connections", self.namespace.name());
+        }
+        namespaces.remove(&self.namespace);
+
+        let keys = self.keys.lock().unwrap();
+        if !keys.is_empty() {
+            debug!("Turning off {} effect(s) of the disconnected client", keys.len());
+        }
+
+        for key in keys.iter() {
+            self.player.stop(key);
+        }
+
+        if self.forget {
+            self.player.unregister_where(|key| self.namespace.contains(key));
+        }
+    }
 }
 
-#[instrument]
-async fn handle_haptic_request(message: PlayerRequest, app_info: BHapticsAppInfo) {
-    //
-}
\ No newline at end of file
+pub(crate) fn count_messages(player: &HapticPlayer, request: &PlayerRequest) {
+    let messages = player.metrics().messages();
+    match request {
+        PlayerRequest::Register(registers) => registers.iter().for_each(|_| messages.inc("Register")),
+        PlayerRequest::Submit(submits) => submits.iter().for_each(|submit| messages.inc(submit.kind())),
+    }
+}
+
+fn send_response(tx: &mpsc::UnboundedSender<Message>, player: &HapticPlayer, namespace: &KeyNamespace) -> Result<(), ()> {
+    let response = serde_json::to_string(&PlayerResponse::from_player(player, namespace)).map_err(|_| ())?;
+    tx.send(Message::text(response)).map_err(|_| ())
+}
+
+#[instrument(skip(message, player))]
+async fn handle_haptic_request(message: PlayerRequest, app_info: BHapticsAppInfo, player: &HapticPlayer, namespace: &KeyNamespace) {
+    if let Err(why) = player.handle_request(message, namespace) {
+        error!("Failed to handle the request: {}", why);
+    }
+}
@@ -1,9 +1,20 @@
-use std::collections::HashMap;
-use serde::{self, Serialize, Deserialize, Deserializer, de};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+};
+use serde::{self, Serialize, Deserialize};
+
+use haptic_lib::BodyPart;
+
+use crate::{
+    bhaptics_studio::namespace::KeyNamespace,
+    haptics::player::HapticPlayer,
+};
 
 /// Reference: [GitHub][reference]
 ///
 /// [reference]: https://github.com/bhaptics/haptic-library/blob/master/include/shared/model.h#LL13C5-L25C7
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PositionType {
     All = 0,
     Left = 1, Right = 2, // deprecated
@@ -18,6 +29,104 @@ pub enum PositionType {
     Custom1 = 251, Custom2 = 252, Custom3 = 253, Custom4 = 254,
 }
 
+impl PositionType {
+    /// Positions reported in [`PlayerResponse`] status.
+    pub const REPORTED: [PositionType; 11] = [
+        PositionType::VestBack, PositionType::VestFront,
+        PositionType::Head,
+        PositionType::FootL, PositionType::FootR,
+        PositionType::HandL, PositionType::HandR,
+        PositionType::ForearmL, PositionType::ForearmR,
+        PositionType::GloveL, PositionType::GloveR,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PositionType::All => "All",
+            PositionType::Left => "Left",
+            PositionType::Right => "Right",
+            PositionType::Vest => "Vest",
+            PositionType::Head => "Head",
+            PositionType::Racket => "Racket",
+            PositionType::HandL => "HandL",
+            PositionType::HandR => "HandR",
+            PositionType::FootL => "FootL",
+            PositionType::FootR => "FootR",
+            PositionType::ForearmL => "ForearmL",
+            PositionType::ForearmR => "ForearmR",
+            PositionType::VestFront => "VestFront",
+            PositionType::VestBack => "VestBack",
+            PositionType::GloveL => "GloveL",
+            PositionType::GloveR => "GloveR",
+            PositionType::Custom1 => "Custom1",
+            PositionType::Custom2 => "Custom2",
+            PositionType::Custom3 => "Custom3",
+            PositionType::Custom4 => "Custom4",
+        }
+    }
+
+    /// Body parts the position drives, in the order their motors are indexed.
+    pub fn body_parts(&self) -> Vec<BodyPart> {
+        match self {
+            PositionType::All => BodyPart::ALL.to_vec(),
+            PositionType::Vest => vec![BodyPart::ChestFront, BodyPart::ChestBack],
+            PositionType::VestFront => vec![BodyPart::ChestFront],
+            PositionType::VestBack => vec![BodyPart::ChestBack],
+            PositionType::Head => vec![BodyPart::Head],
+            PositionType::Left | PositionType::ForearmL => vec![BodyPart::ForearmL],
+            PositionType::Right | PositionType::ForearmR => vec![BodyPart::ForearmR],
+            PositionType::HandL => vec![BodyPart::HandL],
+            PositionType::HandR => vec![BodyPart::HandR],
+            PositionType::FootL => vec![BodyPart::FootL],
+            PositionType::FootR => vec![BodyPart::FootR],
+            PositionType::GloveL => vec![BodyPart::GloveL],
+            PositionType::GloveR => vec![BodyPart::GloveR],
+            PositionType::Racket
+            | PositionType::Custom1 | PositionType::Custom2
+            | PositionType::Custom3 | PositionType::Custom4 => vec![],
+        }
+    }
+
+    pub fn from_body_part(part: BodyPart) -> Self {
+        match part {
+            BodyPart::ChestFront => PositionType::VestFront,
+            BodyPart::ChestBack => PositionType::VestBack,
+            BodyPart::Head => PositionType::Head,
+            BodyPart::ForearmL => PositionType::ForearmL,
+            BodyPart::ForearmR => PositionType::ForearmR,
+            BodyPart::HandL => PositionType::HandL,
+            BodyPart::HandR => PositionType::HandR,
+            BodyPart::FootL => PositionType::FootL,
+            BodyPart::FootR => PositionType::FootR,
+            BodyPart::GloveL => PositionType::GloveL,
+            BodyPart::GloveR => PositionType::GloveR,
+        }
+    }
+}
+
+impl FromStr for PositionType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            PositionType::All,
+            PositionType::Left, PositionType::Right,
+            PositionType::Vest,
+            PositionType::Head,
+            PositionType::Racket,
+            PositionType::HandL, PositionType::HandR,
+            PositionType::FootL, PositionType::FootR,
+            PositionType::ForearmL, PositionType::ForearmR,
+            PositionType::VestFront, PositionType::VestBack,
+            PositionType::GloveL, PositionType::GloveR,
+            PositionType::Custom1, PositionType::Custom2, PositionType::Custom3, PositionType::Custom4,
+        ]
+            .into_iter()
+            .find(|position| position.name() == s)
+            .ok_or_else(|| format!("unknown position {:?}", s))
+    }
+}
+
 /// Message sent from the server to the client.
 ///
 /// # Example Message
@@ -42,20 +151,82 @@ pub enum PositionType {
 ///    }
 /// }
 /// ```
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct PlayerResponse {
     #[serde(rename = "RegisteredKeys")]
-    registered_keys: Vec<String>,
+    pub registered_keys: Vec<String>,
 
     #[serde(rename = "ActiveKeys")]
-    active_keys: Vec<String>,
+    pub active_keys: Vec<String>,
 
     #[serde(rename = "ConnectedDeviceCount")]
-    connected_device_count: u32,
+    pub connected_device_count: u32,
 
     #[serde(rename = "ConnectedPositions")]
-    connected_positions: Vec<String>,
+    pub connected_positions: Vec<String>,
 
+    /// Intensity (`0..=100`) of every motor, per reported position
     #[serde(rename = "Status")]
-    status: HashMap<String, i64>,
+    pub status: HashMap<String, Vec<u8>>,
+}
+
+/// Number of motors reported per position, whatever the actual layout is.
+const STATUS_MOTOR_COUNT: usize = 20;
+
+impl PlayerResponse {
+    /// Response to a client whose keys live in `namespace`, other clients' keys are not reported.
+    pub fn from_player(player: &HapticPlayer, namespace: &KeyNamespace) -> Self {
+        let frame = player.frame();
+        let connected_positions = player.connected_positions();
+        let own_keys = |keys: Vec<String>| keys.iter().filter_map(|key| namespace.unscope(key)).map(String::from).collect();
+
+        Self {
+            registered_keys: own_keys(player.registered_keys()),
+            active_keys: own_keys(player.active_keys()),
+            connected_device_count: player.device_count() as u32,
+            connected_positions: connected_positions
+                .iter()
+                .map(|part| PositionType::from_body_part(*part).name().to_string())
+                .collect(),
+            status: PositionType::REPORTED
+                .iter()
+                .map(|position| {
+                    let mut motors = vec![0; STATUS_MOTOR_COUNT];
+                    if let Some(intensities) = position.body_parts().first().and_then(|part| frame.get(*part)) {
+                        for (motor, intensity) in motors.iter_mut().zip(intensities) {
+                            *motor = (intensity * 100.0).round() as u8;
+                        }
+                    }
+
+                    (position.name().to_string(), motors)
+                })
+                .collect(),
+        }
+    }
+
+    /// Checks what clients rely on: every reported position has its motors, within range,
+    /// and connected positions are known ones.
+    pub fn validate(&self) -> Result<(), String> {
+        for position in PositionType::REPORTED {
+            let motors = self.status
+                .get(position.name())
+                .ok_or_else(|| format!("Status lacks {}", position.name()))?;
+            if motors.len() != STATUS_MOTOR_COUNT {
+                return Err(format!("Status of {} has {} motors instead of {}", position.name(), motors.len(), STATUS_MOTOR_COUNT));
+            }
+            if let Some(intensity) = motors.iter().find(|intensity| **intensity > 100) {
+                return Err(format!("Status of {} has intensity {} above 100", position.name(), intensity));
+            }
+        }
+
+        if let Some(position) = self.connected_positions.iter().find(|position| PositionType::from_str(position).is_err()) {
+            return Err(format!("ConnectedPositions has unknown position {}", position));
+        }
+
+        if self.connected_device_count == 0 && !self.connected_positions.is_empty() {
+            return Err(String::from("ConnectedPositions is not empty without connected devices"));
+        }
+
+        Ok(())
+    }
 }
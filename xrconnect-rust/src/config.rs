@@ -0,0 +1,474 @@
+//! Settings of a whole server in one TOML file, `xrconnect.toml` in the config directory.
+//!
+//! Every section is optional. Sections left out keep the defaults, or what is read from the
+//! dedicated files (`apps.json`, `access.json`) and the saved state. Relative paths are
+//! resolved against the directory of the file.
+//!
+//! A running server watches the file: calibration, safety limits, application settings, the
+//! access policy and the log level are applied as soon as it changes, while changes to the
+//! listeners, frontends, proxy and outputs only take effect after a restart, see
+//! [`Config::restart_required`].
+//!
+//! # Example Configuration
+//! ```toml
+//! [server]
+//! listeners = ["127.0.0.1:15881", "unix:/run/user/1000/xrconnect.sock"]
+//! share_app_namespace = true
+//! control_socket = "/run/user/1000/xrconnect-control.sock"
+//!
+//! [server.tls]
+//! bind = "0.0.0.0:15882"
+//! names = ["xrconnect.local"]
+//!
+//! [frontends]
+//! api_token = "change-me"
+//!
+//! [[outputs]]
+//! type = "audio-live"
+//! mapping = "mappings/vest-to-chair.json"
+//!
+//! [calibration]
+//! profile = "default"
+//!
+//! [safety]
+//! max_on_millis = 5000
+//!
+//! [apps.apps."com.example.game"]
+//! priority = 10
+//!
+//! [access]
+//! unknown_apps = "Ask"
+//!
+//! [logging]
+//! level = "debug"
+//! ```
+
+use std::{
+    fmt,
+    fs,
+    io,
+    net::SocketAddr,
+    path::{ Path, PathBuf },
+    str::FromStr,
+    time::{ Duration, SystemTime },
+};
+
+use serde::{ Serialize, Deserialize };
+use tracing::Level;
+
+use crate::access::{ AccessControl, AccessPolicy };
+use crate::bhaptics_studio::{
+    listener::Listener,
+    proxy::{ BHapticsProxy, ProxyRules },
+    server::Frontends,
+};
+use crate::devices::audio::{ AudioOutputConfig, WavAudioDevice };
+use crate::haptics::{
+    apps::AppProfiles,
+    calibration::CalibrationStore,
+    device::HapticDevice,
+    mapping::{ BodyMapping, MappedDevice },
+    player::HapticPlayer,
+    safety::SafetyLimits,
+};
+use crate::tls::{ TlsCertificate, TlsConfig };
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { file: PathBuf, error: io::Error },
+    /// Not valid TOML
+    Syntax { file: PathBuf, line: usize, column: usize, reason: String },
+    /// Valid TOML, but `key` has an unexpected type or value
+    Invalid { file: PathBuf, key: String, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { file, error } => write!(f, "{}: {}", file.display(), error),
+            ConfigError::Syntax { file, line, column, reason } => write!(f, "{}:{}:{}: {}", file.display(), line, column, reason),
+            ConfigError::Invalid { file, key, reason } if key.is_empty() => write!(f, "{}: {}", file.display(), reason),
+            ConfigError::Invalid { file, key, reason } => write!(f, "{}: {}: {}", file.display(), key, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+
+    pub frontends: Frontends,
+
+    /// Relays the bHaptics clients to another player instead of playing their effects
+    pub proxy: Option<ProxyConfig>,
+
+    /// Output backends added to the ones found automatically
+    pub outputs: Vec<OutputConfig>,
+
+    pub calibration: CalibrationConfig,
+
+    pub safety: Option<SafetyLimits>,
+
+    /// Replaces `apps.json` when present
+    pub apps: Option<AppProfiles>,
+
+    /// Replaces `access.json` when present
+    pub access: Option<AccessPolicy>,
+
+    pub logging: LoggingConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// `host:port` addresses, or `unix:` followed by the path of a Unix domain socket.
+    /// 127.0.0.1:15881 when empty
+    pub listeners: Vec<String>,
+
+    /// Clients with the same app_id share their keys, across reconnects
+    pub share_app_namespace: bool,
+
+    /// Seconds given to the clients to disconnect when shutting down
+    pub shutdown_timeout_secs: Option<f32>,
+
+    /// Additional wss:// listener
+    pub tls: Option<TlsSection>,
+
+    /// Session file the client traffic is recorded to
+    pub record: Option<PathBuf>,
+
+    /// Where the CLI commands reach the server, `control.sock` in the config directory when absent
+    pub control_socket: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsSection {
+    pub bind: SocketAddr,
+
+    /// Certificate chain and private key, PEM encoded. A self-signed certificate is used without them
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+
+    /// Names the self-signed certificate is valid for, besides `localhost` and the loopback addresses
+    pub names: Vec<String>,
+}
+
+impl Default for TlsSection {
+    fn default() -> Self {
+        Self {
+            bind: TlsConfig::default().address,
+            cert: None,
+            key: None,
+            names: vec![],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// bHaptics Player the clients are relayed to, `ws://host:port`
+    pub upstream: String,
+
+    #[serde(default)]
+    pub rules: ProxyRules,
+
+    /// Plays the relayed messages on the local devices as well
+    #[serde(default)]
+    pub mirror: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputKind {
+    /// Renders the audio signal into the WAV file at `path`
+    AudioWav,
+
+    /// Plays the audio signal on the sound card named `device`, the default one without it
+    AudioLive,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    #[serde(rename = "type")]
+    pub kind: OutputKind,
+
+    pub path: Option<PathBuf>,
+
+    pub device: Option<String>,
+
+    /// Channels of the audio signal, stereo chest front and back when absent
+    pub audio: Option<AudioOutputConfig>,
+
+    /// Body mapping the frames go through before reaching the output
+    pub mapping: Option<PathBuf>,
+}
+
+impl OutputConfig {
+    pub fn open(&self) -> Result<Box<dyn HapticDevice>, String> {
+        let audio = self.audio.clone().unwrap_or_default();
+        match self.kind {
+            OutputKind::AudioWav => {
+                let path = self.path.as_ref().ok_or("missing path")?;
+                let device = WavAudioDevice::create(path, &audio)
+                    .map_err(|why| format!("failed to create {}: {}", path.display(), why))?;
+                self.mapped(device)
+            },
+            #[cfg(feature = "audio-live")]
+            OutputKind::AudioLive => {
+                let device = crate::devices::audio::LiveAudioDevice::open(self.device.as_deref(), &audio)
+                    .map_err(|why| format!("failed to open the audio output: {}", why))?;
+                self.mapped(device)
+            },
+            #[cfg(not(feature = "audio-live"))]
+            OutputKind::AudioLive => Err(String::from("live audio output is not available in this build")),
+        }
+    }
+
+    fn mapped<D: HapticDevice + 'static>(&self, device: D) -> Result<Box<dyn HapticDevice>, String> {
+        match &self.mapping {
+            Some(path) => {
+                let mapping = BodyMapping::load(path).map_err(|why| format!("{}: {}", path.display(), why))?;
+                Ok(Box::new(MappedDevice::new(device, mapping)))
+            },
+            None => Ok(Box::new(device)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalibrationConfig {
+    /// Stored profile applied, see [`CalibrationStore`]
+    pub profile: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// `error`, `warn`, `info`, `debug` or `trace`
+    pub level: Option<String>,
+}
+
+impl Config {
+    /// Reads the configuration from `path`, the default configuration when there is no such file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(why) => return Err(ConfigError::Io { file: path.to_path_buf(), error: why }),
+        };
+
+        let mut config = Self::from_toml(&content, path)?;
+        if let Some(dir) = path.parent() {
+            config.resolve_paths(dir);
+        }
+        config.validate(path)?;
+
+        Ok(config)
+    }
+
+    /// Parses `content`, errors mention `file` as where it came from.
+    pub fn from_toml(content: &str, file: &Path) -> Result<Self, ConfigError> {
+        if let Err(why) = content.parse::<toml::Table>() {
+            let (line, column) = why.span().map_or((1, 1), |span| line_and_column(content, span.start));
+            return Err(ConfigError::Syntax {
+                file: file.to_path_buf(),
+                line,
+                column,
+                reason: why.message().to_string(),
+            });
+        }
+
+        serde_path_to_error::deserialize(toml::Deserializer::new(content)).map_err(|why| ConfigError::Invalid {
+            file: file.to_path_buf(),
+            key: match why.path().to_string().as_str() {
+                "." => String::new(),
+                path => path.to_string(),
+            },
+            reason: why.inner().message().to_string(),
+        })
+    }
+
+    /// Checks the values serde cannot, naming the offending key.
+    pub fn validate(&self, file: &Path) -> Result<(), ConfigError> {
+        let invalid = |key: String, reason: String| ConfigError::Invalid { file: file.to_path_buf(), key, reason };
+
+        for (index, listener) in self.server.listeners.iter().enumerate() {
+            parse_listener(listener).map_err(|why| invalid(format!("server.listeners[{}]", index), why))?;
+        }
+
+        if let Some(tls) = &self.server.tls {
+            if tls.cert.is_some() != tls.key.is_some() {
+                return Err(invalid(String::from("server.tls"), String::from("cert and key go together")));
+            }
+        }
+
+        if let Some(proxy) = &self.proxy {
+            BHapticsProxy::new(&proxy.upstream).map_err(|why| invalid(String::from("proxy.upstream"), why.to_string()))?;
+            proxy.rules.validate().map_err(|why| invalid(String::from("proxy.rules.intensity"), why.to_string()))?;
+        }
+
+        for (index, output) in self.outputs.iter().enumerate() {
+            if output.kind == OutputKind::AudioWav && output.path.is_none() {
+                return Err(invalid(format!("outputs[{}].path", index), String::from("required by audio-wav outputs")));
+            }
+            if let Some(path) = &output.mapping {
+                BodyMapping::load(path).map_err(|why| invalid(format!("outputs[{}].mapping", index), format!("{}: {}", path.display(), why)))?;
+            }
+        }
+
+        if let Some(name) = &self.calibration.profile {
+            CalibrationStore::default().load(name).map_err(|why| invalid(String::from("calibration.profile"), why.to_string()))?;
+        }
+
+        if let Some(safety) = &self.safety {
+            safety.validate().map_err(|why| invalid(String::from("safety"), why.to_string()))?;
+        }
+
+        if let Some(apps) = &self.apps {
+            apps.validate().map_err(|why| invalid(String::from("apps"), why.to_string()))?;
+        }
+
+        self.log_level().map_err(|why| invalid(String::from("logging.level"), why))?;
+
+        Ok(())
+    }
+
+    /// Addresses and sockets to listen on, the default one when empty.
+    pub fn listeners(&self) -> Vec<Listener> {
+        let mut listeners: Vec<Listener> = self.server.listeners
+            .iter()
+            .filter_map(|listener| parse_listener(listener).ok())
+            .collect();
+        if listeners.is_empty() {
+            listeners.push(Listener::Tcp(([127, 0, 0, 1], 15881).into()));
+        }
+
+        if let Some(tls) = &self.server.tls {
+            let certificate = match (&tls.cert, &tls.key) {
+                (Some(cert), Some(key)) => TlsCertificate::Files { cert: cert.clone(), key: key.clone() },
+                _ => TlsCertificate::SelfSigned { names: tls.names.clone() },
+            };
+            listeners.push(Listener::Tls(TlsConfig { address: tls.bind, certificate }));
+        }
+
+        listeners
+    }
+
+    pub fn log_level(&self) -> Result<Option<Level>, String> {
+        self.logging.level
+            .as_deref()
+            .map(|level| Level::from_str(level).map_err(|_| format!("unknown level {:?}, expected error, warn, info, debug or trace", level)))
+            .transpose()
+    }
+
+    /// Applies the sections which can change while the server runs, those left out are untouched.
+    pub fn apply_live(&self, player: &HapticPlayer, access: &AccessControl) -> Result<(), String> {
+        if let Some(name) = &self.calibration.profile {
+            let profile = CalibrationStore::default().load(name).map_err(|why| why.to_string())?;
+            player.set_calibration(profile);
+        }
+
+        if let Some(limits) = &self.safety {
+            player.set_safety_limits(limits.clone());
+        }
+
+        if let Some(apps) = &self.apps {
+            player.set_app_profiles(apps.clone());
+        }
+
+        if let Some(policy) = &self.access {
+            access.set_policy(policy.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Sections changed since `previous` which only take effect after a restart.
+    pub fn restart_required(&self, previous: &Config) -> Vec<&'static str> {
+        let mut sections = vec![];
+        if self.server != previous.server {
+            sections.push("server");
+        }
+        if self.frontends != previous.frontends {
+            sections.push("frontends");
+        }
+        if self.proxy != previous.proxy {
+            sections.push("proxy");
+        }
+        if self.outputs != previous.outputs {
+            sections.push("outputs");
+        }
+        sections
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        };
+
+        for listener in &mut self.server.listeners {
+            if let Some(path) = listener.strip_prefix("unix:").filter(|path| Path::new(path).is_relative()) {
+                *listener = format!("unix:{}", dir.join(path).display());
+            }
+        }
+        if let Some(tls) = &mut self.server.tls {
+            tls.cert.as_mut().map(resolve);
+            tls.key.as_mut().map(resolve);
+        }
+        self.server.record.as_mut().map(resolve);
+        self.server.control_socket.as_mut().map(resolve);
+
+        for output in &mut self.outputs {
+            output.path.as_mut().map(resolve);
+            output.mapping.as_mut().map(resolve);
+        }
+    }
+}
+
+/// Calls `on_change` with the new configuration each time the file at `path` is modified,
+/// checking every `interval`. Never returns.
+pub async fn watch(path: PathBuf, interval: Duration, mut on_change: impl FnMut(Result<Config, ConfigError>)) {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+
+    let mut last: Option<SystemTime> = modified(&path);
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+
+        let current = modified(&path);
+        if current.is_some() && current != last {
+            last = current;
+            on_change(Config::load(&path));
+        }
+    }
+}
+
+fn parse_listener(listener: &str) -> Result<Listener, String> {
+    match listener.strip_prefix("unix:") {
+        Some(path) if !path.is_empty() => Ok(Listener::Unix(PathBuf::from(path))),
+        Some(_) => Err(String::from("missing socket path after unix:")),
+        None => listener
+            .parse()
+            .map(Listener::Tcp)
+            .map_err(|_| format!("{:?} is neither host:port nor unix:path", listener)),
+    }
+}
+
+/// 1-based line and column of the byte at `offset`.
+fn line_and_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |line| line.chars().count()) + 1;
+    (line, column)
+}
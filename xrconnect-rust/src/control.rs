@@ -0,0 +1,314 @@
+//! Local control channel of a running server, so that CLI commands can act on it.
+//!
+//! The server listens on a Unix domain socket only the current user may connect to,
+//! `control.sock` in the config directory by default. Every request is a line of JSON,
+//! answered by a line of JSON, and a connection can carry as many requests as needed.
+//!
+//! # Example Exchange
+//! ```json
+//! {"command":"status"}
+//! {"status":{"version":"0.1.0","uptime_secs":42,"listeners":["http://127.0.0.1:15881"],"clients":1,"devices":1,"registered_keys":3,"active_keys":["hit"],"muted":[],"panicked":false}}
+//! {"command":"stop_all"}
+//! {"done":"Stopped 1 effects"}
+//! {"command":"reload"}
+//! {"error":"xrconnect.toml:3:8: invalid string"}
+//! ```
+//!
+//! `play` carries the pattern itself, so files are read by the CLI:
+//! ```json
+//! {"command":"play","key":"hit","pattern":{"clips":[]},"options":{"intensity":0.5}}
+//! ```
+
+use std::{
+    fmt,
+    io,
+    path::{ Path, PathBuf },
+    sync::Arc,
+    time::{ Duration, Instant },
+};
+
+use futures_util::future::BoxFuture;
+use haptic_lib::BodyPart;
+use serde::{ Serialize, Deserialize };
+use tokio_util::sync::CancellationToken;
+use tracing::{ debug, info, warn };
+
+use crate::{
+    bhaptics_studio::clients::Clients,
+    haptics::{
+        model::{ HapticPattern, PlaybackOptions },
+        player::{ DeviceStatus, HapticPlayer },
+    },
+};
+
+/// Time given to a running server to answer a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+    Status,
+
+    /// Stops every effect, of every client
+    StopAll,
+
+    /// Applies the configuration file again, right away
+    Reload,
+
+    /// Plays `pattern` under `key`
+    Play {
+        key: String,
+        pattern: HapticPattern,
+
+        #[serde(default)]
+        options: PlaybackOptions,
+    },
+
+    Devices,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlResponse {
+    Status(ServerStatus),
+    Devices(Vec<DeviceStatus>),
+
+    /// The request was carried out, as described
+    Done(String),
+
+    Error(String),
+}
+
+/// What `status` reports of a running server.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ServerStatus {
+    pub version: String,
+    pub uptime_secs: u64,
+
+    /// Where the server accepts connections, as URLs
+    pub listeners: Vec<String>,
+
+    /// bHaptics clients connected
+    pub clients: usize,
+
+    pub devices: usize,
+    pub registered_keys: usize,
+    pub active_keys: Vec<String>,
+    pub muted: Vec<BodyPart>,
+
+    /// Whether the kill switch is engaged
+    pub panicked: bool,
+}
+
+#[derive(Debug)]
+pub enum ControlError {
+    /// Nothing listens on the socket
+    Unreachable { path: PathBuf, error: io::Error },
+
+    /// The connection failed midway, or the server took too long to answer
+    Io(io::Error),
+
+    /// The answer is not a response
+    Protocol(String),
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlError::Unreachable { path, error } => write!(f, "no running instance at {}: {}", path.display(), error),
+            ControlError::Io(why) => write!(f, "control connection failed: {}", why),
+            ControlError::Protocol(why) => write!(f, "invalid control response: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for ControlError {}
+
+/// Applies the configuration file again, describing what changed or why it could not be applied.
+pub type ReloadHook = Arc<dyn Fn() -> Result<String, String> + Send + Sync>;
+
+/// Control socket of a server, see the [module](self) documentation.
+#[derive(Clone)]
+pub struct ControlSocket {
+    path: PathBuf,
+
+    /// `reload` fails without it
+    reload: Option<ReloadHook>,
+}
+
+impl fmt::Debug for ControlSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ControlSocket")
+            .field("path", &self.path)
+            .field("reload", &self.reload.is_some())
+            .finish()
+    }
+}
+
+/// What requests act on.
+#[derive(Clone)]
+pub(crate) struct ControlTarget {
+    pub player: HapticPlayer,
+    pub clients: Clients,
+    pub listeners: Vec<String>,
+    pub started: Instant,
+}
+
+impl ControlSocket {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            reload: None,
+        }
+    }
+
+    pub fn with_reload(mut self, reload: impl Fn() -> Result<String, String> + Send + Sync + 'static) -> Self {
+        self.reload = Some(Arc::new(reload));
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Starts listening, the returned future answers requests until `shutdown` is cancelled.
+    #[cfg(unix)]
+    pub(crate) fn serve(&self, target: ControlTarget, shutdown: CancellationToken) -> Result<BoxFuture<'static, ()>, String> {
+        let listener = crate::bhaptics_studio::listener::bind_unix(&self.path)?;
+        info!("Control socket listening on {}", self.path.display());
+
+        let socket = self.clone();
+        Ok(Box::pin(async move {
+            loop {
+                let stream = tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => stream,
+                        Err(why) => {
+                            warn!("Failed to accept a control connection: {}", why);
+                            continue;
+                        },
+                    },
+                    _ = shutdown.cancelled() => break,
+                };
+
+                let socket = socket.clone();
+                let target = target.clone();
+                tokio::spawn(async move {
+                    if let Err(why) = socket.answer(stream, &target).await {
+                        debug!("Control connection ended: {}", why);
+                    }
+                });
+            }
+        }))
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn serve(&self, _target: ControlTarget, _shutdown: CancellationToken) -> Result<BoxFuture<'static, ()>, String> {
+        Err(String::from("Unix domain sockets are not supported on this platform"))
+    }
+
+    /// Removes the socket file.
+    pub(crate) fn cleanup(&self) {
+        if let Err(why) = std::fs::remove_file(&self.path) {
+            warn!("Failed to remove {}: {}", self.path.display(), why);
+        }
+    }
+
+    #[cfg(unix)]
+    async fn answer(&self, stream: tokio::net::UnixStream, target: &ControlTarget) -> io::Result<()> {
+        use tokio::io::{ AsyncBufReadExt, AsyncWriteExt, BufReader };
+
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str::<ControlRequest>(&line) {
+                Ok(request) => self.handle(request, target),
+                Err(why) => ControlResponse::Error(format!("invalid request: {}", why)),
+            };
+
+            let mut response = serde_json::to_string(&response).map_err(io::Error::other)?;
+            response.push('\n');
+            writer.write_all(response.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    fn handle(&self, request: ControlRequest, target: &ControlTarget) -> ControlResponse {
+        let player = &target.player;
+
+        match request {
+            ControlRequest::Status => ControlResponse::Status(ServerStatus {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                uptime_secs: target.started.elapsed().as_secs(),
+                listeners: target.listeners.clone(),
+                clients: target.clients.len(),
+                devices: player.device_count(),
+                registered_keys: player.registered_keys().len(),
+                active_keys: player.active_keys(),
+                muted: player.muted().into_iter().collect(),
+                panicked: player.is_panicked(),
+            }),
+            ControlRequest::Devices => ControlResponse::Devices(player.devices()),
+            ControlRequest::StopAll => {
+                let stopped = player.active_keys().len();
+                player.stop_all();
+                info!("Every effect stopped from the control socket");
+                ControlResponse::Done(format!("Stopped {} effects", stopped))
+            },
+            ControlRequest::Reload => match &self.reload {
+                Some(reload) => match reload() {
+                    Ok(done) => ControlResponse::Done(done),
+                    Err(why) => ControlResponse::Error(why),
+                },
+                None => ControlResponse::Error(String::from("this server has no configuration file to reload")),
+            },
+            ControlRequest::Play { key, pattern, options } => {
+                let duration = pattern.duration_millis();
+                match player.play_pattern(key.as_str(), pattern, options) {
+                    Ok(()) => ControlResponse::Done(format!("Playing {} ({} ms)", key, duration)),
+                    Err(why) => ControlResponse::Error(why.to_string()),
+                }
+            },
+        }
+    }
+}
+
+/// Sends `request` to the server listening on `path`, returning its response.
+#[cfg(unix)]
+pub async fn request(path: impl AsRef<Path>, request: &ControlRequest) -> Result<ControlResponse, ControlError> {
+    use tokio::io::{ AsyncBufReadExt, AsyncWriteExt, BufReader };
+
+    let path = path.as_ref();
+    let mut stream = tokio::net::UnixStream::connect(path)
+        .await
+        .map_err(|error| ControlError::Unreachable { path: path.to_path_buf(), error })?;
+
+    let exchange = async {
+        let mut line = serde_json::to_string(request).map_err(|why| ControlError::Protocol(why.to_string()))?;
+        line.push('\n');
+        stream.write_all(line.as_bytes()).await.map_err(ControlError::Io)?;
+
+        let mut response = String::new();
+        if BufReader::new(&mut stream).read_line(&mut response).await.map_err(ControlError::Io)? == 0 {
+            return Err(ControlError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed without a response")));
+        }
+        serde_json::from_str(&response).map_err(|why| ControlError::Protocol(why.to_string()))
+    };
+
+    tokio::time::timeout(REQUEST_TIMEOUT, exchange)
+        .await
+        .map_err(|_| ControlError::Io(io::Error::new(io::ErrorKind::TimedOut, "no response")))?
+}
+
+#[cfg(not(unix))]
+pub async fn request(path: impl AsRef<Path>, _request: &ControlRequest) -> Result<ControlResponse, ControlError> {
+    Err(ControlError::Unreachable {
+        path: path.as_ref().to_path_buf(),
+        error: io::Error::new(io::ErrorKind::Unsupported, "Unix domain sockets are not supported on this platform"),
+    })
+}
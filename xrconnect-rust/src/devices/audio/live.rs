@@ -0,0 +1,113 @@
+use std::{
+    sync::{ mpsc, Arc, Mutex },
+    thread,
+};
+
+use cpal::traits::{ DeviceTrait, HostTrait, StreamTrait };
+use haptic_lib::BodyPart;
+use tracing::error;
+
+use crate::haptics::{
+    device::{ DeviceError, HapticDevice },
+    model::HapticFrame,
+};
+
+use super::{ AudioOutputConfig, AudioSynth };
+
+/// Plays the audio signal on a sound card, the synthesiser is pulled by the audio callback.
+pub struct LiveAudioDevice {
+    name: String,
+    positions: Vec<BodyPart>,
+    synth: Arc<Mutex<AudioSynth>>,
+
+    /// Streams are not `Send` on every platform, so the stream lives on its own
+    /// thread until this sender is dropped
+    _stop: mpsc::Sender<()>,
+}
+
+impl LiveAudioDevice {
+    /// Opens `device_name`, or the default output device when `None`.
+    ///
+    /// The configured sample rate is replaced by the device's default one.
+    pub fn open(device_name: Option<&str>, config: &AudioOutputConfig) -> Result<Self, DeviceError> {
+        let host = cpal::default_host();
+        let device = match device_name {
+            None => host.default_output_device(),
+            Some(name) => host
+                .output_devices()
+                .map_err(audio_error)?
+                .find(|device| device.name().is_ok_and(|n| n == name)),
+        }
+            .ok_or_else(|| DeviceError::Other(format!("no audio output device {:?}", device_name)))?;
+
+        let name = format!("audio:{}", device.name().map_err(audio_error)?);
+        let sample_rate = device.default_output_config().map_err(audio_error)?.sample_rate();
+        let config = AudioOutputConfig {
+            sample_rate: sample_rate.0,
+            ..config.clone()
+        };
+        let stream_config = cpal::StreamConfig {
+            channels: config.channels.len() as u16,
+            sample_rate,
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let synth = Arc::new(Mutex::new(AudioSynth::new(&config)));
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let (ready_tx, ready_rx) = mpsc::channel::<Result<(), DeviceError>>();
+
+        thread::spawn({
+            let synth = synth.clone();
+            let name = name.clone();
+
+            move || {
+                let stream = device.build_output_stream(
+                    &stream_config,
+                    move |data: &mut [f32], _: &cpal::OutputCallbackInfo| synth.lock().unwrap().render(data),
+                    move |why| error!("Audio stream {} failed: {}", name, why),
+                    None,
+                );
+
+                match stream.map_err(audio_error).and_then(|stream| stream.play().map_err(audio_error).map(|_| stream)) {
+                    Ok(stream) => {
+                        let _ = ready_tx.send(Ok(()));
+                        // Blocks until the device is dropped
+                        let _ = stop_rx.recv();
+                        drop(stream);
+                    },
+                    Err(why) => {
+                        let _ = ready_tx.send(Err(why));
+                    },
+                }
+            }
+        });
+
+        ready_rx.recv().map_err(|_| DeviceError::Disconnected)??;
+
+        Ok(Self {
+            name,
+            positions: config.positions(),
+            synth,
+            _stop: stop_tx,
+        })
+    }
+}
+
+impl HapticDevice for LiveAudioDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn positions(&self) -> Vec<BodyPart> {
+        self.positions.clone()
+    }
+
+    fn write(&mut self, frame: &HapticFrame) -> Result<(), DeviceError> {
+        self.synth.lock().unwrap().set_frame(frame);
+        Ok(())
+    }
+}
+
+fn audio_error(why: impl std::fmt::Display) -> DeviceError {
+    DeviceError::Other(format!("audio error: {}", why))
+}
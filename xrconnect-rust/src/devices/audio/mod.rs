@@ -0,0 +1,80 @@
+//! Output backend for tactile transducers (bass shakers, butt kickers...), which are driven by audio.
+//!
+//! Every audio channel plays a sine carrier whose amplitude follows the mixed intensity
+//! of the motors mapped to it.
+
+use haptic_lib::BodyPart;
+use serde::{self, Serialize, Deserialize};
+
+pub mod synth;
+pub mod wav;
+
+#[cfg(feature = "audio-live")]
+pub mod live;
+
+pub use synth::AudioSynth;
+pub use wav::WavAudioDevice;
+
+#[cfg(feature = "audio-live")]
+pub use live::LiveAudioDevice;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AudioChannelConfig {
+    /// Body part whose motors drive the channel
+    pub part: BodyPart,
+
+    /// Motors of the body part mixed into the channel, every motor when empty
+    #[serde(default)]
+    pub motors: Vec<usize>,
+
+    /// Frequency of the carrier, most transducers respond best between 30 and 80 Hz
+    pub carrier_hz: f32,
+
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AudioOutputConfig {
+    pub sample_rate: u32,
+
+    /// One entry per output channel, in the interleaving order
+    pub channels: Vec<AudioChannelConfig>,
+}
+
+impl Default for AudioOutputConfig {
+    /// Stereo output, chest front on the left channel and chest back on the right one.
+    fn default() -> Self {
+        Self {
+            sample_rate: 48_000,
+            channels: vec![
+                AudioChannelConfig {
+                    part: BodyPart::ChestFront,
+                    motors: vec![],
+                    carrier_hz: 40.0,
+                    gain: 1.0,
+                },
+                AudioChannelConfig {
+                    part: BodyPart::ChestBack,
+                    motors: vec![],
+                    carrier_hz: 40.0,
+                    gain: 1.0,
+                },
+            ],
+        }
+    }
+}
+
+impl AudioOutputConfig {
+    /// Distinct body parts driven by the channels.
+    pub fn positions(&self) -> Vec<BodyPart> {
+        let mut positions: Vec<BodyPart> = self.channels.iter().map(|channel| channel.part).collect();
+        positions.sort();
+        positions.dedup();
+        positions
+    }
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
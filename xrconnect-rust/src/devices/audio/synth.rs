@@ -0,0 +1,83 @@
+use std::f32::consts::TAU;
+
+use crate::haptics::model::HapticFrame;
+
+use super::{ AudioChannelConfig, AudioOutputConfig };
+
+/// Time for a channel to go from silent to full amplitude, avoids clicks on sudden changes.
+const AMPLITUDE_RAMP_SECONDS: f32 = 0.005;
+
+struct ChannelState {
+    config: AudioChannelConfig,
+    phase: f32,
+    amplitude: f32,
+    target: f32,
+}
+
+/// Multichannel sine synthesiser, rendering interleaved `f32` samples.
+pub struct AudioSynth {
+    sample_rate: u32,
+    channels: Vec<ChannelState>,
+}
+
+impl AudioSynth {
+    pub fn new(config: &AudioOutputConfig) -> Self {
+        Self {
+            sample_rate: config.sample_rate,
+            channels: config.channels
+                .iter()
+                .map(|channel| ChannelState {
+                    config: channel.clone(),
+                    phase: 0.0,
+                    amplitude: 0.0,
+                    target: 0.0,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Sets the amplitude every channel ramps to, from the strongest of its motors in `frame`.
+    pub fn set_frame(&mut self, frame: &HapticFrame) {
+        for channel in &mut self.channels {
+            let intensity = match frame.get(channel.config.part) {
+                None => 0.0,
+                Some(motors) if channel.config.motors.is_empty() => motors.iter().copied().fold(0.0, f32::max),
+                Some(motors) => channel.config.motors
+                    .iter()
+                    .filter_map(|index| motors.get(*index).copied())
+                    .fold(0.0, f32::max),
+            };
+
+            channel.target = (intensity * channel.config.gain).clamp(0.0, 1.0);
+        }
+    }
+
+    /// Fills `out` with interleaved samples, its length should be a multiple of the channel count.
+    pub fn render(&mut self, out: &mut [f32]) {
+        if self.channels.is_empty() {
+            out.fill(0.0);
+            return;
+        }
+
+        let step = 1.0 / (AMPLITUDE_RAMP_SECONDS * self.sample_rate as f32);
+        let sample_rate = self.sample_rate as f32;
+
+        for samples in out.chunks_mut(self.channels.len()) {
+            for (sample, channel) in samples.iter_mut().zip(self.channels.iter_mut()) {
+                let delta = (channel.target - channel.amplitude).clamp(-step, step);
+                channel.amplitude += delta;
+
+                *sample = channel.amplitude * channel.phase.sin();
+                channel.phase = (channel.phase + TAU * channel.config.carrier_hz / sample_rate) % TAU;
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+};
+
+use haptic_lib::BodyPart;
+
+use crate::haptics::{
+    device::{ DeviceError, HapticDevice },
+    model::HapticFrame,
+    player::FRAME_INTERVAL,
+};
+
+use super::{ AudioOutputConfig, AudioSynth };
+
+/// Renders the audio signal into a WAV file, one [`FRAME_INTERVAL`] of samples per written frame.
+///
+/// Output does not depend on wall clock time, which makes it suitable for tests.
+pub struct WavAudioDevice {
+    name: String,
+    positions: Vec<BodyPart>,
+    synth: AudioSynth,
+    writer: Option<hound::WavWriter<BufWriter<File>>>,
+    buffer: Vec<f32>,
+}
+
+impl WavAudioDevice {
+    pub fn create(path: impl AsRef<Path>, config: &AudioOutputConfig) -> Result<Self, DeviceError> {
+        let spec = hound::WavSpec {
+            channels: config.channels.len() as u16,
+            sample_rate: config.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = hound::WavWriter::create(path.as_ref(), spec).map_err(wav_error)?;
+
+        let samples_per_frame = (config.sample_rate as u128 * FRAME_INTERVAL.as_millis() / 1000) as usize;
+
+        Ok(Self {
+            name: format!("audio:{}", path.as_ref().display()),
+            positions: config.positions(),
+            synth: AudioSynth::new(config),
+            writer: Some(writer),
+            buffer: vec![0.0; samples_per_frame * config.channels.len()],
+        })
+    }
+
+    /// Writes the WAV header sizes. Also done on drop, but errors are lost there.
+    pub fn finish(mut self) -> Result<(), DeviceError> {
+        match self.writer.take() {
+            Some(writer) => writer.finalize().map_err(wav_error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl HapticDevice for WavAudioDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn positions(&self) -> Vec<BodyPart> {
+        self.positions.clone()
+    }
+
+    fn write(&mut self, frame: &HapticFrame) -> Result<(), DeviceError> {
+        let writer = self.writer.as_mut().ok_or(DeviceError::Disconnected)?;
+
+        self.synth.set_frame(frame);
+        self.synth.render(&mut self.buffer);
+
+        for sample in &self.buffer {
+            writer
+                .write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+                .map_err(wav_error)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), DeviceError> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.flush().map_err(wav_error),
+            None => Ok(()),
+        }
+    }
+}
+
+fn wav_error(why: hound::Error) -> DeviceError {
+    match why {
+        hound::Error::IoError(why) => DeviceError::Io(why),
+        why => DeviceError::Other(format!("WAV error: {}", why)),
+    }
+}
@@ -0,0 +1,189 @@
+use haptic_lib::BodyPart;
+use serde::{self, Serialize, Deserialize};
+
+use crate::haptics::model::{ HapticFrame, MotorLayout };
+
+/// Nordic UART RX characteristic, which bHaptics devices take motor packets on.
+pub const MOTOR_CHARACTERISTIC: &str = "6e400002-b5a3-f393-e0a9-e50e24dcca9e";
+
+/// Length of every motor packet, unused trailing bytes are zero.
+pub const MOTOR_PACKET_LENGTH: usize = 20;
+
+/// Highest intensity of gloves, which take one byte per motor.
+const GLOVE_MAX_INTENSITY: f32 = 100.0;
+
+/// Highest intensity of devices packing two motors per byte.
+const NIBBLE_MAX_INTENSITY: f32 = 15.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    fn pick(&self, left: BodyPart, right: BodyPart) -> BodyPart {
+        match self {
+            Side::Left => left,
+            Side::Right => right,
+        }
+    }
+}
+
+/// bHaptics device families, each one with its own motor layout and packet format.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeviceFamily {
+    /// Vest with 8 motors on each side, in 2 columns and 4 rows
+    TactSuitX16,
+    /// Vest with 20 motors on each side, in 4 columns and 5 rows
+    TactSuitX40,
+    /// Arm sleeve, 6 motors in 3 columns and 2 rows
+    Tactosy2(Side),
+    /// Hand, 3 motors in a row
+    TactosyH(Side),
+    /// Foot, 3 motors in a row
+    TactosyF(Side),
+    /// Head, 6 motors in a row
+    Tactal,
+    /// Glove, 5 finger tips and the wrist
+    TactGlove(Side),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub characteristic: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+impl DeviceFamily {
+    /// Families able to drive `part`.
+    pub fn candidates(part: BodyPart) -> Vec<DeviceFamily> {
+        let side = match part {
+            BodyPart::ForearmL | BodyPart::HandL | BodyPart::FootL | BodyPart::GloveL => Side::Left,
+            _ => Side::Right,
+        };
+
+        [
+            DeviceFamily::TactSuitX40,
+            DeviceFamily::TactSuitX16,
+            DeviceFamily::Tactosy2(side),
+            DeviceFamily::TactosyH(side),
+            DeviceFamily::TactosyF(side),
+            DeviceFamily::Tactal,
+            DeviceFamily::TactGlove(side),
+        ]
+            .into_iter()
+            .filter(|family| family.parts().contains(&part))
+            .collect()
+    }
+
+    /// Family of a `.tact` project layout type (`Tactot`, `Tactosy2`, ...), for the given body part.
+    pub fn for_layout_type(layout_type: &str, part: BodyPart) -> Option<DeviceFamily> {
+        Self::candidates(part).into_iter().find(|family| match family {
+            DeviceFamily::TactSuitX40 => matches!(layout_type, "Tactot" | "Tactot2" | "TactotX40" | "TactSuitX40"),
+            DeviceFamily::TactSuitX16 => matches!(layout_type, "TactotX16" | "TactSuitX16"),
+            DeviceFamily::Tactosy2(_) => matches!(layout_type, "Tactosy" | "Tactosy2"),
+            DeviceFamily::TactosyH(_) => layout_type == "TactosyH",
+            DeviceFamily::TactosyF(_) => layout_type == "TactosyF",
+            DeviceFamily::Tactal => layout_type == "Tactal",
+            DeviceFamily::TactGlove(_) => layout_type == "TactGlove",
+        })
+    }
+
+    /// Family of `part` whose motors over `parts` add up to `motor_count`.
+    pub fn for_motor_count(part: BodyPart, parts: &[BodyPart], motor_count: usize) -> Option<DeviceFamily> {
+        Self::candidates(part).into_iter().find(|family| {
+            parts.iter().filter_map(|part| family.layout(*part)).map(|layout| layout.len()).sum::<usize>() == motor_count
+        })
+    }
+
+    /// Smallest family of `part` with more than `index` motors over `parts`.
+    pub fn for_highest_index(part: BodyPart, parts: &[BodyPart], index: usize) -> Option<DeviceFamily> {
+        Self::candidates(part)
+            .into_iter()
+            .map(|family| (parts.iter().filter_map(|part| family.layout(*part)).map(|layout| layout.len()).sum::<usize>(), family))
+            .filter(|(count, _)| *count > index)
+            .min_by_key(|(count, _)| *count)
+            .map(|(_, family)| family)
+    }
+
+    /// Body parts driven by the device, in packet order.
+    pub fn parts(&self) -> Vec<BodyPart> {
+        match self {
+            DeviceFamily::TactSuitX16 | DeviceFamily::TactSuitX40 => vec![BodyPart::ChestFront, BodyPart::ChestBack],
+            DeviceFamily::Tactosy2(side) => vec![side.pick(BodyPart::ForearmL, BodyPart::ForearmR)],
+            DeviceFamily::TactosyH(side) => vec![side.pick(BodyPart::HandL, BodyPart::HandR)],
+            DeviceFamily::TactosyF(side) => vec![side.pick(BodyPart::FootL, BodyPart::FootR)],
+            DeviceFamily::Tactal => vec![BodyPart::Head],
+            DeviceFamily::TactGlove(side) => vec![side.pick(BodyPart::GloveL, BodyPart::GloveR)],
+        }
+    }
+
+    /// Physical motor layout of `part`, `None` when the device does not drive it.
+    pub fn layout(&self, part: BodyPart) -> Option<MotorLayout> {
+        if !self.parts().contains(&part) {
+            return None;
+        }
+
+        Some(match self {
+            DeviceFamily::TactSuitX16 => MotorLayout::grid(2, 4),
+            DeviceFamily::TactSuitX40 => MotorLayout::grid(4, 5),
+            DeviceFamily::Tactosy2(_) => MotorLayout::grid(3, 2),
+            DeviceFamily::TactosyH(_) | DeviceFamily::TactosyF(_) => MotorLayout::grid(3, 1),
+            DeviceFamily::Tactal => MotorLayout::grid(6, 1),
+            DeviceFamily::TactGlove(_) => MotorLayout::default_for(part),
+        })
+    }
+
+    /// Number of motors of the whole device.
+    pub fn motor_count(&self) -> usize {
+        self.parts()
+            .iter()
+            .filter_map(|part| self.layout(*part))
+            .map(|layout| layout.len())
+            .sum()
+    }
+
+    /// Intensities of every motor in packet order, `frame` being in the device's layout.
+    ///
+    /// Missing motors are off, extra motors are ignored.
+    fn motors(&self, frame: &HapticFrame) -> Vec<f32> {
+        self.parts()
+            .iter()
+            .flat_map(|part| {
+                let count = self.layout(*part).map_or(0, |layout| layout.len());
+                let motors = frame.get(*part).unwrap_or(&[]);
+
+                (0..count).map(move |index| motors.get(index).copied().unwrap_or(0.0).clamp(0.0, 1.0))
+            })
+            .collect()
+    }
+
+    /// Encodes `frame` into the packet the device expects.
+    ///
+    /// Gloves take one byte per motor, from 0 to 100. Every other family packs two
+    /// motors per byte, from 0 to 15, the first motor in the high nibble.
+    pub fn encode(&self, frame: &HapticFrame) -> Packet {
+        let motors = self.motors(frame);
+        let mut bytes = vec![0; MOTOR_PACKET_LENGTH];
+
+        match self {
+            DeviceFamily::TactGlove(_) => {
+                for (byte, intensity) in bytes.iter_mut().zip(motors) {
+                    *byte = (intensity * GLOVE_MAX_INTENSITY).round() as u8;
+                }
+            },
+            _ => {
+                for (byte, pair) in bytes.iter_mut().zip(motors.chunks(2)) {
+                    let nibble = |intensity: f32| (intensity * NIBBLE_MAX_INTENSITY).round() as u8;
+                    *byte = nibble(pair[0]) << 4 | pair.get(1).copied().map_or(0, nibble);
+                }
+            },
+        }
+
+        Packet {
+            characteristic: MOTOR_CHARACTERISTIC,
+            bytes,
+        }
+    }
+}
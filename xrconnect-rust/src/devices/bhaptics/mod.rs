@@ -0,0 +1,57 @@
+//! Direct output to bHaptics hardware over Bluetooth LE.
+//!
+//! The encoder is pure and the BLE stack sits behind [`BleTransport`], so that
+//! everything but the actual radio can be developed and tested without an adapter.
+
+use haptic_lib::BodyPart;
+
+use crate::haptics::{
+    device::{ DeviceError, HapticDevice },
+    model::{ HapticFrame, MotorLayout },
+};
+
+pub mod encoder;
+pub mod transport;
+
+pub use encoder::{ DeviceFamily, Packet, Side };
+pub use transport::{ BleTransport, RecordingTransport };
+
+/// bHaptics device connected through a [`BleTransport`].
+pub struct BHapticsBleDevice<T: BleTransport> {
+    name: String,
+    family: DeviceFamily,
+    transport: T,
+}
+
+impl<T: BleTransport> BHapticsBleDevice<T> {
+    pub fn new(name: impl Into<String>, family: DeviceFamily, transport: T) -> Self {
+        Self {
+            name: name.into(),
+            family,
+            transport,
+        }
+    }
+
+    pub fn family(&self) -> DeviceFamily {
+        self.family
+    }
+}
+
+impl<T: BleTransport> HapticDevice for BHapticsBleDevice<T> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn positions(&self) -> Vec<BodyPart> {
+        self.family.parts()
+    }
+
+    fn layout(&self, part: BodyPart) -> MotorLayout {
+        self.family.layout(part).unwrap_or_else(|| MotorLayout::default_for(part))
+    }
+
+    fn write(&mut self, frame: &HapticFrame) -> Result<(), DeviceError> {
+        let packet = self.family.encode(frame);
+        self.transport.write(packet.characteristic, &packet.bytes)
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::{ Arc, Mutex };
+
+use crate::haptics::device::DeviceError;
+
+use super::encoder::Packet;
+
+/// BLE link to a single bHaptics device.
+pub trait BleTransport: Send {
+    /// Writes `bytes` to the GATT characteristic `characteristic` (UUID), without response.
+    fn write(&mut self, characteristic: &'static str, bytes: &[u8]) -> Result<(), DeviceError>;
+}
+
+/// Transport keeping every write, for tests and development without a BLE adapter.
+#[derive(Clone, Default)]
+pub struct RecordingTransport {
+    writes: Arc<Mutex<Vec<Packet>>>,
+}
+
+impl RecordingTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes so far. Clones share the same recording.
+    pub fn writes(&self) -> Vec<Packet> {
+        self.writes.lock().unwrap().clone()
+    }
+}
+
+impl BleTransport for RecordingTransport {
+    fn write(&mut self, characteristic: &'static str, bytes: &[u8]) -> Result<(), DeviceError> {
+        self.writes.lock().unwrap().push(Packet {
+            characteristic,
+            bytes: bytes.to_vec(),
+        });
+        Ok(())
+    }
+}
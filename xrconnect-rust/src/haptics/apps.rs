@@ -0,0 +1,198 @@
+//! Per application settings, applied by the mixer to the effects of each application.
+//!
+//! Effects of an application are mixed together, go through the application's gains and
+//! enabled positions, and are then mixed with the other applications. While an application
+//! with a higher priority plays, lower priority ones are scaled by its `duck_gain`, so that
+//! e.g. a music visualiser yields to gameplay.
+//!
+//! # Example Settings
+//! ```json
+//! {
+//!     "default": { "gain": 0.8 },
+//!     "apps": {
+//!         "com.example.game": {
+//!             "priority": 10,
+//!             "duck_gain": 0.2,
+//!             "positions": { "Head": 0.5 }
+//!         },
+//!         "com.example.visualiser": {
+//!             "gain": 0.6,
+//!             "enabled": ["ChestFront", "ChestBack"]
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::{ BTreeMap, BTreeSet },
+    fmt,
+    fs,
+    io,
+    path::Path,
+};
+
+use haptic_lib::BodyPart;
+use serde::{self, Serialize, Deserialize};
+
+use super::model::HapticFrame;
+
+#[derive(Debug)]
+pub enum AppProfileError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for AppProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppProfileError::Io(why) => write!(f, "application settings I/O error: {}", why),
+            AppProfileError::Parse(why) => write!(f, "failed to parse application settings: {}", why),
+            AppProfileError::Invalid(why) => write!(f, "invalid application settings: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for AppProfileError {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AppProfile {
+    /// Gain applied to every motor
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+
+    /// Gain applied on top of `gain`, per body part
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub positions: BTreeMap<BodyPart, f32>,
+
+    /// Body parts the application may drive, all of them when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<BTreeSet<BodyPart>>,
+
+    /// Applications with a higher priority duck lower priority ones
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Gain applied to lower priority applications while this one plays, `0.0` mutes them
+    #[serde(default = "default_duck_gain")]
+    pub duck_gain: f32,
+}
+
+impl Default for AppProfile {
+    fn default() -> Self {
+        Self {
+            gain: default_gain(),
+            positions: BTreeMap::new(),
+            enabled: None,
+            priority: 0,
+            duck_gain: default_duck_gain(),
+        }
+    }
+}
+
+impl AppProfile {
+    fn validate(&self) -> Result<(), String> {
+        let valid_gain = |gain: f32| gain.is_finite() && gain >= 0.0;
+
+        if !valid_gain(self.gain) {
+            return Err(format!("gain {} is not a non-negative number", self.gain));
+        }
+
+        if let Some((part, gain)) = self.positions.iter().find(|(_, gain)| !valid_gain(**gain)) {
+            return Err(format!("gain {} of {:?} is not a non-negative number", gain, part));
+        }
+
+        if !(0.0..=1.0).contains(&self.duck_gain) {
+            return Err(format!("duck_gain {} is outside of 0..=1", self.duck_gain));
+        }
+
+        Ok(())
+    }
+
+    pub fn is_enabled(&self, part: BodyPart) -> bool {
+        self.enabled.as_ref().is_none_or(|enabled| enabled.contains(&part))
+    }
+
+    /// Applies the gains to the frame of the application, silencing disabled body parts.
+    pub fn apply(&self, frame: &mut HapticFrame) {
+        let gain = |part: BodyPart| match self.is_enabled(part) {
+            true => self.gain * self.positions.get(&part).copied().unwrap_or(1.0),
+            false => 0.0,
+        };
+
+        for (part, motors) in frame.parts_mut() {
+            let gain = gain(part);
+            motors.iter_mut().for_each(|motor| *motor *= gain);
+        }
+
+        for (part, elements) in frame.thermal_parts_mut() {
+            let gain = gain(part);
+            elements.iter_mut().for_each(|element| *element *= gain);
+        }
+    }
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+fn default_duck_gain() -> f32 {
+    0.3
+}
+
+/// Settings of every application, keyed by `app_id`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AppProfiles {
+    /// Settings of the applications not listed in `apps`, and of effects without application
+    #[serde(default)]
+    pub default: AppProfile,
+
+    #[serde(default)]
+    pub apps: BTreeMap<String, AppProfile>,
+}
+
+impl AppProfiles {
+    pub fn validate(&self) -> Result<(), AppProfileError> {
+        self.default
+            .validate()
+            .map_err(|why| AppProfileError::Invalid(format!("default: {}", why)))?;
+
+        for (app, profile) in &self.apps {
+            profile
+                .validate()
+                .map_err(|why| AppProfileError::Invalid(format!("{}: {}", app, why)))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, app: Option<&str>) -> &AppProfile {
+        app.and_then(|app| self.apps.get(app)).unwrap_or(&self.default)
+    }
+
+    /// Reads the settings from `path`, the default settings when there is no such file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AppProfileError> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(why) => return Err(AppProfileError::Io(why)),
+        };
+
+        let profiles: AppProfiles = serde_json::from_str(&content).map_err(AppProfileError::Parse)?;
+        profiles.validate()?;
+
+        Ok(profiles)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), AppProfileError> {
+        self.validate()?;
+
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(AppProfileError::Io)?;
+        }
+
+        let content = serde_json::to_string_pretty(self).map_err(AppProfileError::Parse)?;
+        fs::write(path, content).map_err(AppProfileError::Io)
+    }
+}
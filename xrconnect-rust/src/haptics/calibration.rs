@@ -0,0 +1,338 @@
+//! Per wearer calibration, applied to every mixed frame once resampled to the motor layout of a
+//! device, just before it is written to the device.
+//!
+//! Every motor goes through its response curve, is scaled by its gain, and is then mapped
+//! between the minimum perceptible threshold and the maximum cap, so that the faintest
+//! non-zero intensity is still felt.
+//!
+//! # Example Profile
+//! ```json
+//! {
+//!     "name": "alice",
+//!     "parts": {
+//!         "ChestFront": {
+//!             "gain": 0.8,
+//!             "threshold": 0.15,
+//!             "curve": { "Gamma": { "gamma": 0.7 } },
+//!             "motors": {
+//!                 "4": { "gain": 0.5 }
+//!             }
+//!         },
+//!         "Head": {
+//!             "cap": 0.4,
+//!             "curve": { "Lookup": { "points": [0.0, 0.1, 0.3, 1.0] } }
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs,
+    io,
+    path::{ Path, PathBuf },
+};
+
+use haptic_lib::BodyPart;
+use serde::{self, Serialize, Deserialize};
+
+use super::model::HapticFrame;
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    /// No profile stored under the name
+    NotFound(String),
+    Invalid(String),
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::Io(why) => write!(f, "calibration I/O error: {}", why),
+            CalibrationError::Parse(why) => write!(f, "failed to parse calibration profile: {}", why),
+            CalibrationError::NotFound(name) => write!(f, "no calibration profile named {:?}", name),
+            CalibrationError::Invalid(why) => write!(f, "invalid calibration profile: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum ResponseCurve {
+    #[default]
+    Linear,
+    /// `intensity ^ gamma`, below 1 boosts faint intensities
+    Gamma { gamma: f32 },
+    /// Output for evenly spaced inputs from 0 to 1, linearly interpolated
+    Lookup { points: Vec<f32> },
+}
+
+impl ResponseCurve {
+    pub fn apply(&self, intensity: f32) -> f32 {
+        let intensity = intensity.clamp(0.0, 1.0);
+
+        match self {
+            ResponseCurve::Linear => intensity,
+            ResponseCurve::Gamma { gamma } => intensity.powf(*gamma),
+            ResponseCurve::Lookup { points } => match points.len() {
+                0 => intensity,
+                1 => points[0],
+                len => {
+                    let position = intensity * (len - 1) as f32;
+                    let index = (position.floor() as usize).min(len - 2);
+                    let fraction = position - index as f32;
+                    points[index] + (points[index + 1] - points[index]) * fraction
+                },
+            },
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            ResponseCurve::Gamma { gamma } if !(gamma.is_finite() && *gamma > 0.0) => Err(format!("gamma must be positive, got {}", gamma)),
+            ResponseCurve::Lookup { points } if points.iter().any(|point| !(0.0..=1.0).contains(point)) => {
+                Err("lookup points must be within 0..=1".to_string())
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Overrides of the body part calibration for a single motor.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct MotorCalibration {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gain: Option<f32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cap: Option<f32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curve: Option<ResponseCurve>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PartCalibration {
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+
+    /// Lowest intensity the wearer feels, every non-zero intensity is lifted to it
+    #[serde(default)]
+    pub threshold: f32,
+
+    /// Highest intensity the motors are driven at
+    #[serde(default = "default_cap")]
+    pub cap: f32,
+
+    #[serde(default)]
+    pub curve: ResponseCurve,
+
+    /// Per motor overrides, by motor index in the layout of the device
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub motors: BTreeMap<usize, MotorCalibration>,
+}
+
+impl Default for PartCalibration {
+    fn default() -> Self {
+        Self {
+            gain: default_gain(),
+            threshold: 0.0,
+            cap: default_cap(),
+            curve: ResponseCurve::Linear,
+            motors: BTreeMap::new(),
+        }
+    }
+}
+
+impl PartCalibration {
+    pub fn apply(&self, index: usize, intensity: f32) -> f32 {
+        if intensity <= 0.0 {
+            return 0.0;
+        }
+
+        let motor = self.motors.get(&index);
+        let gain = motor.and_then(|motor| motor.gain).unwrap_or(self.gain);
+        let threshold = motor.and_then(|motor| motor.threshold).unwrap_or(self.threshold);
+        let cap = motor.and_then(|motor| motor.cap).unwrap_or(self.cap);
+        let curve = motor.and_then(|motor| motor.curve.as_ref()).unwrap_or(&self.curve);
+
+        let intensity = (curve.apply(intensity) * gain).clamp(0.0, 1.0);
+        if intensity <= 0.0 {
+            return 0.0;
+        }
+
+        threshold + intensity * (cap - threshold)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let check = |name: &str, gain: f32, threshold: f32, cap: f32, curve: &ResponseCurve| {
+            if !(gain.is_finite() && gain >= 0.0) {
+                return Err(format!("{}gain must be finite and not negative, got {}", name, gain));
+            }
+            if !(0.0..=1.0).contains(&threshold) || !(0.0..=1.0).contains(&cap) {
+                return Err(format!("{}threshold and cap must be within 0..=1", name));
+            }
+            if threshold > cap {
+                return Err(format!("{}threshold {} is above cap {}", name, threshold, cap));
+            }
+            curve.validate().map_err(|why| format!("{}{}", name, why))
+        };
+
+        check("", self.gain, self.threshold, self.cap, &self.curve)?;
+        for (index, motor) in &self.motors {
+            check(
+                &format!("motor {}: ", index),
+                motor.gain.unwrap_or(self.gain),
+                motor.threshold.unwrap_or(self.threshold),
+                motor.cap.unwrap_or(self.cap),
+                motor.curve.as_ref().unwrap_or(&self.curve),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CalibrationProfile {
+    pub name: String,
+
+    /// Body parts without calibration are left untouched
+    #[serde(default)]
+    pub parts: BTreeMap<BodyPart, PartCalibration>,
+}
+
+impl CalibrationProfile {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            parts: BTreeMap::new(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), CalibrationError> {
+        for (part, calibration) in &self.parts {
+            calibration
+                .validate()
+                .map_err(|why| CalibrationError::Invalid(format!("{:?}: {}", part, why)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Calibration of `part`, created with defaults when missing.
+    pub fn part_mut(&mut self, part: BodyPart) -> &mut PartCalibration {
+        self.parts.entry(part).or_default()
+    }
+
+    pub fn apply(&self, frame: &mut HapticFrame) {
+        for (part, motors) in frame.parts_mut() {
+            if let Some(calibration) = self.parts.get(&part) {
+                for (index, motor) in motors.iter_mut().enumerate() {
+                    *motor = calibration.apply(index, *motor);
+                }
+            }
+        }
+    }
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+fn default_cap() -> f32 {
+    1.0
+}
+
+/// Directory of calibration profiles, one `<name>.json` file per profile.
+#[derive(Clone, Debug)]
+pub struct CalibrationStore {
+    dir: PathBuf,
+}
+
+impl Default for CalibrationStore {
+    fn default() -> Self {
+        Self::new(crate::paths::calibration_dir())
+    }
+}
+
+impl CalibrationStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, CalibrationError> {
+        let valid = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            && !name.starts_with('.');
+        if !valid {
+            return Err(CalibrationError::Invalid(format!("profile name {:?} is not a valid file name", name)));
+        }
+
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+
+    /// Names of the stored profiles, sorted.
+    pub fn list(&self) -> Result<Vec<String>, CalibrationError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(why) => return Err(CalibrationError::Io(why)),
+        };
+
+        let mut names = vec![];
+        for entry in entries {
+            let path = entry.map_err(CalibrationError::Io)?.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+
+        Ok(names)
+    }
+
+    pub fn load(&self, name: &str) -> Result<CalibrationProfile, CalibrationError> {
+        let content = fs::read_to_string(self.path(name)?).map_err(|why| match why.kind() {
+            io::ErrorKind::NotFound => CalibrationError::NotFound(name.to_string()),
+            _ => CalibrationError::Io(why),
+        })?;
+
+        let mut profile: CalibrationProfile = serde_json::from_str(&content).map_err(CalibrationError::Parse)?;
+        profile.name = name.to_string();
+        profile.validate()?;
+
+        Ok(profile)
+    }
+
+    pub fn save(&self, profile: &CalibrationProfile) -> Result<(), CalibrationError> {
+        profile.validate()?;
+
+        let path = self.path(&profile.name)?;
+        fs::create_dir_all(&self.dir).map_err(CalibrationError::Io)?;
+        let content = serde_json::to_string_pretty(profile).map_err(CalibrationError::Parse)?;
+        fs::write(path, content).map_err(CalibrationError::Io)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), CalibrationError> {
+        fs::remove_file(self.path(name)?).map_err(|why| match why.kind() {
+            io::ErrorKind::NotFound => CalibrationError::NotFound(name.to_string()),
+            _ => CalibrationError::Io(why),
+        })
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    fmt,
+    sync::{ Arc, Mutex },
+};
+
+use haptic_lib::BodyPart;
+
+use super::model::{ HapticFrame, MotorLayout };
+
+#[derive(Debug)]
+pub enum DeviceError {
+    /// Device is gone and will not accept any more frames
+    Disconnected,
+    Io(std::io::Error),
+    Other(String),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::Disconnected => write!(f, "device disconnected"),
+            DeviceError::Io(why) => write!(f, "device I/O error: {}", why),
+            DeviceError::Other(why) => write!(f, "{}", why),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+impl From<std::io::Error> for DeviceError {
+    fn from(why: std::io::Error) -> Self {
+        DeviceError::Io(why)
+    }
+}
+
+/// Output the player writes mixed frames to.
+pub trait HapticDevice: Send {
+    fn name(&self) -> &str;
+
+    /// Body parts this device is able to drive.
+    fn positions(&self) -> Vec<BodyPart>;
+
+    /// Layout of the motors of `part`, frames are resampled to it before being written.
+    fn layout(&self, part: BodyPart) -> MotorLayout {
+        MotorLayout::default_for(part)
+    }
+
+    /// Turns a frame in the device layout into a frame of its actuators, which is what gets
+    /// calibrated, limited and written.
+    fn map(&self, frame: HapticFrame) -> HapticFrame {
+        frame
+    }
+
+    fn write(&mut self, frame: &HapticFrame) -> Result<(), DeviceError>;
+
+    /// Turns every motor of the device off.
+    fn stop(&mut self) -> Result<(), DeviceError> {
+        let mut frame = HapticFrame::default();
+        for part in self.positions() {
+            frame.set(part, vec![0.0; self.layout(part).len()]);
+        }
+
+        self.write(&frame)
+    }
+
+    /// Pushes buffered output through, called before the device is let go.
+    fn flush(&mut self) -> Result<(), DeviceError> {
+        Ok(())
+    }
+}
+
+/// Virtual device keeping every frame written to it, mostly useful for tests and tooling.
+#[derive(Clone)]
+pub struct RecordingDevice {
+    name: String,
+    positions: Vec<BodyPart>,
+    layout: Option<MotorLayout>,
+    frames: Arc<Mutex<Vec<HapticFrame>>>,
+}
+
+impl RecordingDevice {
+    pub fn new(name: impl Into<String>, positions: Vec<BodyPart>) -> Self {
+        Self {
+            name: name.into(),
+            positions,
+            layout: None,
+            frames: Arc::default(),
+        }
+    }
+
+    /// Lays the motors of every position out as `layout` rather than the default of each part.
+    pub fn with_layout(mut self, layout: MotorLayout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Frames written so far. Clones share the same recording.
+    pub fn frames(&self) -> Vec<HapticFrame> {
+        self.frames.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.frames.lock().unwrap().clear();
+    }
+}
+
+impl HapticDevice for RecordingDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn positions(&self) -> Vec<BodyPart> {
+        self.positions.clone()
+    }
+
+    fn layout(&self, part: BodyPart) -> MotorLayout {
+        self.layout.clone().unwrap_or_else(|| MotorLayout::default_for(part))
+    }
+
+    fn write(&mut self, frame: &HapticFrame) -> Result<(), DeviceError> {
+        self.frames.lock().unwrap().push(frame.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! Events of the player, for tools following what happens without polling it.
+
+use haptic_lib::BodyPart;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::player::DeviceStatus;
+
+/// Events a subscriber may fall behind by before missing some.
+const CAPACITY: usize = 1024;
+
+/// Something that happened to the player.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PlayerEvent {
+    Registered { key: String },
+    Unregistered { key: String },
+
+    /// An effect started, `key` is the registered pattern it plays, if any
+    Played { active_key: String, key: Option<String>, app: Option<String> },
+
+    /// An effect was stopped before its end
+    Stopped { active_key: String },
+
+    /// An effect played until its end
+    Finished { active_key: String },
+
+    /// A device was added, or was stopped or resumed
+    Device { device: DeviceStatus },
+    DeviceDisconnected { name: String },
+
+    /// The kill switch was engaged or cleared
+    Panic { panicked: bool },
+
+    /// A body part was muted or unmuted
+    Muted { part: BodyPart, muted: bool },
+}
+
+/// Broadcasts events to every subscriber, events sent without subscribers are dropped.
+///
+/// Cloning is cheap, clones send to the same subscribers.
+#[derive(Clone)]
+pub struct EventSender<T> {
+    sender: broadcast::Sender<T>,
+}
+
+impl<T: Clone> Default for EventSender<T> {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl<T: Clone> EventSender<T> {
+    pub fn send(&self, event: T) {
+        if self.sender.receiver_count() > 0 {
+            let _ = self.sender.send(event);
+        }
+    }
+
+    /// Receives the events sent from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<T> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,367 @@
+//! Declarative mapping between the motors a pattern was authored for and the actuators of a device.
+//!
+//! A mapping is a list of routes, each one taking the motors of a source body part and
+//! spatially resampling them onto the actuators of a target body part.
+//!
+//! # Example Mapping File
+//! Vest patterns driving two shakers, one behind the chest and one in the seat:
+//! ```json
+//! {
+//!     "name": "vest-to-shakers",
+//!     "routes": [
+//!         {
+//!             "source": "ChestBack",
+//!             "target": "ChestBack",
+//!             "target_layout": [[0.5, 0.25]],
+//!             "resampling": { "Max": { "radius": 0.6 } }
+//!         },
+//!         {
+//!             "source": "ChestFront",
+//!             "target": "ChestFront",
+//!             "target_layout": { "columns": 1, "rows": 1 },
+//!             "resampling": { "Max": { "radius": 1.5 } },
+//!             "gain": 0.8
+//!         }
+//!     ]
+//! }
+//! ```
+
+use std::{
+    fmt,
+    fs,
+    path::Path,
+};
+
+use haptic_lib::{ BodyPart, EffectPoint };
+use serde::{self, Serialize, Deserialize};
+
+use super::{
+    device::{ DeviceError, HapticDevice },
+    model::{ HapticFrame, MotorLayout, PathIntensity },
+};
+
+#[derive(Debug)]
+pub enum MappingError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    /// Mapping is well formed but does not make sense, e.g. coordinates out of range
+    Invalid(String),
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MappingError::Io(why) => write!(f, "failed to read mapping: {}", why),
+            MappingError::Parse(why) => write!(f, "failed to parse mapping: {}", why),
+            MappingError::Invalid(why) => write!(f, "invalid mapping: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for MappingError {}
+
+/// Motor positions, either spelled out or as an evenly spread grid.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum LayoutSpec {
+    Grid { columns: usize, rows: usize },
+    Motors(Vec<(f32, f32)>),
+}
+
+impl LayoutSpec {
+    pub fn to_layout(&self) -> MotorLayout {
+        match self {
+            LayoutSpec::Grid { columns, rows } => MotorLayout::grid(*columns, *rows),
+            LayoutSpec::Motors(motors) => MotorLayout::new(motors.clone()),
+        }
+    }
+}
+
+/// How source motors are combined into a target actuator.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Resampling {
+    /// Takes the closest source motor
+    Nearest,
+    /// Takes the strongest source motor within `radius`, fading with the distance
+    Max { radius: f32 },
+    /// Averages the source motors within `radius`, weighted by their distance
+    Weighted { radius: f32 },
+    /// Takes the strongest source motor within `radius` horizontally whatever its height, collapsing
+    /// columns of motors onto a row
+    Columns { radius: f32 },
+}
+
+impl Default for Resampling {
+    fn default() -> Self {
+        Resampling::Max { radius: 0.5 }
+    }
+}
+
+impl MotorLayout {
+    /// Largest distance between a motor and its closest neighbour, `1.0` for a single motor.
+    pub fn spacing(&self) -> f32 {
+        let motors = self.motors();
+        let spacing = motors
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (x0, y0))| motors
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, (x1, y1))| ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt())
+                .reduce(f32::min))
+            .fold(0.0, f32::max);
+
+        if spacing > 0.0 { spacing } else { 1.0 }
+    }
+
+    /// Largest distance between a motor and the closest motor of `target`.
+    fn reach(&self, target: &MotorLayout) -> f32 {
+        self.motors()
+            .iter()
+            .filter_map(|(x0, y0)| target.motors()
+                .iter()
+                .map(|(x1, y1)| ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt())
+                .reduce(f32::min))
+            .fold(0.0, f32::max)
+    }
+
+    /// Resamples `motors` onto `target`, picking the resampling from the layouts.
+    ///
+    /// Upsampling takes the nearest motor, so that a single motor does not get diluted. Downsampling
+    /// keeps the strongest motor around each target motor, every motor reaching one of them at half
+    /// its intensity or more.
+    pub fn resample_to(&self, motors: &[f32], target: &MotorLayout) -> Vec<f32> {
+        if self == target {
+            let mut motors = motors.to_vec();
+            motors.resize(target.len(), 0.0);
+            return motors;
+        }
+
+        let resampling = if target.len() >= self.len() {
+            Resampling::Nearest
+        } else {
+            Resampling::Max { radius: target.spacing().max(2.0 * self.reach(target)) }
+        };
+
+        self.resample(motors, target, resampling)
+    }
+
+    /// Resamples `motors`, laid out as `self`, onto the motors of `target`.
+    pub fn resample(&self, motors: &[f32], target: &MotorLayout, resampling: Resampling) -> Vec<f32> {
+        let sources: Vec<((f32, f32), f32)> = self.motors()
+            .iter()
+            .zip(motors.iter().chain(std::iter::repeat(&0.0)))
+            .map(|(position, intensity)| (*position, *intensity))
+            .collect();
+        let distance = |(x0, y0): (f32, f32), (x1, y1): (f32, f32)| ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+
+        target.motors()
+            .iter()
+            .map(|actuator| match resampling {
+                Resampling::Nearest => sources
+                    .iter()
+                    .min_by(|(a, _), (b, _)| distance(*a, *actuator).total_cmp(&distance(*b, *actuator)))
+                    .map_or(0.0, |(_, intensity)| *intensity),
+                Resampling::Max { radius } => sources
+                    .iter()
+                    .map(|(position, intensity)| intensity * (1.0 - distance(*position, *actuator) / radius).max(0.0))
+                    .fold(0.0, f32::max),
+                Resampling::Weighted { radius } => {
+                    let (sum, weights) = sources
+                        .iter()
+                        .map(|(position, intensity)| ((1.0 - distance(*position, *actuator) / radius).max(0.0), intensity))
+                        .fold((0.0, 0.0), |(sum, weights), (weight, intensity)| (sum + weight * intensity, weights + weight));
+
+                    if weights > 0.0 { sum / weights } else { 0.0 }
+                },
+                Resampling::Columns { radius } => sources
+                    .iter()
+                    .map(|((x, _), intensity)| intensity * (1.0 - (x - actuator.0).abs() / radius).max(0.0))
+                    .fold(0.0, f32::max),
+            })
+            .collect()
+    }
+}
+
+/// Converts `haptic_lib` points, on a 0 to 255 grid, into normalised path points.
+pub fn effect_points_to_path(points: &[EffectPoint]) -> Vec<PathIntensity> {
+    points
+        .iter()
+        .map(|point| PathIntensity {
+            x: point.x as f32 / u8::MAX as f32,
+            y: point.y as f32 / u8::MAX as f32,
+            intensity: point.intensity as f32 / u8::MAX as f32,
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MappingRoute {
+    pub source: BodyPart,
+
+    /// Layout the source motors are in, the player's default layout of the part when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_layout: Option<LayoutSpec>,
+
+    pub target: BodyPart,
+
+    pub target_layout: LayoutSpec,
+
+    #[serde(default)]
+    pub resampling: Resampling,
+
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+}
+
+impl MappingRoute {
+    fn source_layout(&self) -> MotorLayout {
+        self.source_layout
+            .as_ref()
+            .map_or_else(|| MotorLayout::default_for(self.source), LayoutSpec::to_layout)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BodyMapping {
+    #[serde(default)]
+    pub name: String,
+
+    pub routes: Vec<MappingRoute>,
+}
+
+impl BodyMapping {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MappingError> {
+        let content = fs::read_to_string(path).map_err(MappingError::Io)?;
+        Self::from_json(&content)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, MappingError> {
+        let mapping: Self = serde_json::from_str(content).map_err(MappingError::Parse)?;
+        mapping.validate()?;
+        Ok(mapping)
+    }
+
+    pub fn validate(&self) -> Result<(), MappingError> {
+        for (index, route) in self.routes.iter().enumerate() {
+            let invalid = |why: String| MappingError::Invalid(format!("route {} ({:?} -> {:?}): {}", index, route.source, route.target, why));
+
+            for (name, layout) in [("source_layout", route.source_layout()), ("target_layout", route.target_layout.to_layout())] {
+                if layout.is_empty() {
+                    return Err(invalid(format!("{} has no motor", name)));
+                }
+                if let Some((x, y)) = layout.motors().iter().find(|(x, y)| !(0.0..=1.0).contains(x) || !(0.0..=1.0).contains(y)) {
+                    return Err(invalid(format!("{} motor ({}, {}) is outside 0..=1", name, x, y)));
+                }
+            }
+
+            match route.resampling {
+                Resampling::Max { radius } | Resampling::Weighted { radius } | Resampling::Columns { radius } if !(radius.is_finite() && radius > 0.0) => {
+                    return Err(invalid(format!("resampling radius must be positive, got {}", radius)));
+                },
+                _ => {},
+            }
+
+            if route.gain < 0.0 {
+                return Err(invalid(format!("gain must not be negative, got {}", route.gain)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Source body parts the mapping reads from.
+    pub fn sources(&self) -> Vec<BodyPart> {
+        let mut parts: Vec<BodyPart> = self.routes.iter().map(|route| route.source).collect();
+        parts.sort();
+        parts.dedup();
+        parts
+    }
+
+    /// Target body parts the mapping writes to.
+    pub fn targets(&self) -> Vec<BodyPart> {
+        let mut parts: Vec<BodyPart> = self.routes.iter().map(|route| route.target).collect();
+        parts.sort();
+        parts.dedup();
+        parts
+    }
+
+    /// Maps `frame` onto the target layouts. Routes sharing a target keep the strongest value per actuator.
+    pub fn apply(&self, frame: &HapticFrame) -> HapticFrame {
+        let mut mapped = HapticFrame::default();
+
+        for route in &self.routes {
+            let target_layout = route.target_layout.to_layout();
+            let motors = frame.get(route.source).unwrap_or(&[]);
+            let resampled = route.source_layout().resample(motors, &target_layout, route.resampling);
+
+            let target = mapped.get_mut(route.target);
+            target.resize(target_layout.len(), 0.0);
+            for (actuator, intensity) in target.iter_mut().zip(resampled) {
+                *actuator = actuator.max((intensity * route.gain).clamp(0.0, 1.0));
+            }
+        }
+
+        mapped
+    }
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+/// Device whose frames go through a [`BodyMapping`] first.
+///
+/// The player calibrates and limits frames once mapped, so per motor calibration and limits
+/// address the actuators of the targets.
+pub struct MappedDevice<D: HapticDevice> {
+    device: D,
+    mapping: BodyMapping,
+}
+
+impl<D: HapticDevice> MappedDevice<D> {
+    pub fn new(device: D, mapping: BodyMapping) -> Self {
+        Self {
+            device,
+            mapping,
+        }
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.device
+    }
+}
+
+impl<D: HapticDevice> HapticDevice for MappedDevice<D> {
+    fn name(&self) -> &str {
+        self.device.name()
+    }
+
+    /// Positions patterns can target, which are the sources of the mapping.
+    fn positions(&self) -> Vec<BodyPart> {
+        self.mapping.sources()
+    }
+
+    /// Frames are expected in the layout of the mapping's sources.
+    fn layout(&self, part: BodyPart) -> MotorLayout {
+        self.mapping.routes
+            .iter()
+            .find(|route| route.source == part)
+            .map_or_else(|| MotorLayout::default_for(part), MappingRoute::source_layout)
+    }
+
+    fn map(&self, frame: HapticFrame) -> HapticFrame {
+        self.mapping.apply(&frame)
+    }
+
+    /// Frames are expected mapped already, see [`HapticDevice::map`].
+    fn write(&mut self, frame: &HapticFrame) -> Result<(), DeviceError> {
+        self.device.write(frame)
+    }
+
+    /// Silences every actuator of the mapping's targets, in their layout.
+    fn stop(&mut self) -> Result<(), DeviceError> {
+        self.device.write(&self.mapping.apply(&HapticFrame::default()))
+    }
+}
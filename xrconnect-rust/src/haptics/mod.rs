@@ -1,2 +1,9 @@
+pub mod apps;
+pub mod calibration;
+pub mod device;
+pub mod events;
+pub mod mapping;
 pub mod model;
-pub mod player;
\ No newline at end of file
+pub mod player;
+pub mod safety;
+pub mod watchdog;
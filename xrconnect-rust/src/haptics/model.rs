@@ -0,0 +1,323 @@
+use std::collections::BTreeMap;
+
+use haptic_lib::{ BodyPart, EffectInterpolation };
+use serde::{self, Serialize, Deserialize};
+
+/// Radius (in normalised layout units) within which a path point drives a motor.
+const PATH_POINT_RADIUS: f32 = 0.35;
+
+/// Positions of the motors of a single body part.
+///
+/// Coordinates are normalised to `0.0..=1.0`, with `(0, 0)` being the top left
+/// corner when looking at the wearer's body part from the outside.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MotorLayout {
+    motors: Vec<(f32, f32)>,
+}
+
+impl MotorLayout {
+    pub fn new(motors: Vec<(f32, f32)>) -> Self {
+        Self {
+            motors,
+        }
+    }
+
+    /// Row-major grid of `columns` x `rows` motors, evenly spread over the body part.
+    pub fn grid(columns: usize, rows: usize) -> Self {
+        let coordinate = |i: usize, n: usize| if n > 1 { i as f32 / (n - 1) as f32 } else { 0.5 };
+
+        Self::new(
+            (0..rows)
+                .flat_map(|row| (0..columns).map(move |column| (coordinate(column, columns), coordinate(row, rows))))
+                .collect()
+        )
+    }
+
+    /// Layout used when nothing more specific is known about the body part.
+    pub fn default_for(part: BodyPart) -> Self {
+        match part {
+            BodyPart::ChestFront | BodyPart::ChestBack => Self::grid(4, 5),
+            BodyPart::Head => Self::grid(6, 1),
+            BodyPart::ForearmL | BodyPart::ForearmR => Self::grid(3, 2),
+            BodyPart::HandL | BodyPart::HandR => Self::grid(3, 1),
+            BodyPart::FootL | BodyPart::FootR => Self::grid(3, 1),
+            BodyPart::GloveL | BodyPart::GloveR => {
+                let mut motors = Self::grid(5, 1).motors;
+                motors.push((0.5, 1.0));
+                Self::new(motors)
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.motors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.motors.is_empty()
+    }
+
+    pub fn motors(&self) -> &[(f32, f32)] {
+        &self.motors
+    }
+
+    /// Spreads a path point over the motors close to it, keeping the strongest value per motor.
+    pub fn rasterize(&self, point: &PathIntensity, motors: &mut [f32]) {
+        for (motor, (x, y)) in motors.iter_mut().zip(self.motors.iter()) {
+            let distance = ((x - point.x).powi(2) + (y - point.y).powi(2)).sqrt();
+            if distance < PATH_POINT_RADIUS {
+                let intensity = point.intensity * (1.0 - distance / PATH_POINT_RADIUS);
+                *motor = motor.max(intensity);
+            }
+        }
+    }
+}
+
+/// Mixed intensity (`0.0..=1.0`) of every motor, per body part.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct HapticFrame {
+    parts: BTreeMap<BodyPart, Vec<f32>>,
+
+    /// Level (`0.0..=1.0`) of heating elements, for [`haptic_lib::EffectPath::Thermal`] outputs,
+    /// laid out like the motors of the part
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    thermal: BTreeMap<BodyPart, Vec<f32>>,
+}
+
+impl HapticFrame {
+    /// Frame with every motor of `parts` turned off.
+    pub fn silent(parts: &[BodyPart]) -> Self {
+        Self {
+            parts: parts.iter().map(|part| (*part, vec![0.0; MotorLayout::default_for(*part).len()])).collect(),
+            thermal: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, part: BodyPart) -> Option<&[f32]> {
+        self.parts.get(&part).map(Vec::as_slice)
+    }
+
+    pub fn get_mut(&mut self, part: BodyPart) -> &mut Vec<f32> {
+        self.parts
+            .entry(part)
+            .or_insert_with(|| vec![0.0; MotorLayout::default_for(part).len()])
+    }
+
+    pub fn set(&mut self, part: BodyPart, motors: Vec<f32>) {
+        self.parts.insert(part, motors);
+    }
+
+    pub fn parts(&self) -> impl Iterator<Item = (BodyPart, &[f32])> {
+        self.parts.iter().map(|(part, motors)| (*part, motors.as_slice()))
+    }
+
+    pub fn parts_mut(&mut self) -> impl Iterator<Item = (BodyPart, &mut Vec<f32>)> {
+        self.parts.iter_mut().map(|(part, motors)| (*part, motors))
+    }
+
+    pub fn thermal(&self, part: BodyPart) -> Option<&[f32]> {
+        self.thermal.get(&part).map(Vec::as_slice)
+    }
+
+    pub fn thermal_mut(&mut self, part: BodyPart) -> &mut Vec<f32> {
+        self.thermal
+            .entry(part)
+            .or_insert_with(|| vec![0.0; MotorLayout::default_for(part).len()])
+    }
+
+    pub fn set_thermal(&mut self, part: BodyPart, elements: Vec<f32>) {
+        self.thermal.insert(part, elements);
+    }
+
+    pub fn thermal_parts(&self) -> impl Iterator<Item = (BodyPart, &[f32])> {
+        self.thermal.iter().map(|(part, elements)| (*part, elements.as_slice()))
+    }
+
+    pub fn thermal_parts_mut(&mut self) -> impl Iterator<Item = (BodyPart, &mut Vec<f32>)> {
+        self.thermal.iter_mut().map(|(part, elements)| (*part, elements))
+    }
+
+    /// Strongest motor of `part`, `0.0` when the part is not present.
+    pub fn peak(&self, part: BodyPart) -> f32 {
+        self.get(part)
+            .map(|motors| motors.iter().copied().fold(0.0, f32::max))
+            .unwrap_or(0.0)
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.parts.values().chain(self.thermal.values()).flatten().all(|intensity| *intensity <= 0.0)
+    }
+
+    /// Mixes `other` scaled by `gain` into the frame, keeping the strongest value per motor.
+    pub fn mix(&mut self, other: &HapticFrame, gain: f32) {
+        let mix = |into: &mut Vec<f32>, from: &[f32]| {
+            if into.len() < from.len() {
+                into.resize(from.len(), 0.0);
+            }
+
+            for (value, other) in into.iter_mut().zip(from) {
+                *value = value.max(other * gain);
+            }
+        };
+
+        for (part, motors) in &other.parts {
+            mix(self.parts.entry(*part).or_default(), motors);
+        }
+
+        for (part, elements) in &other.thermal {
+            mix(self.thermal.entry(*part).or_default(), elements);
+        }
+    }
+
+    /// Resamples every part from its default layout to the one given by `layout_for`.
+    pub fn resample(&self, layout_for: impl Fn(BodyPart) -> MotorLayout) -> HapticFrame {
+        Self {
+            parts: self.parts
+                .iter()
+                .map(|(part, motors)| (*part, MotorLayout::default_for(*part).resample_to(motors, &layout_for(*part))))
+                .collect(),
+            thermal: self.thermal.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct DotIntensity {
+    pub index: usize,
+    pub intensity: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PathIntensity {
+    pub x: f32,
+    pub y: f32,
+    pub intensity: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PatternPoints {
+    Dot(Vec<DotIntensity>),
+    Path(Vec<PathIntensity>),
+}
+
+/// Outputs of a body part a clip drives.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClipOutput {
+    #[default]
+    Motors,
+
+    /// Heating elements, intensities being their level
+    Thermal,
+}
+
+impl ClipOutput {
+    fn is_motors(&self) -> bool {
+        *self == ClipOutput::Motors
+    }
+}
+
+/// Single feedback of a pattern, driving one body part for a span of time.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PatternClip {
+    pub part: BodyPart,
+    pub start_millis: u32,
+    pub end_millis: u32,
+    pub interpolation: EffectInterpolation,
+    pub points: PatternPoints,
+
+    #[serde(default, skip_serializing_if = "ClipOutput::is_motors")]
+    pub output: ClipOutput,
+
+    /// Layout dot indices refer to, the part's default layout when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<MotorLayout>,
+}
+
+impl PatternClip {
+    /// Adds the clip's contribution at `time_millis` to `frame`.
+    pub fn render(&self, time_millis: f32, scale: f32, frame: &mut HapticFrame) {
+        if time_millis < self.start_millis as f32 || time_millis >= self.end_millis as f32 {
+            return;
+        }
+
+        let span = (self.end_millis - self.start_millis).max(1) as f32;
+        let factor = self.interpolation.factor((time_millis - self.start_millis as f32) / span) * scale;
+        let motors = match self.output {
+            ClipOutput::Motors => frame.get_mut(self.part),
+            ClipOutput::Thermal => frame.thermal_mut(self.part),
+        };
+
+        match &self.points {
+            PatternPoints::Dot(points) => {
+                let default_layout = MotorLayout::default_for(self.part);
+                let layout = self.layout.as_ref().unwrap_or(&default_layout);
+
+                // Out of range indices are dropped, the layout does not have such motor
+                let mut dots = vec![0.0_f32; layout.len()];
+                for point in points {
+                    if let Some(dot) = dots.get_mut(point.index) {
+                        *dot = dot.max(point.intensity * factor);
+                    }
+                }
+
+                for (motor, intensity) in motors.iter_mut().zip(layout.resample_to(&dots, &default_layout)) {
+                    *motor = motor.max(intensity);
+                }
+            },
+            PatternPoints::Path(points) => {
+                let layout = MotorLayout::default_for(self.part);
+                for point in points {
+                    layout.rasterize(&PathIntensity { intensity: point.intensity * factor, ..*point }, motors);
+                }
+            },
+        }
+    }
+}
+
+/// Protocol independent haptic pattern, as registered with or submitted to the player.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct HapticPattern {
+    pub clips: Vec<PatternClip>,
+}
+
+impl HapticPattern {
+    pub fn new(clips: Vec<PatternClip>) -> Self {
+        Self {
+            clips,
+        }
+    }
+
+    pub fn duration_millis(&self) -> u32 {
+        self.clips.iter().map(|clip| clip.end_millis).max().unwrap_or(0)
+    }
+
+    pub fn render(&self, time_millis: f32, scale: f32, frame: &mut HapticFrame) {
+        for clip in &self.clips {
+            clip.render(time_millis, scale, frame);
+        }
+    }
+}
+
+/// Modifiers applied when a pattern is played.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct PlaybackOptions {
+    /// Intensity multiplier
+    pub intensity: f32,
+
+    /// Duration multiplier, `2.0` plays the pattern half as fast
+    pub duration: f32,
+
+    /// Offset into the pattern to start playing from
+    pub start_millis: u32,
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        Self {
+            intensity: 1.0,
+            duration: 1.0,
+            start_millis: 0,
+        }
+    }
+}
@@ -1,3 +1,589 @@
+use std::{
+    collections::{ BTreeMap, BTreeSet, HashMap },
+    fmt,
+    sync::{
+        atomic::{ AtomicBool, AtomicU64, Ordering },
+        Arc, Mutex, RwLock,
+    },
+    time::{ Duration, Instant },
+};
+
+use haptic_lib::BodyPart;
+use serde::{ Serialize, Deserialize };
+use tracing::{ info, warn };
+
+use crate::metrics::Metrics;
+
+use super::{
+    apps::{ AppProfile, AppProfiles },
+    calibration::CalibrationProfile,
+    device::{ DeviceError, HapticDevice },
+    events::{ EventSender, PlayerEvent },
+    model::{ HapticFrame, HapticPattern, PlaybackOptions },
+    safety::{ SafetyLimiter, SafetyLimits },
+    watchdog::Watchdog,
+};
+
+/// Interval between two frames written to the devices.
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayerError {
+    /// No pattern is registered under the key
+    UnknownKey(String),
+
+    /// The kill switch is engaged, nothing plays until it is cleared
+    Panicked,
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerError::UnknownKey(key) => write!(f, "no pattern registered under key {:?}", key),
+            PlayerError::Panicked => write!(f, "the kill switch is engaged"),
+        }
+    }
+}
+
+impl std::error::Error for PlayerError {}
+
+struct ActiveEffect {
+    pattern: Arc<HapticPattern>,
+    started_at: Instant,
+    options: PlaybackOptions,
+
+    /// Application which played the effect, for its [`AppProfile`]
+    app: Option<String>,
+
+    /// Whether a frame of the effect was written already, for the playback latency
+    written: bool,
+}
+
+impl ActiveEffect {
+    /// Position inside the pattern at `now`, in pattern milliseconds.
+    fn time_millis(&self, now: Instant) -> f32 {
+        let elapsed = now.saturating_duration_since(self.started_at).as_secs_f32() * 1000.0;
+        self.options.start_millis as f32 + elapsed / self.options.duration.max(f32::EPSILON)
+    }
+
+    fn is_finished(&self, now: Instant) -> bool {
+        self.time_millis(now) >= self.pattern.duration_millis() as f32
+    }
+}
+
+/// Device along with the bookkeeping of the watchdog.
+pub(crate) struct DeviceSlot {
+    /// Tells the device apart from the others for the safety limiter, names may repeat
+    pub(crate) id: u64,
+    pub(crate) device: Box<dyn HapticDevice>,
+    pub(crate) last_write: Instant,
+    pub(crate) stopped: bool,
+}
+
+impl DeviceSlot {
+    pub(crate) fn status(&self) -> DeviceStatus {
+        DeviceStatus {
+            name: self.device.name().to_string(),
+            positions: self.device.positions(),
+            stopped: self.stopped,
+        }
+    }
+}
+
+pub(crate) type Devices = Arc<Mutex<Vec<DeviceSlot>>>;
+
+/// What the player knows of a device.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DeviceStatus {
+    pub name: String,
+    pub positions: Vec<BodyPart>,
+
+    /// Whether the watchdog or the kill switch turned the device off
+    pub stopped: bool,
+}
+
+/// Time of the player, the system clock unless driven by hand.
+#[derive(Clone, Default)]
+enum Clock {
+    #[default]
+    System,
+
+    /// Time only moves with [`HapticPlayer::advance`], for deterministic replays
+    Manual(Arc<Mutex<Instant>>),
+}
+
+#[derive(Default)]
+struct PlayerState {
+    patterns: HashMap<String, Arc<HapticPattern>>,
+    active: HashMap<String, ActiveEffect>,
+}
+
+/// Protocol independent player, mixing every active pattern and writing the result to the devices.
+///
+/// Cloning the player is cheap, clones share the same state.
+#[derive(Clone, Default)]
 pub struct HapticPlayer {
+    state: Arc<RwLock<PlayerState>>,
+    devices: Devices,
+    next_device_id: Arc<AtomicU64>,
+    calibration: Arc<RwLock<CalibrationProfile>>,
+    safety: Arc<Mutex<SafetyLimiter>>,
+    panicked: Arc<AtomicBool>,
+    apps: Arc<RwLock<AppProfiles>>,
+
+    /// Body parts silenced whatever plays
+    muted: Arc<RwLock<BTreeSet<BodyPart>>>,
+    events: EventSender<PlayerEvent>,
+    metrics: Metrics,
+    clock: Clock,
+
+    /// Frame last mixed for the devices
+    output: Arc<RwLock<HapticFrame>>,
+}
+
+impl HapticPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Player whose time starts at `start` and only moves with [`HapticPlayer::advance`].
+    ///
+    /// Effects then play the same whatever the load of the machine, e.g. when replaying a session.
+    pub fn with_manual_clock(start: Instant) -> Self {
+        Self {
+            clock: Clock::Manual(Arc::new(Mutex::new(start))),
+            ..Self::default()
+        }
+    }
+
+    /// Current time of the player.
+    pub fn now(&self) -> Instant {
+        match &self.clock {
+            Clock::System => Instant::now(),
+            Clock::Manual(now) => *now.lock().unwrap(),
+        }
+    }
+
+    /// Moves a manual clock forward, does nothing with the system clock.
+    pub fn advance(&self, by: Duration) {
+        if let Clock::Manual(now) = &self.clock {
+            *now.lock().unwrap() += by;
+        }
+    }
+
+    pub fn register(&self, key: impl Into<String>, pattern: HapticPattern) {
+        let key = key.into();
+        self.state.write().unwrap().patterns.insert(key.clone(), Arc::new(pattern));
+        self.events.send(PlayerEvent::Registered { key });
+    }
+
+    pub fn unregister(&self, key: &str) {
+        if self.state.write().unwrap().patterns.remove(key).is_some() {
+            self.events.send(PlayerEvent::Unregistered { key: key.to_string() });
+        }
+    }
+
+    /// Forgets every pattern whose key matches `predicate`.
+    pub fn unregister_where(&self, predicate: impl Fn(&str) -> bool) {
+        self.state.write().unwrap().patterns.retain(|key, _| {
+            let forget = predicate(key);
+            if forget {
+                self.events.send(PlayerEvent::Unregistered { key: key.clone() });
+            }
+            !forget
+        });
+    }
+
+    pub fn is_registered(&self, key: &str) -> bool {
+        self.state.read().unwrap().patterns.contains_key(key)
+    }
+
+    pub fn pattern(&self, key: &str) -> Option<Arc<HapticPattern>> {
+        self.state.read().unwrap().patterns.get(key).cloned()
+    }
+
+    pub fn registered_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.state.read().unwrap().patterns.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Plays the pattern registered under `key`, as the active effect `active_key`.
+    ///
+    /// Playing the same `active_key` again restarts it.
+    pub fn play(&self, key: &str, active_key: impl Into<String>, options: PlaybackOptions) -> Result<(), PlayerError> {
+        self.play_for(None, key, active_key, options)
+    }
+
+    /// Same as [`HapticPlayer::play`], on behalf of the application `app`.
+    pub fn play_for(&self, app: Option<&str>, key: &str, active_key: impl Into<String>, options: PlaybackOptions) -> Result<(), PlayerError> {
+        let pattern = self.pattern(key).ok_or_else(|| PlayerError::UnknownKey(key.to_string()))?;
+        self.start(app, Some(key), active_key.into(), pattern, options)
+    }
+
+    /// Plays a pattern which is not registered, as the active effect `active_key`.
+    pub fn play_pattern(&self, active_key: impl Into<String>, pattern: HapticPattern, options: PlaybackOptions) -> Result<(), PlayerError> {
+        self.play_pattern_for(None, active_key, pattern, options)
+    }
+
+    /// Same as [`HapticPlayer::play_pattern`], on behalf of the application `app`.
+    pub fn play_pattern_for(&self, app: Option<&str>, active_key: impl Into<String>, pattern: HapticPattern, options: PlaybackOptions) -> Result<(), PlayerError> {
+        self.start(app, None, active_key.into(), Arc::new(pattern), options)
+    }
+
+    fn start(&self, app: Option<&str>, key: Option<&str>, active_key: String, pattern: Arc<HapticPattern>, options: PlaybackOptions) -> Result<(), PlayerError> {
+        if self.is_panicked() {
+            return Err(PlayerError::Panicked);
+        }
+
+        self.state.write().unwrap().active.insert(active_key.clone(), ActiveEffect {
+            pattern,
+            started_at: self.now(),
+            options,
+            app: app.map(String::from),
+            written: false,
+        });
+
+        self.events.send(PlayerEvent::Played {
+            active_key,
+            key: key.map(String::from),
+            app: app.map(String::from),
+        });
+
+        Ok(())
+    }
+
+    pub fn stop(&self, active_key: &str) {
+        self.stop_where(|key| key == active_key);
+    }
+
+    /// Stops every active effect whose key matches `predicate`.
+    pub fn stop_where(&self, predicate: impl Fn(&str) -> bool) {
+        self.state.write().unwrap().active.retain(|key, _| {
+            let stop = predicate(key);
+            if stop {
+                self.events.send(PlayerEvent::Stopped { active_key: key.clone() });
+            }
+            !stop
+        });
+    }
+
+    pub fn stop_all(&self) {
+        self.stop_where(|_| true);
+    }
+
+    pub fn is_playing(&self, active_key: &str) -> bool {
+        let now = self.now();
+        self.state.read().unwrap().active
+            .get(active_key)
+            .is_some_and(|effect| !effect.is_finished(now))
+    }
+
+    pub fn active_keys(&self) -> Vec<String> {
+        let now = self.now();
+        let mut keys: Vec<String> = self.state.read().unwrap().active
+            .iter()
+            .filter(|(_, effect)| !effect.is_finished(now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Engages the kill switch: every effect is dropped, every device turned off and
+    /// nothing plays anymore until [`HapticPlayer::clear_panic`] is called.
+    pub fn panic(&self) {
+        warn!("Kill switch engaged, turning every output off");
+        self.panicked.store(true, Ordering::SeqCst);
+        self.events.send(PlayerEvent::Panic { panicked: true });
+        self.stop_all();
+        self.stop_devices();
+    }
+
+    /// Turns every effect and device off and flushes the devices, before letting them go.
+    pub fn shutdown(&self) {
+        self.stop_all();
+        self.stop_devices();
+
+        for slot in self.devices.lock().unwrap().iter_mut() {
+            if let Err(why) = slot.device.flush() {
+                warn!("Failed to flush {}: {}", slot.device.name(), why);
+            }
+        }
+    }
+
+    fn stop_devices(&self) {
+        for slot in self.devices.lock().unwrap().iter_mut() {
+            match slot.device.stop() {
+                Ok(()) => {
+                    if !slot.stopped {
+                        slot.stopped = true;
+                        self.events.send(PlayerEvent::Device { device: slot.status() });
+                    }
+                },
+                Err(why) => warn!("Failed to stop {}: {}", slot.device.name(), why),
+            }
+        }
+    }
+
+    pub fn clear_panic(&self) {
+        if self.panicked.swap(false, Ordering::SeqCst) {
+            info!("Kill switch cleared");
+            self.events.send(PlayerEvent::Panic { panicked: false });
+        }
+    }
+
+    pub fn is_panicked(&self) -> bool {
+        self.panicked.load(Ordering::SeqCst)
+    }
+
+    /// Mixes every active effect at `now`.
+    ///
+    /// Effects are mixed per application first, so that the settings of the application,
+    /// and the ducking of lower priority applications, apply to the application as a whole.
+    pub fn frame_at(&self, now: Instant) -> HapticFrame {
+        let state = self.state.read().unwrap();
+        let profiles = self.apps.read().unwrap();
+
+        let mut apps: BTreeMap<Option<&str>, HapticFrame> = BTreeMap::new();
+        for effect in state.active.values() {
+            let frame = apps.entry(effect.app.as_deref()).or_default();
+            effect.pattern.render(effect.time_millis(now), effect.options.intensity, frame);
+        }
+
+        let playing: Vec<(&AppProfile, HapticFrame)> = apps
+            .into_iter()
+            .map(|(app, mut frame)| {
+                let profile = profiles.get(app);
+                profile.apply(&mut frame);
+                (profile, frame)
+            })
+            .filter(|(_, frame)| !frame.is_silent())
+            .collect();
+
+        let mut frame = HapticFrame::default();
+        for (profile, app_frame) in &playing {
+            let ducking = playing
+                .iter()
+                .filter(|(other, _)| other.priority > profile.priority)
+                .map(|(other, _)| other.duck_gain)
+                .fold(1.0, f32::min);
+
+            frame.mix(app_frame, ducking);
+        }
+
+        for (_, motors) in frame.parts_mut() {
+            motors.iter_mut().for_each(|motor| *motor = motor.clamp(0.0, 1.0));
+        }
+        for (_, elements) in frame.thermal_parts_mut() {
+            elements.iter_mut().for_each(|element| *element = element.clamp(0.0, 1.0));
+        }
+
+        frame
+    }
+
+    pub fn frame(&self) -> HapticFrame {
+        self.frame_at(self.now())
+    }
+
+    /// Switches the calibration applied to every frame written to the devices.
+    pub fn set_calibration(&self, profile: CalibrationProfile) {
+        info!("Using calibration profile {:?}", profile.name);
+        *self.calibration.write().unwrap() = profile;
+    }
+
+    pub fn calibration(&self) -> CalibrationProfile {
+        self.calibration.read().unwrap().clone()
+    }
+
+    /// Switches the per application settings used by the mixer.
+    pub fn set_app_profiles(&self, profiles: AppProfiles) {
+        *self.apps.write().unwrap() = profiles;
+    }
+
+    pub fn app_profiles(&self) -> AppProfiles {
+        self.apps.read().unwrap().clone()
+    }
+
+    /// Silences `part` on every device until unmuted, effects keep playing meanwhile.
+    pub fn set_muted(&self, part: BodyPart, muted: bool) {
+        let changed = match muted {
+            true => self.muted.write().unwrap().insert(part),
+            false => self.muted.write().unwrap().remove(&part),
+        };
+
+        if changed {
+            info!("{:?} {}", part, if muted { "muted" } else { "unmuted" });
+            self.events.send(PlayerEvent::Muted { part, muted });
+        }
+    }
+
+    pub fn muted(&self) -> BTreeSet<BodyPart> {
+        self.muted.read().unwrap().clone()
+    }
+
+    pub fn set_safety_limits(&self, limits: SafetyLimits) {
+        self.safety.lock().unwrap().set_limits(limits);
+    }
+
+    pub fn safety_limits(&self) -> SafetyLimits {
+        self.safety.lock().unwrap().limits().clone()
+    }
+
+    /// Number of times the safety limiter stepped in so far.
+    pub fn safety_interventions(&self) -> u64 {
+        self.safety.lock().unwrap().interventions()
+    }
+
+    pub fn add_device(&self, device: Box<dyn HapticDevice>) {
+        info!("Haptic device {} added", device.name());
+        let slot = DeviceSlot {
+            id: self.next_device_id.fetch_add(1, Ordering::Relaxed),
+            device,
+            last_write: Instant::now(),
+            stopped: false,
+        };
+
+        self.events.send(PlayerEvent::Device { device: slot.status() });
+        self.devices.lock().unwrap().push(slot);
+    }
+
+    pub fn device_count(&self) -> usize {
+        self.devices.lock().unwrap().len()
+    }
+
+    pub fn devices(&self) -> Vec<DeviceStatus> {
+        self.devices.lock().unwrap().iter().map(DeviceSlot::status).collect()
+    }
+
+    /// Body parts driven by at least one device.
+    pub fn connected_positions(&self) -> Vec<BodyPart> {
+        let mut positions: Vec<BodyPart> = self.devices.lock().unwrap()
+            .iter()
+            .flat_map(|slot| slot.device.positions())
+            .collect();
+        positions.sort();
+        positions.dedup();
+        positions
+    }
+
+    /// Mixes a frame at `now` and mutes it, writes it to every device and forgets finished effects.
+    ///
+    /// The frame is resampled to the layout of every device and mapped, then calibrated and limited,
+    /// so that calibration and safety limits apply to the physical motors.
+    pub fn tick(&self, now: Instant) -> HapticFrame {
+        if self.is_panicked() {
+            // Effects submitted while the kill switch was being engaged must not play
+            self.stop_all();
+        }
+
+        let mut frame = self.frame_at(now);
+        let muted = self.muted.read().unwrap();
+        if !muted.is_empty() {
+            for (part, motors) in frame.parts_mut() {
+                if muted.contains(&part) {
+                    motors.fill(0.0);
+                }
+            }
+            for (part, elements) in frame.thermal_parts_mut() {
+                if muted.contains(&part) {
+                    elements.fill(0.0);
+                }
+            }
+        }
+        drop(muted);
+
+        let mut started = vec![];
+        self.state.write().unwrap().active.retain(|key, effect| {
+            if !effect.written {
+                effect.written = true;
+                started.push(effect.started_at);
+            }
+
+            let finished = effect.is_finished(now);
+            if finished {
+                self.events.send(PlayerEvent::Finished { active_key: key.clone() });
+            }
+            !finished
+        });
+
+        let calibration = self.calibration.read().unwrap();
+        let mut safety = self.safety.lock().unwrap();
+        let mut written = false;
+        self.devices.lock().unwrap().retain_mut(|slot| {
+            let mut output = slot.device.map(frame.resample(|part| slot.device.layout(part)));
+            calibration.apply(&mut output);
+            safety.apply(slot.id, &mut output, now);
+
+            match slot.device.write(&output) {
+                Ok(()) => {
+                    written = true;
+                    slot.last_write = Instant::now();
+                    if slot.stopped {
+                        slot.stopped = false;
+                        self.events.send(PlayerEvent::Device { device: slot.status() });
+                    }
+                    true
+                },
+                Err(DeviceError::Disconnected) => {
+                    warn!("Haptic device {} disconnected", slot.device.name());
+                    self.metrics.device_write_failures().inc(slot.device.name());
+                    self.events.send(PlayerEvent::DeviceDisconnected { name: slot.device.name().to_string() });
+                    safety.forget(slot.id);
+                    false
+                },
+                Err(why) => {
+                    warn!("Failed to write frame to {}: {}", slot.device.name(), why);
+                    self.metrics.device_write_failures().inc(slot.device.name());
+                    true
+                },
+            }
+        });
+        drop(safety);
+        drop(calibration);
+
+        if written {
+            let written_at = Instant::now();
+            for started_at in started {
+                self.metrics.playback_latency().observe(written_at.saturating_duration_since(started_at));
+            }
+        }
+
+        *self.output.write().unwrap() = frame.clone();
+        frame
+    }
+
+    /// Metrics of the player, shared with the layers driving it.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Frame last mixed for the devices, before their calibration and limits.
+    pub fn output(&self) -> HapticFrame {
+        self.output.read().unwrap().clone()
+    }
+
+    /// Receives the events of the player from now on.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<PlayerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Starts a watchdog stopping every device which did not get a frame within `deadline`.
+    ///
+    /// The watchdog runs on its own thread, so that it keeps working when the async
+    /// runtime is stuck. It stops with the returned handle.
+    pub fn spawn_watchdog(&self, deadline: Duration) -> Watchdog {
+        Watchdog::spawn(self.devices.clone(), self.events.clone(), deadline)
+    }
+
+    /// Writes frames to the devices every [`FRAME_INTERVAL`], forever.
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(FRAME_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
-}
\ No newline at end of file
+        loop {
+            interval.tick().await;
+            self.tick(self.now());
+        }
+    }
+}
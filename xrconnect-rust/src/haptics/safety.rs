@@ -0,0 +1,368 @@
+//! Last stage of the output pipeline, keeping motors and heating elements within safe limits
+//! whatever games send.
+//!
+//! Limits are enforced per motor (continuous on-time and rolling duty cycle), per body part
+//! (total power budget), and for heating elements (level, continuous on-time). Every device
+//! is limited on its own, in its own motor layout.
+
+use std::{
+    collections::{ BTreeMap, HashMap, HashSet, VecDeque },
+    fmt,
+    time::{ Duration, Instant },
+};
+
+use haptic_lib::BodyPart;
+use serde::{self, Serialize, Deserialize};
+use tracing::warn;
+
+use super::{
+    model::HapticFrame,
+    player::FRAME_INTERVAL,
+};
+
+/// Longest on-time and duty cycle window limits may allow.
+pub const MAX_LIMIT_MILLIS: u64 = 10 * 60 * 1000;
+
+#[derive(Debug)]
+pub enum SafetyError {
+    /// Limits out of their range, which would not keep the outputs safe
+    Invalid(String),
+}
+
+impl fmt::Display for SafetyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SafetyError::Invalid(why) => write!(f, "invalid safety limits: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for SafetyError {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SafetyLimits {
+    /// Intensity above which a motor counts as running
+    #[serde(default = "default_on_threshold")]
+    pub on_threshold: f32,
+
+    /// Longest a motor may run without a break, before being cut for `cooldown_millis`
+    pub max_on_millis: u64,
+
+    pub cooldown_millis: u64,
+
+    /// Window the duty cycle is averaged over
+    pub duty_window_millis: u64,
+
+    /// Highest average intensity over the window, motors are throttled above it
+    pub max_duty_cycle: f32,
+
+    /// Highest sum of motor intensities per body part, all motors of the part are scaled down above it
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub power_budgets: BTreeMap<BodyPart, f32>,
+
+    pub thermal: ThermalLimits,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ThermalLimits {
+    /// Highest level heating elements are driven at
+    pub max_level: f32,
+
+    /// Longest a heating element may stay on, before being cut for `cooldown_millis`
+    pub max_on_millis: u64,
+
+    pub cooldown_millis: u64,
+}
+
+impl Default for SafetyLimits {
+    fn default() -> Self {
+        Self {
+            on_threshold: default_on_threshold(),
+            max_on_millis: 30_000,
+            cooldown_millis: 5_000,
+            duty_window_millis: 60_000,
+            max_duty_cycle: 0.6,
+            power_budgets: BTreeMap::new(),
+            thermal: ThermalLimits {
+                max_level: 0.8,
+                max_on_millis: 60_000,
+                cooldown_millis: 30_000,
+            },
+        }
+    }
+}
+
+impl SafetyLimits {
+    pub fn validate(&self) -> Result<(), SafetyError> {
+        let invalid = |why: String| Err(SafetyError::Invalid(why));
+        let millis = |name: &str, millis: u64| match (1..=MAX_LIMIT_MILLIS).contains(&millis) {
+            true => Ok(()),
+            false => Err(SafetyError::Invalid(format!("{} {} is outside of 1..={}", name, millis, MAX_LIMIT_MILLIS))),
+        };
+
+        if !(0.0..1.0).contains(&self.on_threshold) {
+            return invalid(format!("on_threshold {} is outside of 0..1", self.on_threshold));
+        }
+
+        millis("max_on_millis", self.max_on_millis)?;
+        millis("cooldown_millis", self.cooldown_millis)?;
+        millis("duty_window_millis", self.duty_window_millis)?;
+
+        if !(self.max_duty_cycle > 0.0 && self.max_duty_cycle <= 1.0) {
+            return invalid(format!("max_duty_cycle {} is outside of 0 (excluded)..=1", self.max_duty_cycle));
+        }
+
+        if let Some((part, budget)) = self.power_budgets.iter().find(|(_, budget)| !(budget.is_finite() && **budget > 0.0)) {
+            return invalid(format!("power budget {} of {:?} is not a positive number", budget, part));
+        }
+
+        if !(0.0..=1.0).contains(&self.thermal.max_level) {
+            return invalid(format!("thermal.max_level {} is outside of 0..=1", self.thermal.max_level));
+        }
+
+        millis("thermal.max_on_millis", self.thermal.max_on_millis)?;
+        millis("thermal.cooldown_millis", self.thermal.cooldown_millis)?;
+
+        Ok(())
+    }
+}
+
+fn default_on_threshold() -> f32 {
+    0.05
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Output {
+    /// Motor `index` of a body part of a device
+    Motor { device: u64, part: BodyPart, index: usize },
+    Heater { device: u64, part: BodyPart, index: usize },
+}
+
+impl Output {
+    fn device(&self) -> u64 {
+        match self {
+            Output::Motor { device, .. } | Output::Heater { device, .. } => *device,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Intervention {
+    OnTime,
+    DutyCycle,
+    ThermalLevel,
+}
+
+#[derive(Default)]
+struct OutputState {
+    on_since: Option<Instant>,
+    cooldown_until: Option<Instant>,
+
+    /// Intensity of every frame within the duty cycle window, before being throttled, with its time
+    history: VecDeque<(Instant, f32)>,
+    history_sum: f32,
+
+    /// Interventions active at the previous frame, logged when they start
+    active: Vec<Intervention>,
+}
+
+pub struct SafetyLimiter {
+    limits: SafetyLimits,
+    outputs: HashMap<Output, OutputState>,
+    parts_limited: HashMap<(u64, BodyPart), bool>,
+    interventions: u64,
+}
+
+impl Default for SafetyLimiter {
+    fn default() -> Self {
+        Self::new(SafetyLimits::default())
+    }
+}
+
+impl SafetyLimiter {
+    pub fn new(limits: SafetyLimits) -> Self {
+        Self {
+            limits,
+            outputs: HashMap::new(),
+            parts_limited: HashMap::new(),
+            interventions: 0,
+        }
+    }
+
+    pub fn limits(&self) -> &SafetyLimits {
+        &self.limits
+    }
+
+    /// Replaces the limits, keeping the on-time and duty cycle history.
+    pub fn set_limits(&mut self, limits: SafetyLimits) {
+        self.limits = limits;
+    }
+
+    /// Number of times the limiter started intervening on an output.
+    pub fn interventions(&self) -> u64 {
+        self.interventions
+    }
+
+    /// Forgets the history of the outputs of `device`, which is gone.
+    pub fn forget(&mut self, device: u64) {
+        self.outputs.retain(|output, _| output.device() != device);
+        self.parts_limited.retain(|(id, _), _| *id != device);
+    }
+
+    /// Limits `frame`, as written to `device`, in place. Expected to be called once per [`FRAME_INTERVAL`] and device.
+    ///
+    /// Outputs missing from the frame are off: their on-time ends and their duty cycle goes down.
+    pub fn apply(&mut self, device: u64, frame: &mut HapticFrame, now: Instant) {
+        let mut seen = HashSet::new();
+
+        for (part, motors) in frame.parts_mut() {
+            for (index, motor) in motors.iter_mut().enumerate() {
+                let output = Output::Motor { device, part, index };
+                self.limit(output, motor, now);
+                seen.insert(output);
+            }
+
+            let power: f32 = motors.iter().sum();
+            let budget = self.limits.power_budgets.get(&part).copied();
+            let limited = match budget {
+                Some(budget) if power > budget => {
+                    motors.iter_mut().for_each(|motor| *motor *= budget / power);
+                    true
+                },
+                _ => false,
+            };
+
+            let was_limited = self.parts_limited.insert((device, part), limited).unwrap_or(false);
+            if limited && !was_limited {
+                self.interventions += 1;
+                warn!("Safety limiter: {:?} exceeds its power budget of {:?}, scaling it down", part, budget);
+            }
+        }
+
+        for (part, elements) in frame.thermal_parts_mut() {
+            for (index, element) in elements.iter_mut().enumerate() {
+                let output = Output::Heater { device, part, index };
+                self.limit(output, element, now);
+                seen.insert(output);
+            }
+        }
+
+        let missing: Vec<Output> = self.outputs
+            .keys()
+            .filter(|output| output.device() == device && !seen.contains(output))
+            .copied()
+            .collect();
+        for output in missing {
+            self.limit(output, &mut 0.0, now);
+        }
+
+        for ((id, part), limited) in self.parts_limited.iter_mut() {
+            if *id == device && frame.get(*part).is_none() {
+                *limited = false;
+            }
+        }
+    }
+
+    fn limit(&mut self, output: Output, value: &mut f32, now: Instant) {
+        let limits = &self.limits;
+        let state = self.outputs.entry(output).or_default();
+        let mut active = vec![];
+
+        match output {
+            Output::Motor { .. } => {
+                if !limit_on_time(state, value, now, limits.on_threshold, limits.max_on_millis, limits.cooldown_millis) {
+                    active.push(Intervention::OnTime);
+                }
+
+                if !limit_duty_cycle(state, value, now, limits.duty_window_millis, limits.max_duty_cycle) {
+                    active.push(Intervention::DutyCycle);
+                }
+            },
+            Output::Heater { .. } => {
+                let thermal = &limits.thermal;
+                if *value > thermal.max_level {
+                    *value = thermal.max_level;
+                    active.push(Intervention::ThermalLevel);
+                }
+
+                if !limit_on_time(state, value, now, 0.0, thermal.max_on_millis, thermal.cooldown_millis) {
+                    active.push(Intervention::OnTime);
+                }
+            },
+        }
+
+        report(&mut self.interventions, output, state, active);
+    }
+}
+
+/// Cuts the output once it ran for longer than `max_on_millis`, until the cooldown is over.
+///
+/// Returns whether the output was left untouched.
+fn limit_on_time(state: &mut OutputState, value: &mut f32, now: Instant, threshold: f32, max_on_millis: u64, cooldown_millis: u64) -> bool {
+    if let Some(until) = state.cooldown_until {
+        if now < until {
+            *value = 0.0;
+            return false;
+        }
+        state.cooldown_until = None;
+    }
+
+    if *value <= threshold {
+        state.on_since = None;
+        return true;
+    }
+
+    let on_since = *state.on_since.get_or_insert(now);
+    if now.duration_since(on_since) > Duration::from_millis(max_on_millis) {
+        state.on_since = None;
+        state.cooldown_until = Some(now + Duration::from_millis(cooldown_millis));
+        *value = 0.0;
+        return false;
+    }
+
+    true
+}
+
+/// Throttles the output proportionally once its average over the window is above `max_duty_cycle`,
+/// so that the average of the output converges to it. Every frame counts for a [`FRAME_INTERVAL`] of the window.
+///
+/// The average is the one of the intensity asked for, the throttled output would otherwise settle
+/// at the square root of the limit.
+///
+/// Returns whether the output was left untouched.
+fn limit_duty_cycle(state: &mut OutputState, value: &mut f32, now: Instant, window_millis: u64, max_duty_cycle: f32) -> bool {
+    let window = Duration::from_millis(window_millis.max(1));
+    while let Some((at, intensity)) = state.history.front().copied() {
+        if now.saturating_duration_since(at) < window {
+            break;
+        }
+        state.history.pop_front();
+        state.history_sum -= intensity;
+    }
+    if state.history.is_empty() {
+        state.history_sum = 0.0;
+    }
+
+    state.history.push_back((now, *value));
+    state.history_sum += *value;
+
+    let average = state.history_sum * FRAME_INTERVAL.as_secs_f32() / window.as_secs_f32();
+    let untouched = average <= max_duty_cycle || *value <= 0.0;
+    if !untouched {
+        *value *= max_duty_cycle / average;
+    }
+
+    untouched
+}
+
+fn report(counter: &mut u64, output: Output, state: &mut OutputState, active: Vec<Intervention>) {
+    for intervention in &active {
+        if !state.active.contains(intervention) {
+            *counter += 1;
+            warn!("Safety limiter: {:?} limited by {:?}", output, intervention);
+        }
+    }
+
+    state.active = active;
+}
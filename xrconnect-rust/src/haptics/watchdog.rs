@@ -0,0 +1,81 @@
+use std::{
+    sync::{
+        atomic::{ AtomicBool, Ordering },
+        Arc, PoisonError, TryLockError,
+    },
+    thread,
+    time::{ Duration, Instant },
+};
+
+use tracing::{ error, warn };
+
+use super::{
+    events::{ EventSender, PlayerEvent },
+    player::Devices,
+};
+
+/// Default time a device may go without a frame before the watchdog stops it.
+pub const DEFAULT_DEADLINE: Duration = Duration::from_millis(250);
+
+/// Stops devices left without frames, e.g. when the playback task panicked or stalled.
+///
+/// Dropping the handle stops the watchdog.
+pub struct Watchdog {
+    running: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    pub(crate) fn spawn(devices: Devices, events: EventSender<PlayerEvent>, deadline: Duration) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+
+        thread::Builder::new()
+            .name(String::from("haptic-watchdog"))
+            .spawn({
+                let running = running.clone();
+                move || {
+                    while running.load(Ordering::Relaxed) {
+                        thread::sleep(deadline / 2);
+                        check(&devices, &events, deadline);
+                    }
+                }
+            })
+            .expect("failed to spawn the watchdog thread");
+
+        Self {
+            running,
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn check(devices: &Devices, events: &EventSender<PlayerEvent>, deadline: Duration) {
+    // A panicking playback task poisons the lock, devices still have to be stopped then.
+    // When the lock is held, frames are being written, unless the writer is stuck, in
+    // which case there is nothing more we can do.
+    let mut devices = match devices.try_lock() {
+        Ok(devices) => devices,
+        Err(TryLockError::Poisoned(poisoned)) => PoisonError::into_inner(poisoned),
+        Err(TryLockError::WouldBlock) => return,
+    };
+
+    let now = Instant::now();
+    for slot in devices.iter_mut().filter(|slot| !slot.stopped) {
+        if now.duration_since(slot.last_write) <= deadline {
+            continue;
+        }
+
+        warn!("Watchdog: no frame written to {} for {:?}, stopping it", slot.device.name(), deadline);
+        match slot.device.stop() {
+            Ok(()) => {
+                slot.stopped = true;
+                events.send(PlayerEvent::Device { device: slot.status() });
+            },
+            Err(why) => error!("Watchdog failed to stop {}: {}", slot.device.name(), why),
+        }
+    }
+}
@@ -1,6 +1,24 @@
 #![crate_type = "lib"]
 #![crate_name = "xrconnect"]
 
+pub mod access;
+
+pub mod api;
+
 pub mod haptics;
 
-pub mod bhaptics_studio;
\ No newline at end of file
+pub mod bhaptics_studio;
+
+pub mod config;
+
+pub mod control;
+
+pub mod devices;
+
+pub mod metrics;
+
+pub mod paths;
+
+pub mod state;
+
+pub mod tls;
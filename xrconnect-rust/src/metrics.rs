@@ -0,0 +1,205 @@
+//! Counters and histograms, exposed in the Prometheus text format at `GET /metrics`.
+//!
+//! Metrics are cheap to update, they are plain atomics, or a map behind a lock for
+//! labelled ones. Gauges, such as the connected clients, are read at scrape time from
+//! the state they describe instead of being kept up to date.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{ AtomicU64, Ordering },
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Upper bounds of the playback latency buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 10] = [0.005, 0.01, 0.02, 0.03, 0.05, 0.075, 0.1, 0.25, 0.5, 1.0];
+
+#[derive(Default)]
+pub struct Counter {
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Counters told apart by the value of a single label.
+#[derive(Default)]
+pub struct LabelledCounter {
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+impl LabelledCounter {
+    pub fn inc(&self, label: &str) {
+        let mut values = self.values.lock().unwrap();
+        match values.get_mut(label) {
+            Some(value) => *value += 1,
+            None => {
+                values.insert(label.to_string(), 1);
+            },
+        }
+    }
+
+    pub fn get(&self, label: &str) -> u64 {
+        self.values.lock().unwrap().get(label).copied().unwrap_or(0)
+    }
+
+    fn values(&self) -> BTreeMap<String, u64> {
+        self.values.lock().unwrap().clone()
+    }
+}
+
+pub struct Histogram {
+    bounds: &'static [f64],
+
+    /// Observations per bucket, not cumulative, the last one is `+Inf`
+    buckets: Vec<AtomicU64>,
+
+    /// Sum of the observations, in microseconds
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = self.bounds.iter().position(|bound| seconds <= *bound).unwrap_or(self.bounds.len());
+
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().map(|bucket| bucket.load(Ordering::Relaxed)).sum()
+    }
+}
+
+struct Registry {
+    messages: LabelledCounter,
+    parse_errors: Counter,
+    playback_latency: Histogram,
+    device_write_failures: LabelledCounter,
+}
+
+/// Metrics of the server, shared by the player, the WebSocket behavior and the API.
+///
+/// Cloning is cheap, clones update the same metrics.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Arc<Registry>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            registry: Arc::new(Registry {
+                messages: LabelledCounter::default(),
+                parse_errors: Counter::default(),
+                playback_latency: Histogram::new(&LATENCY_BUCKETS),
+                device_write_failures: LabelledCounter::default(),
+            }),
+        }
+    }
+}
+
+impl Metrics {
+    /// bHaptics messages received, by type, e.g. `Register`, `frame` or `turnOff`.
+    pub fn messages(&self) -> &LabelledCounter {
+        &self.registry.messages
+    }
+
+    /// bHaptics messages which could not be parsed.
+    pub fn parse_errors(&self) -> &Counter {
+        &self.registry.parse_errors
+    }
+
+    /// Time from an effect being started, as its message is handled, to its first frame being written.
+    pub fn playback_latency(&self) -> &Histogram {
+        &self.registry.playback_latency
+    }
+
+    /// Frames which could not be written, by device.
+    pub fn device_write_failures(&self) -> &LabelledCounter {
+        &self.registry.device_write_failures
+    }
+}
+
+/// Writes metrics in the Prometheus text format.
+#[derive(Default)]
+pub struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    pub fn counter(&mut self, name: &str, help: &str, value: u64) {
+        self.header(name, "counter", help);
+        let _ = writeln!(self.text, "{} {}", name, value);
+    }
+
+    pub fn gauge(&mut self, name: &str, help: &str, value: u64) {
+        self.header(name, "gauge", help);
+        let _ = writeln!(self.text, "{} {}", name, value);
+    }
+
+    /// Metric with one sample per value of `label`.
+    pub fn labelled(&mut self, name: &str, kind: &str, help: &str, label: &str, values: &BTreeMap<String, u64>) {
+        self.header(name, kind, help);
+        for (value, count) in values {
+            let _ = writeln!(self.text, "{}{{{}=\"{}\"}} {}", name, label, escape(value), count);
+        }
+    }
+
+    pub fn labelled_counter(&mut self, name: &str, help: &str, label: &str, counter: &LabelledCounter) {
+        self.labelled(name, "counter", help, label, &counter.values());
+    }
+
+    pub fn histogram(&mut self, name: &str, help: &str, histogram: &Histogram) {
+        self.header(name, "histogram", help);
+
+        let mut cumulative = 0;
+        for (bound, bucket) in histogram.bounds.iter().zip(&histogram.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(self.text, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+
+        let count = histogram.count();
+        let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(self.text, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(self.text, "{}_sum {}", name, sum);
+        let _ = writeln!(self.text, "{}_count {}", name, count);
+    }
+
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+/// Escapes a label value, see the Prometheus text format.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+/// Directory xrconnect keeps its configuration in, `xrconnect` in the platform's config directory.
+///
+/// Can be overridden with the `XRCONNECT_CONFIG_DIR` environment variable.
+pub fn config_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XRCONNECT_CONFIG_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("xrconnect")
+}
+
+/// Settings of the server, see [`crate::config::Config`].
+pub fn config_file() -> PathBuf {
+    config_dir().join("xrconnect.toml")
+}
+
+/// Unix domain socket the CLI reaches a running server through, see [`crate::control`].
+pub fn control_socket() -> PathBuf {
+    config_dir().join("control.sock")
+}
+
+/// Directory calibration profiles are stored in.
+pub fn calibration_dir() -> PathBuf {
+    config_dir().join("calibration")
+}
+
+/// File the state surviving restarts is kept in, see [`crate::state::SavedState`].
+pub fn state_file() -> PathBuf {
+    config_dir().join("state.json")
+}
+
+/// File the per application settings are read from, see [`crate::haptics::apps::AppProfiles`].
+pub fn app_profiles_file() -> PathBuf {
+    config_dir().join("apps.json")
+}
+
+/// File the access policy is read from, see [`crate::access::AccessPolicy`].
+pub fn access_policy_file() -> PathBuf {
+    config_dir().join("access.json")
+}
+
+/// File the decisions about applications waiting for approval are kept in.
+pub fn approvals_file() -> PathBuf {
+    config_dir().join("approvals.json")
+}
+
+/// Directory the self-signed TLS certificate is kept in.
+pub fn tls_dir() -> PathBuf {
+    config_dir().join("tls")
+}
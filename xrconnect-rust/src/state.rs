@@ -0,0 +1,69 @@
+use std::{
+    fs,
+    io,
+    path::Path,
+};
+
+use serde::{ Serialize, Deserialize };
+use tracing::warn;
+
+use crate::haptics::{
+    calibration::CalibrationStore,
+    player::HapticPlayer,
+};
+
+/// Player state surviving restarts, written when the server shuts down.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SavedState {
+    /// Calibration profile in use, none when uncalibrated
+    #[serde(default)]
+    pub calibration: Option<String>,
+
+    /// Whether the kill switch was engaged, it stays engaged across restarts
+    #[serde(default)]
+    pub panicked: bool,
+}
+
+impl SavedState {
+    pub fn capture(player: &HapticPlayer) -> Self {
+        let calibration = player.calibration().name;
+
+        Self {
+            calibration: (!calibration.is_empty()).then_some(calibration),
+            panicked: player.is_panicked(),
+        }
+    }
+
+    /// Applies the state to `player`, loading the calibration from `calibrations`.
+    pub fn restore(&self, player: &HapticPlayer, calibrations: &CalibrationStore) {
+        if let Some(name) = &self.calibration {
+            match calibrations.load(name) {
+                Ok(profile) => player.set_calibration(profile),
+                Err(why) => warn!("Failed to restore calibration {:?}: {}", name, why),
+            }
+        }
+
+        if self.panicked {
+            warn!("The kill switch was engaged when shutting down, it stays engaged");
+            player.panic();
+        }
+    }
+
+    /// Reads the state from `path`, the default state when there is no such file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why)),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(why) => Err(why),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
@@ -0,0 +1,127 @@
+//! TLS (`wss://`) listener settings.
+//!
+//! Web pages served over HTTPS may only open secure WebSockets. The server can listen
+//! with TLS on a separate port, next to the plain listener, using either a configured
+//! certificate or a self-signed one, generated once and kept in the config directory.
+//! Browsers have to be told to trust the self-signed certificate, e.g. by opening
+//! `https://<host>:15882/` once and accepting it.
+
+use std::{
+    fmt,
+    fs,
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+};
+
+use tracing::info;
+
+use crate::paths;
+
+#[derive(Debug)]
+pub enum TlsError {
+    Io(io::Error),
+    /// Certificate could not be generated
+    Generate(rcgen::Error),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Io(why) => write!(f, "TLS certificate I/O error: {}", why),
+            TlsError::Generate(why) => write!(f, "failed to generate a self-signed certificate: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TlsCertificate {
+    /// PEM encoded certificate chain and private key
+    Files { cert: PathBuf, key: PathBuf },
+
+    /// Self-signed certificate valid for `localhost`, the loopback addresses and `names`
+    SelfSigned { names: Vec<String> },
+}
+
+impl Default for TlsCertificate {
+    fn default() -> Self {
+        TlsCertificate::SelfSigned { names: vec![] }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsConfig {
+    pub address: SocketAddr,
+    pub certificate: TlsCertificate,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            address: ([127, 0, 0, 1], 15882).into(),
+            certificate: TlsCertificate::default(),
+        }
+    }
+}
+
+impl TlsConfig {
+    /// PEM encoded certificate chain and private key, generating the self-signed ones when missing.
+    pub fn load(&self) -> Result<(Vec<u8>, Vec<u8>), TlsError> {
+        let (cert, key) = match &self.certificate {
+            TlsCertificate::Files { cert, key } => (cert.clone(), key.clone()),
+            TlsCertificate::SelfSigned { names } => self_signed(names)?,
+        };
+
+        Ok((fs::read(cert).map_err(TlsError::Io)?, fs::read(key).map_err(TlsError::Io)?))
+    }
+}
+
+/// Paths of the self-signed certificate and key, generated when missing.
+///
+/// The certificate is generated again when `names` are not all covered by the stored one.
+fn self_signed(names: &[String]) -> Result<(PathBuf, PathBuf), TlsError> {
+    let dir = paths::tls_dir();
+    let cert = dir.join("self-signed.pem");
+    let key = dir.join("self-signed.key");
+    let names_file = dir.join("self-signed.names");
+
+    let mut all_names = vec![String::from("localhost"), String::from("127.0.0.1"), String::from("::1")];
+    all_names.extend(names.iter().cloned());
+
+    let stored_names = fs::read_to_string(&names_file).unwrap_or_default();
+    let covered = all_names.iter().all(|name| stored_names.lines().any(|stored| stored == name));
+    if cert.exists() && key.exists() && covered {
+        return Ok((cert, key));
+    }
+
+    info!("Generating a self-signed TLS certificate for {} in {}", all_names.join(", "), dir.display());
+    let generated = rcgen::generate_simple_self_signed(all_names.clone()).map_err(TlsError::Generate)?;
+
+    fs::create_dir_all(&dir).map_err(TlsError::Io)?;
+    fs::write(&cert, generated.cert.pem()).map_err(TlsError::Io)?;
+    write_private(&key, generated.key_pair.serialize_pem().as_bytes()).map_err(TlsError::Io)?;
+    fs::write(&names_file, all_names.join("\n")).map_err(TlsError::Io)?;
+
+    Ok((cert, key))
+}
+
+/// Writes a file only the current user can read.
+fn write_private(path: &std::path::Path, content: &[u8]) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::{ io::Write, os::unix::fs::OpenOptionsExt };
+
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(content)
+    }
+
+    #[cfg(not(unix))]
+    fs::write(path, content)
+}
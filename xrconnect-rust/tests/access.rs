@@ -0,0 +1,182 @@
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+
+use futures_util::{ SinkExt, StreamExt };
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::{ protocol::frame::coding::CloseCode, Message };
+
+use xrconnect::{
+    access::{ AccessControl, AccessPolicy, Decision, PendingApp, UnknownApps },
+    bhaptics_studio::server::BHapticsStudioServer,
+    haptics::player::HapticPlayer,
+};
+
+mod common;
+
+const GAME: &str = "com.example.game";
+
+fn asking() -> AccessControl {
+    AccessControl::new(AccessPolicy { unknown_apps: UnknownApps::Ask, ..Default::default() })
+}
+
+/// Connection of the application `id`, held until a decision is taken.
+fn connecting(access: &AccessControl, id: &str) -> JoinHandle<Decision> {
+    let access = access.clone();
+    let id = id.to_string();
+    tokio::spawn(async move { access.authorize(&id, "Example Game").await })
+}
+
+/// Waits for the pending applications to be `expected`, up to a second.
+async fn assert_pending(access: &AccessControl, expected: &[&str]) {
+    let ids = || access.pending().into_iter().map(|PendingApp { id, .. }| id).collect::<Vec<_>>();
+
+    for _ in 0..20 {
+        if ids() == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(ids(), expected);
+}
+
+#[tokio::test]
+async fn approving_releases_every_held_connection() {
+    let access = asking();
+    let first = connecting(&access, GAME);
+    let second = connecting(&access, GAME);
+    assert_pending(&access, &[GAME]).await;
+
+    access.approve(GAME).unwrap();
+
+    assert_eq!(first.await.unwrap(), Decision::Approved);
+    assert_eq!(second.await.unwrap(), Decision::Approved);
+    assert_pending(&access, &[]).await;
+    assert_eq!(access.decision(GAME), Some(Decision::Approved));
+    assert_eq!(access.authorize(GAME, "Example Game").await, Decision::Approved);
+}
+
+#[tokio::test]
+async fn denying_refuses_held_connections() {
+    let access = asking();
+    let held = connecting(&access, GAME);
+    assert_pending(&access, &[GAME]).await;
+
+    access.deny(GAME).unwrap();
+
+    assert_eq!(held.await.unwrap(), Decision::Denied);
+    assert_pending(&access, &[]).await;
+    assert_eq!(access.approvals().apps.get(GAME), Some(&Decision::Denied));
+}
+
+#[tokio::test]
+async fn abandoned_applications_are_no_longer_pending() {
+    let access = asking();
+    let first = connecting(&access, GAME);
+    let second = connecting(&access, GAME);
+    let other = connecting(&access, "com.example.other");
+    assert_pending(&access, &["com.example.game", "com.example.other"]).await;
+
+    // Still waited for by the second connection
+    first.abort();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_pending(&access, &["com.example.game", "com.example.other"]).await;
+
+    second.abort();
+    assert_pending(&access, &["com.example.other"]).await;
+    assert_eq!(access.decision(GAME), None);
+
+    // Asked for again when it reconnects
+    let reconnected = connecting(&access, GAME);
+    assert_pending(&access, &["com.example.game", "com.example.other"]).await;
+    access.approve(GAME).unwrap();
+    assert_eq!(reconnected.await.unwrap(), Decision::Approved);
+    other.abort();
+}
+
+async fn server(access: &AccessControl) -> (SocketAddr, HapticPlayer) {
+    let address = common::free_address();
+    let server = BHapticsStudioServer::new(address)
+        .with_state_file(None)
+        .with_access(access.clone());
+    let player = server.player().clone();
+    common::spawn(server).await;
+
+    (address, player)
+}
+
+fn submit(key: &str) -> Message {
+    Message::Text(json!({ "Submit": [{
+        "Type": "frame",
+        "Key": key,
+        "Frame": { "Position": "VestFront", "DotPoints": [{ "Index": 0, "Intensity": 100 }], "PathPoints": [], "DurationMillis": 10_000 },
+    }]}).to_string())
+}
+
+#[tokio::test]
+async fn held_clients_disconnecting_abandon_the_approval() {
+    let access = asking();
+    let (address, _) = server(&access).await;
+
+    let url = format!("ws://{}/v2/feedbacks?app_id={}&app_name=Game", address, GAME);
+    let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    socket.send(submit("held")).await.unwrap();
+    assert_pending(&access, &[GAME]).await;
+
+    drop(socket);
+    assert_pending(&access, &[]).await;
+}
+
+#[tokio::test]
+async fn requests_sent_while_held_are_handled_once_approved() {
+    let access = asking();
+    let (address, player) = server(&access).await;
+
+    let url = format!("ws://{}/v2/feedbacks?app_id={}&app_name=Game", address, GAME);
+    let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    socket.send(submit("held")).await.unwrap();
+    assert_pending(&access, &[GAME]).await;
+    assert!(player.active_keys().is_empty());
+
+    access.approve(GAME).unwrap();
+
+    // Status responses may come first
+    let reflected = async {
+        while let Some(Ok(response)) = socket.next().await {
+            if response.to_text().unwrap().contains("held") {
+                return true;
+            }
+        }
+        false
+    };
+    assert!(tokio::time::timeout(Duration::from_secs(2), reflected).await.unwrap());
+    assert!(player.active_keys().iter().any(|key| key.ends_with("/held")), "{:?}", player.active_keys());
+}
+
+#[tokio::test]
+async fn held_clients_sending_too_much_are_closed() {
+    let access = asking();
+    let (address, _) = server(&access).await;
+
+    let url = format!("ws://{}/v2/feedbacks?app_id={}&app_name=Game", address, GAME);
+    let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    for index in 0..1000 {
+        if socket.send(submit(&format!("held-{}", index))).await.is_err() {
+            break;
+        }
+    }
+
+    let closed = async {
+        while let Some(Ok(message)) = socket.next().await {
+            if let Message::Close(frame) = message {
+                return frame.map(|frame| frame.code);
+            }
+        }
+        None
+    };
+    let code = tokio::time::timeout(Duration::from_secs(2), closed).await.unwrap();
+    assert_eq!(code, Some(CloseCode::Policy));
+    assert_pending(&access, &[]).await;
+}
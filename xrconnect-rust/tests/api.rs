@@ -0,0 +1,132 @@
+use std::net::SocketAddr;
+
+use serde_json::{ json, Value };
+use warp::http::StatusCode;
+
+use xrconnect::{
+    access::AccessControl,
+    api::ControlApi,
+    haptics::{
+        player::HapticPlayer,
+        safety::SafetyLimits,
+    },
+};
+
+mod common;
+
+use common::{ routes, LOCAL, REMOTE };
+
+async fn request(api: ControlApi, method: &str, path: &str, from: impl Into<SocketAddr>, token: Option<&str>) -> StatusCode {
+    let mut request = warp::test::request().method(method).path(path).remote_addr(from.into());
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+
+    request.reply(&routes(api)).await.status()
+}
+
+async fn put(api: ControlApi, path: &str, body: Value) -> StatusCode {
+    let request = warp::test::request().method("PUT").path(path).remote_addr(LOCAL.into()).json(&body);
+    request.reply(&routes(api)).await.status()
+}
+
+fn api(player: &HapticPlayer) -> ControlApi {
+    ControlApi::new(player.clone(), AccessControl::default())
+}
+
+#[tokio::test]
+async fn local_peers_use_the_api() {
+    let player = HapticPlayer::new();
+    player.panic();
+
+    assert_eq!(request(api(&player), "DELETE", "/api/panic", LOCAL, None).await, StatusCode::OK);
+    assert!(!player.is_panicked());
+
+    let mapped: SocketAddr = "[::ffff:127.0.0.1]:40000".parse().unwrap();
+    assert_eq!(request(api(&player), "GET", "/api/panic", mapped, None).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn remote_peers_are_refused() {
+    let player = HapticPlayer::new();
+    player.panic();
+
+    for (method, path) in [
+        ("GET", "/api/panic"),
+        ("DELETE", "/api/panic"),
+        ("POST", "/api/apps/com.example.game/approve"),
+        ("GET", "/api/clients"),
+        ("DELETE", "/api/active"),
+        ("POST", "/api/play"),
+        ("POST", "/api/play/frame"),
+        ("PUT", "/api/muted/Head"),
+        ("PUT", "/api/settings/safety"),
+        ("PUT", "/api/settings/apps"),
+        ("GET", "/dashboard/"),
+        ("GET", "/metrics"),
+    ] {
+        assert_eq!(request(api(&player), method, path, REMOTE, None).await, StatusCode::FORBIDDEN, "{} {}", method, path);
+    }
+
+    assert!(player.is_panicked());
+}
+
+#[tokio::test]
+async fn remote_peers_use_the_api_with_its_token() {
+    let player = HapticPlayer::new();
+    player.panic();
+    let api = || api(&player).with_token(Some(String::from("secret")));
+
+    assert_eq!(request(api(), "DELETE", "/api/panic", REMOTE, None).await, StatusCode::FORBIDDEN);
+    assert_eq!(request(api(), "DELETE", "/api/panic", REMOTE, Some("guess")).await, StatusCode::FORBIDDEN);
+    assert!(player.is_panicked());
+
+    assert_eq!(request(api(), "DELETE", "/api/panic", REMOTE, Some("secret")).await, StatusCode::OK);
+    assert!(!player.is_panicked());
+}
+
+#[tokio::test]
+async fn out_of_range_safety_limits_are_refused() {
+    let player = HapticPlayer::new();
+    let defaults = serde_json::to_value(SafetyLimits::default()).unwrap();
+    let with = |key: &str, value: Value| {
+        let mut limits = defaults.clone();
+        limits[key] = value;
+        limits
+    };
+
+    for limits in [
+        with("max_duty_cycle", json!(0.0)),
+        with("max_duty_cycle", json!(-0.5)),
+        with("max_duty_cycle", json!(1.5)),
+        with("max_on_millis", json!(0)),
+        with("max_on_millis", json!(u64::MAX)),
+        with("duty_window_millis", json!(0)),
+        with("on_threshold", json!(1.0)),
+        with("power_budgets", json!({ "Head": -1.0 })),
+        with("thermal", json!({ "max_level": 2.0, "max_on_millis": 1000, "cooldown_millis": 1000 })),
+        with("thermal", json!({ "max_level": 0.5, "max_on_millis": 86_400_000, "cooldown_millis": 1000 })),
+    ] {
+        assert_eq!(put(api(&player), "/api/settings/safety", limits.clone()).await, StatusCode::BAD_REQUEST, "{}", limits);
+    }
+    assert_eq!(player.safety_limits(), SafetyLimits::default());
+
+    assert_eq!(put(api(&player), "/api/settings/safety", with("max_on_millis", json!(5000))).await, StatusCode::OK);
+    assert_eq!(player.safety_limits().max_on_millis, 5000);
+}
+
+#[tokio::test]
+async fn oversized_bodies_are_refused() {
+    let player = HapticPlayer::new();
+
+    for (method, path) in [
+        ("POST", "/api/play"),
+        ("POST", "/api/play/frame"),
+        ("PUT", "/api/settings/calibration"),
+        ("PUT", "/api/settings/safety"),
+        ("PUT", "/api/settings/apps"),
+    ] {
+        let request = warp::test::request().method(method).path(path).remote_addr(LOCAL.into()).body(vec![b' '; 2 * 1024 * 1024]);
+        assert_eq!(request.reply(&routes(api(&player))).await.status(), StatusCode::PAYLOAD_TOO_LARGE, "{} {}", method, path);
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::{ BTreeMap, BTreeSet },
+    time::{ Duration, Instant },
+};
+
+use haptic_lib::{ BodyPart, EffectInterpolation };
+
+use xrconnect::haptics::{
+    apps::{ AppProfile, AppProfileError, AppProfiles },
+    model::{ ClipOutput, DotIntensity, HapticPattern, PatternClip, PatternPoints, PlaybackOptions },
+    player::HapticPlayer,
+};
+
+const GAME: &str = "com.example.game";
+const VISUALISER: &str = "com.example.visualiser";
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() < 1e-5, "{} instead of {}", actual, expected);
+}
+
+/// First motor of every part in `parts` at full intensity, for `millis`.
+fn pulse(parts: &[BodyPart], millis: u32) -> HapticPattern {
+    HapticPattern::new(parts.iter().map(|part| PatternClip {
+        part: *part,
+        start_millis: 0,
+        end_millis: millis,
+        interpolation: EffectInterpolation::None,
+        points: PatternPoints::Dot(vec![DotIntensity { index: 0, intensity: 1.0 }]),
+        output: ClipOutput::Motors,
+        layout: None,
+    }).collect())
+}
+
+fn play(player: &HapticPlayer, app: &str, key: &str, pattern: HapticPattern) {
+    player.play_pattern_for(Some(app), key, pattern, PlaybackOptions::default()).unwrap();
+}
+
+fn motor(player: &HapticPlayer, part: BodyPart) -> f32 {
+    player.frame().get(part).map_or(0.0, |motors| motors[0])
+}
+
+fn player(apps: BTreeMap<String, AppProfile>) -> HapticPlayer {
+    let player = HapticPlayer::with_manual_clock(Instant::now());
+    player.set_app_profiles(AppProfiles { default: AppProfile::default(), apps });
+    player
+}
+
+#[test]
+fn gains_apply_per_app_and_position() {
+    let player = player(BTreeMap::from([
+        (String::from(GAME), AppProfile { gain: 0.8, positions: BTreeMap::from([(BodyPart::Head, 0.5)]), ..Default::default() }),
+    ]));
+
+    play(&player, GAME, "hit", pulse(&[BodyPart::Head, BodyPart::ChestFront], 1000));
+    assert_close(motor(&player, BodyPart::Head), 0.4);
+    assert_close(motor(&player, BodyPart::ChestFront), 0.8);
+
+    // Applications without settings go through the default ones
+    play(&player, VISUALISER, "beat", pulse(&[BodyPart::ChestBack], 1000));
+    assert_close(motor(&player, BodyPart::ChestBack), 1.0);
+}
+
+#[test]
+fn disabled_positions_are_silent() {
+    let player = player(BTreeMap::from([
+        (String::from(VISUALISER), AppProfile { enabled: Some(BTreeSet::from([BodyPart::ChestFront])), ..Default::default() }),
+    ]));
+
+    play(&player, VISUALISER, "beat", pulse(&[BodyPart::Head, BodyPart::ChestFront], 1000));
+    assert_close(motor(&player, BodyPart::Head), 0.0);
+    assert_close(motor(&player, BodyPart::ChestFront), 1.0);
+
+    // Other applications still drive them
+    play(&player, GAME, "hit", pulse(&[BodyPart::Head], 1000));
+    assert_close(motor(&player, BodyPart::Head), 1.0);
+}
+
+#[test]
+fn higher_priority_apps_duck_lower_priority_ones_while_playing() {
+    let player = player(BTreeMap::from([
+        (String::from(GAME), AppProfile { priority: 10, duck_gain: 0.2, ..Default::default() }),
+        (String::from(VISUALISER), AppProfile { gain: 0.5, ..Default::default() }),
+    ]));
+
+    play(&player, VISUALISER, "beat", pulse(&[BodyPart::ChestFront, BodyPart::ChestBack], 2000));
+    assert_close(motor(&player, BodyPart::ChestBack), 0.5);
+
+    // Ducked on every part, not just the ones the game drives
+    play(&player, GAME, "hit", pulse(&[BodyPart::ChestFront], 500));
+    assert_close(motor(&player, BodyPart::ChestFront), 1.0);
+    assert_close(motor(&player, BodyPart::ChestBack), 0.5 * 0.2);
+
+    // Back to its own gain once the game is done
+    player.advance(Duration::from_millis(600));
+    assert_close(motor(&player, BodyPart::ChestFront), 0.5);
+    assert_close(motor(&player, BodyPart::ChestBack), 0.5);
+}
+
+#[test]
+fn silent_or_equal_priority_apps_do_not_duck() {
+    let player = player(BTreeMap::from([
+        (String::from(GAME), AppProfile { priority: 10, duck_gain: 0.0, enabled: Some(BTreeSet::from([BodyPart::Head])), ..Default::default() }),
+        (String::from(VISUALISER), AppProfile { priority: 10, ..Default::default() }),
+        (String::from("com.example.music"), AppProfile { priority: 0, ..Default::default() }),
+    ]));
+
+    // Only plays on a disabled position, the game is silent and does not duck the others
+    play(&player, GAME, "hit", pulse(&[BodyPart::ChestFront], 1000));
+    play(&player, "com.example.music", "beat", pulse(&[BodyPart::ChestBack], 1000));
+    assert_close(motor(&player, BodyPart::ChestBack), 1.0);
+
+    // Same priority as the visualiser, which it does not duck
+    play(&player, GAME, "ping", pulse(&[BodyPart::Head], 1000));
+    play(&player, VISUALISER, "wave", pulse(&[BodyPart::ChestFront], 1000));
+    assert_close(motor(&player, BodyPart::ChestFront), 1.0);
+    assert_close(motor(&player, BodyPart::ChestBack), 0.0);
+}
+
+#[test]
+fn invalid_settings_are_rejected() {
+    for profile in [
+        AppProfile { gain: -0.5, ..Default::default() },
+        AppProfile { gain: f32::NAN, ..Default::default() },
+        AppProfile { positions: BTreeMap::from([(BodyPart::Head, -1.0)]), ..Default::default() },
+        AppProfile { duck_gain: 1.5, ..Default::default() },
+    ] {
+        let profiles = AppProfiles { default: AppProfile::default(), apps: BTreeMap::from([(String::from(GAME), profile)]) };
+        assert!(matches!(profiles.validate(), Err(AppProfileError::Invalid(_))));
+    }
+}
@@ -0,0 +1,90 @@
+use std::f32::consts::TAU;
+
+use haptic_lib::BodyPart;
+
+use xrconnect::{
+    devices::audio::{ AudioChannelConfig, AudioOutputConfig, AudioSynth, WavAudioDevice },
+    haptics::{
+        device::HapticDevice,
+        model::HapticFrame,
+    },
+};
+
+/// One sample per millisecond, so that a frame is 20 samples and the amplitude ramp 5.
+const SAMPLE_RATE: u32 = 1000;
+const CARRIER_HZ: f32 = 50.0;
+
+fn channel(part: BodyPart, motors: Vec<usize>, gain: f32) -> AudioChannelConfig {
+    AudioChannelConfig { part, motors, carrier_hz: CARRIER_HZ, gain }
+}
+
+fn config(channels: Vec<AudioChannelConfig>) -> AudioOutputConfig {
+    AudioOutputConfig { sample_rate: SAMPLE_RATE, channels }
+}
+
+/// Sample `n` of a carrier ramping from silence to `target`.
+fn expected(n: usize, target: f32) -> f32 {
+    let amplitude = (0.2 * (n + 1) as f32).min(target);
+    amplitude * (TAU * CARRIER_HZ / SAMPLE_RATE as f32 * n as f32).sin()
+}
+
+#[test]
+fn wav_device_renders_frames_to_samples() {
+    let path = std::env::temp_dir().join(format!("xrconnect-audio-{}.wav", std::process::id()));
+    let mut device = WavAudioDevice::create(&path, &config(vec![
+        channel(BodyPart::ChestFront, vec![], 1.0),
+        channel(BodyPart::ChestBack, vec![], 1.0),
+    ])).unwrap();
+    assert_eq!(device.positions(), vec![BodyPart::ChestFront, BodyPart::ChestBack]);
+
+    let mut frame = HapticFrame::silent(&[BodyPart::ChestFront]);
+    frame.get_mut(BodyPart::ChestFront)[7] = 1.0;
+    device.write(&frame).unwrap();
+    device.write(&HapticFrame::default()).unwrap();
+    device.finish().unwrap();
+
+    let mut reader = hound::WavReader::open(&path).unwrap();
+    assert_eq!(reader.spec().channels, 2);
+    assert_eq!(reader.spec().sample_rate, SAMPLE_RATE);
+    let samples = reader.samples::<i16>().map(|sample| sample.unwrap() as f32 / i16::MAX as f32).collect::<Vec<_>>();
+    std::fs::remove_file(&path).unwrap();
+
+    // 20 ms of samples per frame, on both channels
+    assert_eq!(samples.len(), 2 * 2 * 20);
+    let (front, back): (Vec<_>, Vec<_>) = samples.chunks(2).map(|pair| (pair[0], pair[1])).unzip();
+
+    for (n, sample) in front.iter().take(20).enumerate() {
+        assert!((sample - expected(n, 1.0)).abs() < 1e-3, "sample {}: {} instead of {}", n, sample, expected(n, 1.0));
+    }
+    assert!(back.iter().all(|sample| *sample == 0.0));
+
+    // Ramps back down to silence within 5 ms of the silent frame
+    assert!(front[25..].iter().all(|sample| sample.abs() < 1e-3), "{:?}", &front[20..]);
+}
+
+#[test]
+fn synth_follows_the_strongest_mapped_motor() {
+    let mut synth = AudioSynth::new(&config(vec![
+        channel(BodyPart::ChestFront, vec![0, 1], 1.0),
+        channel(BodyPart::ChestFront, vec![], 4.0),
+        channel(BodyPart::Head, vec![], 1.0),
+    ]));
+    assert_eq!(synth.channel_count(), 3);
+
+    let mut frame = HapticFrame::default();
+    frame.set(BodyPart::ChestFront, vec![0.2, 0.5, 0.9]);
+    synth.set_frame(&frame);
+
+    let mut samples = vec![0.0; 3 * 20];
+    synth.render(&mut samples);
+
+    for (n, sample) in samples.chunks(3).enumerate() {
+        // Motor 2 is not mapped to the first channel
+        assert!((sample[0] - expected(n, 0.5)).abs() < 1e-5, "sample {}: {:?}", n, sample);
+
+        // The gain never drives the carrier past full scale
+        assert!((sample[1] - expected(n, 1.0)).abs() < 1e-5, "sample {}: {:?}", n, sample);
+
+        assert_eq!(sample[2], 0.0);
+    }
+}
@@ -0,0 +1,102 @@
+use haptic_lib::BodyPart;
+
+use xrconnect::{
+    devices::bhaptics::{
+        encoder::MOTOR_CHARACTERISTIC,
+        BHapticsBleDevice, DeviceFamily, RecordingTransport, Side,
+    },
+    haptics::{
+        device::HapticDevice,
+        model::HapticFrame,
+    },
+};
+
+fn frame(parts: &[(BodyPart, Vec<f32>)]) -> HapticFrame {
+    let mut frame = HapticFrame::default();
+    for (part, motors) in parts {
+        frame.set(*part, motors.clone());
+    }
+    frame
+}
+
+#[test]
+fn tactsuit_x40_packs_front_then_back() {
+    let mut front = vec![0.0; 20];
+    front[0] = 1.0;
+    front[1] = 0.2;
+    front[19] = 0.5;
+    let mut back = vec![0.0; 20];
+    back[0] = 0.4;
+    back[19] = 1.0;
+
+    let packet = DeviceFamily::TactSuitX40.encode(&frame(&[
+        (BodyPart::ChestFront, front),
+        (BodyPart::ChestBack, back),
+    ]));
+
+    assert_eq!(packet.characteristic, MOTOR_CHARACTERISTIC);
+    assert_eq!(packet.bytes, vec![
+        0xF3, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08,
+        0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0F,
+    ]);
+}
+
+#[test]
+fn tactsuit_x16_pads_packet() {
+    let packet = DeviceFamily::TactSuitX16.encode(&frame(&[
+        (BodyPart::ChestFront, vec![1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]),
+        (BodyPart::ChestBack, vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.6]),
+    ]));
+
+    assert_eq!(packet.bytes, vec![
+        0xFF, 0x00, 0x00, 0x0F, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ]);
+}
+
+#[test]
+fn tactosy_only_encodes_its_side() {
+    let frame = frame(&[
+        (BodyPart::ForearmL, vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0]),
+        (BodyPart::ForearmR, vec![0.0, 0.0, 1.0, 0.0, 0.0, 0.0]),
+        (BodyPart::FootR, vec![0.0, 1.0, 0.0]),
+    ]);
+
+    assert_eq!(DeviceFamily::Tactosy2(Side::Left).encode(&frame).bytes[..4], [0xF0, 0x00, 0x0F, 0x00]);
+    assert_eq!(DeviceFamily::Tactosy2(Side::Right).encode(&frame).bytes[..4], [0x00, 0xF0, 0x00, 0x00]);
+    assert_eq!(DeviceFamily::TactosyF(Side::Right).encode(&frame).bytes[..3], [0x0F, 0x00, 0x00]);
+    assert_eq!(DeviceFamily::TactosyH(Side::Left).encode(&frame).bytes, vec![0; 20]);
+}
+
+#[test]
+fn tactal_clamps_and_ignores_extra_motors() {
+    let packet = DeviceFamily::Tactal.encode(&frame(&[
+        (BodyPart::Head, vec![2.0, -1.0, 0.5, 0.5, 0.0, 1.0, 1.0, 1.0]),
+    ]));
+
+    assert_eq!(packet.bytes[..4], [0xF0, 0x88, 0x0F, 0x00]);
+}
+
+#[test]
+fn tactglove_uses_one_byte_per_motor() {
+    let packet = DeviceFamily::TactGlove(Side::Right).encode(&frame(&[
+        (BodyPart::GloveR, vec![1.0, 0.5, 0.25, 0.0, 0.1, 0.01]),
+    ]));
+
+    assert_eq!(packet.bytes[..7], [100, 50, 25, 0, 10, 1, 0]);
+}
+
+#[test]
+fn device_writes_packets_to_transport() {
+    let transport = RecordingTransport::new();
+    let mut device = BHapticsBleDevice::new("Tactal", DeviceFamily::Tactal, transport.clone());
+
+    device.write(&frame(&[(BodyPart::Head, vec![1.0; 6])])).unwrap();
+    device.stop().unwrap();
+
+    let writes = transport.writes();
+    assert_eq!(writes.len(), 2);
+    assert_eq!(writes[0].characteristic, MOTOR_CHARACTERISTIC);
+    assert_eq!(writes[0].bytes[..4], [0xFF, 0xFF, 0xFF, 0x00]);
+    assert_eq!(writes[1].bytes, vec![0; 20]);
+}
@@ -0,0 +1,161 @@
+use std::{
+    path::PathBuf,
+    time::Duration,
+};
+
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+
+use xrconnect::bhaptics_studio::{
+    client::{
+        load::Simulation,
+        schedule::{ RandomSchedule, Schedule, Script },
+        BHapticsClient, ClientError, FrameDot, PlayerResponse, Submission, TactFile,
+    },
+    server::BHapticsStudioServer,
+};
+
+mod common;
+
+async fn server() -> (String, CancellationToken) {
+    let address = common::free_address();
+    let server = BHapticsStudioServer::new(address).with_state_file(None);
+    let shutdown = server.shutdown_token();
+    common::spawn(server).await;
+
+    (format!("ws://{}", address), shutdown)
+}
+
+/// `.tact` file as exported by bHaptics Designer, a second long pulse on the front of the vest.
+fn tact_file(name: &str) -> PathBuf {
+    let project = json!({ "project": {
+        "id": name, "name": name, "description": "",
+        "mediaFileDuration": 1, "createdAt": 0, "updatedAt": 0,
+        "layout": { "type": "Tactot", "name": "Tactot", "layouts": {} },
+        "tracks": [{ "enable": true, "effects": [{
+            "name": "Effect 1", "startTime": 0, "offsetTime": 1000,
+            "modes": { "VestFront": {
+                "mode": "DOT_MODE",
+                "dotMode": { "dotConnected": false, "feedback": [{
+                    "startTime": 0, "endTime": 1000, "playbackType": "NONE",
+                    "pointList": [{ "index": 0, "intensity": 1.0 }],
+                }]},
+                "pathMode": { "feedback": [] },
+            }},
+        }]}],
+    }});
+
+    let directory = std::env::temp_dir().join(format!("xrconnect-client-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join(format!("{}.tact", name));
+    std::fs::write(&path, project.to_string()).unwrap();
+    path
+}
+
+fn key(key: &str) -> Submission {
+    Submission::Key { key: key.to_string(), intensity: 1.0, duration: 1.0, alt_key: None }
+}
+
+fn alt_key(key: &str, alt_key: &str) -> Submission {
+    Submission::Key { key: key.to_string(), intensity: 1.0, duration: 1.0, alt_key: Some(alt_key.to_string()) }
+}
+
+#[tokio::test]
+async fn registers_and_submits_like_a_game() {
+    let (address, shutdown) = server().await;
+    let tact = TactFile::load(tact_file("hit")).unwrap();
+    assert_eq!(tact.key, "hit");
+
+    let mut client = BHapticsClient::connect(&address, "com.example.game", "Example Game").await.unwrap();
+    client.register(&tact).await.unwrap();
+    client.submit(&key("hit")).await.unwrap();
+    client.submit(&alt_key("hit", "hit-2")).await.unwrap();
+    client.submit(&Submission::Frame {
+        key: String::from("pulse"),
+        position: String::from("VestBack"),
+        dots: vec![FrameDot { index: 3, intensity: 80 }],
+        duration_millis: 500,
+    }).await.unwrap();
+    client.submit(&Submission::TurnOff { key: String::from("hit") }).await.unwrap();
+    client.submit(&Submission::TurnOffAll).await.unwrap();
+
+    assert!(client.responses() >= 6);
+    client.close().await;
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn unknown_keys_are_never_active() {
+    let (address, shutdown) = server().await;
+
+    let mut client = BHapticsClient::connect(&address, "com.example.game", "Example Game").await.unwrap();
+    assert_eq!(client.submit(&key("missing")).await, Err(ClientError::Timeout(String::from("missing active"))));
+
+    client.close().await;
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn simulation_runs_many_apps_at_once() {
+    let (address, shutdown) = server().await;
+    let tact = TactFile::load(tact_file("impact")).unwrap();
+    let schedule = Schedule::Random(RandomSchedule::new(20.0, vec![tact.key.clone()]).with_seed(7));
+
+    let report = Simulation::new(&address, schedule)
+        .with_apps(25)
+        .with_tact_files(vec![tact])
+        .with_duration(Duration::from_secs(1))
+        .run(CancellationToken::new())
+        .await;
+
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(report.connected, 25);
+    assert_eq!(report.registrations, 25);
+    assert!(report.submissions > 100, "{}", report);
+    assert_eq!(report.latencies.len(), report.registrations + report.submissions);
+    assert!(report.latency(50.0) <= report.latency(99.0));
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn unreachable_players_fail_every_app() {
+    let schedule = Schedule::Script(Script { steps: vec![] });
+    let report = Simulation::new(&format!("ws://{}", common::free_address()), schedule)
+        .with_apps(3)
+        .run(CancellationToken::new())
+        .await;
+
+    assert_eq!(report.connected, 0);
+    assert_eq!(report.failures, 3);
+    assert!(!report.is_success());
+}
+
+#[test]
+fn script_steps_are_sorted_by_time() {
+    let script = Script::from_json(r#"{ "steps": [
+        { "at_millis": 500, "type": "turnOffAll" },
+        { "at_millis": 0, "type": "key", "key": "hit", "intensity": 0.5 },
+        { "at_millis": 250, "type": "turnOff", "key": "hit" }
+    ]}"#).unwrap();
+
+    let steps = script.steps.iter().map(|step| (step.at_millis, step.submission.kind())).collect::<Vec<_>>();
+    assert_eq!(steps, vec![(0, "key"), (250, "turnOff"), (500, "turnOffAll")]);
+    assert_eq!(script.steps[0].submission, Submission::Key { key: String::from("hit"), intensity: 0.5, duration: 1.0, alt_key: None });
+}
+
+#[test]
+fn responses_are_checked() {
+    let mut response: PlayerResponse = serde_json::from_value(json!({
+        "RegisteredKeys": [], "ActiveKeys": [], "ConnectedDeviceCount": 1, "ConnectedPositions": ["VestFront"],
+        "Status": {},
+    })).unwrap();
+    assert_eq!(response.validate(), Err(String::from("Status lacks VestBack")));
+
+    for position in ["VestBack", "VestFront", "Head", "FootL", "FootR", "HandL", "HandR", "ForearmL", "ForearmR", "GloveL", "GloveR"] {
+        response.status.insert(position.to_string(), vec![0; 20]);
+    }
+    assert_eq!(response.validate(), Ok(()));
+
+    response.status.get_mut("Head").unwrap()[4] = 101;
+    assert_eq!(response.validate(), Err(String::from("Status of Head has intensity 101 above 100")));
+}
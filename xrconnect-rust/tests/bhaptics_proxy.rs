@@ -0,0 +1,119 @@
+use std::net::SocketAddr;
+
+use futures_util::{ SinkExt, StreamExt };
+use serde_json::{ json, Value };
+use tokio::{ net::TcpListener, sync::mpsc };
+use tokio_tungstenite::tungstenite::{ handshake::server::Request, Message };
+
+use xrconnect::bhaptics_studio::{
+    proxy::{ BHapticsProxy, ProxyRules },
+    server::BHapticsStudioServer,
+};
+
+mod common;
+
+const RESPONSE: &str = r#"{"RegisteredKeys":["upstream"],"ActiveKeys":[],"ConnectedDeviceCount":1,"ConnectedPositions":["Vest"],"Status":{}}"#;
+
+/// Stand-in for the bHaptics Player, answers every message with [`RESPONSE`] and reports
+/// the path it was connected to, then every message it received.
+#[allow(clippy::result_large_err)]
+async fn upstream() -> (SocketAddr, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let path_tx = tx.clone();
+        let mut socket = tokio_tungstenite::accept_hdr_async(stream, move |request: &Request, response| {
+            let _ = path_tx.send(request.uri().to_string());
+            Ok(response)
+        }).await.unwrap();
+
+        while let Some(Ok(Message::Text(message))) = socket.next().await {
+            let _ = tx.send(message);
+            socket.send(Message::Text(RESPONSE.to_string())).await.unwrap();
+        }
+    });
+
+    (address, rx)
+}
+
+async fn proxy(proxy: BHapticsProxy) -> (SocketAddr, tokio_util::sync::CancellationToken) {
+    let address = common::free_address();
+    let server = BHapticsStudioServer::new(address)
+        .with_state_file(None)
+        .with_proxy(proxy);
+    let shutdown = server.shutdown_token();
+    common::spawn(server).await;
+
+    (address, shutdown)
+}
+
+#[tokio::test]
+async fn relays_messages_and_responses() {
+    let (upstream, mut received) = upstream().await;
+    let rules = ProxyRules {
+        drop: vec![String::from("frame")],
+        intensity: 0.5,
+    };
+    let (address, shutdown) = proxy(BHapticsProxy::new(&format!("ws://{}", upstream)).unwrap().with_rules(rules)).await;
+
+    let url = format!("ws://{}/v2/feedbacks?app_id=com.example.game&app_name=Example%20Game", address);
+    let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    let submit = json!({ "Submit": [
+        { "Type": "key", "Key": "hit", "Parameters": { "scaleOption": { "intensity": 0.8, "duration": 1.0 } } },
+        { "Type": "frame", "Key": "f", "Frame": { "Position": "VestFront", "DotPoints": [], "DurationMillis": 100 } },
+    ]});
+    client.send(Message::Text(submit.to_string())).await.unwrap();
+
+    let path = received.recv().await.unwrap();
+    assert_eq!(path, "/v2/feedbacks?app_id=com.example.game&app_name=Example+Game");
+
+    let forwarded: Value = serde_json::from_str(&received.recv().await.unwrap()).unwrap();
+    let submits = forwarded["Submit"].as_array().unwrap();
+    assert_eq!(submits.len(), 1);
+    assert_eq!(submits[0]["Key"], "hit");
+    assert!((submits[0]["Parameters"]["scaleOption"]["intensity"].as_f64().unwrap() - 0.4).abs() < 1e-6);
+
+    match client.next().await {
+        Some(Ok(Message::Text(response))) => assert_eq!(response, RESPONSE),
+        other => panic!("expected the upstream response, got {:?}", other),
+    }
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn closes_clients_when_the_upstream_is_unreachable() {
+    let upstream = common::free_address();
+    let (address, shutdown) = proxy(BHapticsProxy::new(&format!("ws://{}/v2/feedbacks", upstream)).unwrap()).await;
+
+    let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/v2/feedbacks", address)).await.unwrap();
+    match client.next().await {
+        Some(Ok(Message::Close(Some(frame)))) => assert_eq!(u16::from(frame.code), 1011),
+        other => panic!("expected a close frame, got {:?}", other),
+    }
+
+    shutdown.cancel();
+}
+
+#[test]
+fn rules_drop_whole_messages() {
+    let rules = ProxyRules {
+        drop: vec![String::from("Register"), String::from("turnOffAll")],
+        ..Default::default()
+    };
+
+    assert_eq!(rules.apply(r#"{"Register":[]}"#), None);
+    assert_eq!(rules.apply(r#"{"Submit":[{"Type":"turnOffAll"}]}"#), None);
+    assert_eq!(rules.apply("not json").as_deref(), Some("not json"));
+}
+
+#[test]
+fn upstream_must_be_a_websocket_address() {
+    assert_eq!(BHapticsProxy::new("ws://127.0.0.1:15881/v2/feedbacks").unwrap().upstream(), "ws://127.0.0.1:15881");
+    assert!(BHapticsProxy::new("http://127.0.0.1:15881").is_err());
+    assert!(BHapticsProxy::new("ws://").is_err());
+}
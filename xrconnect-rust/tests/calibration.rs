@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use haptic_lib::{ BodyPart, EffectInterpolation };
+
+use xrconnect::haptics::{
+    calibration::{ CalibrationError, CalibrationProfile, MotorCalibration, PartCalibration, ResponseCurve },
+    device::RecordingDevice,
+    model::{ ClipOutput, DotIntensity, HapticPattern, MotorLayout, PatternClip, PatternPoints, PlaybackOptions },
+    player::HapticPlayer,
+};
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() < 1e-5, "{} instead of {}", actual, expected);
+}
+
+/// Vest with 8 motors on each side.
+fn x16_vest() -> RecordingDevice {
+    RecordingDevice::new("x16", vec![BodyPart::ChestFront, BodyPart::ChestBack]).with_layout(MotorLayout::grid(2, 4))
+}
+
+#[test]
+fn curves_shape_the_intensity() {
+    assert_close(ResponseCurve::Linear.apply(0.3), 0.3);
+    assert_close(ResponseCurve::Linear.apply(1.5), 1.0);
+    assert_close(ResponseCurve::Gamma { gamma: 0.5 }.apply(0.25), 0.5);
+
+    let lookup = ResponseCurve::Lookup { points: vec![0.0, 0.1, 0.3, 1.0] };
+    assert_close(lookup.apply(0.0), 0.0);
+    assert_close(lookup.apply(0.5), 0.2);
+    assert_close(lookup.apply(1.0), 1.0);
+    assert_close(ResponseCurve::Lookup { points: vec![0.4] }.apply(0.9), 0.4);
+}
+
+#[test]
+fn intensities_are_mapped_between_threshold_and_cap() {
+    let calibration = PartCalibration { threshold: 0.2, cap: 0.8, ..Default::default() };
+
+    assert_eq!(calibration.apply(0, 0.0), 0.0);
+    assert_close(calibration.apply(0, 0.01), 0.206);
+    assert_close(calibration.apply(0, 0.5), 0.5);
+    assert_close(calibration.apply(0, 1.0), 0.8);
+
+    // Gain goes before the cap, which is never exceeded
+    let calibration = PartCalibration { gain: 2.0, ..calibration };
+    assert_close(calibration.apply(0, 0.75), 0.8);
+}
+
+#[test]
+fn motor_overrides_replace_the_part_settings() {
+    let calibration = PartCalibration {
+        gain: 0.5,
+        motors: BTreeMap::from([
+            (1, MotorCalibration { gain: Some(1.0), ..Default::default() }),
+            (2, MotorCalibration { curve: Some(ResponseCurve::Gamma { gamma: 2.0 }), cap: Some(0.5), ..Default::default() }),
+        ]),
+        ..Default::default()
+    };
+
+    assert_close(calibration.apply(0, 0.8), 0.4);
+    assert_close(calibration.apply(1, 0.8), 0.8);
+    assert_close(calibration.apply(2, 0.8), 0.8 * 0.8 * 0.5 * 0.5);
+}
+
+#[test]
+fn invalid_profiles_are_rejected() {
+    let invalid = |calibration: PartCalibration| {
+        let mut profile = CalibrationProfile::new("invalid");
+        profile.parts.insert(BodyPart::Head, calibration);
+        matches!(profile.validate(), Err(CalibrationError::Invalid(_)))
+    };
+
+    assert!(invalid(PartCalibration { threshold: 0.6, cap: 0.4, ..Default::default() }));
+    assert!(invalid(PartCalibration { gain: -1.0, ..Default::default() }));
+    assert!(invalid(PartCalibration { curve: ResponseCurve::Gamma { gamma: 0.0 }, ..Default::default() }));
+    for value in [f32::NAN, f32::INFINITY] {
+        assert!(invalid(PartCalibration { curve: ResponseCurve::Gamma { gamma: value }, ..Default::default() }));
+        assert!(invalid(PartCalibration { gain: value, ..Default::default() }));
+        assert!(invalid(PartCalibration { threshold: value, ..Default::default() }));
+        assert!(invalid(PartCalibration { cap: value, ..Default::default() }));
+        assert!(invalid(PartCalibration {
+            motors: BTreeMap::from([(3, MotorCalibration { gain: Some(value), ..Default::default() })]),
+            ..Default::default()
+        }));
+    }
+    assert!(invalid(PartCalibration { curve: ResponseCurve::Lookup { points: vec![0.0, 1.2] }, ..Default::default() }));
+    assert!(invalid(PartCalibration {
+        motors: BTreeMap::from([(3, MotorCalibration { threshold: Some(1.5), ..Default::default() })]),
+        ..Default::default()
+    }));
+    assert!(!invalid(PartCalibration::default()));
+}
+
+#[test]
+fn motor_overrides_follow_the_layout_of_each_device() {
+    let x16 = x16_vest();
+    let x40 = RecordingDevice::new("x40", vec![BodyPart::ChestFront, BodyPart::ChestBack]);
+    let player = HapticPlayer::new();
+    player.add_device(Box::new(x16.clone()));
+    player.add_device(Box::new(x40.clone()));
+
+    // Bottom right motor of the front, 7 on an X16 and 19 on an X40
+    let mut profile = CalibrationProfile::new("alice");
+    profile.part_mut(BodyPart::ChestFront).motors.insert(7, MotorCalibration { gain: Some(0.5), ..Default::default() });
+    player.set_calibration(profile);
+
+    player.play_pattern("hit", HapticPattern::new(vec![PatternClip {
+        part: BodyPart::ChestFront,
+        start_millis: 0,
+        end_millis: 1000,
+        interpolation: EffectInterpolation::None,
+        points: PatternPoints::Dot(vec![DotIntensity { index: 0, intensity: 1.0 }, DotIntensity { index: 19, intensity: 1.0 }]),
+        output: ClipOutput::Motors,
+        layout: None,
+    }]), PlaybackOptions::default()).unwrap();
+    player.tick(player.now());
+
+    let x16_front = x16.frames()[0].get(BodyPart::ChestFront).unwrap().to_vec();
+    assert_eq!(x16_front.len(), 8);
+    assert_close(x16_front[0], 1.0);
+    assert_close(x16_front[7], 0.5);
+
+    let x40_front = x40.frames()[0].get(BodyPart::ChestFront).unwrap().to_vec();
+    assert_eq!(x40_front.len(), 20);
+    assert_close(x40_front[7], 0.0);
+    assert_close(x40_front[19], 1.0);
+}
@@ -0,0 +1,42 @@
+//! Helpers shared by the integration tests, each test crate only uses some of them.
+#![allow(dead_code)]
+
+use std::{
+    net::{ SocketAddr, TcpListener as StdTcpListener },
+    time::Duration,
+};
+
+use warp::{ http::StatusCode, Filter, Reply, Rejection };
+
+use xrconnect::{
+    access::Forbidden,
+    api::ControlApi,
+    bhaptics_studio::server::BHapticsStudioServer,
+};
+
+/// Peer of the control API on the same host.
+pub const LOCAL: ([u8; 4], u16) = ([127, 0, 0, 1], 40000);
+
+/// Peer of the control API on the network.
+pub const REMOTE: ([u8; 4], u16) = ([192, 168, 1, 20], 40000);
+
+/// Local address nothing listens on, at least for now.
+pub fn free_address() -> SocketAddr {
+    StdTcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+/// Runs `server` in the background and gives it time to listen.
+pub async fn spawn(server: BHapticsStudioServer) {
+    tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+}
+
+/// Control API answering the way the server does, refused requests with `403 Forbidden`.
+pub fn routes(api: ControlApi) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    api.routes().recover(|rejection: Rejection| async move {
+        match rejection.find::<Forbidden>() {
+            Some(_) => Ok(StatusCode::FORBIDDEN),
+            None => Err(rejection),
+        }
+    })
+}
@@ -0,0 +1,104 @@
+use std::path::{ Path, PathBuf };
+
+use xrconnect::{
+    bhaptics_studio::listener::Listener,
+    config::{ Config, ConfigError },
+};
+
+fn parse(content: &str) -> Result<Config, ConfigError> {
+    let config = Config::from_toml(content, Path::new("xrconnect.toml"))?;
+    config.validate(Path::new("xrconnect.toml"))?;
+    Ok(config)
+}
+
+fn invalid_key(content: &str) -> String {
+    match parse(content) {
+        Err(ConfigError::Invalid { key, .. }) => key,
+        other => panic!("expected an invalid key, got {:?}", other),
+    }
+}
+
+#[test]
+fn reads_every_section() {
+    let config = parse(r#"
+        [server]
+        listeners = ["127.0.0.1:25881", "unix:/tmp/xrconnect.sock"]
+        share_app_namespace = true
+
+        [frontends]
+        api = false
+
+        [proxy]
+        upstream = "ws://127.0.0.1:15881"
+        rules = { drop = ["frame"] }
+
+        [[outputs]]
+        type = "audio-wav"
+        path = "out.wav"
+
+        [safety]
+        max_on_millis = 5000
+
+        [apps.default]
+        gain = 0.5
+
+        [access]
+        unknown_apps = "Deny"
+
+        [logging]
+        level = "debug"
+    "#).unwrap();
+
+    assert_eq!(config.listeners(), vec![
+        Listener::Tcp(([127, 0, 0, 1], 25881).into()),
+        Listener::Unix(PathBuf::from("/tmp/xrconnect.sock")),
+    ]);
+    assert!(config.frontends.bhaptics);
+    assert!(!config.frontends.api);
+    assert_eq!(config.proxy.as_ref().unwrap().rules.drop, vec![String::from("frame")]);
+    assert_eq!(config.outputs.len(), 1);
+    assert_eq!(config.safety.as_ref().unwrap().max_on_millis, 5000);
+    assert_eq!(config.apps.as_ref().unwrap().default.gain, 0.5);
+    assert_eq!(config.log_level(), Ok(Some(tracing::Level::DEBUG)));
+}
+
+#[test]
+fn errors_name_the_offending_key() {
+    assert_eq!(invalid_key("[server]\nlisteners = [\"nowhere\"]"), "server.listeners[0]");
+    assert_eq!(invalid_key("[safety]\nmax_on_millis = \"long\""), "safety.max_on_millis");
+    assert_eq!(invalid_key("[safety]\nmax_duty_cycle = 0.0"), "safety");
+    assert_eq!(invalid_key("[[outputs]]\ntype = \"audio-wav\""), "outputs[0].path");
+    assert_eq!(invalid_key("[[outputs]]\ntype = \"speaker\""), "outputs[0].type");
+    assert_eq!(invalid_key("[proxy]\nupstream = \"http://127.0.0.1\""), "proxy.upstream");
+    assert_eq!(invalid_key("[proxy]\nupstream = \"ws://127.0.0.1:15882\"\n[proxy.rules]\nintensity = -0.5"), "proxy.rules.intensity");
+    assert_eq!(invalid_key("[proxy]\nupstream = \"ws://127.0.0.1:15882\"\n[proxy.rules]\nintensity = nan"), "proxy.rules.intensity");
+    assert_eq!(invalid_key("[logging]\nlevel = \"loud\""), "logging.level");
+    assert_eq!(invalid_key("[frontends]\nbhaptic = false"), "frontends.bhaptic");
+
+    let error = parse("[server]\nlisteners = [\"nowhere\"]").unwrap_err().to_string();
+    assert!(error.starts_with("xrconnect.toml: server.listeners[0]: "), "{}", error);
+}
+
+#[test]
+fn syntax_errors_point_at_the_line() {
+    match parse("[server]\nshare_app_namespace = yes\n") {
+        Err(ConfigError::Syntax { line, .. }) => assert_eq!(line, 2),
+        other => panic!("expected a syntax error, got {:?}", other),
+    }
+}
+
+#[test]
+fn missing_file_is_the_default_configuration() {
+    let config = Config::load("/nonexistent/xrconnect.toml").unwrap();
+
+    assert_eq!(config, Config::default());
+    assert_eq!(config.listeners(), vec![Listener::Tcp(([127, 0, 0, 1], 15881).into())]);
+}
+
+#[test]
+fn only_some_sections_need_a_restart() {
+    let previous = parse("[safety]\nmax_on_millis = 5000").unwrap();
+    let current = parse("[safety]\nmax_on_millis = 3000\n[frontends]\napi = false").unwrap();
+
+    assert_eq!(current.restart_required(&previous), vec!["frontends"]);
+}
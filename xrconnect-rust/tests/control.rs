@@ -0,0 +1,120 @@
+#![cfg(unix)]
+
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    time::Duration,
+};
+
+use haptic_lib::{ BodyPart, EffectInterpolation };
+use tokio_util::sync::CancellationToken;
+
+use xrconnect::{
+    bhaptics_studio::server::BHapticsStudioServer,
+    control::{ self, ControlError, ControlRequest, ControlResponse, ControlSocket },
+    haptics::model::{ ClipOutput, DotIntensity, HapticPattern, PatternClip, PatternPoints, PlaybackOptions },
+};
+
+mod common;
+
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("xrconnect-control-{}-{}.sock", std::process::id(), name))
+}
+
+async fn server(control: ControlSocket) -> CancellationToken {
+    let server = BHapticsStudioServer::new(common::free_address())
+        .with_state_file(None)
+        .with_control_socket(control);
+    let shutdown = server.shutdown_token();
+    common::spawn(server).await;
+
+    shutdown
+}
+
+fn pulse() -> HapticPattern {
+    HapticPattern {
+        clips: vec![PatternClip {
+            part: BodyPart::ChestFront,
+            start_millis: 0,
+            end_millis: 1000,
+            interpolation: EffectInterpolation::None,
+            points: PatternPoints::Dot(vec![DotIntensity { index: 0, intensity: 1.0 }]),
+            output: ClipOutput::Motors,
+            layout: None,
+        }],
+    }
+}
+
+async fn active_keys(path: &PathBuf) -> Vec<String> {
+    match control::request(path, &ControlRequest::Status).await.unwrap() {
+        ControlResponse::Status(status) => status.active_keys,
+        other => panic!("expected a status, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn commands_act_on_the_running_server() {
+    let path = socket_path("commands");
+    let shutdown = server(ControlSocket::new(&path).with_reload(|| Ok(String::from("reloaded")))).await;
+
+    let play = ControlRequest::Play { key: String::from("hit"), pattern: pulse(), options: PlaybackOptions::default() };
+    assert_eq!(control::request(&path, &play).await.unwrap(), ControlResponse::Done(String::from("Playing hit (1000 ms)")));
+    assert_eq!(active_keys(&path).await, vec![String::from("hit")]);
+
+    assert_eq!(control::request(&path, &ControlRequest::StopAll).await.unwrap(), ControlResponse::Done(String::from("Stopped 1 effects")));
+    assert!(active_keys(&path).await.is_empty());
+
+    assert_eq!(control::request(&path, &ControlRequest::Reload).await.unwrap(), ControlResponse::Done(String::from("reloaded")));
+    assert_eq!(control::request(&path, &ControlRequest::Devices).await.unwrap(), ControlResponse::Devices(vec![]));
+
+    shutdown.cancel();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn reload_needs_a_configuration() {
+    let path = socket_path("reload");
+    let shutdown = server(ControlSocket::new(&path)).await;
+
+    assert!(matches!(control::request(&path, &ControlRequest::Reload).await.unwrap(), ControlResponse::Error(_)));
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn only_the_current_user_may_connect() {
+    let path = socket_path("private");
+    let shutdown = server(ControlSocket::new(&path)).await;
+
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    let directory = std::fs::read_dir(path.parent().unwrap()).unwrap();
+    let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+    assert!(
+        directory.flatten().all(|entry| !entry.file_name().to_string_lossy().starts_with(&format!(".{}", file_name))),
+        "the socket was bound somewhere else first and left it behind",
+    );
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn files_in_the_way_are_left_alone() {
+    let path = socket_path("in-the-way");
+    std::fs::write(&path, "notes").unwrap();
+    let shutdown = server(ControlSocket::new(&path)).await;
+
+    assert!(matches!(control::request(&path, &ControlRequest::Status).await, Err(ControlError::Unreachable { .. })));
+
+    shutdown.cancel();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "notes");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn nothing_running_is_unreachable() {
+    let result = control::request(socket_path("nothing"), &ControlRequest::Status).await;
+
+    assert!(matches!(result, Err(ControlError::Unreachable { .. })), "{:?}", result);
+}
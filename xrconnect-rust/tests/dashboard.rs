@@ -0,0 +1,55 @@
+use warp::http::{ header, StatusCode };
+
+use xrconnect::{
+    access::AccessControl,
+    api::ControlApi,
+    haptics::player::HapticPlayer,
+};
+
+mod common;
+
+use common::{ routes, LOCAL, REMOTE };
+
+const ASSETS: [(&str, &str, &str); 3] = [
+    ("/dashboard", "text/html; charset=utf-8", include_str!("../dashboard/index.html")),
+    ("/dashboard/dashboard.js", "text/javascript; charset=utf-8", include_str!("../dashboard/dashboard.js")),
+    ("/dashboard/dashboard.css", "text/css; charset=utf-8", include_str!("../dashboard/dashboard.css")),
+];
+
+fn api() -> ControlApi {
+    ControlApi::new(HapticPlayer::new(), AccessControl::default()).with_token(Some(String::from("secret")))
+}
+
+#[tokio::test]
+async fn assets_are_served_with_their_content_type() {
+    for (path, content_type, content) in ASSETS {
+        let response = warp::test::request().path(path).remote_addr(LOCAL.into()).reply(&routes(api())).await;
+
+        assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], content_type, "{}", path);
+        assert_eq!(response.body(), content.as_bytes(), "{}", path);
+    }
+
+    let response = warp::test::request().path("/").remote_addr(LOCAL.into()).reply(&routes(api())).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()[header::LOCATION], "/dashboard/");
+}
+
+#[tokio::test]
+async fn assets_are_only_served_to_local_peers_or_with_the_token() {
+    for (path, _, _) in ASSETS {
+        let refused = warp::test::request().path(path).remote_addr(REMOTE.into()).reply(&routes(api())).await;
+        assert_eq!(refused.status(), StatusCode::FORBIDDEN, "{}", path);
+
+        let authorized = warp::test::request()
+            .path(path)
+            .remote_addr(REMOTE.into())
+            .header("authorization", "Bearer secret")
+            .reply(&routes(api()))
+            .await;
+        assert_eq!(authorized.status(), StatusCode::OK, "{}", path);
+    }
+
+    let refused = warp::test::request().path("/").remote_addr(REMOTE.into()).reply(&routes(api())).await;
+    assert_eq!(refused.status(), StatusCode::FORBIDDEN);
+}
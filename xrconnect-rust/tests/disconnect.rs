@@ -0,0 +1,110 @@
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+
+use futures_util::{ SinkExt, StreamExt };
+use serde_json::json;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{ tungstenite::Message, MaybeTlsStream, WebSocketStream };
+use tokio_util::sync::CancellationToken;
+
+use xrconnect::{
+    bhaptics_studio::{ namespace::NamespaceMode, server::BHapticsStudioServer },
+    haptics::player::HapticPlayer,
+};
+
+mod common;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn server(namespaces: NamespaceMode) -> (SocketAddr, HapticPlayer, CancellationToken) {
+    let address = common::free_address();
+    let server = BHapticsStudioServer::new(address).with_state_file(None).with_namespaces(namespaces);
+    let player = server.player().clone();
+    let shutdown = server.shutdown_token();
+    common::spawn(server).await;
+
+    (address, player, shutdown)
+}
+
+/// Connects like a game and submits a ten second frame under `key`.
+async fn submitting(address: SocketAddr, app_id: &str, key: &str) -> Socket {
+    let url = format!("ws://{}/v2/feedbacks?app_id={}&app_name={}", address, app_id, app_id);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    let submit = json!({ "Submit": [{
+        "Type": "frame",
+        "Key": key,
+        "Frame": {
+            "Position": "VestFront",
+            "DotPoints": [{ "Index": 0, "Intensity": 100 }],
+            "PathPoints": [],
+            "DurationMillis": 10_000,
+        },
+    }]});
+    socket.send(Message::Text(submit.to_string())).await.unwrap();
+    socket.next().await.unwrap().unwrap();
+
+    socket
+}
+
+/// Waits for the server to catch up, up to a second.
+async fn eventually(condition: impl Fn() -> bool) -> bool {
+    for _ in 0..20 {
+        if condition() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    condition()
+}
+
+fn playing(player: &HapticPlayer, key: &str) -> bool {
+    player.active_keys().iter().any(|active| active.ends_with(key))
+}
+
+#[tokio::test]
+async fn closing_the_connection_turns_its_effects_off() {
+    let (address, player, shutdown) = server(NamespaceMode::PerConnection).await;
+    let mut closing = submitting(address, "com.example.closing", "closing").await;
+    let _staying = submitting(address, "com.example.staying", "staying").await;
+    assert!(eventually(|| playing(&player, "closing") && playing(&player, "staying")).await, "{:?}", player.active_keys());
+
+    closing.close(None).await.unwrap();
+
+    assert!(eventually(|| !playing(&player, "closing")).await, "{:?}", player.active_keys());
+    assert!(playing(&player, "staying"));
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn dropping_the_connection_turns_its_effects_off() {
+    let (address, player, shutdown) = server(NamespaceMode::PerConnection).await;
+    let dropping = submitting(address, "com.example.dropping", "dropping").await;
+    let _staying = submitting(address, "com.example.staying", "staying").await;
+    assert!(eventually(|| playing(&player, "dropping") && playing(&player, "staying")).await, "{:?}", player.active_keys());
+
+    // Gone without a close frame, the server reads an error
+    drop(dropping);
+
+    assert!(eventually(|| !playing(&player, "dropping")).await, "{:?}", player.active_keys());
+    assert!(playing(&player, "staying"));
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn apps_keep_their_effects_until_their_last_connection_is_gone() {
+    let (address, player, shutdown) = server(NamespaceMode::PerApp).await;
+    let first = submitting(address, "com.example.game", "shared").await;
+    let mut second = submitting(address, "com.example.game", "shared").await;
+    assert!(eventually(|| playing(&player, "shared")).await, "{:?}", player.active_keys());
+
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(playing(&player, "shared"), "{:?}", player.active_keys());
+
+    second.close(None).await.unwrap();
+    assert!(eventually(|| !playing(&player, "shared")).await, "{:?}", player.active_keys());
+    shutdown.cancel();
+}
@@ -0,0 +1,156 @@
+use haptic_lib::BodyPart;
+use serde_json::json;
+
+use xrconnect::{
+    bhaptics_studio::tact::SubmitFrame,
+    haptics::{
+        device::{ HapticDevice, RecordingDevice },
+        model::{ DotIntensity, HapticFrame, MotorLayout, PatternPoints },
+        player::HapticPlayer,
+    },
+};
+
+fn x16() -> MotorLayout {
+    MotorLayout::grid(2, 4)
+}
+
+fn x40() -> MotorLayout {
+    MotorLayout::grid(4, 5)
+}
+
+/// Vest with 8 motors on each side.
+fn x16_vest() -> RecordingDevice {
+    RecordingDevice::new("x16", vec![BodyPart::ChestFront, BodyPart::ChestBack]).with_layout(x16())
+}
+
+fn submitted(frame: serde_json::Value) -> Vec<(BodyPart, Vec<DotIntensity>, MotorLayout)> {
+    let frame: SubmitFrame = serde_json::from_value(frame).unwrap();
+    frame.to_pattern().unwrap().clips
+        .into_iter()
+        .map(|clip| match clip.points {
+            PatternPoints::Dot(dots) => (clip.part, dots, clip.layout.unwrap()),
+            PatternPoints::Path(_) => panic!("expected dots on {:?}", clip.part),
+        })
+        .collect()
+}
+
+fn dot(index: usize, intensity: f32) -> DotIntensity {
+    DotIntensity { index, intensity }
+}
+
+#[test]
+fn same_layout_is_kept_as_is() {
+    assert_eq!(x40().resample_to(&[0.5, 1.0], &x40()), [vec![0.5, 1.0], vec![0.0; 18]].concat());
+    assert_eq!(x16().resample_to(&[0.1; 10], &x16()), vec![0.1; 8]);
+}
+
+#[test]
+fn upsampling_takes_the_nearest_motor() {
+    let mut motors = vec![0.0; 8];
+    motors[0] = 1.0;
+    motors[7] = 0.5;
+
+    let resampled = x16().resample_to(&motors, &x40());
+
+    // Top left motor of the X16 is the closest one to the first two of the top row
+    let mut expected = vec![0.0; 20];
+    expected[0] = 1.0;
+    expected[1] = 1.0;
+    expected[18] = 0.5;
+    expected[19] = 0.5;
+    assert_eq!(resampled, expected);
+}
+
+#[test]
+fn downsampling_keeps_every_motor() {
+    // Every single X40 motor still reaches the X16, at half its intensity or more
+    for index in 0..20 {
+        let mut motors = vec![0.0; 20];
+        motors[index] = 1.0;
+
+        let resampled = x40().resample_to(&motors, &x16());
+        assert_eq!(resampled.len(), 8);
+        assert!(resampled.iter().copied().fold(0.0, f32::max) >= 0.5, "motor {}: {:?}", index, resampled);
+    }
+
+    let mut corner = vec![0.0; 20];
+    corner[0] = 0.8;
+    assert_eq!(x40().resample_to(&corner, &x16())[0], 0.8);
+}
+
+#[test]
+fn frame_resamples_to_device_layouts() {
+    let mut frame = HapticFrame::default();
+    frame.get_mut(BodyPart::ChestFront)[19] = 1.0;
+    frame.get_mut(BodyPart::Head)[0] = 1.0;
+
+    let resampled = frame.resample(|part| match part {
+        BodyPart::ChestFront => x16(),
+        part => MotorLayout::default_for(part),
+    });
+
+    assert_eq!(resampled.get(BodyPart::ChestFront).unwrap().len(), 8);
+    assert_eq!(resampled.get(BodyPart::ChestFront).unwrap()[7], 1.0);
+    assert_eq!(resampled.get(BodyPart::Head), frame.get(BodyPart::Head));
+}
+
+#[test]
+fn motor_count_picks_the_layout_of_submitted_dots() {
+    let clips = submitted(json!({
+        "Position": "Vest", "DurationMillis": 100,
+        "DotPoints": [
+            { "Index": 1, "Intensity": 100, "MotorCount": 16 },
+            { "Index": 9, "Intensity": 50, "MotorCount": 16 },
+        ],
+    }));
+
+    assert_eq!(clips, vec![
+        (BodyPart::ChestFront, vec![dot(1, 1.0)], x16()),
+        (BodyPart::ChestBack, vec![dot(1, 0.5)], x16()),
+    ]);
+}
+
+#[test]
+fn highest_index_picks_the_layout_of_submitted_dots() {
+    // Indices of an X40 without MotorCount, the back starting at 20
+    let clips = submitted(json!({
+        "Position": "Vest", "DurationMillis": 100,
+        "DotPoints": [{ "Index": 9, "Intensity": 100 }, { "Index": 39, "Intensity": 100 }],
+    }));
+    assert_eq!(clips, vec![
+        (BodyPart::ChestFront, vec![dot(9, 1.0)], x40()),
+        (BodyPart::ChestBack, vec![dot(19, 1.0)], x40()),
+    ]);
+
+    // Beyond the largest vest, the dot does not fit any layout
+    let clips = submitted(json!({
+        "Position": "VestFront", "DurationMillis": 100,
+        "DotPoints": [{ "Index": 0, "Intensity": 100 }, { "Index": 20, "Intensity": 100 }],
+    }));
+    assert_eq!(clips, vec![(BodyPart::ChestFront, vec![dot(0, 1.0)], x40())]);
+}
+
+#[test]
+fn stopping_silences_the_device_layout() {
+    let mut device = x16_vest();
+    device.stop().unwrap();
+
+    let frames = device.frames();
+    assert_eq!(frames[0].get(BodyPart::ChestFront), Some(&[0.0; 8][..]));
+    assert_eq!(frames[0].get(BodyPart::ChestBack), Some(&[0.0; 8][..]));
+}
+
+#[test]
+fn kill_switch_silences_the_device_layout() {
+    let device = x16_vest();
+    let player = HapticPlayer::new();
+    player.add_device(Box::new(device.clone()));
+
+    player.panic();
+
+    let frames = device.frames();
+    assert!(!frames.is_empty());
+    for frame in frames {
+        assert!(frame.parts().all(|(_, motors)| motors.len() == 8), "{:?}", frame);
+    }
+}
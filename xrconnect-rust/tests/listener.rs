@@ -0,0 +1,82 @@
+use std::{
+    net::{ TcpListener as StdTcpListener, TcpStream },
+    path::PathBuf,
+    time::Duration,
+};
+
+use xrconnect::{
+    bhaptics_studio::{
+        listener::Listener,
+        server::{ BHapticsStudioServer, ServerError },
+    },
+    tls::{ TlsCertificate, TlsConfig },
+};
+
+mod common;
+
+/// Certificate and key files in a directory of the test, with the given content.
+fn certificate(name: &str, cert: &str, key: &str) -> TlsCertificate {
+    let directory = std::env::temp_dir().join(format!("xrconnect-listener-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&directory).unwrap();
+    let (cert_path, key_path): (PathBuf, PathBuf) = (directory.join("cert.pem"), directory.join("key.pem"));
+    std::fs::write(&cert_path, cert).unwrap();
+    std::fs::write(&key_path, key).unwrap();
+
+    TlsCertificate::Files { cert: cert_path, key: key_path }
+}
+
+fn valid_certificate(name: &str) -> TlsCertificate {
+    let generated = rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+    certificate(name, &generated.cert.pem(), &generated.key_pair.serialize_pem())
+}
+
+/// Runs a server on `listeners`, returning whether it is still running after a while.
+async fn running(listeners: Vec<Listener>) -> bool {
+    let server = BHapticsStudioServer::with_listeners(listeners).with_state_file(None);
+    let shutdown = server.shutdown_token();
+    let task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let running = !task.is_finished();
+    shutdown.cancel();
+    assert!(task.await.is_ok(), "the server panicked");
+    running
+}
+
+#[tokio::test]
+async fn tls_listens_with_a_valid_certificate() {
+    let address = common::free_address();
+    let server = BHapticsStudioServer::with_listeners(vec![Listener::Tls(TlsConfig { address, certificate: valid_certificate("valid") })])
+        .with_state_file(None);
+    let shutdown = server.shutdown_token();
+    tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    assert!(TcpStream::connect(address).is_ok());
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn invalid_certificates_leave_the_other_listeners_running() {
+    let tcp = common::free_address();
+    let tls = TlsConfig { address: common::free_address(), certificate: certificate("invalid", "not a certificate", "not a key") };
+
+    assert!(running(vec![Listener::Tcp(tcp), Listener::Tls(tls)]).await);
+}
+
+#[tokio::test]
+async fn tls_addresses_in_use_leave_the_other_listeners_running() {
+    let taken = StdTcpListener::bind("127.0.0.1:0").unwrap();
+    let tls = TlsConfig { address: taken.local_addr().unwrap(), certificate: valid_certificate("taken") };
+
+    assert!(running(vec![Listener::Tcp(common::free_address()), Listener::Tls(tls)]).await);
+}
+
+#[tokio::test]
+async fn failing_tls_alone_is_no_listener() {
+    let taken = StdTcpListener::bind("127.0.0.1:0").unwrap();
+    let tls = TlsConfig { address: taken.local_addr().unwrap(), certificate: valid_certificate("alone") };
+    let server = BHapticsStudioServer::with_listeners(vec![Listener::Tls(tls)]).with_state_file(None);
+
+    assert!(matches!(server.run().await, Err(ServerError::NoListener)));
+}
@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+use haptic_lib::{ BodyPart, EffectInterpolation };
+
+use xrconnect::haptics::{
+    calibration::{ CalibrationProfile, MotorCalibration },
+    device::{ HapticDevice, RecordingDevice },
+    mapping::{ BodyMapping, MappedDevice, MappingError },
+    model::{ ClipOutput, DotIntensity, HapticFrame, HapticPattern, PatternClip, PatternPoints, PlaybackOptions },
+    player::HapticPlayer,
+};
+
+fn shipped(name: &str) -> BodyMapping {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("mappings").join(format!("{}.json", name));
+    BodyMapping::load(&path).unwrap_or_else(|why| panic!("{}: {}", path.display(), why))
+}
+
+/// Vest frame with the given motors of the default 4 x 5 grid set.
+fn vest(front: &[(usize, f32)], back: &[(usize, f32)]) -> HapticFrame {
+    let mut frame = HapticFrame::silent(&[BodyPart::ChestFront, BodyPart::ChestBack]);
+    for (part, motors) in [(BodyPart::ChestFront, front), (BodyPart::ChestBack, back)] {
+        for (index, intensity) in motors {
+            frame.get_mut(part)[*index] = *intensity;
+        }
+    }
+    frame
+}
+
+fn assert_motors(frame: &HapticFrame, part: BodyPart, expected: &[f32]) {
+    let motors = frame.get(part).unwrap_or_else(|| panic!("{:?} missing from {:?}", part, frame));
+    assert_eq!(motors.len(), expected.len(), "{:?}: {:?}", part, motors);
+    for (motor, expected) in motors.iter().zip(expected) {
+        assert!((motor - expected).abs() < 1e-3, "{:?}: {:?} instead of {:?}", part, motors, expected);
+    }
+}
+
+#[test]
+fn shipped_mappings_are_valid() {
+    for name in ["vest-to-belt", "vest-to-shakers"] {
+        let mapping = shipped(name);
+        assert_eq!(mapping.name, name);
+        assert_eq!(mapping.sources(), vec![BodyPart::ChestFront, BodyPart::ChestBack]);
+        assert_eq!(mapping.targets(), vec![BodyPart::ChestFront, BodyPart::ChestBack]);
+    }
+}
+
+#[test]
+fn vest_to_belt_collapses_the_columns() {
+    let recording = RecordingDevice::new("belt", vec![BodyPart::ChestFront, BodyPart::ChestBack]);
+    let mut device = MappedDevice::new(recording.clone(), shipped("vest-to-belt"));
+    assert_eq!(device.positions(), vec![BodyPart::ChestFront, BodyPart::ChestBack]);
+
+    // Top left motor of the front, second column bottom motor of the front, bottom right one of the back
+    let frame = device.map(vest(&[(0, 1.0), (17, 0.6)], &[(19, 0.5)]));
+    device.write(&frame).unwrap();
+
+    let frames = recording.frames();
+    assert_eq!(frames.len(), 1);
+    assert_motors(&frames[0], BodyPart::ChestFront, &[1.0, 0.6 * (1.0 - (1.0 / 6.0) / 0.6), 0.0]);
+    assert_motors(&frames[0], BodyPart::ChestBack, &[0.0, 0.5 * (1.0 - 0.5 / 0.6), 0.5]);
+
+    // Every row of a column reaches the belt
+    for row in 0..5 {
+        let frame = device.map(vest(&[(row * 4 + 3, 0.8)], &[]));
+        assert_motors(&frame, BodyPart::ChestFront, &[0.0, 0.8 * (1.0 - 0.5 / 0.6), 0.8]);
+    }
+}
+
+#[test]
+fn mapped_devices_are_calibrated_on_their_actuators() {
+    let recording = RecordingDevice::new("belt", vec![BodyPart::ChestFront, BodyPart::ChestBack]);
+    let player = HapticPlayer::new();
+    player.add_device(Box::new(MappedDevice::new(recording.clone(), shipped("vest-to-belt"))));
+
+    // Right actuator of the belt, which would be a motor of the top row of the vest
+    let mut profile = CalibrationProfile::new("alice");
+    profile.part_mut(BodyPart::ChestFront).motors.insert(2, MotorCalibration { gain: Some(0.5), ..Default::default() });
+    player.set_calibration(profile);
+
+    player.play_pattern("hit", HapticPattern::new(vec![PatternClip {
+        part: BodyPart::ChestFront,
+        start_millis: 0,
+        end_millis: 1000,
+        interpolation: EffectInterpolation::None,
+        points: PatternPoints::Dot(vec![DotIntensity { index: 2, intensity: 1.0 }, DotIntensity { index: 19, intensity: 1.0 }]),
+        output: ClipOutput::Motors,
+        layout: None,
+    }]), PlaybackOptions::default()).unwrap();
+    player.tick(player.now());
+
+    assert_motors(&recording.frames()[0], BodyPart::ChestFront, &[0.0, 1.0 - (1.0 / 6.0) / 0.6, 0.5]);
+}
+
+#[test]
+fn vest_to_shakers_drives_a_single_actuator_per_side() {
+    let recording = RecordingDevice::new("shakers", vec![BodyPart::ChestFront, BodyPart::ChestBack]);
+    let mut device = MappedDevice::new(recording.clone(), shipped("vest-to-shakers"));
+
+    // Back motor at (1/3, 0.25), 1/6 away from the shaker; front corner motor, half a diagonal away
+    let frame = device.map(vest(&[(0, 1.0)], &[(5, 0.9)]));
+    device.write(&frame).unwrap();
+
+    let frame = &recording.frames()[0];
+    assert_motors(frame, BodyPart::ChestBack, &[0.9 * (1.0 - (1.0 / 6.0) / 0.6)]);
+    assert_motors(frame, BodyPart::ChestFront, &[0.8 * (1.0 - 0.5_f32.sqrt() / 1.5)]);
+}
+
+#[test]
+fn invalid_mappings_are_rejected() {
+    let route = |extra: &str| format!(r#"{{ "routes": [{{ "source": "ChestFront", "target": "ChestFront", {} }}] }}"#, extra);
+
+    for content in [
+        route(r#""target_layout": []"#),
+        route(r#""target_layout": [[0.5, 1.5]]"#),
+        route(r#""target_layout": [[0.5, 0.5]], "resampling": { "Max": { "radius": 0.0 } }"#),
+        route(r#""target_layout": [[0.5, 0.5]], "resampling": { "Columns": { "radius": -0.5 } }"#),
+        route(r#""target_layout": [[0.5, 0.5]], "gain": -1.0"#),
+    ] {
+        assert!(matches!(BodyMapping::from_json(&content), Err(MappingError::Invalid(_))), "{}", content);
+    }
+
+    assert!(matches!(BodyMapping::from_json(&route(r#""target_layout": "belt""#)), Err(MappingError::Parse(_))));
+}
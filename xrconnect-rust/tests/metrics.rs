@@ -0,0 +1,123 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    time::Duration,
+};
+
+use futures_util::SinkExt;
+use haptic_lib::BodyPart;
+use serde_json::json;
+use tokio::{
+    io::{ AsyncReadExt, AsyncWriteExt },
+    net::TcpStream,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+use xrconnect::{
+    bhaptics_studio::server::BHapticsStudioServer,
+    haptics::{
+        device::{ DeviceError, HapticDevice, RecordingDevice },
+        model::HapticFrame,
+        safety::SafetyLimits,
+    },
+};
+
+mod common;
+
+/// Device failing every write.
+struct BrokenDevice;
+
+impl HapticDevice for BrokenDevice {
+    fn name(&self) -> &str {
+        "broken"
+    }
+
+    fn positions(&self) -> Vec<BodyPart> {
+        vec![BodyPart::ChestFront]
+    }
+
+    fn write(&mut self, _frame: &HapticFrame) -> Result<(), DeviceError> {
+        Err(DeviceError::Other(String::from("unplugged")))
+    }
+}
+
+async fn scrape(address: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let request = format!("GET /metrics HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", address);
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+/// Value of the sample `sample`, e.g. `xrconnect_parse_errors_total`.
+fn sample(metrics: &str, sample: &str) -> Option<f64> {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(sample).and_then(|value| value.strip_prefix(' ')))
+        .map(|value| value.parse().unwrap())
+}
+
+#[tokio::test]
+async fn metrics_count_the_traffic_and_the_devices() {
+    let address = common::free_address();
+    let server = BHapticsStudioServer::new(address).with_state_file(None);
+    let player = server.player().clone();
+    player.add_device(Box::new(RecordingDevice::new("vest", vec![BodyPart::ChestFront])));
+    player.add_device(Box::new(BrokenDevice));
+    player.set_safety_limits(SafetyLimits {
+        power_budgets: BTreeMap::from([(BodyPart::ChestFront, 0.5)]),
+        ..Default::default()
+    });
+    common::spawn(server).await;
+
+    let url = format!("ws://{}/v2/feedbacks?app_id=com.example.game&app_name=Game", address);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    let submit = json!({ "Submit": [{
+        "Type": "frame",
+        "Key": "hit",
+        "Frame": { "Position": "VestFront", "DotPoints": [{ "Index": 0, "Intensity": 100 }], "PathPoints": [], "DurationMillis": 1000 },
+    }]});
+    socket.send(Message::Text(submit.to_string())).await.unwrap();
+    socket.send(Message::Text(String::from("not json"))).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let response = scrape(address).await;
+    let (head, metrics) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert!(head.to_lowercase().contains("content-type: text/plain; version=0.0.4"), "{}", head);
+
+    // Every sample follows the help and type of its metric
+    let mut kind = None;
+    for line in metrics.lines() {
+        if let Some(comment) = line.strip_prefix("# ") {
+            if let Some(declared) = comment.strip_prefix("TYPE ") {
+                let (name, declared) = declared.split_once(' ').unwrap();
+                assert!(["counter", "gauge", "histogram"].contains(&declared), "{}", line);
+                kind = Some(name.to_string());
+            }
+            continue;
+        }
+
+        let name = kind.as_deref().unwrap_or_else(|| panic!("{} has no type", line));
+        assert!(line.starts_with(name), "{} is not a sample of {}", line, name);
+        let (_, value) = line.rsplit_once(' ').unwrap();
+        value.parse::<f64>().unwrap_or_else(|why| panic!("{}: {}", line, why));
+    }
+
+    assert_eq!(sample(metrics, r#"xrconnect_connected_clients{app="com.example.game"}"#), Some(1.0));
+    assert_eq!(sample(metrics, r#"xrconnect_messages_received_total{type="frame"}"#), Some(1.0));
+    assert_eq!(sample(metrics, "xrconnect_parse_errors_total"), Some(1.0));
+    assert_eq!(sample(metrics, "xrconnect_active_keys"), Some(1.0));
+    assert_eq!(sample(metrics, "xrconnect_devices"), Some(2.0));
+
+    assert_eq!(sample(metrics, "xrconnect_playback_latency_seconds_count"), Some(1.0));
+    assert_eq!(sample(metrics, r#"xrconnect_playback_latency_seconds_bucket{le="+Inf"}"#), Some(1.0));
+    assert_eq!(sample(metrics, r#"xrconnect_playback_latency_seconds_bucket{le="1"}"#), Some(1.0));
+    assert!(sample(metrics, "xrconnect_playback_latency_seconds_sum").unwrap() > 0.0);
+
+    assert!(sample(metrics, r#"xrconnect_device_write_failures_total{device="broken"}"#).unwrap() >= 1.0);
+    assert_eq!(sample(metrics, r#"xrconnect_device_write_failures_total{device="vest"}"#), None);
+    assert!(sample(metrics, "xrconnect_safety_interventions_total").unwrap() >= 1.0);
+}
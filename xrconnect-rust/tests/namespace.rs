@@ -0,0 +1,110 @@
+use haptic_lib::{ BodyPart, EffectInterpolation };
+use serde_json::json;
+
+use xrconnect::{
+    bhaptics_studio::{
+        client::PlayerResponse,
+        namespace::{ KeyNamespace, NamespaceMode },
+        server::BHapticsAppInfo,
+        tact::PlayerRequest,
+        BHapticsStudioPlayer,
+    },
+    haptics::{
+        model::{ ClipOutput, DotIntensity, HapticPattern, PatternClip, PatternPoints, PlaybackOptions },
+        player::HapticPlayer,
+    },
+};
+
+fn app(id: &str) -> BHapticsAppInfo {
+    serde_json::from_value(json!({ "app_id": id, "app_name": id })).unwrap()
+}
+
+fn pulse() -> HapticPattern {
+    HapticPattern::new(vec![PatternClip {
+        part: BodyPart::ChestFront,
+        start_millis: 0,
+        end_millis: 10_000,
+        interpolation: EffectInterpolation::None,
+        points: PatternPoints::Dot(vec![DotIntensity { index: 0, intensity: 1.0 }]),
+        output: ClipOutput::Motors,
+        layout: None,
+    }])
+}
+
+/// Registers and plays `key` in `namespace`, the way a client does.
+fn play(player: &HapticPlayer, namespace: &KeyNamespace, key: &str) {
+    player.register(namespace.scope(key), pulse());
+    player.play_for(namespace.app(), &namespace.scope(key), namespace.scope(key), PlaybackOptions::default()).unwrap();
+}
+
+#[test]
+fn connections_have_their_own_keys() {
+    let first = KeyNamespace::for_client(NamespaceMode::PerConnection, 1, &app("com.example.game"));
+    let second = KeyNamespace::for_client(NamespaceMode::PerConnection, 2, &app("com.example.game"));
+
+    assert_eq!(first.name(), "connection:1");
+    assert_eq!(first.app(), Some("com.example.game"));
+    assert_eq!(first.scope("Hit"), "connection:1/Hit");
+    assert_eq!(first.unscope("connection:1/Hit"), Some("Hit"));
+    assert_eq!(first.unscope("connection:2/Hit"), None);
+    assert!(!second.contains(&first.scope("Hit")));
+
+    // Connection 1 is not a prefix of connection 10
+    let tenth = KeyNamespace::for_client(NamespaceMode::PerConnection, 10, &app("com.example.game"));
+    assert!(!first.contains(&tenth.scope("Hit")));
+}
+
+#[test]
+fn connections_of_an_app_share_its_keys() {
+    let first = KeyNamespace::for_client(NamespaceMode::PerApp, 1, &app("com.example.game"));
+    let second = KeyNamespace::for_client(NamespaceMode::PerApp, 2, &app("com.example.game"));
+
+    assert_eq!(first, second);
+    assert_eq!(first.scope("Hit"), "app:com.example.game/Hit");
+}
+
+#[test]
+fn slashes_in_app_ids_do_not_reach_into_other_namespaces() {
+    let parent = KeyNamespace::for_client(NamespaceMode::PerApp, 1, &app("a"));
+    let nested = KeyNamespace::for_client(NamespaceMode::PerApp, 2, &app("a/b"));
+    let escaped = KeyNamespace::for_client(NamespaceMode::PerApp, 3, &app("a%2Fb"));
+
+    assert_eq!(nested.name(), "app:a%2Fb");
+    assert!(!parent.contains(&nested.scope("Hit")));
+    assert_eq!(parent.unscope(&nested.scope("Hit")), None);
+    assert_ne!(nested.scope("Hit"), escaped.scope("Hit"));
+    assert!(!nested.contains(&escaped.scope("Hit")));
+    assert_eq!(nested.unscope(&nested.scope("b/Hit")), Some("b/Hit"));
+}
+
+#[test]
+fn responses_only_report_the_keys_of_the_client() {
+    let player = HapticPlayer::new();
+    let parent = KeyNamespace::for_client(NamespaceMode::PerApp, 1, &app("a"));
+    let nested = KeyNamespace::for_client(NamespaceMode::PerApp, 2, &app("a/b"));
+    play(&player, &parent, "Hit");
+    play(&player, &nested, "b/Shot");
+    player.register("local/Test", pulse());
+
+    let response = PlayerResponse::from_player(&player, &parent);
+    assert_eq!(response.registered_keys, vec!["Hit"]);
+    assert_eq!(response.active_keys, vec!["Hit"]);
+
+    let response = PlayerResponse::from_player(&player, &nested);
+    assert_eq!(response.registered_keys, vec!["b/Shot"]);
+    assert_eq!(response.active_keys, vec!["b/Shot"]);
+}
+
+#[test]
+fn turning_everything_off_stays_within_the_namespace() {
+    let player = HapticPlayer::new();
+    let parent = KeyNamespace::for_client(NamespaceMode::PerApp, 1, &app("a"));
+    let nested = KeyNamespace::for_client(NamespaceMode::PerApp, 2, &app("a/b"));
+    play(&player, &parent, "Hit");
+    play(&player, &nested, "Shot");
+
+    let request: PlayerRequest = serde_json::from_value(json!({ "Submit": [{ "Type": "turnOffAll" }] })).unwrap();
+    player.handle_request(request, &parent).unwrap();
+
+    assert_eq!(player.active_keys(), vec![nested.scope("Shot")]);
+}
@@ -0,0 +1,60 @@
+use serde_json::{ json, Value };
+
+use xrconnect::bhaptics_studio::tact::project::{ Project, ProjectError };
+
+/// Project with a single dot effect starting at `start_time`, its feedback from `feedback_start` to `feedback_end`.
+fn project(start_time: Value, feedback_start: Value, feedback_end: Value) -> String {
+    json!({
+        "id": "hit", "name": "hit", "description": "",
+        "mediaFileDuration": 1, "createdAt": 0, "updatedAt": 0,
+        "layout": { "type": "Tactot", "name": "Tactot", "layouts": {} },
+        "tracks": [{ "enable": true, "effects": [{
+            "name": "Effect 1", "startTime": start_time, "offsetTime": 1000,
+            "modes": {
+                "VestFront": {
+                    "mode": "DOT_MODE",
+                    "dotMode": { "dotConnected": false, "feedback": [{
+                        "startTime": feedback_start, "endTime": feedback_end, "playbackType": "NONE",
+                        "pointList": [{ "index": 0, "intensity": 1.0 }],
+                    }]},
+                    "pathMode": { "feedback": [] },
+                },
+                "VestBack": {
+                    "mode": "PATH_MODE",
+                    "dotMode": { "dotConnected": false, "feedback": [] },
+                    "pathMode": { "feedback": [{
+                        "movingPattern": "CONST_SPEED", "playbackType": "NONE", "visible": true,
+                        "pointList": [
+                            { "x": 0.0, "y": 0.0, "intensity": 1.0, "time": 0 },
+                            { "x": 1.0, "y": 1.0, "intensity": 1.0, "time": 100 },
+                        ],
+                    }]},
+                },
+            },
+        }]}],
+    }).to_string()
+}
+
+#[test]
+fn times_beyond_u32_are_rejected() {
+    for content in [
+        project(json!(u64::from(u32::MAX) + 1), json!(0), json!(100)),
+        project(json!(0), json!(0), json!(u64::MAX)),
+        project(json!(-1), json!(0), json!(100)),
+    ] {
+        assert!(matches!(Project::from_json(&content), Err(ProjectError::Parse(_))));
+    }
+}
+
+#[test]
+fn late_effects_end_at_the_latest_time() {
+    let project = Project::from_json(&project(json!(u32::MAX - 10), json!(0), json!(100))).unwrap();
+
+    let pattern = project.to_pattern();
+    assert!(!pattern.clips.is_empty());
+    for clip in &pattern.clips {
+        assert!(clip.start_millis >= u32::MAX - 10, "{:?}", clip);
+        assert!(clip.start_millis <= clip.end_millis, "{:?}", clip);
+    }
+    assert!(pattern.clips.iter().any(|clip| clip.end_millis == u32::MAX));
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::BTreeMap,
+    time::{ Duration, Instant },
+};
+
+use haptic_lib::{ BodyPart, EffectInterpolation };
+
+use xrconnect::haptics::{
+    device::RecordingDevice,
+    model::{ ClipOutput, DotIntensity, HapticFrame, HapticPattern, PatternClip, PatternPoints, PlaybackOptions },
+    player::{ HapticPlayer, FRAME_INTERVAL },
+    safety::{ SafetyError, SafetyLimiter, SafetyLimits, ThermalLimits, MAX_LIMIT_MILLIS },
+};
+
+const DEVICE: u64 = 0;
+
+/// Head with its first motor at `intensity`.
+fn head(intensity: f32) -> HapticFrame {
+    let mut frame = HapticFrame::default();
+    frame.get_mut(BodyPart::Head)[0] = intensity;
+    frame
+}
+
+/// Feeds `frame` every frame interval for `duration`, returning the limited first head motor of every frame.
+fn run(limiter: &mut SafetyLimiter, now: &mut Instant, duration: Duration, frame: &HapticFrame) -> Vec<f32> {
+    let mut outputs = vec![];
+    let end = *now + duration;
+    while *now < end {
+        let mut limited = frame.clone();
+        limiter.apply(DEVICE, &mut limited, *now);
+        outputs.push(limited.get(BodyPart::Head).map_or(0.0, |motors| motors[0]));
+        *now += FRAME_INTERVAL;
+    }
+    outputs
+}
+
+fn frames(duration: Duration) -> usize {
+    (duration.as_millis() / FRAME_INTERVAL.as_millis()) as usize
+}
+
+#[test]
+fn motors_running_too_long_are_cut_for_the_cooldown() {
+    let mut limiter = SafetyLimiter::new(SafetyLimits { max_on_millis: 1000, cooldown_millis: 500, ..Default::default() });
+    let mut now = Instant::now();
+
+    let outputs = run(&mut limiter, &mut now, Duration::from_millis(2000), &head(1.0));
+
+    let cut = outputs.iter().position(|output| *output == 0.0).unwrap();
+    assert_eq!(cut, frames(Duration::from_millis(1000)) + 1);
+    assert!(outputs[cut..cut + frames(Duration::from_millis(500))].iter().all(|output| *output == 0.0));
+    assert_eq!(outputs[cut + frames(Duration::from_millis(500))], 1.0);
+    assert_eq!(limiter.interventions(), 1);
+}
+
+#[test]
+fn breaks_reset_the_on_time() {
+    for silence in [HapticFrame::default(), head(0.0)] {
+        let mut limiter = SafetyLimiter::new(SafetyLimits { max_on_millis: 1000, ..Default::default() });
+        let mut now = Instant::now();
+
+        // Silent parts are left out of mixed frames, which must end the on-time all the same
+        run(&mut limiter, &mut now, Duration::from_millis(800), &head(1.0));
+        run(&mut limiter, &mut now, Duration::from_secs(60), &silence);
+        let outputs = run(&mut limiter, &mut now, Duration::from_millis(800), &head(1.0));
+
+        assert!(outputs.iter().all(|output| *output == 1.0), "{:?}", outputs);
+        assert_eq!(limiter.interventions(), 0);
+    }
+}
+
+#[test]
+fn duty_cycle_converges_to_the_limit() {
+    let mut limiter = SafetyLimiter::new(SafetyLimits {
+        max_on_millis: 60_000,
+        duty_window_millis: 1000,
+        max_duty_cycle: 0.5,
+        ..Default::default()
+    });
+    let mut now = Instant::now();
+
+    let outputs = run(&mut limiter, &mut now, Duration::from_secs(5), &head(1.0));
+
+    // Full intensity until half the window is used up, then throttled
+    let window = frames(Duration::from_secs(1));
+    assert!(outputs[..window / 2].iter().all(|output| *output == 1.0));
+    let average = outputs[outputs.len() - window..].iter().sum::<f32>() / window as f32;
+    assert!((average - 0.5).abs() < 0.05, "{}", average);
+    assert_eq!(limiter.interventions(), 1);
+
+    // Gone from the mixed frames for a window, the motor is back to full intensity
+    run(&mut limiter, &mut now, Duration::from_secs(1), &HapticFrame::default());
+    assert_eq!(run(&mut limiter, &mut now, FRAME_INTERVAL, &head(1.0)), vec![1.0]);
+}
+
+#[test]
+fn duty_cycle_history_expires_with_time() {
+    let mut limiter = SafetyLimiter::new(SafetyLimits {
+        max_on_millis: 60_000,
+        duty_window_millis: 1000,
+        max_duty_cycle: 0.5,
+        ..Default::default()
+    });
+    let mut now = Instant::now();
+
+    run(&mut limiter, &mut now, Duration::from_secs(2), &head(1.0));
+
+    // No frame at all for a while, e.g. the player was not ticking
+    now += Duration::from_secs(1);
+    assert_eq!(run(&mut limiter, &mut now, FRAME_INTERVAL, &head(1.0)), vec![1.0]);
+}
+
+#[test]
+fn power_budget_scales_the_whole_part_down() {
+    let mut limiter = SafetyLimiter::new(SafetyLimits {
+        power_budgets: BTreeMap::from([(BodyPart::ChestFront, 2.0)]),
+        ..Default::default()
+    });
+    let now = Instant::now();
+
+    for _ in 0..3 {
+        let mut frame = HapticFrame::silent(&[BodyPart::ChestFront, BodyPart::ChestBack]);
+        frame.get_mut(BodyPart::ChestFront)[..4].fill(1.0);
+        frame.get_mut(BodyPart::ChestBack)[..4].fill(1.0);
+        limiter.apply(DEVICE, &mut frame, now);
+
+        assert_eq!(frame.get(BodyPart::ChestFront).unwrap()[..5], [0.5, 0.5, 0.5, 0.5, 0.0]);
+        assert_eq!(frame.get(BodyPart::ChestBack).unwrap()[..5], [1.0, 1.0, 1.0, 1.0, 0.0]);
+    }
+
+    // Counted once, when the part goes over its budget
+    assert_eq!(limiter.interventions(), 1);
+}
+
+#[test]
+fn devices_are_limited_on_their_own() {
+    let mut limiter = SafetyLimiter::new(SafetyLimits { max_on_millis: 1000, ..Default::default() });
+    let mut now = Instant::now();
+
+    let outputs = run(&mut limiter, &mut now, Duration::from_millis(1200), &head(1.0));
+    assert_eq!(outputs.last(), Some(&0.0));
+
+    let mut frame = head(1.0);
+    limiter.apply(DEVICE + 1, &mut frame, now);
+    assert_eq!(frame.get(BodyPart::Head).unwrap()[0], 1.0);
+}
+
+#[test]
+fn heaters_are_capped_in_level_and_time() {
+    let mut limiter = SafetyLimiter::new(SafetyLimits {
+        thermal: ThermalLimits { max_level: 0.6, max_on_millis: 1000, cooldown_millis: 2000 },
+        ..Default::default()
+    });
+    let mut now = Instant::now();
+    let mut levels = vec![];
+
+    for _ in 0..frames(Duration::from_secs(3)) {
+        let mut frame = HapticFrame::default();
+        frame.set_thermal(BodyPart::ChestBack, vec![1.0]);
+        limiter.apply(DEVICE, &mut frame, now);
+        levels.push(frame.thermal(BodyPart::ChestBack).unwrap()[0]);
+        now += FRAME_INTERVAL;
+    }
+
+    let cut = frames(Duration::from_millis(1000)) + 1;
+    assert!(levels[..cut].iter().all(|level| *level == 0.6));
+    assert!(levels[cut..].iter().all(|level| *level == 0.0));
+    assert_eq!(limiter.interventions(), 2);
+}
+
+#[test]
+fn thermal_clips_reach_the_devices_limited() {
+    let device = RecordingDevice::new("heater", vec![BodyPart::ChestBack]);
+    let player = HapticPlayer::new();
+    player.add_device(Box::new(device.clone()));
+
+    player.play_pattern("warm", HapticPattern::new(vec![PatternClip {
+        part: BodyPart::ChestBack,
+        start_millis: 0,
+        end_millis: 1000,
+        interpolation: EffectInterpolation::None,
+        points: PatternPoints::Dot(vec![DotIntensity { index: 2, intensity: 1.0 }]),
+        output: ClipOutput::Thermal,
+        layout: None,
+    }]), PlaybackOptions::default()).unwrap();
+    player.tick(player.now());
+
+    let frame = &device.frames()[0];
+    assert_eq!(frame.thermal(BodyPart::ChestBack).unwrap()[2], SafetyLimits::default().thermal.max_level);
+    assert!(frame.get(BodyPart::ChestBack).is_none());
+    assert_eq!(player.safety_interventions(), 1);
+}
+
+#[test]
+fn limits_out_of_range_are_rejected() {
+    let invalid = |limits: SafetyLimits| matches!(limits.validate(), Err(SafetyError::Invalid(_)));
+    let thermal = SafetyLimits::default().thermal;
+
+    assert!(!invalid(SafetyLimits::default()));
+    assert!(invalid(SafetyLimits { max_duty_cycle: 0.0, ..Default::default() }));
+    assert!(invalid(SafetyLimits { max_duty_cycle: f32::NAN, ..Default::default() }));
+    assert!(invalid(SafetyLimits { max_on_millis: MAX_LIMIT_MILLIS + 1, ..Default::default() }));
+    assert!(invalid(SafetyLimits { cooldown_millis: 0, ..Default::default() }));
+    assert!(invalid(SafetyLimits { power_budgets: BTreeMap::from([(BodyPart::Head, 0.0)]), ..Default::default() }));
+    assert!(invalid(SafetyLimits { thermal: ThermalLimits { max_level: -0.1, ..thermal.clone() }, ..Default::default() }));
+    assert!(invalid(SafetyLimits { thermal: ThermalLimits { max_on_millis: 0, ..thermal }, ..Default::default() }));
+}
@@ -0,0 +1,126 @@
+use std::{
+    path::PathBuf,
+    time::{ Duration, Instant },
+};
+
+use futures_util::SinkExt;
+use haptic_lib::BodyPart;
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+
+use xrconnect::{
+    bhaptics_studio::{
+        server::BHapticsStudioServer,
+        session::{ Session, SessionEvent, SessionRecorder, SessionReplayer },
+    },
+    haptics::{
+        device::RecordingDevice,
+        model::HapticFrame,
+        player::{ HapticPlayer, FRAME_INTERVAL },
+    },
+};
+
+mod common;
+
+fn session_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("xrconnect-session-{}-{}.jsonl", std::process::id(), name))
+}
+
+/// Submission of a frame driving the first motor of the front of the vest for `millis`.
+fn submit(key: &str, millis: u64) -> String {
+    json!({ "Submit": [{
+        "Type": "frame",
+        "Key": key,
+        "Frame": { "Position": "VestFront", "DotPoints": [{ "Index": 0, "Intensity": 100 }], "PathPoints": [], "DurationMillis": millis },
+    }]}).to_string()
+}
+
+/// Replays `session` frame by frame into a device on the front of the vest, returning every frame written.
+fn replay(session: &Session) -> Vec<HapticFrame> {
+    let device = RecordingDevice::new("vest", vec![BodyPart::ChestFront]);
+    let player = HapticPlayer::with_manual_clock(Instant::now());
+    player.add_device(Box::new(device.clone()));
+
+    SessionReplayer::new(session.clone()).replay_stepped(&player);
+    device.frames()
+}
+
+/// Indices of the frames where the first motor of the front runs.
+fn running(frames: &[HapticFrame]) -> Vec<usize> {
+    frames
+        .iter()
+        .enumerate()
+        .filter(|(_, frame)| frame.get(BodyPart::ChestFront).is_some_and(|motors| motors[0] > 0.0))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+fn frames(millis: u64) -> usize {
+    (millis / FRAME_INTERVAL.as_millis() as u64) as usize
+}
+
+#[tokio::test]
+async fn client_traffic_is_recorded_and_replayed() {
+    let path = session_path("recorded");
+    let address = common::free_address();
+    let server = BHapticsStudioServer::new(address)
+        .with_state_file(None)
+        .with_recorder(SessionRecorder::create(&path).unwrap());
+    let shutdown = server.shutdown_token();
+    common::spawn(server).await;
+
+    let url = format!("ws://{}/v2/feedbacks?app_id=com.example.game&app_name=Game", address);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    socket.send(Message::Text(submit("hit", 200))).await.unwrap();
+    socket.send(Message::Text(String::from("not json"))).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    socket.close(None).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    shutdown.cancel();
+
+    let session = Session::load(&path).unwrap();
+    let connection = session.entries[0].connection;
+    assert!(session.entries.iter().all(|entry| entry.connection == connection));
+    assert!(session.entries.windows(2).all(|pair| pair[0].at_millis <= pair[1].at_millis));
+
+    let events: Vec<SessionEvent> = session.entries.iter().map(|entry| entry.event.clone()).collect();
+    assert!(matches!(&events[..], [
+        SessionEvent::Connected { app },
+        SessionEvent::Message { message: first },
+        SessionEvent::Message { message: second },
+        SessionEvent::Disconnected,
+    ] if app.id() == "com.example.game" && *first == submit("hit", 200) && second == "not json"), "{:?}", events);
+
+    // The effect plays for its whole duration, however long the replay takes
+    let replayed = replay(&session);
+    assert_eq!(running(&replayed).len(), frames(200));
+    assert_eq!(replay(&session), replayed);
+}
+
+#[test]
+fn stepped_replays_are_deterministic() {
+    let path = session_path("stepped");
+    let entries = [
+        json!({ "at_millis": 1000, "connection": 1, "event": "Connected", "app": { "app_id": "com.example.game", "app_name": "Game" } }),
+        json!({ "at_millis": 1100, "connection": 1, "event": "Message", "message": submit("hit", 300) }),
+        json!({ "at_millis": 1200, "connection": 1, "event": "Message", "message": submit("ignored", 10_000) }),
+        json!({ "at_millis": 1300, "connection": 1, "event": "Disconnected" }),
+        json!({ "at_millis": 1600, "connection": 2, "event": "Connected", "app": { "app_id": "com.example.game", "app_name": "Game" } }),
+        json!({ "at_millis": 1700, "connection": 2, "event": "Message", "message": submit("hit", 100) }),
+    ];
+    std::fs::write(&path, entries.iter().map(|entry| entry.to_string() + "\n").collect::<String>()).unwrap();
+
+    let session = Session::load(&path).unwrap();
+    assert_eq!(session.duration(), Duration::from_millis(700));
+
+    // Timed from the first entry: on from 100 ms until the first client disconnects at 300 ms,
+    // then from 700 ms for 100 ms, the effect of the second client ending the replay
+    let replayed = replay(&session);
+    let expected: Vec<usize> = (frames(100)..frames(300)).chain(frames(700)..frames(800)).collect();
+    assert_eq!(running(&replayed), expected);
+    assert_eq!(replayed.len(), frames(800) + 1);
+
+    for _ in 0..3 {
+        assert_eq!(replay(&session), replayed);
+    }
+}
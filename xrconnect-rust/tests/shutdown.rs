@@ -0,0 +1,195 @@
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{ atomic::{ AtomicBool, Ordering }, Arc },
+    time::{ Duration, Instant },
+};
+
+use futures_util::{ SinkExt, StreamExt };
+use haptic_lib::BodyPart;
+use serde_json::json;
+use tokio::{ net::TcpSocket, task::JoinHandle };
+use tokio_tungstenite::tungstenite::{ protocol::frame::coding::CloseCode, Message };
+use tokio_util::sync::CancellationToken;
+
+use xrconnect::{
+    access::{ AccessControl, AccessPolicy, UnknownApps },
+    bhaptics_studio::server::{ BHapticsStudioServer, ServerError },
+    haptics::{
+        calibration::CalibrationProfile,
+        device::{ DeviceError, HapticDevice, RecordingDevice },
+        model::HapticFrame,
+        player::HapticPlayer,
+    },
+    state::SavedState,
+};
+
+mod common;
+
+/// Vest keeping what is written to it, and whether it was flushed.
+#[derive(Clone)]
+struct Vest {
+    recording: RecordingDevice,
+    flushed: Arc<AtomicBool>,
+}
+
+impl Vest {
+    fn new() -> Self {
+        Self {
+            recording: RecordingDevice::new("vest", vec![BodyPart::ChestFront, BodyPart::ChestBack]),
+            flushed: Arc::default(),
+        }
+    }
+
+    fn is_silent(&self) -> bool {
+        let frames = self.recording.frames();
+        frames.last().is_some_and(|frame| frame.parts().all(|(_, motors)| motors.iter().all(|motor| *motor == 0.0)))
+    }
+}
+
+impl HapticDevice for Vest {
+    fn name(&self) -> &str {
+        self.recording.name()
+    }
+
+    fn positions(&self) -> Vec<BodyPart> {
+        self.recording.positions()
+    }
+
+    fn write(&mut self, frame: &HapticFrame) -> Result<(), DeviceError> {
+        self.recording.write(frame)
+    }
+
+    fn flush(&mut self) -> Result<(), DeviceError> {
+        self.flushed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+struct Running {
+    address: SocketAddr,
+    player: HapticPlayer,
+    vest: Vest,
+    state_file: PathBuf,
+    shutdown: CancellationToken,
+    task: JoinHandle<Result<(), ServerError>>,
+}
+
+/// Runs the server `configured` from the default one, with a vest and a state file.
+async fn running(name: &str, configured: impl FnOnce(BHapticsStudioServer) -> BHapticsStudioServer) -> Running {
+    let state_file = std::env::temp_dir().join(format!("xrconnect-shutdown-{}-{}.json", std::process::id(), name));
+    let _ = std::fs::remove_file(&state_file);
+
+    let address = common::free_address();
+    let server = configured(BHapticsStudioServer::new(address)).with_state_file(Some(state_file.clone()));
+    let vest = Vest::new();
+    let player = server.player().clone();
+    player.add_device(Box::new(vest.clone()));
+    player.set_calibration(CalibrationProfile::new("alice"));
+    let shutdown = server.shutdown_token();
+    let task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    Running { address, player, vest, state_file, shutdown, task }
+}
+
+fn url(address: SocketAddr) -> String {
+    format!("ws://{}/v2/feedbacks?app_id=com.example.game&app_name=Game", address)
+}
+
+/// Submission of ten second frames, under `keys`.
+fn submit(keys: impl IntoIterator<Item = String>) -> Message {
+    let submits: Vec<_> = keys
+        .into_iter()
+        .map(|key| json!({
+            "Type": "frame",
+            "Key": key,
+            "Frame": { "Position": "VestFront", "DotPoints": [{ "Index": 0, "Intensity": 100 }], "PathPoints": [], "DurationMillis": 10_000 },
+        }))
+        .collect();
+
+    Message::Text(json!({ "Submit": submits }).to_string())
+}
+
+/// Waits for the next close frame, up to two seconds.
+async fn close_code<S>(socket: &mut S) -> Option<CloseCode>
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    let closed = async {
+        while let Some(Ok(message)) = socket.next().await {
+            if let Message::Close(frame) = message {
+                return frame.map(|frame| frame.code);
+            }
+        }
+        None
+    };
+
+    tokio::time::timeout(Duration::from_secs(2), closed).await.unwrap()
+}
+
+#[tokio::test]
+async fn shutting_down_closes_clients_and_stops_devices() {
+    let server = running("graceful", |server| server).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(url(server.address)).await.unwrap();
+    socket.send(submit([String::from("hit")])).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!server.player.active_keys().is_empty());
+    assert!(!server.vest.is_silent());
+
+    server.shutdown.cancel();
+
+    assert_eq!(close_code(&mut socket).await, Some(CloseCode::Away));
+    tokio::time::timeout(Duration::from_secs(2), server.task).await.unwrap().unwrap().unwrap();
+    assert!(server.player.active_keys().is_empty());
+    assert!(server.vest.is_silent());
+    assert!(server.vest.flushed.load(Ordering::SeqCst));
+
+    let saved = SavedState::load(&server.state_file).unwrap();
+    assert_eq!(saved.calibration.as_deref(), Some("alice"));
+    let _ = std::fs::remove_file(&server.state_file);
+}
+
+#[tokio::test]
+async fn clients_held_for_approval_are_closed_as_going_away() {
+    let access = AccessControl::new(AccessPolicy { unknown_apps: UnknownApps::Ask, ..Default::default() });
+    let server = running("held", |server| server.with_access(access.clone())).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(url(server.address)).await.unwrap();
+    socket.send(submit([String::from("held")])).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(access.pending().len(), 1);
+
+    server.shutdown.cancel();
+
+    assert_eq!(close_code(&mut socket).await, Some(CloseCode::Away));
+    tokio::time::timeout(Duration::from_secs(2), server.task).await.unwrap().unwrap().unwrap();
+    let _ = std::fs::remove_file(&server.state_file);
+}
+
+#[tokio::test]
+async fn clients_not_reading_do_not_hold_the_shutdown_past_the_timeout() {
+    let timeout = Duration::from_millis(500);
+    let server = running("timeout", |server| server.with_shutdown_timeout(timeout)).await;
+
+    // A client never reading its responses, which fill the socket buffers so that the close frame cannot be sent
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.set_recv_buffer_size(4096).unwrap();
+    let stream = socket.connect(server.address).await.unwrap();
+    let (mut socket, _) = tokio_tungstenite::client_async(url(server.address), stream).await.unwrap();
+    socket.send(submit((0..1000).map(|index| format!("{:0>200}", index)))).await.unwrap();
+    for _ in 0..200 {
+        socket.send(Message::Text(String::from(r#"{ "Submit": [] }"#))).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let started = Instant::now();
+    server.shutdown.cancel();
+
+    tokio::time::timeout(timeout + Duration::from_secs(2), server.task).await.unwrap().unwrap().unwrap();
+    assert!(started.elapsed() >= timeout, "{:?}", started.elapsed());
+    assert!(server.player.active_keys().is_empty());
+    assert!(server.vest.is_silent());
+    assert!(server.vest.flushed.load(Ordering::SeqCst));
+    assert!(server.state_file.exists());
+    let _ = std::fs::remove_file(&server.state_file);
+}
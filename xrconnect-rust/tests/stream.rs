@@ -0,0 +1,132 @@
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+
+use futures_util::StreamExt;
+use haptic_lib::{ BodyPart, EffectInterpolation };
+use serde_json::Value;
+use tokio::{
+    io::{ AsyncReadExt, AsyncWriteExt },
+    net::TcpStream,
+    time::Instant,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+use xrconnect::{
+    access::AccessControl,
+    api::ControlApi,
+    haptics::{
+        device::RecordingDevice,
+        model::{ ClipOutput, DotIntensity, HapticPattern, PatternClip, PatternPoints, PlaybackOptions },
+        player::HapticPlayer,
+    },
+};
+
+/// States per second asked by the subscribers.
+const RATE: usize = 10;
+
+/// Player writing frames, with a ten second hit on the front of the vest registered, and the API serving it.
+fn serving() -> (SocketAddr, HapticPlayer) {
+    let player = HapticPlayer::new();
+    player.add_device(Box::new(RecordingDevice::new("vest", vec![BodyPart::ChestFront, BodyPart::ChestBack])));
+    player.register("hit", HapticPattern::new(vec![PatternClip {
+        part: BodyPart::ChestFront,
+        start_millis: 0,
+        end_millis: 10_000,
+        interpolation: EffectInterpolation::None,
+        points: PatternPoints::Dot(vec![DotIntensity { index: 0, intensity: 1.0 }]),
+        output: ClipOutput::Motors,
+        layout: None,
+    }]));
+    tokio::spawn({
+        let player = player.clone();
+        async move { player.run().await }
+    });
+
+    let api = ControlApi::new(player.clone(), AccessControl::default());
+    let (address, server) = warp::serve(api.routes()).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    (address, player)
+}
+
+/// Plays the hit a second once subscribed, then stops it, gathering the messages of `messages` in the meantime.
+async fn gathered(player: &HapticPlayer, messages: impl futures_util::Stream<Item = Value>) -> Vec<Value> {
+    tokio::pin!(messages);
+    let first = tokio::time::timeout(Duration::from_secs(1), messages.next()).await.unwrap().unwrap();
+    assert_eq!(first["type"], "state");
+
+    let mut gathered = vec![];
+    let started = Instant::now();
+    player.play("hit", "hit", PlaybackOptions::default()).unwrap();
+    let mut stopped = false;
+
+    while let Ok(Some(message)) = tokio::time::timeout_at(started + Duration::from_millis(1500), messages.next()).await {
+        gathered.push(message);
+        if !stopped && started.elapsed() >= Duration::from_secs(1) {
+            player.stop("hit");
+            stopped = true;
+        }
+    }
+
+    gathered
+}
+
+fn assert_streamed(messages: &[Value]) {
+    let states: Vec<&Value> = messages.iter().filter(|message| message["type"] == "state").collect();
+    assert!((RATE + RATE / 4..=2 * RATE).contains(&states.len()), "{} states in 1.5 s", states.len());
+    assert!(states.iter().any(|state| state["frame"]["parts"]["ChestFront"][0] == 1.0), "{:?}", states);
+    assert!(states.iter().all(|state| state["panicked"] == false));
+
+    let events: Vec<&str> = messages
+        .iter()
+        .filter(|message| message["type"] == "event" && message["active_key"] == "hit")
+        .map(|message| message["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(events, ["played", "stopped"]);
+}
+
+#[tokio::test]
+async fn websocket_streams_states_and_events() {
+    let (address, player) = serving();
+    let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/api/stream?rate={}", address, RATE)).await.unwrap();
+
+    let messages = socket.filter_map(|message| async move {
+        match message.unwrap() {
+            Message::Text(text) => Some(serde_json::from_str(&text).unwrap()),
+            _ => None,
+        }
+    });
+
+    assert_streamed(&gathered(&player, messages).await);
+}
+
+#[tokio::test]
+async fn server_sent_events_stream_states_and_events() {
+    let (address, player) = serving();
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let request = format!("GET /api/stream/events?rate={} HTTP/1.1\r\nHost: {}\r\nAccept: text/event-stream\r\n\r\n", RATE, address);
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    // Every `data:` line carries a message, named after its type by the `event:` line before it
+    let lines = futures_util::stream::unfold((stream, String::new()), |(mut stream, mut pending)| async move {
+        loop {
+            if let Some(end) = pending.find('\n') {
+                let line = pending[..end].trim_end().to_string();
+                pending.drain(..=end);
+                return Some((line, (stream, pending)));
+            }
+
+            let mut buffer = [0; 4096];
+            let read = stream.read(&mut buffer).await.ok().filter(|read| *read > 0)?;
+            pending.push_str(&String::from_utf8_lossy(&buffer[..read]));
+        }
+    });
+    let messages = lines.filter_map(|line| async move {
+        line.strip_prefix("data:").map(|data| serde_json::from_str::<Value>(data).unwrap())
+    });
+
+    let messages = gathered(&player, messages).await;
+    assert_streamed(&messages);
+}
@@ -0,0 +1,132 @@
+use std::{
+    sync::{
+        atomic::{ AtomicBool, Ordering },
+        Arc, Mutex,
+    },
+    time::{ Duration, Instant },
+};
+
+use haptic_lib::BodyPart;
+
+use xrconnect::haptics::{
+    device::{ DeviceError, HapticDevice },
+    model::HapticFrame,
+    player::HapticPlayer,
+};
+
+const DEADLINE: Duration = Duration::from_millis(100);
+
+/// Device whose writes time out once it stops acknowledging them, keeping when it was stopped.
+#[derive(Clone)]
+struct StallingDevice {
+    acknowledging: Arc<AtomicBool>,
+    stops: Arc<Mutex<Vec<Instant>>>,
+}
+
+impl StallingDevice {
+    fn new() -> Self {
+        Self {
+            acknowledging: Arc::new(AtomicBool::new(true)),
+            stops: Arc::default(),
+        }
+    }
+
+    fn stops(&self) -> Vec<Instant> {
+        self.stops.lock().unwrap().clone()
+    }
+}
+
+impl HapticDevice for StallingDevice {
+    fn name(&self) -> &str {
+        "stalling"
+    }
+
+    fn positions(&self) -> Vec<BodyPart> {
+        vec![BodyPart::Head]
+    }
+
+    fn write(&mut self, _frame: &HapticFrame) -> Result<(), DeviceError> {
+        if self.acknowledging.load(Ordering::Relaxed) {
+            Ok(())
+        } else {
+            Err(DeviceError::Io(std::io::ErrorKind::TimedOut.into()))
+        }
+    }
+
+    fn stop(&mut self) -> Result<(), DeviceError> {
+        self.stops.lock().unwrap().push(Instant::now());
+        Ok(())
+    }
+}
+
+fn playing(player: &HapticPlayer) -> tokio::task::JoinHandle<()> {
+    let player = player.clone();
+    tokio::spawn(async move { player.run().await })
+}
+
+#[tokio::test]
+async fn devices_acknowledging_writes_are_left_alone() {
+    let device = StallingDevice::new();
+    let player = HapticPlayer::new();
+    player.add_device(Box::new(device.clone()));
+    let playback = playing(&player);
+    let _watchdog = player.spawn_watchdog(DEADLINE);
+
+    tokio::time::sleep(DEADLINE * 5).await;
+
+    assert!(device.stops().is_empty());
+    assert!(!player.devices()[0].stopped);
+    playback.abort();
+}
+
+#[tokio::test]
+async fn devices_no_longer_acknowledging_writes_are_stopped_within_the_deadline() {
+    let device = StallingDevice::new();
+    let player = HapticPlayer::new();
+    player.add_device(Box::new(device.clone()));
+    let playback = playing(&player);
+    let _watchdog = player.spawn_watchdog(DEADLINE);
+    tokio::time::sleep(DEADLINE * 2).await;
+
+    let stalled = Instant::now();
+    device.acknowledging.store(false, Ordering::Relaxed);
+    tokio::time::sleep(DEADLINE * 3).await;
+
+    // Checked every half deadline, the device is stopped once and only once
+    let stops = device.stops();
+    assert_eq!(stops.len(), 1);
+    assert!(stops[0].duration_since(stalled) <= DEADLINE * 2, "stopped after {:?}", stops[0].duration_since(stalled));
+    assert!(player.devices()[0].stopped);
+
+    // Back to acknowledging writes, the device is playing again
+    device.acknowledging.store(true, Ordering::Relaxed);
+    tokio::time::sleep(DEADLINE).await;
+    assert!(!player.devices()[0].stopped);
+    playback.abort();
+}
+
+#[tokio::test]
+async fn devices_left_without_frames_are_stopped() {
+    let device = StallingDevice::new();
+    let player = HapticPlayer::new();
+    player.add_device(Box::new(device.clone()));
+
+    // No playback task at all, as if it panicked
+    let _watchdog = player.spawn_watchdog(DEADLINE);
+    tokio::time::sleep(DEADLINE * 3).await;
+
+    assert_eq!(device.stops().len(), 1);
+    assert!(player.devices()[0].stopped);
+}
+
+#[tokio::test]
+async fn dropping_the_watchdog_stops_it() {
+    let device = StallingDevice::new();
+    let player = HapticPlayer::new();
+    player.add_device(Box::new(device.clone()));
+
+    drop(player.spawn_watchdog(DEADLINE));
+    tokio::time::sleep(DEADLINE * 3).await;
+
+    assert!(device.stops().is_empty());
+}